@@ -0,0 +1,214 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+    ops::Range,
+};
+
+use crate::elf::SourceLine;
+
+use super::reg::OperationState;
+
+const PAGE_SHIFT: u32 = 12;
+const PAGE_WORDS: usize = (1 << PAGE_SHIFT) / 2 / 64;
+
+/// Bits for each halfword in a page of memory, set if an instruction started there.
+#[derive(Default, Clone, Debug)]
+struct Page {
+    arm: [u64; PAGE_WORDS],
+    thumb: [u64; PAGE_WORDS],
+}
+
+impl Page {
+    fn bits(&self, state: OperationState) -> &[u64; PAGE_WORDS] {
+        match state {
+            OperationState::Arm => &self.arm,
+            OperationState::Thumb => &self.thumb,
+        }
+    }
+}
+
+/// Records the address and state of every executed instruction.
+///
+/// Mirrored addresses are recorded at their first mirror.
+#[derive(Default, Clone, Debug)]
+pub struct Coverage {
+    pages: HashMap<u32, Page>,
+}
+
+fn canonical_addr(addr: u32) -> u32 {
+    match addr >> 24 {
+        0x02 => 0x0200_0000 | (addr & 0x3_ffff),
+        0x03 => 0x0300_0000 | (addr & 0x7fff),
+        0x08..=0x0d => 0x0800_0000 | (addr & 0x01ff_ffff),
+        _ => addr,
+    }
+}
+
+fn bit_index(addr: u32) -> (usize, u32) {
+    let idx = (addr & ((1 << PAGE_SHIFT) - 1)) as usize / 2;
+    (idx / 64, u32::try_from(idx % 64).unwrap())
+}
+
+impl Coverage {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, addr: u32, state: OperationState) {
+        let addr = canonical_addr(addr);
+        let page = self.pages.entry(addr >> PAGE_SHIFT).or_default();
+        let bits = match state {
+            OperationState::Arm => &mut page.arm,
+            OperationState::Thumb => &mut page.thumb,
+        };
+        let (word, bit) = bit_index(addr);
+        bits[word] |= 1 << bit;
+    }
+
+    /// Whether an instruction at `addr` was executed in `state`.
+    #[must_use]
+    pub fn contains(&self, addr: u32, state: OperationState) -> bool {
+        let addr = canonical_addr(addr);
+        self.pages.get(&(addr >> PAGE_SHIFT)).map_or(false, |page| {
+            let (word, bit) = bit_index(addr);
+            page.bits(state)[word] & (1 << bit) != 0
+        })
+    }
+
+    /// Whether any instruction in `addrs` was executed, in either state.
+    #[must_use]
+    pub fn contains_any(&self, addrs: Range<u32>) -> bool {
+        addrs.step_by(2).any(|addr| {
+            self.contains(addr, OperationState::Arm) || self.contains(addr, OperationState::Thumb)
+        })
+    }
+
+    /// Returns the executed addresses and their states, in address order.
+    #[must_use]
+    pub fn addrs(&self) -> Vec<(u32, OperationState)> {
+        let mut page_keys: Vec<_> = self.pages.keys().copied().collect();
+        page_keys.sort_unstable();
+
+        let mut addrs = Vec::new();
+        for key in page_keys {
+            let page = &self.pages[&key];
+            for (word, (&arm, &thumb)) in (0..).zip(page.arm.iter().zip(&page.thumb)) {
+                for bit in 0..64 {
+                    let addr = (key << PAGE_SHIFT) + 2 * (word * 64 + bit);
+                    if arm & (1 << bit) != 0 {
+                        addrs.push((addr, OperationState::Arm));
+                    }
+                    if thumb & (1 << bit) != 0 {
+                        addrs.push((addr, OperationState::Thumb));
+                    }
+                }
+            }
+        }
+
+        addrs
+    }
+
+    /// Writes each executed address on its own line, followed by its state (`arm` or `thumb`).
+    ///
+    /// # Errors
+    /// Returns an error if writing fails.
+    pub fn write_addrs(&self, w: &mut impl Write) -> io::Result<()> {
+        for (addr, state) in self.addrs() {
+            let state = match state {
+                OperationState::Arm => "arm",
+                OperationState::Thumb => "thumb",
+            };
+            writeln!(w, "{addr:08x} {state}")?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes source line coverage in the lcov tracefile format, given the code generated for
+    /// each line (like from `Elf::source_lines`).
+    ///
+    /// A line is hit if any of its code was executed; as execution isn't counted, hits are 0 or 1.
+    ///
+    /// # Errors
+    /// Returns an error if writing fails.
+    pub fn write_lcov(&self, w: &mut impl Write, lines: &[SourceLine]) -> io::Result<()> {
+        let mut files = BTreeMap::<&str, BTreeMap<u32, bool>>::new();
+        for line in lines {
+            let hit = files
+                .entry(&line.file)
+                .or_default()
+                .entry(line.line)
+                .or_default();
+            *hit |= self.contains_any(line.addrs.clone());
+        }
+
+        writeln!(w, "TN:")?;
+        for (file, lines) in files {
+            writeln!(w, "SF:{file}")?;
+            for (line, &hit) in &lines {
+                writeln!(w, "DA:{line},{}", u8::from(hit))?;
+            }
+            writeln!(w, "LF:{}", lines.len())?;
+            writeln!(w, "LH:{}", lines.values().filter(|&&hit| hit).count())?;
+            writeln!(w, "end_of_record")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coverage_works() {
+        let mut coverage = Coverage::new();
+        coverage.record(0x0800_0000, OperationState::Arm);
+        coverage.record(0x0a00_0102, OperationState::Thumb);
+        coverage.record(0x0300_8000, OperationState::Thumb);
+
+        assert!(coverage.contains(0x0800_0000, OperationState::Arm));
+        assert!(!coverage.contains(0x0800_0000, OperationState::Thumb));
+        assert!(coverage.contains(0x0800_0102, OperationState::Thumb));
+        assert!(coverage.contains_any(0x0800_0100..0x0800_0104));
+        assert!(!coverage.contains_any(0x0800_0104..0x0800_0200));
+        assert_eq!(
+            coverage.addrs(),
+            [
+                (0x0300_0000, OperationState::Thumb),
+                (0x0800_0000, OperationState::Arm),
+                (0x0800_0102, OperationState::Thumb),
+            ]
+        );
+
+        let mut addrs = Vec::new();
+        coverage.write_addrs(&mut addrs).unwrap();
+        assert_eq!(
+            String::from_utf8(addrs).unwrap(),
+            "03000000 thumb\n08000000 arm\n08000102 thumb\n"
+        );
+
+        let source_line = |line, addrs| SourceLine {
+            file: "main.c".into(),
+            line,
+            addrs,
+        };
+        let mut lcov = Vec::new();
+        coverage
+            .write_lcov(
+                &mut lcov,
+                &[
+                    source_line(3, 0x0800_0000..0x0800_0004),
+                    source_line(4, 0x0800_0004..0x0800_0100),
+                    source_line(3, 0x0800_0100..0x0800_0104),
+                ],
+            )
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(lcov).unwrap(),
+            "TN:\nSF:main.c\nDA:3,1\nDA:4,0\nLF:2\nLH:1\nend_of_record\n"
+        );
+    }
+}
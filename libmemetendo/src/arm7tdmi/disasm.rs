@@ -0,0 +1,433 @@
+use bitmatch::bitmatch;
+use intbits::Bits;
+
+use crate::arbitrary_sign_extend;
+
+use super::reg::{OperationState, LR_INDEX, PC_INDEX, SP_INDEX};
+
+/// Disassembles an instruction located at `addr`, which is used to resolve branch targets.
+// THUMB instructions are only 16 bits wide, so we never panic.
+#[allow(clippy::missing_panics_doc)]
+#[must_use]
+pub fn disassemble(state: OperationState, instr: u32, addr: u32) -> String {
+    match state {
+        OperationState::Arm => disassemble_arm(instr, addr),
+        OperationState::Thumb => disassemble_thumb(instr.bits(..16).try_into().unwrap(), addr),
+    }
+}
+
+fn reg_name(r: usize) -> String {
+    match r {
+        SP_INDEX => "sp".into(),
+        LR_INDEX => "lr".into(),
+        PC_INDEX => "pc".into(),
+        _ => format!("r{r}"),
+    }
+}
+
+fn reg_list(r_list: u16) -> String {
+    let mut names = Vec::new();
+    let mut r = 0;
+    while r < 16 {
+        if !r_list.bit(r) {
+            r += 1;
+            continue;
+        }
+
+        // Collapse runs of 3 or more low registers into a range, like r0-r3.
+        let start = r;
+        while r < 13 && r_list.bit(r + 1) {
+            r += 1;
+        }
+        if r - start >= 2 {
+            names.push(format!("{}-{}", reg_name(start), reg_name(r)));
+        } else {
+            names.extend((start..=r).map(reg_name));
+        }
+        r += 1;
+    }
+
+    format!("{{{}}}", names.join(","))
+}
+
+fn signed_hex(value: i64) -> String {
+    if value < 0 {
+        format!("-0x{:x}", -value)
+    } else {
+        format!("0x{value:x}")
+    }
+}
+
+const COND_SUFFIXES: [&str; 16] = [
+    "eq", "ne", "cs", "cc", "mi", "pl", "vs", "vc", "hi", "ls", "ge", "lt", "gt", "le", "", "nv",
+];
+
+const SHIFT_NAMES: [&str; 4] = ["lsl", "lsr", "asr", "ror"];
+
+fn r_index_arm(instr: u32, pos: u8) -> usize {
+    instr.bits(pos..pos + 4).try_into().unwrap()
+}
+
+/// Formats a register operand shifted by an immediate, like the interpreter's special zero
+/// offsets.
+fn arm_shifted_reg(instr: u32) -> String {
+    let rm = reg_name(r_index_arm(instr, 0));
+    let op = usize::try_from(instr.bits(5..7)).unwrap();
+
+    if instr.bit(4) {
+        return format!(
+            "{rm},{} {}",
+            SHIFT_NAMES[op],
+            reg_name(r_index_arm(instr, 8))
+        );
+    }
+    match (op, instr.bits(7..12)) {
+        (0, 0) => rm,
+        (3, 0) => format!("{rm},rrx"),
+        (1 | 2, 0) => format!("{rm},{} #0x20", SHIFT_NAMES[op]),
+        (_, amount) => format!("{rm},{} #0x{amount:x}", SHIFT_NAMES[op]),
+    }
+}
+
+/// Disassembles an ARM instruction located at `addr`.
+#[allow(clippy::too_many_lines)]
+#[bitmatch]
+#[must_use]
+pub fn disassemble_arm(instr: u32, addr: u32) -> String {
+    let cond = COND_SUFFIXES[usize::try_from(instr.bits(28..)).unwrap()];
+    let rn = reg_name(r_index_arm(instr, 16));
+    let rd = reg_name(r_index_arm(instr, 12));
+
+    #[bitmatch]
+    match instr.bits(..28) {
+        "0001_0010_1111_1111_1111_????_????" => {
+            format!("bx{cond} {}", reg_name(r_index_arm(instr, 0)))
+        }
+        "0001_0?00_????_????_0000_1001_????" => {
+            let b = if instr.bit(22) { "b" } else { "" };
+            format!(
+                "swp{cond}{b} {rd},{},[{rn}]",
+                reg_name(r_index_arm(instr, 0))
+            )
+        }
+        "0000_????_????_????_????_1001_????" => {
+            let s = if instr.bit(20) { "s" } else { "" };
+            let rm = reg_name(r_index_arm(instr, 0));
+            let rs = reg_name(r_index_arm(instr, 8));
+            if instr.bit(23) {
+                let op = ["umull", "umlal", "smull", "smlal"]
+                    [usize::try_from(instr.bits(21..23)).unwrap()];
+                format!("{op}{cond}{s} {rd},{rn},{rm},{rs}")
+            } else if instr.bit(21) {
+                format!("mla{cond}{s} {rn},{rm},{rs},{rd}")
+            } else {
+                format!("mul{cond}{s} {rn},{rm},{rs}")
+            }
+        }
+        "000?_????_????_????_????_1??1_????" => {
+            let op = match (instr.bit(20), instr.bits(5..7)) {
+                (true, 1) => "ldrh",
+                (true, 2) => "ldrsb",
+                (true, 3) => "ldrsh",
+                (false, 1) => "strh",
+                _ => "undefined",
+            };
+            let offset = if instr.bit(22) {
+                format!("#0x{:x}", instr.bits(..4).with_bits(4.., instr.bits(8..12)))
+            } else {
+                reg_name(r_index_arm(instr, 0))
+            };
+            let sign = if instr.bit(23) { "" } else { "-" };
+
+            format!(
+                "{op}{cond} {rd},{}",
+                arm_address(instr, &rn, &format!("{sign}{offset}"))
+            )
+        }
+        "00?1_0??0_????_????_????_????_????" => {
+            let psr = if instr.bit(22) { "spsr" } else { "cpsr" };
+            if instr.bit(21) {
+                let mut fields = String::new();
+                if instr.bit(19) {
+                    fields.push('f');
+                }
+                if instr.bit(16) {
+                    fields.push('c');
+                }
+                let value = if instr.bit(25) {
+                    format!(
+                        "#0x{:x}",
+                        instr.bits(..8).rotate_right(2 * instr.bits(8..12))
+                    )
+                } else {
+                    reg_name(r_index_arm(instr, 0))
+                };
+
+                format!("msr{cond} {psr}_{fields},{value}")
+            } else {
+                format!("mrs{cond} {rd},{psr}")
+            }
+        }
+        "1111_????_????_????_????_????_????" => format!("swi{cond} #0x{:x}", instr.bits(..24)),
+        "011?_????_????_????_????_???1_????" => "undefined".into(),
+        "100?_????_????_????_????_????_????" => {
+            let op = if instr.bit(20) { "ldm" } else { "stm" };
+            let mode = ["da", "ia", "db", "ib"][usize::try_from(instr.bits(23..25)).unwrap()];
+            let writeback = if instr.bit(21) { "!" } else { "" };
+            let user = if instr.bit(22) { "^" } else { "" };
+            let r_list = reg_list(instr.bits(..16).try_into().unwrap());
+
+            format!("{op}{cond}{mode} {rn}{writeback},{r_list}{user}")
+        }
+        "101?_????_????_????_????_????_????" => {
+            let l = if instr.bit(24) { "l" } else { "" };
+            let offset = arbitrary_sign_extend!(u32, instr.bits(..24), 24).wrapping_mul(4);
+            format!(
+                "b{l}{cond} 0x{:08x}",
+                addr.wrapping_add(8).wrapping_add(offset)
+            )
+        }
+        "00??_????_????_????_????_????_????" => {
+            let op = instr.bits(21..25);
+            let name = [
+                "and", "eor", "sub", "rsb", "add", "adc", "sbc", "rsc", "tst", "teq", "cmp", "cmn",
+                "orr", "mov", "bic", "mvn",
+            ][usize::try_from(op).unwrap()];
+            let s = if instr.bit(20) && !(8..=11).contains(&op) {
+                "s"
+            } else {
+                ""
+            };
+            let op2 = if instr.bit(25) {
+                format!(
+                    "#0x{:x}",
+                    instr.bits(..8).rotate_right(2 * instr.bits(8..12))
+                )
+            } else {
+                arm_shifted_reg(instr)
+            };
+
+            match op {
+                8..=11 => format!("{name}{cond} {rn},{op2}"),
+                13 | 15 => format!("{name}{cond}{s} {rd},{op2}"),
+                _ => format!("{name}{cond}{s} {rd},{rn},{op2}"),
+            }
+        }
+        "01??_????_????_????_????_????_????" => {
+            let op = if instr.bit(20) { "ldr" } else { "str" };
+            let b = if instr.bit(22) { "b" } else { "" };
+            let t = if !instr.bit(24) && instr.bit(21) {
+                "t"
+            } else {
+                ""
+            };
+            let sign = if instr.bit(23) { "" } else { "-" };
+            let offset = if instr.bit(25) {
+                arm_shifted_reg(instr)
+            } else {
+                format!("#0x{:x}", instr.bits(..12))
+            };
+
+            format!(
+                "{op}{cond}{b}{t} {rd},{}",
+                arm_address(instr, &rn, &format!("{sign}{offset}"))
+            )
+        }
+        "11??_????_????_????_????_????_????" => format!("coprocessor{cond}"),
+        _ => "undefined".into(),
+    }
+}
+
+/// Formats the address operand of a single or half-word data transfer.
+fn arm_address(instr: u32, rn: &str, offset: &str) -> String {
+    let zero_offset = offset.ends_with("#0x0");
+    if instr.bit(24) {
+        let writeback = if instr.bit(21) { "!" } else { "" };
+        if zero_offset {
+            format!("[{rn}]{writeback}")
+        } else {
+            format!("[{rn},{offset}]{writeback}")
+        }
+    } else {
+        format!("[{rn}],{offset}")
+    }
+}
+
+fn r_index_thumb(instr: u16, pos: u8) -> usize {
+    instr.bits(pos..pos + 3).into()
+}
+
+/// Disassembles a THUMB instruction located at `addr`.
+#[allow(clippy::too_many_lines)]
+#[bitmatch]
+#[must_use]
+pub fn disassemble_thumb(instr: u16, addr: u32) -> String {
+    let r0 = reg_name(r_index_thumb(instr, 0));
+    let r3 = reg_name(r_index_thumb(instr, 3));
+    let r6 = reg_name(r_index_thumb(instr, 6));
+    let r8 = reg_name(r_index_thumb(instr, 8));
+    let imm8 = u32::from(instr.bits(..8));
+    let pc = addr.wrapping_add(4);
+
+    #[bitmatch]
+    match u8::try_from(instr.bits(8..)).unwrap() {
+        "1011_0000" => {
+            let sign = if instr.bit(7) { "-" } else { "" };
+            format!("add sp,#{sign}0x{:x}", u32::from(instr.bits(..7)) * 4)
+        }
+        "1101_1111" => format!("swi #0x{imm8:x}"),
+        "0100_00??" => {
+            let op = [
+                "and", "eor", "lsl", "lsr", "asr", "adc", "sbc", "ror", "tst", "neg", "cmp", "cmn",
+                "orr", "mul", "bic", "mvn",
+            ][usize::from(instr.bits(6..10))];
+            format!("{op} {r0},{r3}")
+        }
+        "0100_01??" => {
+            let rs = reg_name(r_index_thumb(instr, 3).with_bit(3, instr.bit(6)));
+            let rd = reg_name(r_index_thumb(instr, 0).with_bit(3, instr.bit(7)));
+            match instr.bits(8..10) {
+                0 => format!("add {rd},{rs}"),
+                1 => format!("cmp {rd},{rs}"),
+                2 => format!("mov {rd},{rs}"),
+                _ => format!("bx {rs}"),
+            }
+        }
+        "0001_1???" => {
+            let op = if instr.bit(9) { "sub" } else { "add" };
+            if instr.bit(10) {
+                format!("{op} {r0},{r3},#0x{:x}", r_index_thumb(instr, 6))
+            } else {
+                format!("{op} {r0},{r3},{r6}")
+            }
+        }
+        "0100_1???" => format!("ldr {r8},[pc,#0x{:x}]", imm8 * 4),
+        "1110_0???" => {
+            let offset = arbitrary_sign_extend!(u32, instr.bits(..11), 11).wrapping_mul(2);
+            format!("b 0x{:08x}", pc.wrapping_add(offset))
+        }
+        "0101_????" => {
+            let op = if instr.bit(9) {
+                ["strh", "ldsb", "ldrh", "ldsh"]
+            } else {
+                ["str", "strb", "ldr", "ldrb"]
+            }[usize::from(instr.bits(10..12))];
+            format!("{op} {r0},[{r3},{r6}]")
+        }
+        "1000_????" => {
+            let op = if instr.bit(11) { "ldrh" } else { "strh" };
+            format!("{op} {r0},[{r3},#0x{:x}]", instr.bits(6..11) * 2)
+        }
+        "1001_????" => {
+            let op = if instr.bit(11) { "ldr" } else { "str" };
+            format!("{op} {r8},[sp,#0x{:x}]", imm8 * 4)
+        }
+        "1010_????" => {
+            let base = if instr.bit(11) { "sp" } else { "pc" };
+            format!("add {r8},{base},#0x{:x}", imm8 * 4)
+        }
+        "1011_????" => {
+            let pop = instr.bit(11);
+            let extra = if pop { PC_INDEX } else { LR_INDEX };
+            let r_list = instr.bits(..8).with_bit(extra, instr.bit(8));
+            format!("{} {}", if pop { "pop" } else { "push" }, reg_list(r_list))
+        }
+        "1100_????" => {
+            let op = if instr.bit(11) { "ldmia" } else { "stmia" };
+            format!("{op} {r8}!,{}", reg_list(instr.bits(..8)))
+        }
+        "1101_????" => {
+            let cond = COND_SUFFIXES[usize::from(instr.bits(8..12))];
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let offset = (2 * i32::from(instr as i8)) as u32;
+            format!("b{cond} 0x{:08x}", pc.wrapping_add(offset))
+        }
+        "1111_????" => {
+            let offset_part = u32::from(instr.bits(..11));
+            if instr.bit(11) {
+                format!("bl lr+0x{:x}", offset_part << 1)
+            } else {
+                let offset_hi = arbitrary_sign_extend!(u32, offset_part << 12, 23);
+                #[allow(clippy::cast_possible_wrap)]
+                let offset_hi = signed_hex(i64::from(offset_hi as i32));
+                format!(
+                    "bl (prefix) pc{}{offset_hi}",
+                    if offset_hi.starts_with('-') { "" } else { "+" }
+                )
+            }
+        }
+        "000?_????" => {
+            let op = ["lsl", "lsr", "asr"][usize::from(instr.bits(11..13))];
+            format!("{op} {r0},{r3},#0x{:x}", instr.bits(6..11))
+        }
+        "001?_????" => {
+            let op = ["mov", "cmp", "add", "sub"][usize::from(instr.bits(11..13))];
+            format!("{op} {r8},#0x{imm8:x}")
+        }
+        "011?_????" => {
+            let op = ["str", "ldr", "strb", "ldrb"][usize::from(instr.bits(11..13))];
+            let scale = if instr.bit(12) { 1 } else { 4 };
+            format!("{op} {r0},[{r3},#0x{:x}]", instr.bits(6..11) * scale)
+        }
+        _ => "undefined".into(),
+    }
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassemble_arm_works() {
+        let cases: &[(u32, &str)] = &[
+            (0xe3a0_0012, "mov r0,#0x12"),
+            (0xe091_2003, "adds r2,r1,r3"),
+            (0xe1a0_1102, "mov r1,r2,lsl #0x2"),
+            (0xe1a0_1062, "mov r1,r2,rrx"),
+            (0x0350_0000, "cmpeq r0,#0x0"),
+            (0xea00_002e, "b 0x080000c0"),
+            (0xeb00_0000, "bl 0x08000008"),
+            (0xe59f_1004, "ldr r1,[pc,#0x4]"),
+            (0xe5b2_3004, "ldr r3,[r2,#0x4]!"),
+            (0xe492_3004, "ldr r3,[r2],#0x4"),
+            (0xe1d2_30b2, "ldrh r3,[r2,#0x2]"),
+            (0xe92d_400f, "stmdb sp!,{r0-r3,lr}"),
+            (0xe12f_ff1e, "bx lr"),
+            (0xe10f_0000, "mrs r0,cpsr"),
+            (0xe129_f000, "msr cpsr_fc,r0"),
+            (0xe002_0091, "mul r2,r1,r0"),
+            (0xef00_0006, "swi #0x6"),
+        ];
+        for &(instr, expected) in cases {
+            assert_eq!(disassemble_arm(instr, 0x0800_0000), expected, "{instr:08x}");
+        }
+    }
+
+    #[test]
+    fn disassemble_thumb_works() {
+        let cases: &[(u16, &str)] = &[
+            (0b001_00_000_0001_0010, "mov r0,#0x12"),
+            (0b000_00_00011_001_100, "lsl r4,r1,#0x3"),
+            (0b0001_1_1_0_001_001_011, "add r3,r1,#0x1"),
+            (0b0100_0011_01_001_100, "mul r4,r1"),
+            (0b0100_0111_0_1110_000, "bx lr"),
+            (0b0100_1_001_0000_0001, "ldr r1,[pc,#0x4]"),
+            (0b1011_0101_0000_0011, "push {r0,r1,lr}"),
+            (0b1011_1101_0000_1111, "pop {r0-r3,pc}"),
+            (0b1101_0000_1111_1110, "beq 0x08000000"),
+            (0b1110_0_000_0000_0000, "b 0x08000004"),
+            (0b1111_0_000_0000_0000, "bl (prefix) pc+0x0"),
+            (0b1111_1_000_0000_0010, "bl lr+0x4"),
+            (0b1011_0000_1000_0010, "add sp,#-0x8"),
+            (0b1101_1111_0000_0110, "swi #0x6"),
+        ];
+        for &(instr, expected) in cases {
+            assert_eq!(
+                disassemble_thumb(instr, 0x0800_0000),
+                expected,
+                "{instr:04x}"
+            );
+        }
+    }
+}
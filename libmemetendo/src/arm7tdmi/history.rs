@@ -0,0 +1,361 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display, Formatter},
+};
+
+use super::{reg::OperationState, Exception};
+
+/// Maximum depth of the shadow call stack; the outermost frames are forgotten past this, as calls
+/// that never return (like tail calls made with BL) would otherwise grow it forever.
+const MAX_CALL_DEPTH: usize = 256;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BranchKind {
+    Jump,
+    Call,
+    Return,
+    Exception(Exception),
+}
+
+/// A taken branch, or consecutive repeats of the same one, like in a busy loop.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Branch {
+    pub from: u32,
+    pub to: u32,
+    pub state: OperationState,
+    pub kind: BranchKind,
+    pub count: u32,
+}
+
+impl Display for Branch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let state = match self.state {
+            OperationState::Arm => "ARM",
+            OperationState::Thumb => "THUMB",
+        };
+        write!(f, "{:08x} -> {:08x} ({state}", self.from, self.to)?;
+        match self.kind {
+            BranchKind::Jump => {}
+            BranchKind::Call => write!(f, ", call")?,
+            BranchKind::Return => write!(f, ", return")?,
+            BranchKind::Exception(exception) => write!(f, ", {exception:?}")?,
+        }
+        if self.count > 1 {
+            write!(f, ", x{}", self.count)?;
+        }
+
+        write!(f, ")")
+    }
+}
+
+/// A call that has not yet returned.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Frame {
+    pub call_site: u32,
+    pub target: u32,
+    pub return_addr: u32,
+    pub exception: Option<Exception>,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:08x} (called from {:08x}, returns to {:08x}",
+            self.target, self.call_site, self.return_addr
+        )?;
+        if let Some(exception) = self.exception {
+            write!(f, ", {exception:?}")?;
+        }
+
+        write!(f, ")")
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FaultKind {
+    InvalidRegion,
+    UndefinedInstr,
+}
+
+/// A snapshot of the history taken when the CPU first did something suspicious.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub addr: u32,
+    pub call_stack: Vec<Frame>,
+    pub branches: Vec<Branch>,
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            FaultKind::InvalidRegion => {
+                writeln!(f, "executing from invalid region at {:08x}", self.addr)?;
+            }
+            FaultKind::UndefinedInstr => {
+                writeln!(f, "undefined instruction at {:08x}", self.addr)?;
+            }
+        }
+
+        writeln!(f, "call stack (innermost first):")?;
+        for frame in self.call_stack.iter().rev() {
+            writeln!(f, "  {frame}")?;
+        }
+        write!(f, "last taken branches (newest first):")?;
+        for branch in self.branches.iter().rev() {
+            write!(f, "\n  {branch}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Tracks a shadow call stack and the last taken branches, for diagnosing crashes.
+///
+/// Calls are detected by the link register holding the address after the branching instruction
+/// (BL, or `mov lr,pc` followed by BX or `mov pc`), and returns by branching to the return address
+/// of a frame on the stack.
+#[derive(Clone, Debug)]
+pub struct History {
+    branches: VecDeque<Branch>,
+    branch_capacity: usize,
+    call_stack: Vec<Frame>,
+    fault: Option<Fault>,
+    /// The kind and address of the last recorded fault, and the generation it was recorded in.
+    last_fault: Option<(FaultKind, u32, u64)>,
+    generation: u64,
+}
+
+impl History {
+    /// Creates a history that remembers the last `branch_capacity` taken branches.
+    #[must_use]
+    pub fn new(branch_capacity: usize) -> Self {
+        Self {
+            branches: VecDeque::with_capacity(branch_capacity),
+            branch_capacity,
+            call_stack: Vec::new(),
+            fault: None,
+            last_fault: None,
+            generation: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.branches.clear();
+        self.call_stack.clear();
+        self.fault = None;
+        self.last_fault = None;
+    }
+
+    /// Taken branches, oldest first.
+    #[must_use]
+    pub fn branches(&self) -> impl DoubleEndedIterator<Item = &Branch> + '_ {
+        self.branches.iter()
+    }
+
+    /// Frames of the shadow call stack, outermost first.
+    #[must_use]
+    pub fn call_stack(&self) -> &[Frame] {
+        &self.call_stack
+    }
+
+    /// Takes the first fault recorded since the last call.
+    pub fn take_fault(&mut self) -> Option<Fault> {
+        self.fault.take()
+    }
+
+    /// Records a fault at `addr`, unless one is already waiting to be taken.
+    ///
+    /// Repeats of the last fault are ignored, so a game that keeps faulting doesn't flood reports:
+    /// that is, a fault of the same kind at the same address, or with no branch taken since.
+    pub fn record_fault(&mut self, kind: FaultKind, addr: u32) {
+        if self.fault.is_some() {
+            return;
+        }
+        if let Some((last_kind, last_addr, generation)) = self.last_fault {
+            if last_kind == kind && (last_addr == addr || generation == self.generation) {
+                return;
+            }
+        }
+
+        self.last_fault = Some((kind, addr, self.generation));
+        self.fault = Some(Fault {
+            kind,
+            addr,
+            call_stack: self.call_stack.clone(),
+            branches: self.branches.iter().copied().collect(),
+        });
+    }
+
+    /// Incremented whenever something is recorded, so callers can tell if an instruction
+    /// recorded its own branch (like by entering an exception).
+    pub(super) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn push_branch(&mut self, branch: Branch) {
+        self.generation += 1;
+        if self.branch_capacity == 0 {
+            return;
+        }
+
+        if let Some(last) = self.branches.back_mut() {
+            if (last.from, last.to, last.state, last.kind)
+                == (branch.from, branch.to, branch.state, branch.kind)
+            {
+                last.count = last.count.saturating_add(1);
+                return;
+            }
+        }
+        if self.branches.len() >= self.branch_capacity {
+            self.branches.pop_front();
+        }
+        self.branches.push_back(branch);
+    }
+
+    fn push_frame(&mut self, frame: Frame) {
+        if self.call_stack.len() >= MAX_CALL_DEPTH {
+            self.call_stack.remove(0);
+        }
+        self.call_stack.push(frame);
+    }
+
+    /// Records a branch from the instruction at `from` (of `instr_size` bytes) to `to`, with `lr`
+    /// being the value of the link register afterwards.
+    pub(super) fn record_branch(
+        &mut self,
+        from: u32,
+        instr_size: u32,
+        to: u32,
+        state: OperationState,
+        lr: u32,
+    ) {
+        let kind = if lr & !1 == from.wrapping_add(instr_size) {
+            self.push_frame(Frame {
+                call_site: from,
+                target: to,
+                return_addr: lr & !1,
+                exception: None,
+            });
+
+            BranchKind::Call
+        } else if let Some(i) = self
+            .call_stack
+            .iter()
+            .rposition(|frame| frame.return_addr == to)
+        {
+            self.call_stack.truncate(i);
+
+            BranchKind::Return
+        } else {
+            BranchKind::Jump
+        };
+
+        self.push_branch(Branch {
+            from,
+            to,
+            state,
+            kind,
+            count: 1,
+        });
+    }
+
+    /// Records entering `exception` from `from`, which will resume at `return_addr`.
+    pub(super) fn record_exception(
+        &mut self,
+        exception: Exception,
+        from: u32,
+        to: u32,
+        return_addr: u32,
+    ) {
+        self.push_frame(Frame {
+            call_site: from,
+            target: to,
+            return_addr,
+            exception: Some(exception),
+        });
+        self.push_branch(Branch {
+            from,
+            to,
+            state: OperationState::Arm,
+            kind: BranchKind::Exception(exception),
+            count: 1,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_stack_works() {
+        let mut history = History::new(4);
+        let state = OperationState::Arm;
+
+        history.record_branch(0x100, 4, 0x200, state, 0x104);
+        history.record_branch(0x204, 4, 0x300, state, 0x208);
+        history.record_branch(0x304, 4, 0x400, state, 0x208); // jump
+        assert_eq!(history.call_stack().len(), 2);
+        assert_eq!(history.call_stack()[1].target, 0x300);
+
+        // Returning past the inner frame, like longjmp, should pop both.
+        history.record_branch(0x400, 4, 0x104, state, 0x208);
+        assert!(history.call_stack().is_empty());
+
+        let kinds: Vec<_> = history.branches().map(|b| b.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                BranchKind::Call,
+                BranchKind::Call,
+                BranchKind::Jump,
+                BranchKind::Return
+            ]
+        );
+
+        // THUMB BL sets bit 0 of LR.
+        history.record_branch(0x102, 2, 0x200, OperationState::Thumb, 0x105);
+        assert_eq!(history.call_stack()[0].return_addr, 0x104);
+    }
+
+    #[test]
+    fn branches_work() {
+        let mut history = History::new(2);
+        for _ in 0..3 {
+            history.record_branch(0x100, 4, 0x100, OperationState::Arm, 0);
+        }
+        assert_eq!(history.branches().count(), 1);
+        assert_eq!(history.branches().next().unwrap().count, 3);
+
+        history.record_branch(0x200, 4, 0x300, OperationState::Arm, 0);
+        history.record_exception(Exception::Interrupt, 0x300, 0x18, 0x300);
+        let from: Vec<_> = history.branches().map(|b| b.from).collect();
+        assert_eq!(from, [0x200, 0x300]);
+        assert_eq!(
+            history.call_stack()[0].exception,
+            Some(Exception::Interrupt)
+        );
+
+        history.record_fault(FaultKind::UndefinedInstr, 0x18);
+        history.record_fault(FaultKind::InvalidRegion, 0x1000_0000);
+        let fault = history.take_fault().unwrap();
+        assert_eq!(fault.kind, FaultKind::UndefinedInstr);
+        assert_eq!(fault.branches.len(), 2);
+        assert!(history.take_fault().is_none());
+
+        // Repeats aren't recorded again, like the same instruction faulting or running on through
+        // an invalid region.
+        history.record_fault(FaultKind::UndefinedInstr, 0x18);
+        history.record_fault(FaultKind::InvalidRegion, 0x1000_0000);
+        assert_eq!(history.take_fault().unwrap().addr, 0x1000_0000);
+        history.record_fault(FaultKind::InvalidRegion, 0x1000_0004);
+        assert!(history.take_fault().is_none());
+        history.record_branch(0x1000_0008, 4, 0x1000_0000, OperationState::Arm, 0);
+        history.record_fault(FaultKind::InvalidRegion, 0x1000_0000);
+        assert!(history.take_fault().is_none());
+        history.record_fault(FaultKind::InvalidRegion, 0x1000_0004);
+        assert_eq!(history.take_fault().unwrap().addr, 0x1000_0004);
+    }
+}
@@ -275,7 +275,7 @@ impl Cpu {
         }
     }
 
-    fn meets_condition(&self, cond: u8) -> bool {
+    pub(in crate::arm7tdmi) fn meets_condition(&self, cond: u8) -> bool {
         match cond {
             // EQ
             0 => self.reg.cpsr.zero,
@@ -450,10 +450,16 @@ impl Cpu {
     }
 
     fn op_str(bus: &mut impl Bus, addr: u32, value: u32) {
+        if addr & 0b11 != 0 {
+            bus.misaligned_access(addr, 4, true);
+        }
         bus.write_word_aligned(addr, value);
     }
 
     fn op_strh(bus: &mut impl Bus, addr: u32, value: u16) {
+        if addr & 1 != 0 {
+            bus.misaligned_access(addr, 2, true);
+        }
         bus.write_hword_aligned(addr, value);
     }
 
@@ -462,10 +468,16 @@ impl Cpu {
     }
 
     fn op_ldr(bus: &mut impl Bus, addr: u32) -> u32 {
+        if addr & 0b11 != 0 {
+            bus.misaligned_access(addr, 4, false);
+        }
         bus.read_word_aligned(addr).rotate_right(8 * (addr & 0b11))
     }
 
     fn op_ldrh_or_ldsh(bus: &mut impl Bus, addr: u32, sign_extend: bool) -> u32 {
+        if addr & 1 != 0 {
+            bus.misaligned_access(addr, 2, false);
+        }
         if sign_extend && (addr & 1) == 1 {
             return Self::op_ldrb_or_ldsb(bus, addr, true);
         }
@@ -0,0 +1,301 @@
+use bitmatch::bitmatch;
+use intbits::Bits;
+
+use crate::arm7tdmi::{
+    reg::{OperationState, SP_INDEX},
+    Cpu,
+};
+
+/// Whether executing `instr` would access IO registers or video memory, which behave differently
+/// depending on the state of the rest of the system.
+///
+/// The addresses are decoded from the instruction and the registers, without executing it. This
+/// may give false positives (like for empty register lists), but never false negatives.
+pub fn accesses_hardware(cpu: &Cpu, instr: u32) -> bool {
+    let range = match cpu.reg.cpsr.state {
+        OperationState::Arm => arm_access(cpu, instr),
+        #[allow(clippy::cast_possible_truncation)]
+        OperationState::Thumb => thumb_access(cpu, instr as u16),
+    };
+
+    range.map_or(false, |(start, len)| {
+        // Words and half-words are accessed at aligned addresses.
+        let start = start & !(len.min(4) - 1);
+        let end = start.wrapping_add(len - 1);
+        let overlaps = |start, end| start < 0x0800_0000 && end >= 0x0400_0000;
+        if end < start {
+            overlaps(start, u32::MAX) || overlaps(0, end)
+        } else {
+            overlaps(start, end)
+        }
+    })
+}
+
+fn r(cpu: &Cpu, instr: u32, pos: u8) -> u32 {
+    cpu.reg.r[usize::try_from(instr.bits(pos..pos + 4)).unwrap()]
+}
+
+/// The lowest address and length of the words transferred by a block transfer of `r_list` from
+/// `base_addr`. Empty lists act weird, so they're assumed to cover every register.
+fn block_range(base_addr: u32, r_list: u16, preindex: bool, ascend: bool) -> (u32, u32) {
+    let len = 4 * if r_list == 0 { 16 } else { r_list.count_ones() };
+    let start = match (ascend, preindex) {
+        (true, false) => base_addr,
+        (true, true) => base_addr.wrapping_add(4),
+        (false, false) => base_addr.wrapping_sub(len).wrapping_add(4),
+        (false, true) => base_addr.wrapping_sub(len),
+    };
+
+    (start, len)
+}
+
+/// The start address and length of the memory accessed by an ARM instruction, mirroring the
+/// decoding order of `Cpu::execute_arm`.
+#[bitmatch]
+fn arm_access(cpu: &Cpu, instr: u32) -> Option<(u32, u32)> {
+    if !cpu.meets_condition(instr.bits(28..).try_into().unwrap()) {
+        return None;
+    }
+
+    let base_addr = r(cpu, instr, 16);
+    let offset_addr = |offset: u32| {
+        if !instr.bit(24) {
+            base_addr
+        } else if instr.bit(23) {
+            base_addr.wrapping_add(offset)
+        } else {
+            base_addr.wrapping_sub(offset)
+        }
+    };
+
+    #[bitmatch]
+    match instr.bits(..28) {
+        "0001_0010_1111_1111_1111_????_????" => None,
+        "0001_0?00_????_????_0000_1001_????" => {
+            Some((base_addr, if instr.bit(22) { 1 } else { 4 }))
+        }
+        "0000_????_????_????_????_1001_????" => None,
+        "000?_????_????_????_????_1??1_????" => {
+            let offset = if instr.bit(22) {
+                instr.bits(..4).with_bits(4.., instr.bits(8..12))
+            } else {
+                r(cpu, instr, 0)
+            };
+
+            let len = match (instr.bit(20), instr.bits(5..7)) {
+                // Reserved
+                (_, 0) | (false, 2 | 3) => return None,
+                (_, 2) => 1,
+                _ => 2,
+            };
+            Some((offset_addr(offset), len))
+        }
+        "00?1_0??0_????_????_????_????_????" => None,
+        "1111_????_????_????_????_????_????" => None,
+        "011?_????_????_????_????_???1_????" => None,
+        "100?_????_????_????_????_????_????" => {
+            let r_list = instr.bits(..16).try_into().unwrap();
+            Some(block_range(base_addr, r_list, instr.bit(24), instr.bit(23)))
+        }
+        "01??_????_????_????_????_????_????" => {
+            let offset = if instr.bit(25) {
+                shift_imm(cpu, instr.bits(5..7), r(cpu, instr, 0), instr.bits(7..12))
+            } else {
+                instr.bits(..12)
+            };
+
+            Some((offset_addr(offset), if instr.bit(22) { 1 } else { 4 }))
+        }
+        _ => None,
+    }
+}
+
+/// The offset of a single data transfer, shifted by an immediate.
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn shift_imm(cpu: &Cpu, op: u32, value: u32, amount: u32) -> u32 {
+    match (op, amount) {
+        (0, _) => value << amount,
+        // #0 works like #32
+        (1, 0) => 0,
+        (1, _) => value >> amount,
+        (2, 0) => ((value as i32) >> 31) as u32,
+        (2, _) => ((value as i32) >> amount) as u32,
+        // #0 works like RRX #1
+        (3, 0) => (value >> 1).with_bit(31, cpu.reg.cpsr.carry),
+        (3, _) => value.rotate_right(amount),
+        _ => unreachable!(),
+    }
+}
+
+/// The start address and length of the memory accessed by a THUMB instruction.
+#[bitmatch]
+fn thumb_access(cpu: &Cpu, instr: u16) -> Option<(u32, u32)> {
+    let r = |pos: u8| cpu.reg.r[usize::from(instr.bits(pos..pos + 3))];
+    let imm5 = u32::from(instr.bits(6..11));
+    let sp = cpu.reg.r[SP_INDEX];
+
+    #[bitmatch]
+    match u8::try_from(instr.bits(8..)).unwrap() {
+        // Thumb.6: PC-relative load
+        "0100_1???" => {
+            let pc = cpu.reg.r[15] & !0b10;
+            Some((pc.wrapping_add(u32::from(instr.bits(..8)) * 4), 4))
+        }
+        // Thumb.7 and Thumb.8: Load/store with register offset, and sign-extended
+        "0101_????" => {
+            let len = match instr.bits(9..12) {
+                0 | 4 => 4,
+                1 | 5 | 7 => 2,
+                _ => 1,
+            };
+            Some((r(3).wrapping_add(r(6)), len))
+        }
+        // Thumb.9: Load/store with immediate offset
+        "011?_????" => {
+            let len = if instr.bit(12) { 1 } else { 4 };
+            Some((r(3).wrapping_add(imm5 * len), len))
+        }
+        // Thumb.10: Load/store halfword
+        "1000_????" => Some((r(3).wrapping_add(imm5 * 2), 2)),
+        // Thumb.11: SP-relative load/store
+        "1001_????" => Some((sp.wrapping_add(u32::from(instr.bits(..8)) * 4), 4)),
+        // Thumb.14: Push/pop registers
+        "1011_?10?" => Some(block_range(
+            sp,
+            instr.bits(..9),
+            !instr.bit(11),
+            instr.bit(11),
+        )),
+        // Thumb.15: Multiple load/store
+        "1100_????" => Some(block_range(r(8), instr.bits(..8), false, true)),
+        _ => None,
+    }
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{arm7tdmi::reg::OperationMode, bus::Bus};
+
+    #[derive(Default)]
+    struct HardwareBus {
+        accessed: bool,
+    }
+
+    impl Bus for HardwareBus {
+        fn read_byte(&mut self, addr: u32) -> u8 {
+            self.accessed |= (0x0400_0000..0x0800_0000).contains(&addr);
+            0
+        }
+
+        fn write_byte(&mut self, addr: u32, _value: u8) {
+            self.accessed |= (0x0400_0000..0x0800_0000).contains(&addr);
+        }
+    }
+
+    /// Checks that `accesses_hardware` agrees with executing each instruction, for random register
+    /// values near the bounds of the hardware regions.
+    fn assert_decodes_accesses(state: OperationState, instrs: impl Iterator<Item = u32>) {
+        let mut seed = 0x1357_9bdf_u32;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed
+        };
+
+        let (mut count, mut hits) = (0, 0);
+        for instr in instrs {
+            let mut cpu = Cpu::new();
+            cpu.reg.change_mode(OperationMode::System);
+            cpu.reg.cpsr.state = state;
+            cpu.reg.cpsr.carry = next().bit(0);
+            for r in &mut cpu.reg.r[..15] {
+                // Small values too, for offsets.
+                *r = match next() % 3 {
+                    0 => 0x0400_0000 - next() % 0x40,
+                    1 => 0x0800_0000 - next() % 0x40,
+                    _ => next() % 0x40,
+                };
+            }
+            cpu.reg.r[15] = 0x0400_0000 - next() % 0x200 * 2;
+
+            let expected = {
+                let mut cpu = Cpu {
+                    tracer: None,
+                    history: None,
+                    coverage: None,
+                    ..cpu
+                };
+                let mut bus = HardwareBus::default();
+                match state {
+                    OperationState::Arm => cpu.execute_arm(&mut bus, instr),
+                    #[allow(clippy::cast_possible_truncation)]
+                    OperationState::Thumb => cpu.execute_thumb(&mut bus, instr as u16),
+                }
+
+                bus.accessed
+            };
+            assert_eq!(
+                accesses_hardware(&cpu, instr),
+                expected,
+                "{state:?} instruction {instr:08x} with {}",
+                cpu.reg
+            );
+            count += 1;
+            hits += u32::from(expected);
+        }
+
+        // The registers should give a mix of both.
+        assert!(hits > count / 10 && hits < count * 9 / 10, "{hits}/{count}");
+    }
+
+    #[test]
+    fn accesses_hardware_works() {
+        let mut seed = 0x2468_ace0_u32;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed
+        };
+
+        // Loads and stores that the JIT leaves to the interpreter, always passing their condition.
+        let mut arm = Vec::new();
+        while arm.len() < 20_000 {
+            let instr = next().with_bits(28.., 0b1110);
+            let class = instr.bits(25..28);
+            let transfer = match class {
+                // Half-word and signed transfers, and swaps.
+                0b000 => instr.bit(7) && instr.bit(4),
+                0b010 => true,
+                // Undefined instructions would enter an exception.
+                0b011 => !instr.bit(4),
+                // Empty lists are assumed to access every address they might.
+                0b100 => !instr.bit(15) && !instr.bit(22) && instr.bits(..16) != 0,
+                _ => false,
+            };
+            if transfer && instr.bits(12..16) != 15 && instr.bits(16..20) != 15 {
+                arm.push(instr);
+            }
+        }
+        assert_decodes_accesses(OperationState::Arm, arm.into_iter());
+
+        let mut thumb = Vec::new();
+        while thumb.len() < 20_000 {
+            let instr = next() & 0xffff;
+            let op = instr >> 12;
+            let r_list = instr.bits(..8);
+            if matches!(op, 0b0101..=0b1001)
+                || instr >> 11 == 0b01001
+                || (op == 0b1100 && r_list != 0)
+                || (op == 0b1011 && instr.bits(9..11) == 0b10 && !instr.bit(8) && r_list != 0)
+            {
+                thumb.push(instr);
+            }
+        }
+        assert_decodes_accesses(OperationState::Thumb, thumb.into_iter());
+    }
+}
@@ -0,0 +1,200 @@
+use std::ops::Range;
+
+use crate::bus::Bus;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AccessKind {
+    ReadByte,
+    ReadHword,
+    ReadWord,
+    WriteByte,
+    WriteHword,
+    WriteWord,
+}
+
+/// A memory access made by a compiled block, recorded in differential mode.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub addr: u32,
+    pub value: u32,
+}
+
+/// Wraps the bus seen by instructions that compiled blocks delegate to the interpreter.
+///
+/// Requests an early exit from the block after writes that may have side effects on the rest of
+/// the system (IO), or that modify the code of the running block.
+#[allow(clippy::module_name_repetitions)]
+pub struct JitBus<'a, B> {
+    pub inner: &'a mut B,
+    pub exit: &'a mut bool,
+    pub block_range: Range<u32>,
+    pub log: Option<&'a mut Vec<Access>>,
+}
+
+impl<B: Bus> JitBus<'_, B> {
+    fn record(&mut self, kind: AccessKind, addr: u32, value: u32) {
+        if let Some(log) = self.log.as_mut() {
+            log.push(Access { kind, addr, value });
+        }
+    }
+
+    fn notify_write(&mut self, addr: u32, len: u32) {
+        let io = (0x0400_0000..0x0500_0000).contains(&addr);
+        let self_modifying =
+            addr < self.block_range.end && addr.wrapping_add(len) > self.block_range.start;
+
+        *self.exit |= io || self_modifying;
+    }
+}
+
+impl<B: Bus> Bus for JitBus<'_, B> {
+    fn read_byte(&mut self, addr: u32) -> u8 {
+        let value = self.inner.read_byte(addr);
+        self.record(AccessKind::ReadByte, addr, value.into());
+
+        value
+    }
+
+    fn read_hword(&mut self, addr: u32) -> u16 {
+        let value = self.inner.read_hword(addr);
+        self.record(AccessKind::ReadHword, addr, value.into());
+
+        value
+    }
+
+    fn read_word(&mut self, addr: u32) -> u32 {
+        let value = self.inner.read_word(addr);
+        self.record(AccessKind::ReadWord, addr, value);
+
+        value
+    }
+
+    fn write_byte(&mut self, addr: u32, value: u8) {
+        self.record(AccessKind::WriteByte, addr, value.into());
+        self.notify_write(addr, 1);
+        self.inner.write_byte(addr, value);
+    }
+
+    fn write_hword(&mut self, addr: u32, value: u16) {
+        self.record(AccessKind::WriteHword, addr, value.into());
+        self.notify_write(addr, 2);
+        self.inner.write_hword(addr, value);
+    }
+
+    fn write_word(&mut self, addr: u32, value: u32) {
+        self.record(AccessKind::WriteWord, addr, value);
+        self.notify_write(addr, 4);
+        self.inner.write_word(addr, value);
+    }
+
+    fn misaligned_access(&mut self, addr: u32, size: u32, write: bool) {
+        self.inner.misaligned_access(addr, size, write);
+    }
+
+    fn prefetch_instr(&mut self, addr: u32) {
+        self.inner.prefetch_instr(addr);
+    }
+}
+
+/// Replays the memory accesses recorded from a compiled block to the interpreter, so it can
+/// re-execute the block without repeating its side effects.
+///
+/// Instruction fetches are passed through to the real bus, which is side-effect free for the
+/// memory regions that the JIT compiles code from.
+#[allow(clippy::module_name_repetitions)]
+pub struct ReplayBus<'a, B> {
+    inner: &'a mut B,
+    log: std::slice::Iter<'a, Access>,
+    fetching: bool,
+    pub mismatch: Option<String>,
+}
+
+impl<'a, B: Bus> ReplayBus<'a, B> {
+    pub fn new(inner: &'a mut B, log: &'a [Access]) -> Self {
+        Self {
+            inner,
+            log: log.iter(),
+            fetching: false,
+            mismatch: None,
+        }
+    }
+
+    fn replay(&mut self, kind: AccessKind, addr: u32, value: u32) -> u32 {
+        let expected = self.log.next().copied();
+        match expected {
+            Some(access) if access.kind == kind && access.addr == addr => {
+                if matches!(
+                    kind,
+                    AccessKind::WriteByte | AccessKind::WriteHword | AccessKind::WriteWord
+                ) && access.value != value
+                {
+                    self.mismatch.get_or_insert(format!(
+                        "interpreter wrote {value:08x}, but JIT wrote {:08x} ({kind:?} at {addr:08x})",
+                        access.value
+                    ));
+                }
+
+                access.value
+            }
+            _ => {
+                self.mismatch.get_or_insert(format!(
+                    "interpreter made access {kind:?} at {addr:08x}, but JIT made {expected:?}"
+                ));
+
+                0
+            }
+        }
+    }
+
+    /// Checks that the interpreter made every access that the JIT did.
+    pub fn finish(mut self) -> Option<String> {
+        if let Some(access) = self.log.next() {
+            self.mismatch.get_or_insert(format!(
+                "JIT made access {access:?}, but the interpreter did not"
+            ));
+        }
+
+        self.mismatch
+    }
+}
+
+// Truncation is fine; the replayed value was recorded from an access of the same width.
+#[allow(clippy::cast_possible_truncation)]
+impl<B: Bus> Bus for ReplayBus<'_, B> {
+    fn read_byte(&mut self, addr: u32) -> u8 {
+        self.replay(AccessKind::ReadByte, addr, 0) as u8
+    }
+
+    fn read_hword(&mut self, addr: u32) -> u16 {
+        if std::mem::take(&mut self.fetching) {
+            return self.inner.read_hword(addr);
+        }
+
+        self.replay(AccessKind::ReadHword, addr, 0) as u16
+    }
+
+    fn read_word(&mut self, addr: u32) -> u32 {
+        if std::mem::take(&mut self.fetching) {
+            return self.inner.read_word(addr);
+        }
+
+        self.replay(AccessKind::ReadWord, addr, 0)
+    }
+
+    fn write_byte(&mut self, addr: u32, value: u8) {
+        self.replay(AccessKind::WriteByte, addr, value.into());
+    }
+
+    fn write_hword(&mut self, addr: u32, value: u16) {
+        self.replay(AccessKind::WriteHword, addr, value.into());
+    }
+
+    fn write_word(&mut self, addr: u32, value: u32) {
+        self.replay(AccessKind::WriteWord, addr, value);
+    }
+
+    fn prefetch_instr(&mut self, _addr: u32) {
+        self.fetching = true;
+    }
+}
@@ -0,0 +1,831 @@
+use std::mem::MaybeUninit;
+use std::ptr::addr_of;
+
+use bitmatch::bitmatch;
+use intbits::Bits;
+
+use crate::{
+    arbitrary_sign_extend,
+    arm7tdmi::{
+        reg::{OperationState, LR_INDEX, PC_INDEX, SP_INDEX},
+        Cpu,
+    },
+    bus::Bus,
+};
+
+use super::{
+    x64::{Alu, Asm, Cond, Label, Reg, Shift},
+    Context,
+};
+
+/// Blocks are kept short so that interrupts and DMA are not held off for too long.
+const MAX_BLOCK_INSTRS: u32 = 32;
+
+/// Offsets of the guest state accessed by compiled code, relative to the `Cpu` and `Context`.
+pub struct Layout {
+    r: [i32; 16],
+    signed: i32,
+    zero: i32,
+    carry: i32,
+    overflow: i32,
+    pipeline_reloaded: i32,
+    exec: i32,
+    exit: i32,
+    skipped: i32,
+    max_instrs: i32,
+}
+
+fn offset_of<T, U>(base: *const T, field: *const U) -> i32 {
+    i32::try_from(field as usize - base as usize).unwrap()
+}
+
+impl Layout {
+    pub fn new() -> Self {
+        let cpu = MaybeUninit::<Cpu>::uninit();
+        let cpu = cpu.as_ptr();
+        let ctx = MaybeUninit::<Context>::uninit();
+        let ctx = ctx.as_ptr();
+
+        // SAFETY: addr_of! only computes field addresses; nothing is read from the uninitialized
+        // values.
+        unsafe {
+            Self {
+                r: std::array::from_fn(|i| offset_of(cpu, addr_of!((*cpu).reg.r[i]))),
+                signed: offset_of(cpu, addr_of!((*cpu).reg.cpsr.signed)),
+                zero: offset_of(cpu, addr_of!((*cpu).reg.cpsr.zero)),
+                carry: offset_of(cpu, addr_of!((*cpu).reg.cpsr.carry)),
+                overflow: offset_of(cpu, addr_of!((*cpu).reg.cpsr.overflow)),
+                pipeline_reloaded: offset_of(cpu, addr_of!((*cpu).pipeline_reloaded)),
+                exec: offset_of(ctx, addr_of!((*ctx).exec)),
+                exit: offset_of(ctx, addr_of!((*ctx).exit)),
+                skipped: offset_of(ctx, addr_of!((*ctx).skipped)),
+                max_instrs: offset_of(ctx, addr_of!((*ctx).max_instrs)),
+            }
+        }
+    }
+}
+
+pub struct CompiledBlock {
+    pub code: Vec<u8>,
+    pub first_instr: u32,
+    pub instrs: u32,
+}
+
+/// How an instruction is translated.
+enum Translation {
+    /// Emitted as native code.
+    Native,
+    /// Emitted as native code, and always ends the block.
+    Branch,
+    /// Emitted as a call to the interpreter, which cannot change control flow or the mode.
+    Interpret,
+    /// Cannot be compiled; the block ends before it.
+    Unsupported,
+}
+
+struct Compiler<'a> {
+    asm: Asm,
+    layout: &'a Layout,
+    state: OperationState,
+    /// Address of the instruction being compiled.
+    addr: u32,
+    /// Index of the instruction being compiled within the block.
+    index: u32,
+    early_exits: Vec<(Label, u32)>,
+}
+
+/// Compiles a block starting at `addr`, or returns `None` if its first instruction cannot be
+/// compiled.
+pub fn compile(
+    bus: &mut impl Bus,
+    layout: &Layout,
+    addr: u32,
+    state: OperationState,
+) -> Option<CompiledBlock> {
+    let mut compiler = Compiler {
+        asm: Asm::new(),
+        layout,
+        state,
+        addr,
+        index: 0,
+        early_exits: Vec::new(),
+    };
+
+    let mut first_instr = 0;
+    let mut ends_with_branch = false;
+    while compiler.index < MAX_BLOCK_INSTRS && !ends_with_branch {
+        if compiler.index > 0 {
+            compiler.emit_max_instrs_check();
+        }
+        let translation = match state {
+            OperationState::Arm => {
+                let instr = bus.read_word(compiler.addr);
+                if compiler.index == 0 {
+                    first_instr = instr;
+                }
+                compiler.compile_arm(instr)
+            }
+            OperationState::Thumb => {
+                let instr = bus.read_hword(compiler.addr);
+                if compiler.index == 0 {
+                    first_instr = instr.into();
+                }
+                compiler.compile_thumb(instr)
+            }
+        };
+
+        match translation {
+            Translation::Unsupported => break,
+            Translation::Native | Translation::Interpret => {}
+            Translation::Branch => ends_with_branch = true,
+        }
+        compiler.addr = compiler.addr.wrapping_add(state.instr_size());
+        compiler.index += 1;
+    }
+
+    if compiler.index == 0 {
+        return None;
+    }
+
+    if !ends_with_branch {
+        // Fall through to the next instruction.
+        compiler.asm.store_imm(layout.r[PC_INDEX], compiler.addr);
+        compiler.asm.ret_imm(compiler.index);
+    }
+    for (label, executed) in std::mem::take(&mut compiler.early_exits) {
+        compiler.asm.bind(label);
+        let next_addr = addr.wrapping_add(executed * state.instr_size());
+        compiler.asm.store_imm(layout.r[PC_INDEX], next_addr);
+        compiler.asm.ret_imm(executed);
+    }
+
+    Some(CompiledBlock {
+        first_instr,
+        instrs: compiler.index,
+        code: compiler.asm.finish(),
+    })
+}
+
+impl Compiler<'_> {
+    /// The value of PC seen by the instruction being compiled, which is two instructions ahead
+    /// due to pipelining.
+    fn pc(&self) -> u32 {
+        self.addr.wrapping_add(2 * self.state.instr_size())
+    }
+
+    fn load_r(&mut self, dst: Reg, r: usize) {
+        if r == PC_INDEX {
+            self.asm.mov_imm(dst, self.pc());
+        } else {
+            self.asm.load(dst, self.layout.r[r]);
+        }
+    }
+
+    fn store_r(&mut self, r: usize, src: Reg) {
+        debug_assert_ne!(r, PC_INDEX);
+        self.asm.store(self.layout.r[r], src);
+    }
+
+    fn set_nz(&mut self) {
+        self.asm.set_cc(Cond::Sign, self.layout.signed);
+        self.asm.set_cc(Cond::Zero, self.layout.zero);
+    }
+
+    fn set_nz_from(&mut self, reg: Reg) {
+        self.asm.test(reg, reg);
+        self.set_nz();
+    }
+
+    fn set_nzcv(&mut self) {
+        self.set_nz();
+        self.asm.set_cc(Cond::Carry, self.layout.carry);
+        self.asm.set_cc(Cond::Overflow, self.layout.overflow);
+    }
+
+    /// Copies the carry flag to CF. Clobbers `edx`.
+    fn load_carry(&mut self) {
+        self.asm.load_byte(Reg::Edx, self.layout.carry);
+        self.asm.bt(Reg::Edx, 0);
+    }
+
+    /// `eax = eax + ecx + carry_in`, where `carry_in` is `None` to use the carry flag. The x86
+    /// carry and overflow semantics match ARM when subtraction is done as `a + !b + 1`.
+    fn emit_add(&mut self, update_cond: bool, invert_b: bool, carry_in: Option<bool>) {
+        if invert_b {
+            self.asm.not(Reg::Ecx);
+        }
+        match carry_in {
+            Some(false) => self.asm.alu(Alu::Add, Reg::Eax, Reg::Ecx),
+            Some(true) => {
+                self.asm.stc();
+                self.asm.alu(Alu::Adc, Reg::Eax, Reg::Ecx);
+            }
+            None => {
+                self.load_carry();
+                self.asm.alu(Alu::Adc, Reg::Eax, Reg::Ecx);
+            }
+        }
+        if update_cond {
+            self.set_nzcv();
+        }
+    }
+
+    /// `ecx = ecx <shift> amount`, like `Cpu::op_shift_operand` with a special zero offset.
+    fn emit_shift_imm(&mut self, op: u8, update_cond: bool, amount: u8) {
+        let set_carry = |c: &mut Self| {
+            if update_cond {
+                c.asm.set_cc(Cond::Carry, c.layout.carry);
+            }
+        };
+
+        match (op, amount) {
+            // LSL #0
+            (0, 0) => {}
+            // LSR #32
+            (1, 0) => {
+                self.asm.bt(Reg::Ecx, 31);
+                set_carry(self);
+                self.asm.mov_imm(Reg::Ecx, 0);
+            }
+            // ASR #32
+            (2, 0) => {
+                self.asm.bt(Reg::Ecx, 31);
+                set_carry(self);
+                self.asm.shift_imm(Shift::Sar, Reg::Ecx, 31);
+            }
+            // RRX
+            (3, 0) => {
+                self.load_carry();
+                self.asm.shift_imm(Shift::Rcr, Reg::Ecx, 1);
+                set_carry(self);
+            }
+            (_, amount) => {
+                let shift = match op {
+                    0 => Shift::Shl,
+                    1 => Shift::Shr,
+                    2 => Shift::Sar,
+                    3 => Shift::Ror,
+                    _ => unreachable!(),
+                };
+                self.asm.shift_imm(shift, Reg::Ecx, amount);
+                set_carry(self);
+            }
+        }
+    }
+
+    /// Emits a jump that is taken if `cond` fails, like `Cpu::meets_condition`.
+    fn emit_cond_check(&mut self, cond: u8) -> Option<Label> {
+        let (signed, zero, carry, overflow) = (
+            self.layout.signed,
+            self.layout.zero,
+            self.layout.carry,
+            self.layout.overflow,
+        );
+        let load = |c: &mut Self, dst, flag| c.asm.load_byte(dst, flag);
+
+        match cond {
+            // AL
+            14 => return None,
+            // Reserved; never executed.
+            15 => return Some(self.asm.jmp()),
+            // EQ, NE
+            0 | 1 => load(self, Reg::Eax, zero),
+            // CS, CC
+            2 | 3 => load(self, Reg::Eax, carry),
+            // MI, PL
+            4 | 5 => load(self, Reg::Eax, signed),
+            // VS, VC
+            6 | 7 => load(self, Reg::Eax, overflow),
+            // HI, LS
+            8 | 9 => {
+                load(self, Reg::Eax, zero);
+                self.asm.alu_imm(Alu::Xor, Reg::Eax, 1);
+                load(self, Reg::Ecx, carry);
+                self.asm.alu(Alu::And, Reg::Eax, Reg::Ecx);
+            }
+            // GE, LT
+            10 | 11 => {
+                load(self, Reg::Eax, signed);
+                load(self, Reg::Ecx, overflow);
+                self.asm.alu(Alu::Xor, Reg::Eax, Reg::Ecx);
+                self.asm.alu_imm(Alu::Xor, Reg::Eax, 1);
+            }
+            // GT, LE
+            12 | 13 => {
+                load(self, Reg::Eax, signed);
+                load(self, Reg::Ecx, overflow);
+                self.asm.alu(Alu::Xor, Reg::Eax, Reg::Ecx);
+                load(self, Reg::Ecx, zero);
+                self.asm.alu(Alu::Or, Reg::Eax, Reg::Ecx);
+                self.asm.alu_imm(Alu::Xor, Reg::Eax, 1);
+            }
+            _ => unreachable!(),
+        }
+
+        // Odd conditions are the negations of the even ones before them.
+        if cond.bit(0) {
+            self.asm.alu_imm(Alu::Xor, Reg::Eax, 1);
+        }
+        self.asm.test(Reg::Eax, Reg::Eax);
+
+        Some(self.asm.jcc(Cond::Zero))
+    }
+
+    /// Ends the block by branching to the address in `eax`. Like the interpreter, this is noted
+    /// by setting `Cpu::pipeline_reloaded`.
+    fn emit_branch_eax(&mut self) {
+        self.asm.store(self.layout.r[PC_INDEX], Reg::Eax);
+        self.asm.store_byte_imm(self.layout.pipeline_reloaded, true);
+        self.asm.ret_imm(self.index + 1);
+    }
+
+    /// Ends the block after a branch that wasn't taken.
+    fn emit_branch_not_taken(&mut self) {
+        let next_addr = self.addr.wrapping_add(self.state.instr_size());
+        self.asm.store_imm(self.layout.r[PC_INDEX], next_addr);
+        self.asm.ret_imm(self.index + 1);
+    }
+
+    /// Ends the block by branching to `target` if `cond` passes, or to the next instruction.
+    fn emit_branch(&mut self, cond: u8, target: u32) {
+        let skip = self.emit_cond_check(cond);
+        self.asm.mov_imm(Reg::Eax, target);
+        self.emit_branch_eax();
+
+        if let Some(skip) = skip {
+            self.asm.bind(skip);
+            self.emit_branch_not_taken();
+        }
+    }
+
+    /// Exits the block before this instruction if the block may not execute any more of them.
+    fn emit_max_instrs_check(&mut self) {
+        self.asm.cmp_ctx_imm(self.layout.max_instrs, self.index);
+        let label = self.asm.jcc(Cond::BelowOrEqual);
+        self.early_exits.push((label, self.index));
+    }
+
+    /// Calls the interpreter to execute this instruction, exiting the block early if it asked,
+    /// or before this instruction if it was skipped.
+    fn emit_interpret(&mut self, instr: u32) -> Translation {
+        self.asm.store_imm(self.layout.r[PC_INDEX], self.pc());
+        self.asm.call_ctx(self.layout.exec, instr, self.index);
+
+        self.asm.cmp_ctx_byte_zero(self.layout.skipped);
+        let label = self.asm.jcc(Cond::NotZero);
+        self.early_exits.push((label, self.index));
+        self.asm.cmp_ctx_byte_zero(self.layout.exit);
+        let label = self.asm.jcc(Cond::NotZero);
+        self.early_exits.push((label, self.index + 1));
+
+        Translation::Interpret
+    }
+}
+
+fn r_index_thumb(instr: u16, pos: u8) -> usize {
+    instr.bits(pos..pos + 3).into()
+}
+
+fn r_index_arm(instr: u32, pos: u8) -> usize {
+    instr.bits(pos..pos + 4).try_into().unwrap()
+}
+
+impl Compiler<'_> {
+    // Mirrors the decoding order of Cpu::execute_thumb.
+    #[bitmatch]
+    fn compile_thumb(&mut self, instr: u16) -> Translation {
+        #[bitmatch]
+        match u8::try_from(instr.bits(8..)).unwrap() {
+            "1011_0000" => self.compile_thumb13(instr),
+            "0100_00??" => self.compile_thumb4(instr),
+            "0100_01??" => self.compile_thumb5(instr),
+            "0001_1???" => self.compile_thumb2(instr),
+            "1110_0???" => {
+                let offset = arbitrary_sign_extend!(u32, instr.bits(..11), 11).wrapping_mul(2);
+                self.emit_branch(14, self.pc().wrapping_add(offset));
+
+                Translation::Branch
+            }
+            "1010_????" => self.compile_thumb12(instr),
+            "1101_1111" => Translation::Unsupported, // SWI
+            "1101_????" => {
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let offset = (2 * i32::from(instr as i8)) as u32;
+                let cond = instr.bits(8..12).try_into().unwrap();
+                self.emit_branch(cond, self.pc().wrapping_add(offset));
+
+                Translation::Branch
+            }
+            "1111_????" => self.compile_thumb19(instr),
+            "000?_????" => self.compile_thumb1(instr),
+            "001?_????" => self.compile_thumb3(instr),
+            "0100_1???" => self.emit_interpret(instr.into()),
+            "0101_????" => self.emit_interpret(instr.into()),
+            "1000_????" => self.emit_interpret(instr.into()),
+            "1001_????" => self.emit_interpret(instr.into()),
+            "1011_????" => {
+                if instr.bit(11) && instr.bit(8) {
+                    Translation::Unsupported // POP {Rlist,PC}
+                } else {
+                    self.emit_interpret(instr.into())
+                }
+            }
+            "1100_????" => {
+                if instr.bit(11) && instr.bits(..8) == 0 {
+                    Translation::Unsupported // LDMIA with an empty Rlist loads PC
+                } else {
+                    self.emit_interpret(instr.into())
+                }
+            }
+            "011?_????" => self.emit_interpret(instr.into()),
+            _ => Translation::Unsupported,
+        }
+    }
+
+    /// Thumb.1: Move shifted register.
+    fn compile_thumb1(&mut self, instr: u16) -> Translation {
+        self.load_r(Reg::Ecx, r_index_thumb(instr, 3));
+        let amount = instr.bits(6..11).try_into().unwrap();
+        self.emit_shift_imm(instr.bits(11..13).try_into().unwrap(), true, amount);
+        self.set_nz_from(Reg::Ecx);
+        self.store_r(r_index_thumb(instr, 0), Reg::Ecx);
+
+        Translation::Native
+    }
+
+    /// Thumb.2: Add or subtract.
+    fn compile_thumb2(&mut self, instr: u16) -> Translation {
+        self.load_r(Reg::Eax, r_index_thumb(instr, 3));
+        let r = r_index_thumb(instr, 6);
+        if instr.bit(10) {
+            self.asm.mov_imm(Reg::Ecx, r.try_into().unwrap());
+        } else {
+            self.load_r(Reg::Ecx, r);
+        }
+
+        let sub = instr.bit(9);
+        self.emit_add(true, sub, Some(sub));
+        self.store_r(r_index_thumb(instr, 0), Reg::Eax);
+
+        Translation::Native
+    }
+
+    /// Thumb.3: Move, compare, add or subtract immediate.
+    fn compile_thumb3(&mut self, instr: u16) -> Translation {
+        let r_dst = r_index_thumb(instr, 8);
+        self.load_r(Reg::Eax, r_dst);
+        self.asm.mov_imm(Reg::Ecx, instr.bits(..8).into());
+
+        match instr.bits(11..13) {
+            // MOV
+            0 => {
+                self.set_nz_from(Reg::Ecx);
+                self.store_r(r_dst, Reg::Ecx);
+            }
+            // CMP
+            1 => self.emit_add(true, true, Some(true)),
+            // ADD
+            2 => {
+                self.emit_add(true, false, Some(false));
+                self.store_r(r_dst, Reg::Eax);
+            }
+            // SUB
+            3 => {
+                self.emit_add(true, true, Some(true));
+                self.store_r(r_dst, Reg::Eax);
+            }
+            _ => unreachable!(),
+        }
+
+        Translation::Native
+    }
+
+    /// Thumb.4: ALU operations.
+    fn compile_thumb4(&mut self, instr: u16) -> Translation {
+        let r_dst = r_index_thumb(instr, 0);
+        let op = instr.bits(6..10);
+        if matches!(op, 2 | 3 | 4 | 7) {
+            // Shifts by register have awkward semantics for large offsets.
+            return self.emit_interpret(instr.into());
+        }
+
+        self.load_r(Reg::Eax, r_dst);
+        self.load_r(Reg::Ecx, r_index_thumb(instr, 3));
+
+        let mut write_result = true;
+        match op {
+            0 => self.asm.alu(Alu::And, Reg::Eax, Reg::Ecx),
+            1 => self.asm.alu(Alu::Xor, Reg::Eax, Reg::Ecx),
+            5 => self.emit_add(true, false, None),
+            6 => self.emit_add(true, true, None),
+            8 => {
+                self.asm.alu(Alu::And, Reg::Eax, Reg::Ecx);
+                write_result = false;
+            }
+            // NEG
+            9 => {
+                self.asm.mov_imm(Reg::Eax, 0);
+                self.emit_add(true, true, Some(true));
+            }
+            10 => {
+                self.emit_add(true, true, Some(true));
+                write_result = false;
+            }
+            11 => {
+                self.emit_add(true, false, Some(false));
+                write_result = false;
+            }
+            12 => self.asm.alu(Alu::Or, Reg::Eax, Reg::Ecx),
+            13 => self.asm.imul(Reg::Eax, Reg::Ecx),
+            // BIC
+            14 => {
+                self.asm.not(Reg::Ecx);
+                self.asm.alu(Alu::And, Reg::Eax, Reg::Ecx);
+            }
+            // MVN
+            15 => {
+                self.asm.mov(Reg::Eax, Reg::Ecx);
+                self.asm.not(Reg::Eax);
+            }
+            _ => unreachable!(),
+        }
+
+        if !matches!(op, 5 | 6 | 9 | 10 | 11) {
+            self.set_nz_from(Reg::Eax);
+        }
+        if write_result {
+            self.store_r(r_dst, Reg::Eax);
+        }
+
+        Translation::Native
+    }
+
+    /// Thumb.5: Hi register operations. BX and writes to PC change control flow, so they are
+    /// left to the interpreter.
+    fn compile_thumb5(&mut self, instr: u16) -> Translation {
+        let op = instr.bits(8..10);
+        let r_src = r_index_thumb(instr, 3).with_bit(3, instr.bit(6));
+        let r_dst = r_index_thumb(instr, 0).with_bit(3, instr.bit(7));
+        if op == 3 || (op != 1 && r_dst == PC_INDEX) {
+            return Translation::Unsupported;
+        }
+
+        self.load_r(Reg::Eax, r_dst);
+        self.load_r(Reg::Ecx, r_src);
+        match op {
+            0 => {
+                self.emit_add(false, false, Some(false));
+                self.store_r(r_dst, Reg::Eax);
+            }
+            1 => self.emit_add(true, true, Some(true)),
+            2 => self.store_r(r_dst, Reg::Ecx),
+            _ => unreachable!(),
+        }
+
+        Translation::Native
+    }
+
+    /// Thumb.12: Get relative address.
+    fn compile_thumb12(&mut self, instr: u16) -> Translation {
+        let offset = u32::from(instr.bits(..8)) * 4;
+        if instr.bit(11) {
+            self.load_r(Reg::Eax, SP_INDEX);
+            self.asm.alu_imm(Alu::Add, Reg::Eax, offset);
+        } else {
+            self.asm
+                .mov_imm(Reg::Eax, (self.pc() & !0b10).wrapping_add(offset));
+        }
+        self.store_r(r_index_thumb(instr, 8), Reg::Eax);
+
+        Translation::Native
+    }
+
+    /// Thumb.13: Add offset to SP.
+    fn compile_thumb13(&mut self, instr: u16) -> Translation {
+        let offset = u32::from(instr.bits(..7)) * 4;
+        let offset = if instr.bit(7) {
+            offset.wrapping_neg()
+        } else {
+            offset
+        };
+
+        self.load_r(Reg::Eax, SP_INDEX);
+        self.asm.alu_imm(Alu::Add, Reg::Eax, offset);
+        self.store_r(SP_INDEX, Reg::Eax);
+
+        Translation::Native
+    }
+
+    /// Thumb.19: Long branch with link.
+    fn compile_thumb19(&mut self, instr: u16) -> Translation {
+        let offset_part = u32::from(instr.bits(..11));
+        if instr.bit(11) {
+            self.load_r(Reg::Eax, LR_INDEX);
+            self.asm.alu_imm(Alu::Add, Reg::Eax, offset_part << 1);
+            let return_addr = self.addr.wrapping_add(2);
+            self.asm.store_imm(self.layout.r[LR_INDEX], return_addr | 1);
+            self.emit_branch_eax();
+
+            Translation::Branch
+        } else {
+            let offset_hi = arbitrary_sign_extend!(u32, offset_part << 12, 23);
+            self.asm
+                .mov_imm(Reg::Eax, self.pc().wrapping_add(offset_hi));
+            self.store_r(LR_INDEX, Reg::Eax);
+
+            Translation::Native
+        }
+    }
+
+    // Mirrors the decoding order of Cpu::execute_arm.
+    #[bitmatch]
+    fn compile_arm(&mut self, instr: u32) -> Translation {
+        let cond: u8 = instr.bits(28..).try_into().unwrap();
+        let r_16 = r_index_arm(instr, 16);
+        let r_12 = r_index_arm(instr, 12);
+        let preindex = instr.bit(24);
+        let writeback = instr.bit(21);
+        let load = instr.bit(20);
+
+        #[bitmatch]
+        match instr.bits(..28) {
+            "0001_0010_1111_1111_1111_????_????" => Translation::Unsupported, // BX
+            "0001_0?00_????_????_0000_1001_????" => {
+                if r_12 == PC_INDEX {
+                    Translation::Unsupported
+                } else {
+                    self.emit_interpret(instr)
+                }
+            }
+            "0000_????_????_????_????_1001_????" => {
+                let long = instr.bit(23);
+                if r_16 == PC_INDEX || (long && r_12 == PC_INDEX) {
+                    Translation::Unsupported
+                } else {
+                    self.emit_interpret(instr)
+                }
+            }
+            "000?_????_????_????_????_1??1_????" => {
+                let writes_pc =
+                    (load && r_12 == PC_INDEX) || ((writeback || !preindex) && r_16 == PC_INDEX);
+                if writes_pc {
+                    Translation::Unsupported
+                } else {
+                    self.emit_interpret(instr)
+                }
+            }
+            "00?1_0??0_????_????_????_????_????" => {
+                let msr = instr.bit(21);
+                let writes_cpsr_control = msr && !instr.bit(22) && instr.bit(16);
+                if writes_cpsr_control || (!msr && r_12 == PC_INDEX) {
+                    Translation::Unsupported
+                } else {
+                    self.emit_interpret(instr)
+                }
+            }
+            "1111_????_????_????_????_????_????" => Translation::Unsupported, // SWI
+            "011?_????_????_????_????_???1_????" => Translation::Unsupported, // Undefined
+            "100?_????_????_????_????_????_????" => {
+                let r_list = instr.bits(..16);
+                let loads_pc = load && (r_list.bit(PC_INDEX) || r_list == 0);
+                if loads_pc || instr.bit(22) || (writeback && r_16 == PC_INDEX) {
+                    Translation::Unsupported
+                } else {
+                    self.emit_interpret(instr)
+                }
+            }
+            "101?_????_????_????_????_????_????" => {
+                let offset = arbitrary_sign_extend!(u32, instr.bits(..24), 24).wrapping_mul(4);
+                let skip = self.emit_cond_check(cond);
+                if instr.bit(24) {
+                    self.asm
+                        .store_imm(self.layout.r[LR_INDEX], self.addr.wrapping_add(4));
+                }
+                self.asm.mov_imm(Reg::Eax, self.pc().wrapping_add(offset));
+                self.emit_branch_eax();
+
+                if let Some(skip) = skip {
+                    self.asm.bind(skip);
+                    self.emit_branch_not_taken();
+                }
+
+                Translation::Branch
+            }
+            "00??_????_????_????_????_????_????" => self.compile_arm_data_processing(instr),
+            "01??_????_????_????_????_????_????" => {
+                let writes_pc =
+                    (load && r_12 == PC_INDEX) || ((writeback || !preindex) && r_16 == PC_INDEX);
+                if writes_pc {
+                    Translation::Unsupported
+                } else {
+                    self.emit_interpret(instr)
+                }
+            }
+            _ => Translation::Unsupported,
+        }
+    }
+
+    fn compile_arm_data_processing(&mut self, instr: u32) -> Translation {
+        let r_dst = r_index_arm(instr, 12);
+        let op = instr.bits(21..25);
+        if r_dst == PC_INDEX {
+            return Translation::Unsupported;
+        }
+        if (!instr.bit(25) && instr.bit(4)) || op == 7 {
+            // Register-specified shifts and RSC are rare enough to leave to the interpreter.
+            return self.emit_interpret(instr);
+        }
+
+        let skip = self.emit_cond_check(instr.bits(28..).try_into().unwrap());
+        let update_cond = instr.bit(20);
+
+        // ADC and SBC use the carry from before the shifter operand was computed.
+        let uses_old_carry = op == 5 || op == 6;
+        if uses_old_carry {
+            self.asm.load_byte(Reg::Eax, self.layout.carry);
+        }
+
+        if instr.bit(25) {
+            let rotate = 2 * instr.bits(8..12);
+            let value = instr.bits(..8).rotate_right(rotate);
+            if update_cond && rotate > 0 {
+                self.asm.store_byte_imm(self.layout.carry, value.bit(31));
+            }
+            self.asm.mov_imm(Reg::Ecx, value);
+        } else {
+            self.load_r(Reg::Ecx, r_index_arm(instr, 0));
+            let amount = instr.bits(7..12).try_into().unwrap();
+            self.emit_shift_imm(instr.bits(5..7).try_into().unwrap(), update_cond, amount);
+        }
+
+        if uses_old_carry {
+            self.asm.mov(Reg::Edx, Reg::Eax);
+        }
+        self.load_r(Reg::Eax, r_index_arm(instr, 16));
+
+        let mut write_result = true;
+        match op {
+            0 => self.asm.alu(Alu::And, Reg::Eax, Reg::Ecx),
+            1 => self.asm.alu(Alu::Xor, Reg::Eax, Reg::Ecx),
+            2 => self.emit_add(update_cond, true, Some(true)),
+            3 => {
+                // RSB
+                self.asm.mov(Reg::Edx, Reg::Eax);
+                self.asm.mov(Reg::Eax, Reg::Ecx);
+                self.asm.mov(Reg::Ecx, Reg::Edx);
+                self.emit_add(update_cond, true, Some(true));
+            }
+            4 => self.emit_add(update_cond, false, Some(false)),
+            5 | 6 => {
+                if op == 6 {
+                    self.asm.not(Reg::Ecx);
+                }
+                self.asm.bt(Reg::Edx, 0);
+                self.asm.alu(Alu::Adc, Reg::Eax, Reg::Ecx);
+                if update_cond {
+                    self.set_nzcv();
+                }
+            }
+            8 => {
+                self.asm.alu(Alu::And, Reg::Eax, Reg::Ecx);
+                write_result = false;
+            }
+            9 => {
+                self.asm.alu(Alu::Xor, Reg::Eax, Reg::Ecx);
+                write_result = false;
+            }
+            10 => {
+                self.emit_add(true, true, Some(true));
+                write_result = false;
+            }
+            11 => {
+                self.emit_add(true, false, Some(false));
+                write_result = false;
+            }
+            12 => self.asm.alu(Alu::Or, Reg::Eax, Reg::Ecx),
+            13 => self.asm.mov(Reg::Eax, Reg::Ecx),
+            14 => {
+                self.asm.not(Reg::Ecx);
+                self.asm.alu(Alu::And, Reg::Eax, Reg::Ecx);
+            }
+            15 => {
+                self.asm.mov(Reg::Eax, Reg::Ecx);
+                self.asm.not(Reg::Eax);
+            }
+            _ => unreachable!(),
+        }
+
+        let logical = matches!(op, 0 | 1 | 8 | 9 | 12..=15);
+        if logical && (update_cond || (8..=11).contains(&op)) {
+            self.set_nz_from(Reg::Eax);
+        }
+        if write_result {
+            self.store_r(r_dst, Reg::Eax);
+        }
+        if let Some(skip) = skip {
+            self.asm.bind(skip);
+        }
+
+        Translation::Native
+    }
+}
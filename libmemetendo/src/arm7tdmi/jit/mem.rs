@@ -0,0 +1,84 @@
+use std::{io, ptr::NonNull};
+
+/// A fixed-size region of executable memory that compiled blocks are appended to.
+///
+/// The region is only ever writable while code is being copied into it (W^X).
+pub struct CodeBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+    used: usize,
+}
+
+impl CodeBuffer {
+    pub fn new(len: usize) -> io::Result<Self> {
+        // SAFETY: we request a fresh anonymous mapping, so no existing memory is affected.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_EXEC,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            ptr: NonNull::new(ptr.cast()).unwrap(),
+            len,
+            used: 0,
+        })
+    }
+
+    fn protect(&mut self, prot: libc::c_int) {
+        // SAFETY: the whole mapping is ours, and nothing executes from it while it is writable.
+        let result = unsafe { libc::mprotect(self.ptr.as_ptr().cast(), self.len, prot) };
+        assert_eq!(result, 0, "mprotect failed: {}", io::Error::last_os_error());
+    }
+
+    /// Copies `code` into the buffer, returning its offset, or `None` if the buffer is full.
+    pub fn push(&mut self, code: &[u8]) -> Option<usize> {
+        if self.len - self.used < code.len() {
+            return None;
+        }
+
+        self.protect(libc::PROT_READ | libc::PROT_WRITE);
+        // SAFETY: the destination is within the mapping and was just made writable.
+        unsafe {
+            self.ptr
+                .as_ptr()
+                .add(self.used)
+                .copy_from_nonoverlapping(code.as_ptr(), code.len());
+        }
+        self.protect(libc::PROT_READ | libc::PROT_EXEC);
+
+        let offset = self.used;
+        self.used += code.len();
+
+        Some(offset)
+    }
+
+    /// Forgets all code in the buffer; any offsets returned by `push` become invalid.
+    pub fn clear(&mut self) {
+        self.used = 0;
+    }
+
+    pub fn ptr(&self, offset: usize) -> *const u8 {
+        assert!(offset < self.used, "code offset OOB");
+
+        // SAFETY: the offset was just checked to be within the mapping.
+        unsafe { self.ptr.as_ptr().add(offset) }
+    }
+}
+
+impl Drop for CodeBuffer {
+    fn drop(&mut self) {
+        // SAFETY: the mapping is ours and no compiled code can run after we're gone.
+        unsafe {
+            libc::munmap(self.ptr.as_ptr().cast(), self.len);
+        }
+    }
+}
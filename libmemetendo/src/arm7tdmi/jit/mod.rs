@@ -0,0 +1,532 @@
+//! An optional x86-64 dynamic recompiler.
+//!
+//! Blocks of straight-line ARM or THUMB code are compiled to native code the first time they're
+//! run. Common ALU operations and branches are translated directly, memory accesses call back into
+//! the interpreter's instruction handlers, and anything that could switch the mode, state or raise
+//! an exception ends the block so that the interpreter can handle it.
+//!
+//! The rest of the system is only caught up once a block finishes, so blocks stop before any
+//! hardware event (like an interrupt) could happen, and before anything but their first
+//! instruction accesses IO registers or video memory. This keeps the timing the same as stepping
+//! the interpreter one instruction at a time.
+
+#[cfg(not(all(target_arch = "x86_64", unix)))]
+compile_error!("the `jit` feature is only supported on x86-64 Unix hosts");
+
+mod access;
+mod bus;
+mod compile;
+mod mem;
+mod x64;
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    io,
+    panic::{self, AssertUnwindSafe},
+};
+
+use log::{debug, trace};
+
+use crate::{bus::Bus, gba::CodeWrites};
+
+use self::{
+    access::accesses_hardware,
+    bus::{Access, JitBus, ReplayBus},
+    compile::Layout,
+    mem::CodeBuffer,
+};
+
+use super::{reg::OperationState, Cpu, Exception};
+
+const CODE_BUFFER_LEN: usize = 16 * 1024 * 1024;
+
+type BlockFn = unsafe extern "C" fn(*mut Cpu, *mut Context) -> u32;
+type ExecFn = unsafe extern "C" fn(*mut Cpu, *mut Context, u32, u32);
+
+/// State shared between compiled code and the functions it calls.
+struct Context {
+    bus: *mut (),
+    exec: ExecFn,
+    /// Set when the block should exit after the current instruction.
+    exit: bool,
+    /// Set when the current instruction was left for the interpreter, so the block should exit
+    /// before it.
+    skipped: bool,
+    /// The block exits before executing more than this many instructions.
+    max_instrs: u32,
+    block_range: std::ops::Range<u32>,
+    log: Option<Vec<Access>>,
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+// The compiler only passes 16-bit THUMB instructions.
+#[allow(clippy::cast_possible_truncation)]
+fn execute(cpu: &mut Cpu, bus: &mut impl Bus, instr: u32) {
+    match cpu.reg.cpsr.state {
+        OperationState::Arm => cpu.execute_arm(bus, instr),
+        OperationState::Thumb => cpu.execute_thumb(bus, instr as u16),
+    }
+}
+
+/// Executes `instr` (the `index`th of the block) with a bus that watches for writes that should
+/// end the block, catching any panic so that it doesn't unwind through compiled code.
+///
+/// # Safety
+///
+/// `ctx` and `cpu` must be valid, and `ctx.bus` must point to a `B`.
+unsafe extern "C" fn exec<B: Bus>(cpu: *mut Cpu, ctx: *mut Context, instr: u32, index: u32) {
+    let (cpu, ctx) = (&mut *cpu, &mut *ctx);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        // The rest of the system is only caught up to the start of the block.
+        if index > 0 && accesses_hardware(cpu, instr) {
+            ctx.skipped = true;
+            return;
+        }
+
+        let mut bus = JitBus {
+            inner: &mut *ctx.bus.cast::<B>(),
+            exit: &mut ctx.exit,
+            block_range: ctx.block_range.clone(),
+            log: ctx.log.as_mut(),
+        };
+        execute(cpu, &mut bus, instr);
+    }));
+    if let Err(payload) = result {
+        ctx.panic = Some(payload);
+        ctx.exit = true;
+    }
+}
+
+struct Block {
+    code_offset: usize,
+    first_instr: u32,
+    instrs: u32,
+    len: u32,
+}
+
+type BlockKey = (u32, OperationState);
+
+pub struct Jit {
+    code: CodeBuffer,
+    layout: Layout,
+    /// `None` entries are blocks whose first instruction cannot be compiled.
+    blocks: HashMap<BlockKey, Option<Block>>,
+    /// Blocks compiled from each page of work RAM, for invalidating them after writes.
+    page_blocks: HashMap<usize, Vec<BlockKey>>,
+    /// Whether to re-execute every block with the interpreter and check that the results match.
+    pub differential: bool,
+}
+
+impl Jit {
+    /// # Errors
+    ///
+    /// Returns an error if executable memory could not be allocated for compiled code.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            code: CodeBuffer::new(CODE_BUFFER_LEN)?,
+            layout: Layout::new(),
+            blocks: HashMap::new(),
+            page_blocks: HashMap::new(),
+            differential: false,
+        })
+    }
+
+    /// Discards all compiled code. Needed after memory is modified without going through a bus.
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.page_blocks.clear();
+        self.code.clear();
+    }
+
+    /// Discards blocks compiled from work RAM that has been written to since the last call.
+    pub fn invalidate(&mut self, writes: &mut CodeWrites) {
+        for page in writes.take_dirty_pages() {
+            for key in self.page_blocks.remove(&page).into_iter().flatten() {
+                trace!("invalidating JIT block at {:08x} ({:?})", key.0, key.1);
+                self.blocks.remove(&key);
+            }
+        }
+    }
+
+    /// Executes a compiled block starting at the next instruction, compiling it if needed. At
+    /// most `max_instrs` instructions are executed, which should be enough to reach the next
+    /// hardware event, but not pass it.
+    ///
+    /// Returns the number of instructions executed, or `None` if the interpreter should step
+    /// instead, such as when an exception needs servicing, the code cannot be compiled, or
+    /// instructions or coverage are being traced. Taken branches are recorded to `Cpu::history`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if differential mode is enabled and the interpreter disagrees with the JIT.
+    pub fn step<B: Bus>(&mut self, cpu: &mut Cpu, bus: &mut B, max_instrs: u32) -> Option<u32> {
+        let state = cpu.reg.cpsr.state;
+        let addr = cpu.reg.r[15].wrapping_sub(2 * state.instr_size());
+        if !is_compilable_region(addr)
+            || has_serviceable_exception(cpu)
+            || cpu.tracer.is_some()
+            || cpu.coverage.is_some()
+        {
+            return None;
+        }
+
+        let key = (addr, state);
+        if !self.blocks.contains_key(&key) {
+            self.compile(bus, key);
+        }
+        let block = self.blocks.get(&key)?.as_ref()?;
+
+        // The interpreter executes what it already fetched, which may differ from memory if it
+        // was modified after being fetched.
+        if block.first_instr != cpu.pipeline_instrs[0] {
+            return None;
+        }
+
+        // Unserviceable exceptions (masked interrupts) are dropped, as in Cpu::step.
+        cpu.pending_exceptions.fill(false);
+        let shadow = self.differential.then_some(Cpu {
+            tracer: None,
+            history: None,
+            coverage: None,
+            ..*cpu
+        });
+
+        let mut ctx = Context {
+            bus: (bus as *mut B).cast(),
+            exec: exec::<B>,
+            exit: false,
+            skipped: false,
+            max_instrs,
+            block_range: addr..addr.wrapping_add(block.len),
+            log: self.differential.then(Vec::new),
+            panic: None,
+        };
+
+        cpu.pipeline_reloaded = false;
+        // SAFETY: the code was compiled for this layout of Cpu and Context, and only accesses
+        // them and the bus through the pointers given here.
+        let executed = unsafe {
+            let f: BlockFn = std::mem::transmute(self.code.ptr(block.code_offset));
+            f(cpu, &mut ctx)
+        };
+        if let Some(payload) = ctx.panic {
+            panic::resume_unwind(payload);
+        }
+        debug_assert!(executed > 0 && executed <= block.instrs.min(max_instrs));
+
+        let branched = cpu.pipeline_reloaded;
+        cpu.reload_pipeline(bus);
+        if let Some(history) = cpu.history.as_mut().filter(|_| branched) {
+            // Only a block's last instruction can branch, and none can change the state.
+            let from = addr.wrapping_add((executed - 1) * state.instr_size());
+            let to = cpu.reg.r[15].wrapping_sub(2 * state.instr_size());
+            history.record_branch(from, state.instr_size(), to, state, cpu.reg.r[14]);
+        }
+
+        if let Some(mut shadow) = shadow {
+            let log = ctx.log.unwrap_or_default();
+            let mut replay_bus = ReplayBus::new(bus, &log);
+            for _ in 0..executed {
+                shadow.step(&mut replay_bus);
+            }
+
+            let mismatch = replay_bus.finish();
+            assert!(
+                mismatch.is_none() && shadow.reg == cpu.reg,
+                "JIT block at {addr:08x} ({state:?}) diverged from the interpreter after \
+                 {executed} instructions: {}\n\njit:\n{}\n\ninterpreter:\n{}",
+                mismatch.as_deref().unwrap_or("registers differ"),
+                cpu.reg,
+                shadow.reg,
+            );
+        }
+
+        Some(executed)
+    }
+
+    fn compile(&mut self, bus: &mut impl Bus, key: BlockKey) {
+        let (addr, state) = key;
+        let block = compile::compile(bus, &self.layout, addr, state).and_then(|compiled| {
+            let code_offset = self.code.push(&compiled.code).or_else(|| {
+                debug!("JIT code buffer full; flushing");
+                self.clear();
+                self.code.push(&compiled.code)
+            })?;
+
+            Some(Block {
+                code_offset,
+                first_instr: compiled.first_instr,
+                instrs: compiled.instrs,
+                len: compiled.instrs * state.instr_size(),
+            })
+        });
+
+        let len = block.as_ref().map_or(state.instr_size(), |block| block.len);
+        let last_addr = addr.wrapping_add(len - 1);
+        let pages = (addr..=last_addr)
+            .step_by(CodeWrites::PAGE_LEN)
+            .chain(std::iter::once(last_addr))
+            .filter_map(CodeWrites::page);
+        for page in pages {
+            let keys = self.page_blocks.entry(page).or_default();
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+
+        trace!(
+            "compiled JIT block at {addr:08x} ({state:?}): {:?} instrs",
+            block.as_ref().map(|block| block.instrs)
+        );
+        self.blocks.insert(key, block);
+    }
+}
+
+/// Compiled code is only run from memory that can be read without side effects: work RAM and
+/// cartridge ROM. BIOS code is left to the interpreter, as its protection depends on fetches.
+fn is_compilable_region(addr: u32) -> bool {
+    matches!(addr >> 24, 0x02 | 0x03 | 0x08..=0x0c)
+}
+
+fn has_serviceable_exception(cpu: &Cpu) -> bool {
+    cpu.pending_exceptions
+        .iter()
+        .enumerate()
+        .any(|(priority, &raised)| {
+            raised
+                && match Exception::from_priority(priority) {
+                    Some(Exception::Interrupt) => !cpu.reg.cpsr.irq_disabled,
+                    Some(Exception::FastInterrupt) => !cpu.reg.cpsr.fiq_disabled,
+                    _ => true,
+                }
+        })
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        arm7tdmi::reg::{OperationMode, PC_INDEX},
+        bus::tests::VecBus,
+    };
+
+    use intbits::Bits;
+
+    /// Runs the program at address 0 with the JIT in differential mode until `steps` instructions
+    /// have executed, returning the final CPU state. Blocks are cut short to not run past `steps`.
+    fn run(bus: &mut VecBus, state: OperationState, setup: &dyn Fn(&mut Cpu), steps: u32) -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.reg.change_mode(OperationMode::System);
+        cpu.reg.cpsr.state = state;
+        setup(&mut cpu);
+        cpu.reg.r[PC_INDEX] = 0x0200_0000;
+        cpu.reload_pipeline(bus);
+
+        let mut jit = Jit::new().unwrap();
+        jit.differential = true;
+        let mut executed = 0;
+        while executed < steps {
+            executed += jit
+                .step(&mut cpu, bus, steps - executed)
+                .unwrap_or_else(|| {
+                    cpu.step(bus);
+                    1
+                });
+        }
+
+        cpu
+    }
+
+    fn load_program(bus: &mut VecBus, program: &[u8]) {
+        for (i, &byte) in program.iter().enumerate() {
+            bus.write_byte(0x0200_0000 + u32::try_from(i).unwrap(), byte);
+        }
+    }
+
+    #[test]
+    fn thumb_matches_interpreter() {
+        let mut bus = VecBus::new(0x0200_1000);
+        let program: &[u16] = &[
+            0b001_00_000_1111_1111,   // MOV R0,#255
+            0b000_00_11000_000_001,   // LSL R1,R0,#24
+            0b000_10_00000_001_010,   // ASR R2,R1,#32
+            0b0001_1_1_0_001_001_011, // ADD R3,R1,#1
+            0b0100_0001_01_000_011,   // ADC R3,R0
+            0b0100_0010_01_011_100,   // NEG R4,R3
+            0b0100_0011_01_001_100,   // MUL R4,R1
+            0b0100_0110_10_000_000,   // MOV R8,R0
+            0b1011_0000_1000_0010,    // SUB SP,#8
+            0b1001_0_000_0000_0000,   // STR R0,[SP,#0]
+            0b1001_1_101_0000_0000,   // LDR R5,[SP,#0]
+            0b0010_1_101_1111_1111,   // CMP R5,#255
+            0b1101_0000_1111_1101,    // BEQ -6 (back to the CMP)
+            0b1110_0_111_1111_1110,   // B -4 (spins)
+        ];
+        load_program(
+            &mut bus,
+            &program
+                .iter()
+                .flat_map(|x| x.to_le_bytes())
+                .collect::<Vec<_>>(),
+        );
+
+        run(
+            &mut bus,
+            OperationState::Thumb,
+            &|cpu| cpu.reg.r[13] = 0x0200_0800,
+            40,
+        );
+    }
+
+    #[test]
+    fn arm_matches_interpreter() {
+        let mut bus = VecBus::new(0x0200_1000);
+        let program: &[u32] = &[
+            0b1110_00_1_1101_1_0000_0000_1100_0000_0011, // MOVS R0,#0x3000
+            0b1110_00_0_0100_1_0000_0001_00001_01_0_0000, // ADDS R1,R0,R0,LSR #1
+            0b1110_00_0_1101_1_0000_0010_00000_11_0_0001, // MOVS R2,R1,RRX
+            0b1110_00_0_0101_1_0001_0011_11111_00_0_0010, // ADCS R3,R1,R2,LSL #31
+            0b0000_00_1_0010_1_0011_0100_0000_0000_0001, // SUBEQS R4,R3,#1
+            0b1110_00_1_1010_1_0100_0000_0000_0000_0000, // CMP R4,#0
+            0b1110_01_0_1100_0_0000_0001_0000_0000_0000, // STRB R1,[R0]
+            0b1110_01_0_1100_1_0000_0101_0000_0000_0000, // LDRB R5,[R0]
+            0b1101_1010_1111_1111_1111_1111_1111_0111,   // BLLE -0x24 (loops back to the start)
+            0b1110_1010_1111_1111_1111_1111_1111_1110,   // B -8 (spins)
+        ];
+        load_program(
+            &mut bus,
+            &program
+                .iter()
+                .flat_map(|x| x.to_le_bytes())
+                .collect::<Vec<_>>(),
+        );
+
+        run(&mut bus, OperationState::Arm, &|_| {}, 40);
+    }
+
+    /// Compares random ALU instructions, which are compiled to native code, with the interpreter.
+    #[test]
+    fn random_alu_matches_interpreter() {
+        let mut seed = 0x1234_5678_u32;
+        let mut rand = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed
+        };
+
+        for _ in 0..200 {
+            let mut bus = VecBus::new(0x0200_0100);
+            let regs: [u32; 15] = std::array::from_fn(|_| rand());
+            let flags = rand();
+            let setup = |cpu: &mut Cpu| {
+                cpu.reg.r[..15].copy_from_slice(&regs);
+                cpu.reg.cpsr.signed = flags.bit(0);
+                cpu.reg.cpsr.zero = flags.bit(1);
+                cpu.reg.cpsr.carry = flags.bit(2);
+                cpu.reg.cpsr.overflow = flags.bit(3);
+            };
+
+            // Random data processing instructions (not writing PC, not PSR transfers).
+            let mut program = Vec::new();
+            while program.len() < 16 {
+                let instr = rand() & !(0b11 << 26) & !(0b1111 << 12) | ((rand() % 15) << 12);
+                let reg_shift = !instr.bit(25) && instr.bit(4);
+                let psr_transfer = (instr.bits(23..25) == 0b10) && !instr.bit(20);
+                if !reg_shift && !psr_transfer {
+                    program.push(instr);
+                }
+            }
+            program.push(0b1110_1010_1111_1111_1111_1111_1111_1110); // B -8 (spins)
+            load_program(
+                &mut bus,
+                &program
+                    .iter()
+                    .flat_map(|x| x.to_le_bytes())
+                    .collect::<Vec<_>>(),
+            );
+            run(&mut bus, OperationState::Arm, &setup, 17);
+
+            // Random THUMB.1-5 instructions (not writing PC or using BX).
+            let mut program = Vec::new();
+            while program.len() < 16 {
+                #[allow(clippy::cast_possible_truncation)]
+                let instr = rand() as u16 & 0x47ff;
+                let hi_reg_op = instr.bits(10..16) == 0b01_0001;
+                let writes_pc = instr.bits(8..10) != 1 && instr.bit(7) && instr.bits(..3) == 7;
+                if !hi_reg_op || (instr.bits(8..10) != 3 && !writes_pc) {
+                    program.push(instr);
+                }
+            }
+            program.push(0b1110_0_111_1111_1110); // B -4 (spins)
+            load_program(
+                &mut bus,
+                &program
+                    .iter()
+                    .flat_map(|x| x.to_le_bytes())
+                    .collect::<Vec<_>>(),
+            );
+            run(&mut bus, OperationState::Thumb, &setup, 17);
+        }
+    }
+
+    #[test]
+    fn stops_before_hardware_access() {
+        let mut bus = VecBus::new(0x0200_1000);
+        let program: &[u32] = &[
+            0b1110_00_1_1101_0_0000_0000_0011_0000_0001, // MOV R0,#0x4000000
+            0b1110_00_1_1101_0_0000_0001_0000_0000_0001, // MOV R1,#1
+            0b1110_01_0_1100_1_0000_0010_0000_0000_0000, // LDRB R2,[R0]
+            0b1110_1010_1111_1111_1111_1111_1111_1110,   // B -8 (spins)
+        ];
+        load_program(
+            &mut bus,
+            &program
+                .iter()
+                .flat_map(|x| x.to_le_bytes())
+                .collect::<Vec<_>>(),
+        );
+        let mut cpu = Cpu::new();
+        cpu.reg.r[PC_INDEX] = 0x0200_0000;
+        cpu.reload_pipeline(&mut bus);
+        let mut jit = Jit::new().unwrap();
+
+        // Only the first instruction of a block may access IO, as the rest of the system hasn't
+        // caught up with those before it.
+        assert_eq!(jit.step(&mut cpu, &mut bus, u32::MAX), Some(2));
+        assert_eq!(cpu.next_instr_addr(), 0x0200_0008);
+        assert_eq!(cpu.reg.r[1], 1);
+
+        cpu.reg.r[PC_INDEX] = 0x0200_0000;
+        cpu.reload_pipeline(&mut bus);
+        assert_eq!(jit.step(&mut cpu, &mut bus, 1), Some(1));
+        assert_eq!(cpu.next_instr_addr(), 0x0200_0004);
+    }
+
+    #[test]
+    fn invalidate_works() {
+        let mut bus = VecBus::new(0x0200_1000);
+        let mut cpu = Cpu::new();
+        cpu.reg.cpsr.state = OperationState::Thumb;
+        let mut jit = Jit::new().unwrap();
+        let mut writes = CodeWrites::default();
+
+        let mut run_block = |bus: &mut VecBus, jit: &mut Jit| {
+            cpu.reg.r[PC_INDEX] = 0x0200_0000;
+            cpu.reload_pipeline(bus);
+            jit.step(&mut cpu, bus, u32::MAX).unwrap();
+            cpu.reg.r[0]
+        };
+
+        load_program(&mut bus, &0b001_00_000_0000_0001_u16.to_le_bytes()); // MOV R0,#1
+        assert_eq!(run_block(&mut bus, &mut jit), 1);
+
+        load_program(&mut bus, &0b001_00_000_0000_0010_u16.to_le_bytes()); // MOV R0,#2
+        writes.notify(0x0200_0000);
+        jit.invalidate(&mut writes);
+        assert_eq!(run_block(&mut bus, &mut jit), 2);
+    }
+}
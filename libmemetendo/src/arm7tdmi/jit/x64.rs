@@ -0,0 +1,280 @@
+//! A tiny x86-64 machine code assembler, supporting just what the block compiler needs.
+//!
+//! Guest state is addressed relative to `rbx` (the `Cpu`) and `rbp` (the JIT `Context`), which
+//! the prologue sets up from the System V arguments. `eax`, `ecx` and `edx` are scratch.
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Reg {
+    Eax = 0,
+    Ecx = 1,
+    Edx = 2,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Alu {
+    Add = 0,
+    Or = 1,
+    Adc = 2,
+    And = 4,
+    Xor = 6,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Shift {
+    Ror = 1,
+    Rcr = 3,
+    Shl = 4,
+    Shr = 5,
+    Sar = 7,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Cond {
+    Overflow = 0x0,
+    Carry = 0x2,
+    Zero = 0x4,
+    NotZero = 0x5,
+    BelowOrEqual = 0x6,
+    Sign = 0x8,
+}
+
+/// The position of a rel32 operand that still needs to be patched to point at its target.
+#[must_use]
+#[derive(Copy, Clone)]
+pub struct Label(usize);
+
+const RBX: u8 = 3;
+const RBP: u8 = 5;
+
+fn modrm(mode: u8, reg: u8, rm: u8) -> u8 {
+    (mode << 6) | (reg << 3) | rm
+}
+
+#[derive(Default)]
+pub struct Asm {
+    buf: Vec<u8>,
+    epilogue_jumps: Vec<Label>,
+}
+
+impl Asm {
+    pub fn new() -> Self {
+        let mut asm = Self::default();
+        asm.bytes(&[
+            0x53, // push rbx
+            0x55, // push rbp
+            0x48, 0x83, 0xec, 0x08, // sub rsp,8 (keeps the stack 16-byte aligned for calls)
+            0x48, 0x89, 0xfb, // mov rbx,rdi
+            0x48, 0x89, 0xf5, // mov rbp,rsi
+        ]);
+
+        asm
+    }
+
+    /// Binds all returns to a shared epilogue and returns the finished machine code.
+    pub fn finish(mut self) -> Vec<u8> {
+        for label in std::mem::take(&mut self.epilogue_jumps) {
+            self.bind(label);
+        }
+        self.bytes(&[
+            0x48, 0x83, 0xc4, 0x08, // add rsp,8
+            0x5d, // pop rbp
+            0x5b, // pop rbx
+            0xc3, // ret
+        ]);
+
+        self.buf
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn imm32(&mut self, imm: u32) {
+        self.bytes(&imm.to_le_bytes());
+    }
+
+    fn cpu_operand(&mut self, reg: u8, disp: i32) {
+        self.buf.push(modrm(0b10, reg, RBX));
+        self.bytes(&disp.to_le_bytes());
+    }
+
+    fn ctx_operand(&mut self, reg: u8, disp: i32) {
+        self.buf.push(modrm(0b10, reg, RBP));
+        self.bytes(&disp.to_le_bytes());
+    }
+
+    /// `mov dst,dword [rbx+disp]`
+    pub fn load(&mut self, dst: Reg, disp: i32) {
+        self.buf.push(0x8b);
+        self.cpu_operand(dst as _, disp);
+    }
+
+    /// `movzx dst,byte [rbx+disp]`
+    pub fn load_byte(&mut self, dst: Reg, disp: i32) {
+        self.bytes(&[0x0f, 0xb6]);
+        self.cpu_operand(dst as _, disp);
+    }
+
+    /// `mov dword [rbx+disp],src`
+    pub fn store(&mut self, disp: i32, src: Reg) {
+        self.buf.push(0x89);
+        self.cpu_operand(src as _, disp);
+    }
+
+    /// `mov dword [rbx+disp],imm`
+    pub fn store_imm(&mut self, disp: i32, imm: u32) {
+        self.buf.push(0xc7);
+        self.cpu_operand(0, disp);
+        self.imm32(imm);
+    }
+
+    /// `mov byte [rbx+disp],imm`
+    pub fn store_byte_imm(&mut self, disp: i32, imm: bool) {
+        self.buf.push(0xc6);
+        self.cpu_operand(0, disp);
+        self.buf.push(imm.into());
+    }
+
+    /// `setcc byte [rbx+disp]`
+    pub fn set_cc(&mut self, cond: Cond, disp: i32) {
+        self.bytes(&[0x0f, 0x90 | cond as u8]);
+        self.cpu_operand(0, disp);
+    }
+
+    /// `mov dst,imm`
+    pub fn mov_imm(&mut self, dst: Reg, imm: u32) {
+        self.buf.push(0xb8 | dst as u8);
+        self.imm32(imm);
+    }
+
+    /// `mov dst,src`
+    pub fn mov(&mut self, dst: Reg, src: Reg) {
+        self.bytes(&[0x89, modrm(0b11, src as _, dst as _)]);
+    }
+
+    /// `op dst,src`
+    pub fn alu(&mut self, op: Alu, dst: Reg, src: Reg) {
+        self.bytes(&[((op as u8) << 3) | 1, modrm(0b11, src as _, dst as _)]);
+    }
+
+    /// `op dst,imm`
+    pub fn alu_imm(&mut self, op: Alu, dst: Reg, imm: u32) {
+        self.bytes(&[0x81, modrm(0b11, op as _, dst as _)]);
+        self.imm32(imm);
+    }
+
+    /// `test a,b`
+    pub fn test(&mut self, a: Reg, b: Reg) {
+        self.bytes(&[0x85, modrm(0b11, b as _, a as _)]);
+    }
+
+    /// `not dst`
+    pub fn not(&mut self, dst: Reg) {
+        self.bytes(&[0xf7, modrm(0b11, 2, dst as _)]);
+    }
+
+    /// `imul dst,src`
+    pub fn imul(&mut self, dst: Reg, src: Reg) {
+        self.bytes(&[0x0f, 0xaf, modrm(0b11, dst as _, src as _)]);
+    }
+
+    /// `op dst,count`
+    pub fn shift_imm(&mut self, op: Shift, dst: Reg, count: u8) {
+        self.bytes(&[0xc1, modrm(0b11, op as _, dst as _), count]);
+    }
+
+    /// `bt src,bit`, copying the bit to CF.
+    pub fn bt(&mut self, src: Reg, bit: u8) {
+        self.bytes(&[0x0f, 0xba, modrm(0b11, 4, src as _), bit]);
+    }
+
+    /// `stc`
+    pub fn stc(&mut self) {
+        self.buf.push(0xf9);
+    }
+
+    /// `jcc rel32`, to be bound later.
+    pub fn jcc(&mut self, cond: Cond) -> Label {
+        self.bytes(&[0x0f, 0x80 | cond as u8]);
+        self.imm32(0);
+
+        Label(self.buf.len() - 4)
+    }
+
+    /// `jmp rel32`, to be bound later.
+    pub fn jmp(&mut self) -> Label {
+        self.buf.push(0xe9);
+        self.imm32(0);
+
+        Label(self.buf.len() - 4)
+    }
+
+    /// Points the jump at `label` to the current position.
+    // Blocks are tiny, so their offsets always fit.
+    #[allow(clippy::missing_panics_doc)]
+    pub fn bind(&mut self, label: Label) {
+        let rel = i32::try_from(self.buf.len() - (label.0 + 4)).unwrap();
+        self.buf[label.0..label.0 + 4].copy_from_slice(&rel.to_le_bytes());
+    }
+
+    /// Returns `value` (in `eax`) from the block.
+    pub fn ret_imm(&mut self, value: u32) {
+        self.mov_imm(Reg::Eax, value);
+        let label = self.jmp();
+        self.epilogue_jumps.push(label);
+    }
+
+    /// Calls the function pointer at `[rbp+disp]` as `f(cpu, ctx, arg, arg2)`.
+    pub fn call_ctx(&mut self, disp: i32, arg: u32, arg2: u32) {
+        self.bytes(&[0x48, 0x89, 0xdf]); // mov rdi,rbx
+        self.bytes(&[0x48, 0x89, 0xee]); // mov rsi,rbp
+        self.buf.push(0xba); // mov edx,arg
+        self.imm32(arg);
+        self.buf.push(0xb9); // mov ecx,arg2
+        self.imm32(arg2);
+        self.buf.push(0xff);
+        self.ctx_operand(2, disp);
+    }
+
+    /// `cmp byte [rbp+disp],0`
+    pub fn cmp_ctx_byte_zero(&mut self, disp: i32) {
+        self.buf.push(0x80);
+        self.ctx_operand(7, disp);
+        self.buf.push(0);
+    }
+
+    /// `cmp dword [rbp+disp],imm`
+    pub fn cmp_ctx_imm(&mut self, disp: i32, imm: u32) {
+        self.buf.push(0x81);
+        self.ctx_operand(7, disp);
+        self.imm32(imm);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodings_work() {
+        let mut asm = Asm::default();
+        asm.load(Reg::Ecx, 0x10);
+        assert_eq!(asm.buf, [0x8b, 0x8b, 0x10, 0, 0, 0]);
+
+        asm.buf.clear();
+        asm.alu(Alu::Add, Reg::Eax, Reg::Ecx);
+        asm.alu_imm(Alu::Xor, Reg::Eax, 1);
+        asm.shift_imm(Shift::Shl, Reg::Edx, 3);
+        assert_eq!(asm.buf, [0x01, 0xc8, 0x81, 0xf0, 1, 0, 0, 0, 0xc1, 0xe2, 3]);
+
+        asm.buf.clear();
+        let label = asm.jcc(Cond::Zero);
+        asm.stc();
+        asm.bind(label);
+        assert_eq!(asm.buf, [0x0f, 0x84, 1, 0, 0, 0, 0xf9]);
+
+        asm.buf.clear();
+        asm.cmp_ctx_imm(0x20, 3);
+        assert_eq!(asm.buf, [0x81, 0xbd, 0x20, 0, 0, 0, 3, 0, 0, 0]);
+    }
+}
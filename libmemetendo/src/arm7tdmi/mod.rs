@@ -1,19 +1,35 @@
+pub mod coverage;
+pub mod disasm;
+pub mod history;
 mod isa;
+#[cfg(feature = "jit")]
+pub mod jit;
 pub mod reg;
+pub mod trace;
 
 use std::mem::take;
 
 use intbits::Bits;
-use log::trace;
+use log::{error, trace};
 use strum::EnumCount;
 use strum_macros::{EnumCount, EnumIter, FromRepr};
 
-use crate::bus::Bus;
+use crate::{
+    bus::Bus,
+    video::{HBLANK_DOT, VBLANK_DOT},
+};
 
-use self::reg::{OperationMode, OperationState, Registers, LR_INDEX, PC_INDEX, SP_INDEX};
+use self::{
+    coverage::Coverage,
+    history::{FaultKind, History},
+    reg::{OperationMode, OperationState, Registers, LR_INDEX, PC_INDEX, SP_INDEX},
+    trace::Tracer,
+};
 
 /// 280,896 cycles per frame at ~59.737 Hz.
 pub const CYCLES_PER_SECOND: u32 = 16_779_884;
+/// Cycles per frame, including the vertical blanking period.
+pub const CYCLES_PER_FRAME: u32 = 4 * (HBLANK_DOT as u32 + 68) * (VBLANK_DOT as u32 + 68);
 
 #[derive(Copy, Clone, PartialEq, Eq, FromRepr, EnumIter, EnumCount, Debug)]
 pub enum Exception {
@@ -82,12 +98,16 @@ impl Exception {
     }
 }
 
-#[derive(Default, Copy, Clone, Debug)]
+#[derive(Default, Debug)]
 pub struct Cpu {
     pub reg: Registers,
+    pub tracer: Option<Tracer>,
+    pub history: Option<History>,
+    pub coverage: Option<Coverage>,
     pipeline_instrs: [u32; 2],
     pipeline_reloaded: bool,
     pending_exceptions: [bool; Exception::COUNT],
+    entered_exception: Option<Exception>,
 }
 
 impl Cpu {
@@ -98,6 +118,9 @@ impl Cpu {
 
     pub fn reset(&mut self, bus: &mut impl Bus, skip_bios: bool) {
         self.pending_exceptions.fill(false);
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
         self.enter_exception(bus, Exception::Reset);
 
         if skip_bios {
@@ -142,7 +165,20 @@ impl Cpu {
         self.pipeline_instrs[1] = self.prefetch_instr(bus);
         self.pipeline_reloaded = false;
 
+        let state = self.reg.cpsr.state;
+        let addr = self.next_instr_addr();
+        let history_generation = self.history.as_ref().map(History::generation);
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(addr, state);
+        }
+
         trace!("next instr: {instr:08x}\n{}", self.reg);
+        if let Some(tracer) = &mut self.tracer {
+            if let Err(e) = tracer.trace(&self.reg, instr) {
+                error!("failed to write instruction trace, disabling it: {e}");
+                self.tracer = None;
+            }
+        }
         match self.reg.cpsr.state {
             OperationState::Arm => self.execute_arm(bus, instr),
             OperationState::Thumb => {
@@ -152,9 +188,22 @@ impl Cpu {
         if !self.pipeline_reloaded {
             self.reg.align_pc();
             self.reg.advance_pc();
+        } else if let Some(history) = &mut self.history {
+            // Entering an exception records its own branch.
+            if history_generation == Some(history.generation()) {
+                let to = self.reg.r[PC_INDEX].wrapping_sub(2 * self.reg.cpsr.state.instr_size());
+                let lr = self.reg.r[LR_INDEX];
+                history.record_branch(addr, state.instr_size(), to, self.reg.cpsr.state, lr);
+            }
         }
     }
 
+    /// Address of the next instruction to be executed, ignoring pending exceptions.
+    #[must_use]
+    pub fn next_instr_addr(&self) -> u32 {
+        self.reg.r[PC_INDEX].wrapping_sub(2 * self.reg.cpsr.state.instr_size())
+    }
+
     fn prefetch_instr(&mut self, bus: &mut impl Bus) -> u32 {
         bus.prefetch_instr(self.reg.r[PC_INDEX]);
 
@@ -177,6 +226,11 @@ impl Cpu {
         self.pending_exceptions[exception.priority()] = true;
     }
 
+    /// Takes the last exception entered since the previous call.
+    pub fn take_entered_exception(&mut self) -> Option<Exception> {
+        self.entered_exception.take()
+    }
+
     fn enter_exception(&mut self, bus: &mut impl Bus, exception: Exception) -> bool {
         if (self.reg.cpsr.irq_disabled && exception == Exception::Interrupt)
             || (self.reg.cpsr.fiq_disabled && exception == Exception::FastInterrupt)
@@ -185,6 +239,7 @@ impl Cpu {
         }
 
         trace!("entering exception: {:?}", exception);
+        self.entered_exception = Some(exception);
         let old_cpsr = self.reg.cpsr;
         self.reg.change_mode(exception.entry_mode());
         self.reg.cpsr.fiq_disabled |= exception.disables_fiq();
@@ -195,6 +250,23 @@ impl Cpu {
         self.reg.r[LR_INDEX] = base_pc.wrapping_add(exception.return_addr_offset(old_cpsr.state));
         self.reg.set_spsr(old_cpsr.bits());
 
+        if let Some(history) = self
+            .history
+            .as_mut()
+            .filter(|_| exception != Exception::Reset)
+        {
+            let resume_addr = match exception {
+                Exception::SoftwareInterrupt | Exception::UndefinedInstr => {
+                    base_pc.wrapping_add(old_cpsr.state.instr_size())
+                }
+                _ => base_pc,
+            };
+            history.record_exception(exception, base_pc, exception.vector_addr(), resume_addr);
+            if exception == Exception::UndefinedInstr {
+                history.record_fault(FaultKind::UndefinedInstr, base_pc);
+            }
+        }
+
         self.reg.r[PC_INDEX] = exception.vector_addr();
         self.reload_pipeline(bus);
 
@@ -208,6 +280,8 @@ mod tests {
 
     use crate::bus::tests::{NullBus, VecBus};
 
+    use self::history::BranchKind;
+
     use strum::IntoEnumIterator;
 
     fn assert_exception_result(cpu: &mut Cpu, exception: Exception, old_reg: Registers) {
@@ -359,4 +433,40 @@ mod tests {
         assert_eq!(102 + 4, cpu.reg.r[PC_INDEX]);
         assert_eq!(33, cpu.reg.r[1]);
     }
+
+    #[test]
+    fn history_works() {
+        let mut bus = VecBus::new(0x20);
+        bus.write_word(0, 0xeb00_0002); // BL 0x10
+        bus.write_word(4, 0xe7f0_00f0); // Undefined
+        bus.write_word(0x10, 0xe12f_ff1e); // BX LR
+
+        let mut cpu = Cpu::new();
+        cpu.history = Some(History::new(8));
+        cpu.reset(&mut bus, false);
+
+        cpu.step(&mut bus);
+        let history = cpu.history.as_ref().unwrap();
+        assert_eq!(history.call_stack().len(), 1);
+        assert_eq!(history.call_stack()[0].return_addr, 4);
+
+        cpu.step(&mut bus);
+        let history = cpu.history.as_ref().unwrap();
+        assert!(history.call_stack().is_empty());
+        assert_eq!(history.branches().last().unwrap().kind, BranchKind::Return);
+
+        cpu.step(&mut bus);
+        let history = cpu.history.as_mut().unwrap();
+        let fault = history.take_fault().unwrap();
+        assert_eq!((fault.kind, fault.addr), (FaultKind::UndefinedInstr, 4));
+        assert_eq!(
+            fault.branches.last().unwrap().kind,
+            BranchKind::Exception(Exception::UndefinedInstr)
+        );
+        assert_eq!(
+            history.call_stack()[0].exception,
+            Some(Exception::UndefinedInstr)
+        );
+        assert_eq!(history.branches().count(), 3);
+    }
 }
@@ -38,7 +38,7 @@ pub const SP_INDEX: usize = 13;
 pub const LR_INDEX: usize = 14;
 pub const PC_INDEX: usize = 15;
 
-#[derive(Default, Copy, Clone, Debug)]
+#[derive(Default, Copy, Clone, PartialEq, Eq, Debug)]
 pub struct Registers {
     pub r: [u32; 16],
     pub cpsr: StatusRegister,
@@ -64,7 +64,7 @@ impl Display for Registers {
     }
 }
 
-#[derive(Default, Copy, Clone, Debug)]
+#[derive(Default, Copy, Clone, PartialEq, Eq, Debug)]
 struct Bank {
     sp: u32,
     lr: u32,
@@ -145,9 +145,43 @@ impl Registers {
             self.cpsr.bits()
         }
     }
+
+    /// Reads register `index` as banked for `mode`, which need not be the current mode.
+    #[must_use]
+    pub fn banked(&self, mode: OperationMode, index: usize) -> u32 {
+        let mut reg = *self;
+        reg.change_mode(mode);
+
+        reg.r[index]
+    }
+
+    /// Writes register `index` as banked for `mode`, which need not be the current mode.
+    pub fn set_banked(&mut self, mode: OperationMode, index: usize, value: u32) {
+        let old_mode = self.cpsr.mode;
+        self.change_mode(mode);
+        self.r[index] = value;
+        self.change_mode(old_mode);
+    }
+
+    /// Reads the SPSR of `mode`, which need not be the current mode.
+    #[must_use]
+    pub fn banked_spsr(&self, mode: OperationMode) -> u32 {
+        let mut reg = *self;
+        reg.change_mode(mode);
+
+        reg.spsr()
+    }
+
+    /// Writes the SPSR of `mode`, which need not be the current mode.
+    pub fn set_banked_spsr(&mut self, mode: OperationMode, bits: u32) {
+        let old_mode = self.cpsr.mode;
+        self.change_mode(mode);
+        self.set_spsr(bits);
+        self.change_mode(old_mode);
+    }
 }
 
-#[derive(Default, Copy, Clone, PartialEq, Eq, FromRepr, Debug)]
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash, FromRepr, Debug)]
 pub enum OperationState {
     #[default]
     Arm = 0,
@@ -194,6 +228,11 @@ impl StatusRegister {
         self.mode
     }
 
+    #[must_use]
+    pub fn state(self) -> OperationState {
+        self.state
+    }
+
     #[must_use]
     pub fn bits(self) -> u32 {
         0.with_bit(31, self.signed)
@@ -280,4 +319,24 @@ mod tests {
         assert_eq!(1337, bank.sp);
         assert_eq!(1337, bank.lr);
     }
+
+    #[test]
+    fn banked_works() {
+        let mut reg = Registers::default();
+        reg.change_mode(OperationMode::System);
+        reg.r[8] = 1;
+        reg.r[SP_INDEX] = 2;
+
+        reg.set_banked(OperationMode::FastInterrupt, 8, 3);
+        reg.set_banked(OperationMode::Interrupt, SP_INDEX, 4);
+        reg.set_banked_spsr(OperationMode::Interrupt, 0b1_0000);
+        assert_eq!(OperationMode::System, reg.cpsr.mode);
+        assert_eq!([1, 2], [reg.r[8], reg.r[SP_INDEX]]);
+
+        assert_eq!(3, reg.banked(OperationMode::FastInterrupt, 8));
+        assert_eq!(1, reg.banked(OperationMode::Interrupt, 8));
+        assert_eq!(4, reg.banked(OperationMode::Interrupt, SP_INDEX));
+        assert_eq!(0b1_0000, reg.banked_spsr(OperationMode::Interrupt));
+        assert_eq!(0, reg.banked_spsr(OperationMode::FastInterrupt));
+    }
 }
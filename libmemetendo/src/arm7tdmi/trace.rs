@@ -0,0 +1,337 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{self, BufWriter, Write},
+    ops::RangeInclusive,
+    str::FromStr,
+};
+
+use intbits::Bits;
+
+use super::{
+    disasm::disassemble,
+    reg::{OperationState, Registers, PC_INDEX},
+};
+
+/// Writes one [`Entry`] per executed instruction.
+pub struct Tracer {
+    out: BufWriter<Box<dyn Write>>,
+    /// Only instructions whose address is within this range are traced, if set.
+    pub range: Option<RangeInclusive<u32>>,
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("range", &self.range)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tracer {
+    #[must_use]
+    pub fn new(out: impl Write + 'static) -> Self {
+        Self {
+            out: BufWriter::new(Box::new(out)),
+            range: None,
+        }
+    }
+
+    /// Traces `instr`, which is about to be executed with the registers in `reg`.
+    pub(super) fn trace(&mut self, reg: &Registers, instr: u32) -> io::Result<()> {
+        // Check the range before building the entry, as disassembling is costly.
+        let addr = reg.r[PC_INDEX].wrapping_sub(2 * reg.cpsr.state.instr_size());
+        if self
+            .range
+            .as_ref()
+            .map_or(true, |range| range.contains(&addr))
+        {
+            writeln!(self.out, "{}", Entry::new(reg, instr))?;
+        }
+
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the underlying writer could not be flushed.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// A line of an instruction trace, like:
+///
+/// ```text
+/// 00000000 [...] 08000008 cpsr: 0000001F | EA00002E: b 0x080000c0
+/// ```
+///
+/// That is, the 16 registers and CPSR as they were before executing the instruction, then the
+/// opcode and its disassembly. THUMB opcodes are padded to the width of ARM opcodes. As the CPU is
+/// pipelined, R15 is 2 instructions ahead of the address of the executing instruction.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Entry {
+    pub r: [u32; 16],
+    pub cpsr: u32,
+    pub opcode: u32,
+    pub disasm: String,
+}
+
+impl Entry {
+    #[must_use]
+    pub fn new(reg: &Registers, instr: u32) -> Self {
+        let state = reg.cpsr.state;
+        let opcode = match state {
+            OperationState::Arm => instr,
+            OperationState::Thumb => instr.bits(..16),
+        };
+        let addr = reg.r[PC_INDEX].wrapping_sub(2 * state.instr_size());
+
+        Self {
+            r: reg.r,
+            cpsr: reg.cpsr.bits(),
+            opcode,
+            disasm: disassemble(state, opcode, addr),
+        }
+    }
+
+    // Any CPSR value maps to a state, so we never panic.
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn state(&self) -> OperationState {
+        OperationState::from_bits(self.cpsr).unwrap()
+    }
+
+    /// Address of the traced instruction.
+    #[must_use]
+    pub fn addr(&self) -> u32 {
+        self.r[PC_INDEX].wrapping_sub(2 * self.state().instr_size())
+    }
+
+    /// Lists the differences between the registers, CPSR or opcode of both entries.
+    #[must_use]
+    pub fn differences(&self, other: &Self) -> Vec<String> {
+        let mut differences: Vec<_> = (0..self.r.len())
+            .filter(|&i| self.r[i] != other.r[i])
+            .map(|i| format!("r{i}: {:08X} != {:08X}", self.r[i], other.r[i]))
+            .collect();
+        if self.cpsr != other.cpsr {
+            differences.push(format!("cpsr: {:08X} != {:08X}", self.cpsr, other.cpsr));
+        }
+        if self.opcode != other.opcode {
+            differences.push(format!(
+                "opcode: {:08X} != {:08X}",
+                self.opcode, other.opcode
+            ));
+        }
+
+        differences
+    }
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for r in self.r {
+            write!(f, "{r:08X} ")?;
+        }
+        write!(f, "cpsr: {:08X} | ", self.cpsr)?;
+        match self.state() {
+            OperationState::Arm => write!(f, "{:08X}", self.opcode)?,
+            OperationState::Thumb => write!(f, "    {:04X}", self.opcode)?,
+        }
+
+        write!(f, ": {}", self.disasm)
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct ParseEntryError;
+
+impl Display for ParseEntryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid trace entry")
+    }
+}
+
+impl Error for ParseEntryError {}
+
+impl FromStr for Entry {
+    type Err = ParseEntryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_hex = |s: &str| u32::from_str_radix(s, 16).map_err(|_| ParseEntryError);
+
+        let (reg, instr) = s.split_once('|').ok_or(ParseEntryError)?;
+        let mut reg = reg.split_whitespace();
+        let mut r = [0; 16];
+        for r in &mut r {
+            *r = parse_hex(reg.next().ok_or(ParseEntryError)?)?;
+        }
+        if !reg
+            .next()
+            .map_or(false, |s| s.eq_ignore_ascii_case("cpsr:"))
+        {
+            return Err(ParseEntryError);
+        }
+        let cpsr = parse_hex(reg.next().ok_or(ParseEntryError)?)?;
+        if reg.next().is_some() || OperationState::from_bits(cpsr).is_none() {
+            return Err(ParseEntryError);
+        }
+
+        let (opcode, disasm) = instr.split_once(':').unwrap_or((instr, ""));
+
+        Ok(Self {
+            r,
+            cpsr,
+            opcode: parse_hex(opcode.trim())?,
+            disasm: disasm.trim().into(),
+        })
+    }
+}
+
+/// The first point at which two traces disagree.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Divergence {
+    /// 1-based line numbers of the diverging entries within each trace.
+    pub line_a: usize,
+    pub line_b: usize,
+    /// Matching entries that came just before the divergence, oldest first.
+    pub context: Vec<Entry>,
+    /// The diverging entries, or `None` if that trace ended early.
+    pub a: Option<Entry>,
+    pub b: Option<Entry>,
+}
+
+/// Finds the first divergence between traces `a` and `b`, keeping up to `context_len` entries
+/// before it.
+///
+/// As traces may have been started at different times, entries in `b` are first skipped until one
+/// executes the same instruction at the same address as the first entry in `a`.
+///
+/// # Errors
+///
+/// Returns the first error yielded by either trace.
+pub fn diff<E>(
+    a: impl IntoIterator<Item = Result<Entry, E>>,
+    b: impl IntoIterator<Item = Result<Entry, E>>,
+    context_len: usize,
+) -> Result<Option<Divergence>, E> {
+    let mut a = a.into_iter();
+    let mut b = b.into_iter();
+    let mut line_a = 1;
+    let mut line_b = 1;
+
+    let Some(first_a) = a.next().transpose()? else {
+        return Ok(b.next().transpose()?.map(|b| Divergence {
+            line_a,
+            line_b,
+            context: Vec::new(),
+            a: None,
+            b: Some(b),
+        }));
+    };
+    let mut entry_b = b.next().transpose()?;
+    while let Some(eb) = &entry_b {
+        if eb.addr() == first_a.addr() && eb.opcode == first_a.opcode {
+            break;
+        }
+        entry_b = b.next().transpose()?;
+        line_b += 1;
+    }
+
+    let mut entry_a = Some(first_a);
+    let mut context = VecDeque::with_capacity(context_len + 1);
+    loop {
+        match (entry_a, entry_b) {
+            (None, None) => return Ok(None),
+            (Some(ea), Some(eb)) if ea.differences(&eb).is_empty() => {
+                context.push_back(ea);
+                if context.len() > context_len {
+                    context.pop_front();
+                }
+            }
+            (ea, eb) => {
+                return Ok(Some(Divergence {
+                    line_a,
+                    line_b,
+                    context: context.into(),
+                    a: ea,
+                    b: eb,
+                }))
+            }
+        }
+
+        entry_a = a.next().transpose()?;
+        entry_b = b.next().transpose()?;
+        line_a += 1;
+        line_b += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(pc: u32, opcode: u32) -> Entry {
+        let mut r = [0; 16];
+        r[PC_INDEX] = pc + 8;
+
+        Entry {
+            r,
+            cpsr: 0x1f,
+            opcode,
+            disasm: String::new(),
+        }
+    }
+
+    fn ok(entries: Vec<Entry>) -> impl Iterator<Item = Result<Entry, ()>> {
+        entries.into_iter().map(Ok)
+    }
+
+    #[test]
+    fn entry_round_trips() {
+        let mut reg = Registers::default();
+        reg.r[0] = 0xdead_beef;
+        reg.r[PC_INDEX] = 0x0800_0008;
+        let arm = Entry::new(&reg, 0xea00_002e);
+        assert_eq!(arm.addr(), 0x0800_0000);
+        assert_eq!(arm.disasm, "b 0x080000c0");
+        let line = arm.to_string();
+        assert!(line.starts_with("DEADBEEF 00000000"));
+        assert!(line.ends_with("| EA00002E: b 0x080000c0"));
+        assert_eq!(line.parse(), Ok(arm));
+
+        reg.cpsr.state = OperationState::Thumb;
+        reg.r[PC_INDEX] = 0x0800_0004;
+        let thumb = Entry::new(&reg, 0x1234_2012);
+        assert_eq!(thumb.opcode, 0x2012);
+        assert!(thumb.to_string().ends_with("|     2012: mov r0,#0x12"));
+        assert_eq!(thumb.to_string().parse(), Ok(thumb));
+
+        assert_eq!("garbage".parse::<Entry>(), Err(ParseEntryError));
+    }
+
+    #[test]
+    fn diff_works() {
+        let a = vec![entry(0, 1), entry(4, 2), entry(8, 3)];
+        assert_eq!(diff(ok(a.clone()), ok(a.clone()), 1), Ok(None));
+
+        // b started earlier, so its first line should be skipped.
+        let mut b = vec![entry(0x100, 0), entry(0, 1), entry(4, 2), entry(8, 4)];
+        let divergence = diff(ok(a.clone()), ok(b.clone()), 1).unwrap().unwrap();
+        assert_eq!((divergence.line_a, divergence.line_b), (3, 4));
+        assert_eq!(divergence.context, vec![entry(4, 2)]);
+        assert_eq!(divergence.a, Some(entry(8, 3)));
+        assert_eq!(divergence.b, Some(entry(8, 4)));
+        assert_eq!(
+            divergence.a.unwrap().differences(&divergence.b.unwrap()),
+            vec!["opcode: 00000003 != 00000004"]
+        );
+
+        b.truncate(3);
+        let divergence = diff(ok(a), ok(b), 5).unwrap().unwrap();
+        assert_eq!(divergence.context.len(), 2);
+        assert_eq!(divergence.b, None);
+    }
+}
@@ -138,6 +138,10 @@ impl ToneAndSweep {
         &mut self.tone.length_and_envelope
     }
 
+    pub fn is_channel_enabled(&self) -> bool {
+        self.tone.length_and_envelope.length.is_channel_enabled()
+    }
+
     pub fn set_ctrl_byte(&mut self, idx: usize, value: u8) {
         match idx {
             // SOUND1CNT_L
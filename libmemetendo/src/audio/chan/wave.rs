@@ -1,3 +1,5 @@
+use std::mem::take;
+
 use intbits::Bits;
 
 use crate::{
@@ -141,6 +143,8 @@ pub struct Fifo<const FIFO_A: bool> {
     samples: [i8; 32],
     start_idx: usize,
     len: usize,
+    /// Whether more samples were requested by DMA since last taken.
+    refill_requested: bool,
 }
 
 impl<const FIFO_A: bool> Fifo<FIFO_A> {
@@ -166,6 +170,7 @@ impl<const FIFO_A: bool> Fifo<FIFO_A> {
         };
 
         if self.len <= 16 {
+            self.refill_requested = true;
             dma.notify(if FIFO_A {
                 Event::AudioFifoA
             } else {
@@ -178,6 +183,10 @@ impl<const FIFO_A: bool> Fifo<FIFO_A> {
         self.sample
     }
 
+    pub fn take_refill_requested(&mut self) -> bool {
+        take(&mut self.refill_requested)
+    }
+
     pub fn reset(&mut self) {
         self.sample = 0;
         self.start_idx = 0;
@@ -2,7 +2,7 @@ use std::mem::{replace, take};
 
 use intbits::Bits;
 
-use crate::{arm7tdmi::CYCLES_PER_SECOND, bus::Bus, dma::Dma};
+use crate::{arm7tdmi::CYCLES_PER_SECOND, bus::Bus, dma::Dma, io_regs::IoRegister};
 
 use self::chan::{
     noise::Noise,
@@ -167,6 +167,14 @@ impl Audio {
         sample
     }
 
+    /// Returns whether FIFO A and B requested more samples by DMA since the last call.
+    pub(crate) fn take_fifo_refill_requests(&mut self) -> [bool; 2] {
+        [
+            self.channels.4.take_refill_requested(),
+            self.channels.5.take_refill_requested(),
+        ]
+    }
+
     pub fn notify_timer_overflow(&mut self, timer_idx: usize, count: u8) {
         if self.fifo_timer_idx[0] == timer_idx {
             self.fifo_pending_steps[0] += count;
@@ -177,6 +185,162 @@ impl Audio {
     }
 }
 
+impl Audio {
+    /// Bits 0-3 of `SOUNDCNT_X`, set for each of channels 1-4 that is playing.
+    fn active_channel_bits(&self) -> u8 {
+        0u8.with_bit(0, self.channels.0.is_channel_enabled())
+            .with_bit(
+                1,
+                self.channels
+                    .1
+                    .length_and_envelope
+                    .length
+                    .is_channel_enabled(),
+            )
+            .with_bit(2, self.channels.2.length.is_channel_enabled())
+            .with_bit(
+                3,
+                self.channels
+                    .3
+                    .length_and_envelope
+                    .length
+                    .is_channel_enabled(),
+            )
+    }
+
+    pub(crate) fn io_registers(&self) -> Vec<IoRegister> {
+        let soundcnt = self.cached_soundcnt_bits;
+        let soundcnt_x = u8::from(self.enabled) << 7 | self.active_channel_bits();
+        let soundbias = self.cached_soundbias_bits;
+        let channel_names = |side| match side {
+            0 => ["right_1", "right_2", "right_3", "right_4"],
+            _ => ["left_1", "left_2", "left_3", "left_4"],
+        };
+
+        let mut regs = self.channel_io_registers();
+        regs.extend([
+            IoRegister::new(
+                "SOUNDCNT_L",
+                0x80,
+                2,
+                soundcnt.bits(..16).try_into().unwrap(),
+            )
+            .number("right_volume", self.out_dmg_volume.0)
+            .number("left_volume", self.out_dmg_volume.1)
+            .flags(&channel_names(0), soundcnt.bits(8..12).try_into().unwrap())
+            .flags(&channel_names(1), soundcnt.bits(12..16).try_into().unwrap()),
+            IoRegister::new(
+                "SOUNDCNT_H",
+                0x82,
+                2,
+                soundcnt.bits(16..32).try_into().unwrap(),
+            )
+            .named(
+                "dmg_volume",
+                ["25%", "50%", "100%"][usize::from(self.dmg_volume_ratio)],
+            )
+            .flag("fifo_a_full_volume", self.fifo_full_volume[0])
+            .flag("fifo_b_full_volume", self.fifo_full_volume[1])
+            .flags(
+                &["fifo_a_right", "fifo_a_left"],
+                soundcnt.bits(24..26).try_into().unwrap(),
+            )
+            .number(
+                "fifo_a_timer",
+                u32::try_from(self.fifo_timer_idx[0]).unwrap(),
+            )
+            .flags(
+                &["fifo_b_right", "fifo_b_left"],
+                soundcnt.bits(28..30).try_into().unwrap(),
+            )
+            .number(
+                "fifo_b_timer",
+                u32::try_from(self.fifo_timer_idx[1]).unwrap(),
+            ),
+            IoRegister::new("SOUNDCNT_X", 0x84, 2, soundcnt_x.into())
+                .flags(&["on_1", "on_2", "on_3", "on_4"], soundcnt_x.into())
+                .flag("enabled", self.enabled),
+            IoRegister::new(
+                "SOUNDBIAS",
+                0x88,
+                2,
+                soundbias.bits(..16).try_into().unwrap(),
+            )
+            .number("bias", u32::try_from(self.bias).unwrap_or(0))
+            .number("sampling_cycle", self.sampling_cycle),
+        ]);
+
+        regs
+    }
+
+    fn channel_io_registers(&self) -> Vec<IoRegister> {
+        // The channels' registers as last written, in 64-bit blocks from SOUND1CNT_L.
+        let ctrl = [
+            self.channels.0.ctrl_bits(),
+            self.channels.1.ctrl_bits(),
+            self.channels.2.ctrl_bits(),
+            self.channels.3.ctrl_bits(),
+        ];
+        let hword = |addr: u32| -> u16 {
+            let (chan, offset) = ((addr - 0x60) as usize / 8, 8 * (addr as usize % 8));
+            ctrl[chan].bits(offset..offset + 16).try_into().unwrap()
+        };
+        let reg = |name, addr| IoRegister::new(name, addr, 2, hword(addr).into());
+        let envelope = |name, addr, duty| {
+            let bits = hword(addr);
+            let reg = reg(name, addr).number("length", bits.bits(..6));
+            let reg = if duty {
+                reg.number("duty", bits.bits(6..8))
+            } else {
+                reg
+            };
+            reg.number("envelope_step", bits.bits(8..11))
+                .flag("envelope_increase", bits.bit(11))
+                .number("initial_volume", bits.bits(12..))
+        };
+        let frequency = |name, addr| {
+            let bits = hword(addr);
+            reg(name, addr)
+                .number("frequency", bits.bits(..11))
+                .flag("length_enabled", bits.bit(14))
+        };
+        let (sound1cnt_l, sound3cnt_l, sound3cnt_h, sound4cnt_h) =
+            (hword(0x60), hword(0x70), hword(0x72), hword(0x7c));
+
+        vec![
+            reg("SOUND1CNT_L", 0x60)
+                .number("sweep_shift", sound1cnt_l.bits(..3))
+                .flag("sweep_decrease", sound1cnt_l.bit(3))
+                .number("sweep_time", sound1cnt_l.bits(4..7)),
+            envelope("SOUND1CNT_H", 0x62, true),
+            frequency("SOUND1CNT_X", 0x64),
+            envelope("SOUND2CNT_L", 0x68, true),
+            frequency("SOUND2CNT_H", 0x6c),
+            reg("SOUND3CNT_L", 0x70)
+                .flag("two_banks", sound3cnt_l.bit(5))
+                .number("bank", sound3cnt_l.bits(6..7))
+                .flag("enabled", sound3cnt_l.bit(7)),
+            reg("SOUND3CNT_H", 0x72)
+                .number("length", sound3cnt_h.bits(..8))
+                .named(
+                    "volume",
+                    if sound3cnt_h.bit(15) {
+                        "75%"
+                    } else {
+                        ["0%", "100%", "50%", "25%"][usize::from(sound3cnt_h.bits(13..15))]
+                    },
+                ),
+            frequency("SOUND3CNT_X", 0x74),
+            envelope("SOUND4CNT_L", 0x78, false),
+            reg("SOUND4CNT_H", 0x7c)
+                .number("divider", sound4cnt_h.bits(..3))
+                .flag("width_7", sound4cnt_h.bit(3))
+                .number("shift", sound4cnt_h.bits(4..8))
+                .flag("length_enabled", sound4cnt_h.bit(14)),
+        ]
+    }
+}
+
 impl Bus for Audio {
     fn read_byte(&mut self, addr: u32) -> u8 {
         let ctrl_offset = 8 * usize::try_from(addr & 7).unwrap();
@@ -220,32 +384,7 @@ impl Bus for Audio {
                         .unwrap();
 
                 if addr == 0x84 {
-                    cached_bits
-                        .with_bit(
-                            0,
-                            self.channels
-                                .0
-                                .length_and_envelope()
-                                .length
-                                .is_channel_enabled(),
-                        )
-                        .with_bit(
-                            1,
-                            self.channels
-                                .1
-                                .length_and_envelope
-                                .length
-                                .is_channel_enabled(),
-                        )
-                        .with_bit(2, self.channels.2.length.is_channel_enabled())
-                        .with_bit(
-                            3,
-                            self.channels
-                                .3
-                                .length_and_envelope
-                                .length
-                                .is_channel_enabled(),
-                        )
+                    cached_bits.with_bits(..4, self.active_channel_bits())
                 } else {
                     cached_bits
                 }
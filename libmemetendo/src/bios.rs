@@ -25,6 +25,11 @@ impl Rom {
     pub fn new(buf: Rc<[u8]>) -> Result<Self, InvalidRomSize> {
         Self::try_from(buf)
     }
+
+    #[must_use]
+    pub fn bytes(&self) -> &[u8] {
+        self.0.as_ref()
+    }
 }
 
 #[derive(Clone)]
@@ -44,6 +49,11 @@ impl Bios {
         }
     }
 
+    #[must_use]
+    pub fn rom(&self) -> &Rom {
+        &self.rom
+    }
+
     pub fn reset(&mut self) {
         self.readable = false;
         self.prefetch_addr = 0;
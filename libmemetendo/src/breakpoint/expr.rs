@@ -0,0 +1,414 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+/// The state that expressions are evaluated against.
+pub trait Context {
+    /// Reads register `index` (0 to 15), with R15 being the address of the next instruction.
+    fn reg(&self, index: usize) -> u32;
+
+    fn cpsr(&self) -> u32;
+
+    fn spsr(&self) -> u32;
+
+    /// Reads `size` (1, 2 or 4) bytes from `addr`, without side effects.
+    ///
+    /// Returns `None` if the memory cannot be read.
+    fn read(&mut self, addr: u32, size: u32) -> Option<u32>;
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum UnaryOp {
+    Neg,
+    Not,
+    LogicalNot,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+}
+
+impl BinaryOp {
+    fn from_token(token: &str) -> Option<Self> {
+        Some(match token {
+            "||" => Self::Or,
+            "&&" => Self::And,
+            "==" => Self::Eq,
+            "!=" => Self::Ne,
+            "<" => Self::Lt,
+            "<=" => Self::Le,
+            ">" => Self::Gt,
+            ">=" => Self::Ge,
+            "|" => Self::BitOr,
+            "^" => Self::BitXor,
+            "&" => Self::BitAnd,
+            "<<" => Self::Shl,
+            ">>" => Self::Shr,
+            "+" => Self::Add,
+            "-" => Self::Sub,
+            "*" => Self::Mul,
+            _ => return None,
+        })
+    }
+
+    /// Like Rust, comparisons bind more loosely than bitwise operators.
+    fn precedence(self) -> u8 {
+        match self {
+            Self::Or => 1,
+            Self::And => 2,
+            Self::Eq | Self::Ne | Self::Lt | Self::Le | Self::Gt | Self::Ge => 3,
+            Self::BitOr => 4,
+            Self::BitXor => 5,
+            Self::BitAnd => 6,
+            Self::Shl | Self::Shr => 7,
+            Self::Add | Self::Sub => 8,
+            Self::Mul => 9,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Node {
+    Num(u32),
+    Reg(usize),
+    Cpsr,
+    Spsr,
+    Mem { size: u32, addr: Box<Node> },
+    Unary(UnaryOp, Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+}
+
+impl Node {
+    fn eval(&self, ctx: &mut impl Context) -> Option<u32> {
+        Some(match self {
+            &Self::Num(value) => value,
+            &Self::Reg(index) => ctx.reg(index),
+            Self::Cpsr => ctx.cpsr(),
+            Self::Spsr => ctx.spsr(),
+            Self::Mem { size, addr } => {
+                let addr = addr.eval(ctx)?;
+                ctx.read(addr, *size)?
+            }
+            Self::Unary(op, node) => {
+                let value = node.eval(ctx)?;
+                match op {
+                    UnaryOp::Neg => value.wrapping_neg(),
+                    UnaryOp::Not => !value,
+                    UnaryOp::LogicalNot => (value == 0).into(),
+                }
+            }
+            // Short-circuit, so guards like `r0 != 0 && [r0] == 1` avoid bad reads.
+            Self::Binary(BinaryOp::Or, lhs, rhs) => {
+                (lhs.eval(ctx)? != 0 || rhs.eval(ctx)? != 0).into()
+            }
+            Self::Binary(BinaryOp::And, lhs, rhs) => {
+                (lhs.eval(ctx)? != 0 && rhs.eval(ctx)? != 0).into()
+            }
+            Self::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(ctx)?, rhs.eval(ctx)?);
+                match op {
+                    BinaryOp::Eq => (lhs == rhs).into(),
+                    BinaryOp::Ne => (lhs != rhs).into(),
+                    BinaryOp::Lt => (lhs < rhs).into(),
+                    BinaryOp::Le => (lhs <= rhs).into(),
+                    BinaryOp::Gt => (lhs > rhs).into(),
+                    BinaryOp::Ge => (lhs >= rhs).into(),
+                    BinaryOp::BitOr => lhs | rhs,
+                    BinaryOp::BitXor => lhs ^ rhs,
+                    BinaryOp::BitAnd => lhs & rhs,
+                    BinaryOp::Shl => lhs.checked_shl(rhs).unwrap_or(0),
+                    BinaryOp::Shr => lhs.checked_shr(rhs).unwrap_or(0),
+                    BinaryOp::Add => lhs.wrapping_add(rhs),
+                    BinaryOp::Sub => lhs.wrapping_sub(rhs),
+                    BinaryOp::Mul => lhs.wrapping_mul(rhs),
+                    BinaryOp::Or | BinaryOp::And => unreachable!(),
+                }
+            }
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseExprError(String);
+
+impl Display for ParseExprError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid expression: {}", self.0)
+    }
+}
+
+impl Error for ParseExprError {}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Token {
+    Num(u32),
+    Ident(String),
+    Op(&'static str),
+}
+
+const OPS: [&str; 22] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "<", ">", "|", "^", "&", "+", "-", "*", "!",
+    "~", "[", "]", "(", ")",
+];
+
+fn tokenize(s: &str) -> Result<Vec<Token>, ParseExprError> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            let num = if let Some(hex) = word.strip_prefix("0x").or(word.strip_prefix("0X")) {
+                Some(u32::from_str_radix(hex, 16))
+            } else if c.is_ascii_digit() {
+                Some(word.parse())
+            } else {
+                None
+            };
+            tokens.push(match num {
+                Some(Ok(value)) => Token::Num(value),
+                Some(Err(_)) => return Err(ParseExprError(format!("invalid number {word:?}"))),
+                None => Token::Ident(word.to_ascii_lowercase()),
+            });
+            rest = &rest[len..];
+        } else if let Some(op) = OPS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(ParseExprError(format!("unexpected character {c:?}")));
+        }
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), ParseExprError> {
+        match self.next() {
+            Some(Token::Op(next)) if next == op => Ok(()),
+            _ => Err(ParseExprError(format!("expected {op:?}"))),
+        }
+    }
+
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Node, ParseExprError> {
+        let mut lhs = self.parse_unary()?;
+        while let Some(&Token::Op(token)) = self.peek() {
+            let Some(op) = BinaryOp::from_token(token) else {
+                break;
+            };
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.parse_binary(op.precedence() + 1)?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Node, ParseExprError> {
+        let op = match self.peek() {
+            Some(Token::Op("-")) => UnaryOp::Neg,
+            Some(Token::Op("~")) => UnaryOp::Not,
+            Some(Token::Op("!")) => UnaryOp::LogicalNot,
+            _ => return self.parse_primary(),
+        };
+        self.pos += 1;
+
+        Ok(Node::Unary(op, Box::new(self.parse_unary()?)))
+    }
+
+    fn parse_mem(&mut self, size: u32) -> Result<Node, ParseExprError> {
+        let addr = self.parse_binary(0)?;
+        self.expect("]")?;
+
+        Ok(Node::Mem {
+            size,
+            addr: Box::new(addr),
+        })
+    }
+
+    fn parse_primary(&mut self) -> Result<Node, ParseExprError> {
+        match self.next() {
+            Some(Token::Num(value)) => Ok(Node::Num(value)),
+            Some(Token::Op("(")) => {
+                let node = self.parse_binary(0)?;
+                self.expect(")")?;
+                Ok(node)
+            }
+            Some(Token::Op("[")) => self.parse_mem(4),
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "sp" => Ok(Node::Reg(13)),
+                "lr" => Ok(Node::Reg(14)),
+                "pc" => Ok(Node::Reg(15)),
+                "cpsr" => Ok(Node::Cpsr),
+                "spsr" => Ok(Node::Spsr),
+                "u8" | "u16" | "u32" => {
+                    self.expect("[")?;
+                    self.parse_mem(if ident == "u8" {
+                        1
+                    } else if ident == "u16" {
+                        2
+                    } else {
+                        4
+                    })
+                }
+                _ => match ident.strip_prefix('r').map(str::parse) {
+                    Some(Ok(index @ 0..=15)) => Ok(Node::Reg(index)),
+                    _ => Err(ParseExprError(format!("unknown identifier {ident:?}"))),
+                },
+            },
+            Some(Token::Op(op)) => Err(ParseExprError(format!("unexpected {op:?}"))),
+            None => Err(ParseExprError("unexpected end".into())),
+        }
+    }
+}
+
+/// An expression over registers and memory, like `r0 == 1 && u16[sp + 4] != 0`.
+///
+/// Values are 32-bit and arithmetic wraps. `[ADDR]` reads a word from memory, while
+/// `u8[ADDR]`, `u16[ADDR]` and `u32[ADDR]` read a specific size. Comparisons and logical
+/// operators evaluate to 0 or 1.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Expr {
+    root: Node,
+    source: String,
+}
+
+impl Expr {
+    /// Returns `None` if memory that cannot be read was accessed.
+    pub fn eval(&self, ctx: &mut impl Context) -> Option<u32> {
+        self.root.eval(ctx)
+    }
+
+    /// Whether the expression evaluates to non-zero.
+    pub fn is_true(&self, ctx: &mut impl Context) -> bool {
+        self.eval(ctx).map_or(false, |value| value != 0)
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ParseExprError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let root = parser.parse_binary(0)?;
+        if let Some(token) = parser.peek() {
+            return Err(ParseExprError(format!("unexpected {token:?}")));
+        }
+
+        Ok(Self {
+            root,
+            source: s.trim().to_string(),
+        })
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    pub struct TestContext {
+        pub r: [u32; 16],
+        pub mem: Vec<u8>,
+    }
+
+    impl Context for TestContext {
+        fn reg(&self, index: usize) -> u32 {
+            self.r[index]
+        }
+
+        fn cpsr(&self) -> u32 {
+            0x1f
+        }
+
+        fn spsr(&self) -> u32 {
+            0x10
+        }
+
+        fn read(&mut self, addr: u32, size: u32) -> Option<u32> {
+            (0..size).try_fold(0, |value, i| {
+                let byte = *self.mem.get(usize::try_from(addr + i).ok()?)?;
+                Some(value | u32::from(byte) << (8 * i))
+            })
+        }
+    }
+
+    #[test]
+    fn expr_works() {
+        let mut ctx = TestContext {
+            r: [0; 16],
+            mem: vec![0x78, 0x56, 0x34, 0x12],
+        };
+        ctx.r[0] = 5;
+        ctx.r[13] = 2;
+        let mut eval = |s: &str| s.parse::<Expr>().unwrap().eval(&mut ctx);
+
+        assert_eq!(eval("1 + 2 * 3"), Some(7));
+        assert_eq!(eval("(1 + 2) * 3"), Some(9));
+        assert_eq!(eval("0 - 1"), Some(u32::MAX));
+        assert_eq!(eval("-1 == ~0"), Some(1));
+        assert_eq!(eval("R0 & 1 == 1"), Some(1));
+        assert_eq!(eval("r0 >= 5 && r0 < 0x6"), Some(1));
+        assert_eq!(eval("!r1 || [0x10] == 0"), Some(1));
+        assert_eq!(eval("1 << 32"), Some(0));
+        assert_eq!(eval("[0]"), Some(0x1234_5678));
+        assert_eq!(eval("u16[sp]"), Some(0x1234));
+        assert_eq!(eval("u8[r0 - 4] | spsr"), Some(0x56 | 0x10));
+        assert_eq!(eval("cpsr"), Some(0x1f));
+        assert_eq!(eval("[0x10]"), None);
+
+        for s in [
+            "", "1 +", "(1", "[r0", "r16", "foo", "0xzz", "1 2", "r0 $ 1",
+        ] {
+            assert!(s.parse::<Expr>().is_err(), "{s:?} should fail to parse");
+        }
+        assert_eq!(" r0 == 1 ".parse::<Expr>().unwrap().to_string(), "r0 == 1");
+    }
+}
@@ -0,0 +1,440 @@
+pub mod expr;
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    ops::RangeInclusive,
+};
+
+use crate::{
+    arm7tdmi::{reg::OperationState, Exception},
+    irq::{Interrupt, Irq},
+};
+
+use self::expr::{Context, Expr};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+impl WatchKind {
+    fn matches(self, write: bool) -> bool {
+        match self {
+            Self::Read => !write,
+            Self::Write => write,
+            Self::Access => true,
+        }
+    }
+}
+
+/// A breakpoint on executing the instruction at an address.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ExecBreakpoint {
+    /// Only break if executing in this state.
+    pub state: Option<OperationState>,
+    /// Only break if this evaluates to non-zero.
+    pub condition: Option<Expr>,
+    /// Number of hits to ignore before breaking.
+    pub ignore_count: u32,
+    /// Number of times the breakpoint was hit (with its state and condition satisfied),
+    /// including ignored hits.
+    pub hits: u32,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u32>,
+    pub kind: WatchKind,
+    pub hits: u32,
+}
+
+/// Something happening that can be broken on, other than executing or accessing an address.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    /// Entering the IRQ exception while this interrupt is pending.
+    Interrupt(Interrupt),
+    /// A DMA transfer starting on a channel (0 to 3).
+    DmaStart(usize),
+    /// Entering an exception.
+    Exception(Exception),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StopReason {
+    /// About to execute the instruction at `addr`.
+    Breakpoint { addr: u32, hits: u32 },
+    /// The last instruction (or a DMA transfer) accessed `addr`, which is watched by `kind`.
+    Watchpoint {
+        addr: u32,
+        kind: WatchKind,
+        write: bool,
+    },
+    /// The last step entered the IRQ exception, while `Interrupt` was pending.
+    Interrupt(Interrupt),
+    /// A DMA transfer started on a channel during the last step.
+    DmaStart(usize),
+    /// The last step entered an exception.
+    Exception(Exception),
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Breakpoint { addr, hits } => {
+                write!(f, "breakpoint at {addr:08x} (hit {hits} times)")
+            }
+            Self::Watchpoint { addr, write, .. } => {
+                let access = if write { "write" } else { "read" };
+                write!(f, "watchpoint hit by {access} of {addr:08x}")
+            }
+            Self::Interrupt(interrupt) => write!(f, "IRQ entered for {interrupt:?} interrupt"),
+            Self::DmaStart(chan_idx) => write!(f, "DMA{chan_idx} transfer started"),
+            Self::Exception(exception) => write!(f, "{exception:?} exception entered"),
+        }
+    }
+}
+
+/// Breakpoints, watchpoints and events that stop `Gba::step`.
+///
+/// When one is hit, `Gba::step` returns early (for breakpoints, before executing the instruction),
+/// and the reason can be retrieved with `take_stop`.
+#[derive(Default, Debug)]
+pub struct Breakpoints {
+    exec: BTreeMap<u32, ExecBreakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    events: Vec<Event>,
+    stop: Option<StopReason>,
+    resume_addr: Option<u32>,
+}
+
+impl Breakpoints {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.exec.is_empty() && self.watchpoints.is_empty() && self.events.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.exec.clear();
+        self.watchpoints.clear();
+        self.events.clear();
+    }
+
+    /// Adds an unconditional breakpoint at `addr`.
+    ///
+    /// Returns false if there was already a breakpoint at `addr`.
+    pub fn add_exec(&mut self, addr: u32) -> bool {
+        if self.exec.contains_key(&addr) {
+            return false;
+        }
+
+        self.exec.insert(addr, ExecBreakpoint::default());
+        true
+    }
+
+    /// Adds a breakpoint at `addr`, returning the one it replaced.
+    pub fn insert_exec(&mut self, addr: u32, breakpoint: ExecBreakpoint) -> Option<ExecBreakpoint> {
+        self.exec.insert(addr, breakpoint)
+    }
+
+    /// Returns false if there was no breakpoint at `addr`.
+    pub fn remove_exec(&mut self, addr: u32) -> bool {
+        self.exec.remove(&addr).is_some()
+    }
+
+    #[must_use]
+    pub fn exec_at(&mut self, addr: u32) -> Option<&mut ExecBreakpoint> {
+        self.exec.get_mut(&addr)
+    }
+
+    pub fn exec(&self) -> impl Iterator<Item = (u32, &ExecBreakpoint)> + '_ {
+        self.exec
+            .iter()
+            .map(|(&addr, breakpoint)| (addr, breakpoint))
+    }
+
+    pub fn add_watch(&mut self, range: RangeInclusive<u32>, kind: WatchKind) {
+        self.watchpoints.push(Watchpoint {
+            range,
+            kind,
+            hits: 0,
+        });
+    }
+
+    /// Returns false if there was no such watchpoint.
+    pub fn remove_watch(&mut self, range: &RangeInclusive<u32>, kind: WatchKind) -> bool {
+        let Some(i) = self
+            .watchpoints
+            .iter()
+            .position(|w| w.range == *range && w.kind == kind)
+        else {
+            return false;
+        };
+        self.watchpoints.remove(i);
+
+        true
+    }
+
+    #[must_use]
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Returns false if already breaking on `event`.
+    pub fn add_event(&mut self, event: Event) -> bool {
+        if self.events.contains(&event) {
+            return false;
+        }
+
+        self.events.push(event);
+        true
+    }
+
+    /// Returns false if not breaking on `event`.
+    pub fn remove_event(&mut self, event: Event) -> bool {
+        let len = self.events.len();
+        self.events.retain(|&e| e != event);
+
+        self.events.len() != len
+    }
+
+    #[must_use]
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Takes the reason for the last stop, if any.
+    pub fn take_stop(&mut self) -> Option<StopReason> {
+        self.stop.take()
+    }
+
+    fn stop(&mut self, reason: StopReason) {
+        if self.stop.is_none() {
+            self.stop = Some(reason);
+        }
+    }
+
+    /// Whether to stop before executing the instruction at `addr` in `state`, evaluating
+    /// conditions against `ctx`.
+    ///
+    /// Resuming from a breakpoint executes its instruction rather than stopping there again.
+    pub(crate) fn check_exec(
+        &mut self,
+        addr: u32,
+        state: OperationState,
+        ctx: &mut impl Context,
+    ) -> bool {
+        if self.resume_addr.take() == Some(addr) {
+            return false;
+        }
+        let Some(breakpoint) = self.exec.get_mut(&addr) else {
+            return false;
+        };
+        if breakpoint.state.map_or(false, |s| s != state)
+            || !breakpoint
+                .condition
+                .as_ref()
+                .map_or(true, |condition| condition.is_true(ctx))
+        {
+            return false;
+        }
+
+        breakpoint.hits = breakpoint.hits.saturating_add(1);
+        if breakpoint.hits <= breakpoint.ignore_count {
+            return false;
+        }
+
+        let hits = breakpoint.hits;
+        self.stop(StopReason::Breakpoint { addr, hits });
+        self.resume_addr = Some(addr);
+        true
+    }
+
+    #[inline]
+    pub(crate) fn is_watching(&self) -> bool {
+        !self.watchpoints.is_empty()
+    }
+
+    #[inline]
+    pub(crate) fn has_events(&self) -> bool {
+        !self.events.is_empty()
+    }
+
+    /// Notifies of a data access; instruction fetches should not be passed here.
+    pub(crate) fn notify_access(&mut self, addr: u32, write: bool) {
+        if let Some(w) = self
+            .watchpoints
+            .iter_mut()
+            .find(|w| w.kind.matches(write) && w.range.contains(&addr))
+        {
+            w.hits = w.hits.saturating_add(1);
+            let kind = w.kind;
+            self.stop(StopReason::Watchpoint { addr, kind, write });
+        }
+    }
+
+    pub(crate) fn notify_exception(&mut self, exception: Exception, irq: &Irq) {
+        let reason = self.events.iter().find_map(|&event| match event {
+            Event::Exception(e) if e == exception => Some(StopReason::Exception(exception)),
+            Event::Interrupt(interrupt)
+                if exception == Exception::Interrupt && irq.is_pending(interrupt) =>
+            {
+                Some(StopReason::Interrupt(interrupt))
+            }
+            _ => None,
+        });
+        if let Some(reason) = reason {
+            self.stop(reason);
+        }
+    }
+
+    pub(crate) fn notify_dma_start(&mut self, chan_idx: usize) {
+        if self.events.contains(&Event::DmaStart(chan_idx)) {
+            self.stop(StopReason::DmaStart(chan_idx));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expr::tests::TestContext, *};
+
+    use crate::bus::Bus;
+
+    fn ctx() -> TestContext {
+        TestContext {
+            r: [0; 16],
+            mem: vec![0; 4],
+        }
+    }
+
+    #[test]
+    fn exec_works() {
+        let mut bps = Breakpoints::new();
+        let mut ctx = ctx();
+        let arm = OperationState::Arm;
+        assert!(bps.add_exec(0x100));
+        assert!(!bps.add_exec(0x100));
+
+        assert!(!bps.check_exec(0x0fc, arm, &mut ctx));
+        assert!(bps.check_exec(0x100, arm, &mut ctx));
+        assert_eq!(
+            bps.take_stop(),
+            Some(StopReason::Breakpoint {
+                addr: 0x100,
+                hits: 1
+            })
+        );
+
+        // Resuming should execute the instruction, but hit it again next time.
+        assert!(!bps.check_exec(0x100, arm, &mut ctx));
+        assert!(!bps.check_exec(0x104, arm, &mut ctx));
+        assert!(bps.check_exec(0x100, arm, &mut ctx));
+        assert_eq!(bps.exec_at(0x100).unwrap().hits, 2);
+
+        assert!(bps.remove_exec(0x100));
+        assert!(bps.is_empty());
+    }
+
+    #[test]
+    fn exec_conditions_work() {
+        let mut bps = Breakpoints::new();
+        let mut ctx = ctx();
+        bps.insert_exec(
+            0x100,
+            ExecBreakpoint {
+                state: Some(OperationState::Thumb),
+                condition: Some("r0 == 2".parse().unwrap()),
+                ignore_count: 1,
+                ..ExecBreakpoint::default()
+            },
+        );
+
+        assert!(!bps.check_exec(0x100, OperationState::Thumb, &mut ctx));
+        ctx.r[0] = 2;
+        assert!(!bps.check_exec(0x100, OperationState::Arm, &mut ctx));
+        // Ignored.
+        assert!(!bps.check_exec(0x100, OperationState::Thumb, &mut ctx));
+        assert!(bps.check_exec(0x100, OperationState::Thumb, &mut ctx));
+        assert_eq!(
+            bps.take_stop(),
+            Some(StopReason::Breakpoint {
+                addr: 0x100,
+                hits: 2
+            })
+        );
+    }
+
+    #[test]
+    fn watch_works() {
+        let mut bps = Breakpoints::new();
+        bps.add_watch(0x0400_0000..=0x0400_0001, WatchKind::Write);
+        bps.add_watch(0x0300_0000..=0x0300_0003, WatchKind::Read);
+
+        bps.notify_access(0x0400_0000, false);
+        bps.notify_access(0x0300_0004, false);
+        assert_eq!(bps.take_stop(), None);
+
+        bps.notify_access(0x0400_0001, true);
+        assert_eq!(
+            bps.take_stop(),
+            Some(StopReason::Watchpoint {
+                addr: 0x0400_0001,
+                kind: WatchKind::Write,
+                write: true
+            })
+        );
+        assert_eq!(bps.watchpoints()[0].hits, 1);
+
+        bps.notify_access(0x0300_0002, false);
+        assert!(matches!(
+            bps.take_stop(),
+            Some(StopReason::Watchpoint { write: false, .. })
+        ));
+
+        assert!(bps.remove_watch(&(0x0300_0000..=0x0300_0003), WatchKind::Read));
+        assert!(!bps.remove_watch(&(0x0300_0000..=0x0300_0003), WatchKind::Read));
+    }
+
+    #[test]
+    fn events_work() {
+        let mut bps = Breakpoints::new();
+        assert!(bps.add_event(Event::Interrupt(Interrupt::VBlank)));
+        assert!(!bps.add_event(Event::Interrupt(Interrupt::VBlank)));
+        assert!(bps.add_event(Event::DmaStart(3)));
+        assert!(bps.add_event(Event::Exception(Exception::SoftwareInterrupt)));
+
+        let mut irq = Irq::new();
+        irq.request(Interrupt::VBlank);
+        bps.notify_exception(Exception::Interrupt, &irq);
+        assert_eq!(bps.take_stop(), None, "VBlank not enabled in IE");
+        irq.write_byte(0x200, 1);
+        bps.notify_exception(Exception::Interrupt, &irq);
+        assert_eq!(
+            bps.take_stop(),
+            Some(StopReason::Interrupt(Interrupt::VBlank))
+        );
+
+        bps.notify_dma_start(2);
+        assert_eq!(bps.take_stop(), None);
+        bps.notify_dma_start(3);
+        assert_eq!(bps.take_stop(), Some(StopReason::DmaStart(3)));
+
+        bps.notify_exception(Exception::SoftwareInterrupt, &irq);
+        assert_eq!(
+            bps.take_stop(),
+            Some(StopReason::Exception(Exception::SoftwareInterrupt))
+        );
+
+        assert!(bps.remove_event(Event::DmaStart(3)));
+        assert!(!bps.remove_event(Event::DmaStart(3)));
+    }
+}
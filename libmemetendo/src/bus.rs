@@ -41,6 +41,11 @@ pub trait Bus {
         self.write_hword(addr.wrapping_add(2), value.bits(16..).try_into().unwrap());
     }
 
+    /// Called by the CPU before a halfword or word load or store from an `addr` not aligned to
+    /// its `size`, which is then forced into alignment.
+    #[inline]
+    fn misaligned_access(&mut self, _addr: u32, _size: u32, _write: bool) {}
+
     #[inline]
     fn prefetch_instr(&mut self, _addr: u32) {}
 }
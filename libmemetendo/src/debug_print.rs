@@ -0,0 +1,270 @@
+use std::fmt::{self, Debug, Formatter};
+
+use intbits::Bits;
+use log::log;
+
+use crate::bus::Bus;
+
+/// Severity of a message, as given by the `mGBA` debug registers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Level {
+    Fatal,
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 7 {
+            0 => Self::Fatal,
+            1 => Self::Error,
+            2 => Self::Warn,
+            3 => Self::Info,
+            _ => Self::Debug,
+        }
+    }
+}
+
+impl From<Level> for log::Level {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Fatal | Level::Error => log::Level::Error,
+            Level::Warn => log::Level::Warn,
+            Level::Info => log::Level::Info,
+            Level::Debug => log::Level::Debug,
+        }
+    }
+}
+
+/// Called with each line of text printed by the program.
+pub type Callback = Box<dyn FnMut(Level, &str)>;
+
+/// A no$gba string output that reads a string from memory, requested by writing its address.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StringRequest {
+    pub addr: u32,
+    pub line_feed: bool,
+}
+
+const NOCASH_ID: &[u8; 16] = b"no$gba memetendo";
+const MGBA_ENABLE_REQUEST: u16 = 0xc0de;
+const MGBA_ENABLE_RESPONSE: u16 = 0x1dea;
+
+/// The debug output ports used by homebrew to print text: the `mGBA` debug registers
+/// (0x4fff600-0x4fff781) and the no$gba message port (0x4fffa00-0x4fffa1f).
+///
+/// Addresses are offsets from 0x4fff000.
+pub struct DebugPrint {
+    callback: Option<Callback>,
+    mgba_enabled: bool,
+    mgba_enable: u16,
+    mgba_string: Box<[u8; 0x100]>,
+    mgba_flags: u16,
+    nocash_line: Vec<u8>,
+    nocash_ptr: u32,
+    string_request: Option<StringRequest>,
+}
+
+impl Default for DebugPrint {
+    fn default() -> Self {
+        Self {
+            callback: None,
+            mgba_enabled: false,
+            mgba_enable: 0,
+            mgba_string: Box::new([0; 0x100]),
+            mgba_flags: 0,
+            nocash_line: Vec::new(),
+            nocash_ptr: 0,
+            string_request: None,
+        }
+    }
+}
+
+impl Debug for DebugPrint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DebugPrint")
+            .field("mgba_enabled", &self.mgba_enabled)
+            .field("nocash_line", &self.nocash_line)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DebugPrint {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the callback to receive printed lines. If `None`, they're sent to the `log` crate.
+    pub fn set_callback(&mut self, callback: Option<Callback>) {
+        self.callback = callback;
+    }
+
+    /// Takes the pending no$gba string output request, if any. The bus should read the
+    /// NUL-terminated string at the address and pass it to `print_nocash_string`.
+    pub fn take_string_request(&mut self) -> Option<StringRequest> {
+        self.string_request.take()
+    }
+
+    /// Prints a string read for a `StringRequest`.
+    ///
+    /// `%parameter%`s (like `%r0%`) are printed as-is, as the port has no access to the CPU.
+    pub fn print_nocash_string(&mut self, s: &[u8], line_feed: bool) {
+        for &c in s {
+            self.print_nocash_char(c);
+        }
+        if line_feed {
+            self.print_nocash_char(b'\n');
+        }
+    }
+
+    fn print_nocash_char(&mut self, c: u8) {
+        if c == b'\n' {
+            let line = String::from_utf8_lossy(&self.nocash_line).into_owned();
+            self.nocash_line.clear();
+            self.print(Level::Info, &line);
+        } else {
+            self.nocash_line.push(c);
+        }
+    }
+
+    fn flush_mgba_string(&mut self) {
+        let len = self
+            .mgba_string
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(self.mgba_string.len());
+        let text = String::from_utf8_lossy(&self.mgba_string[..len]).into_owned();
+        self.mgba_string.fill(0);
+
+        let level = Level::from_bits(self.mgba_flags.bits(..8).try_into().unwrap());
+        for line in text.lines() {
+            self.print(level, line);
+        }
+    }
+
+    fn print(&mut self, level: Level, text: &str) {
+        if let Some(callback) = &mut self.callback {
+            callback(level, text);
+        } else if level == Level::Fatal {
+            log!(level.into(), "fatal: {text}");
+        } else {
+            log!(level.into(), "{text}");
+        }
+    }
+}
+
+impl Bus for DebugPrint {
+    fn read_byte(&mut self, addr: u32) -> u8 {
+        match addr {
+            0x600..=0x6ff if self.mgba_enabled => self.mgba_string[addr as usize - 0x600],
+            0x700 if self.mgba_enabled => self.mgba_flags.bits(..8).try_into().unwrap(),
+            0x701 if self.mgba_enabled => self.mgba_flags.bits(8..).try_into().unwrap(),
+            0x780 if self.mgba_enabled => MGBA_ENABLE_RESPONSE.bits(..8).try_into().unwrap(),
+            0x781 if self.mgba_enabled => MGBA_ENABLE_RESPONSE.bits(8..).try_into().unwrap(),
+            0xa00..=0xa0f => NOCASH_ID[addr as usize - 0xa00],
+            _ => 0xff,
+        }
+    }
+
+    fn write_byte(&mut self, addr: u32, value: u8) {
+        match addr {
+            0x600..=0x6ff if self.mgba_enabled => self.mgba_string[addr as usize - 0x600] = value,
+            0x700 if self.mgba_enabled => self.mgba_flags.set_bits(..8, value.into()),
+            0x701 if self.mgba_enabled => {
+                self.mgba_flags.set_bits(8.., value.into());
+                // Bit 8 sends the string.
+                if value.bit(0) {
+                    self.flush_mgba_string();
+                    self.mgba_flags.set_bit(8, false);
+                }
+            }
+            0x780 => self.mgba_enable.set_bits(..8, value.into()),
+            0x781 => {
+                self.mgba_enable.set_bits(8.., value.into());
+                self.mgba_enabled = self.mgba_enable == MGBA_ENABLE_REQUEST;
+            }
+            // String output (raw), with %parameters, and with %parameters plus a line feed.
+            // Triggered by writing the last byte of the address.
+            0xa10..=0xa1b => {
+                let byte_idx = (addr & 3) as usize * 8;
+                self.nocash_ptr
+                    .set_bits(byte_idx..byte_idx + 8, value.into());
+                if addr & 3 == 3 {
+                    self.string_request = Some(StringRequest {
+                        addr: self.nocash_ptr,
+                        line_feed: addr >> 2 == 0xa18 >> 2,
+                    });
+                }
+            }
+            // Character output.
+            0xa1c => self.print_nocash_char(value),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::bus::Bus as _;
+
+    use super::*;
+
+    #[test]
+    fn debug_print_works() {
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let mut debug_print = DebugPrint::new();
+        let callback_lines = Rc::clone(&lines);
+        debug_print.set_callback(Some(Box::new(move |level, text: &str| {
+            callback_lines.borrow_mut().push((level, text.to_string()));
+        })));
+
+        // mGBA registers do nothing until enabled.
+        debug_print.write_byte(0x600, b'x');
+        debug_print.write_hword(0x700, 0x102);
+        assert_eq!(debug_print.read_hword(0x780), 0xffff);
+        assert!(lines.borrow().is_empty());
+
+        debug_print.write_hword(0x780, 0xc0de);
+        assert_eq!(debug_print.read_hword(0x780), 0x1dea);
+        for (i, &c) in b"hello".iter().enumerate() {
+            debug_print.write_byte(0x600 + u32::try_from(i).unwrap(), c);
+        }
+        debug_print.write_hword(0x700, 0x102);
+        assert_eq!(
+            lines.borrow().last().unwrap(),
+            &(Level::Warn, "hello".into())
+        );
+        assert_eq!(debug_print.read_byte(0x600), 0, "string should be cleared");
+        assert_eq!(
+            debug_print.read_hword(0x700),
+            2,
+            "send bit should be cleared"
+        );
+
+        // no$gba.
+        assert_eq!(debug_print.read_byte(0xa00), b'n');
+        for &c in b"ab\n" {
+            debug_print.write_byte(0xa1c, c);
+        }
+        assert_eq!(lines.borrow().last().unwrap(), &(Level::Info, "ab".into()));
+
+        debug_print.write_word(0xa18, 0x0200_0000);
+        let request = debug_print.take_string_request().unwrap();
+        assert_eq!(request.addr, 0x0200_0000);
+        assert!(request.line_feed);
+        debug_print.print_nocash_string(b"r0=%r0%", request.line_feed);
+        assert_eq!(
+            lines.borrow().last().unwrap(),
+            &(Level::Info, "r0=%r0%".into())
+        );
+
+        debug_print.write_word(0xa10, 0x0300_0000);
+        assert!(!debug_print.take_string_request().unwrap().line_feed);
+        assert!(debug_print.take_string_request().is_none());
+    }
+}
@@ -6,6 +6,7 @@ use strum_macros::FromRepr;
 use crate::{
     bus::{AlignedExt, Bus},
     cart::Cartridge,
+    io_regs::IoRegister,
     irq::{Interrupt, Irq},
 };
 
@@ -72,6 +73,10 @@ impl Dma {
         (1..=2).contains(&chan_idx) && self.0[chan_idx].timing_mode == TimingMode::Special
     }
 
+    fn in_video_capture_mode(&self, chan_idx: usize) -> bool {
+        chan_idx == 3 && self.0[chan_idx].timing_mode == TimingMode::Special
+    }
+
     fn start_transfer(&mut self, chan_idx: usize) {
         let audio_fifo = self.in_audio_fifo_mode(chan_idx);
         let chan = &mut self.0[chan_idx];
@@ -97,7 +102,7 @@ impl Dma {
         cart: &mut Cartridge,
         cycles: u8,
     ) -> Option<impl Fn(&mut B)> {
-        // TODO: proper cycle transfer timings, cart DRQ, special timing modes
+        // TODO: proper cycle transfer timings, cart DRQ
         for chan_idx in 0..self.0.len() {
             if !self.0[chan_idx].enabled || self.0[chan_idx].state == State::None {
                 continue;
@@ -182,6 +187,28 @@ impl Dma {
         None
     }
 
+    /// The channel whose transfer will start on the next call to `step`, if any.
+    #[must_use]
+    pub fn starting_transfer(&self) -> Option<usize> {
+        let chan_idx = self.active_transfer()?;
+
+        (self.0[chan_idx].state == State::StartingTransfer).then_some(chan_idx)
+    }
+
+    /// The channel whose transfer will start or continue on the next call to `step`, if any.
+    #[must_use]
+    pub fn active_transfer(&self) -> Option<usize> {
+        self.0
+            .iter()
+            .position(|chan| chan.enabled && chan.state != State::None)
+    }
+
+    /// The address that channel `chan_idx` will next read from.
+    #[must_use]
+    pub fn src_addr(&self, chan_idx: usize) -> u32 {
+        self.0[chan_idx].src_addr
+    }
+
     #[must_use]
     pub fn transfer_in_progress(&self) -> bool {
         self.0.iter().any(|chan| chan.state != State::None)
@@ -194,6 +221,10 @@ pub enum Event {
     HBlank,
     AudioFifoA,
     AudioFifoB,
+    /// H-Blank of a scanline that DMA3 video capture transfers on (2 to 161).
+    VideoCapture,
+    /// Scanline 162 started, which stops DMA3 video capture.
+    VideoCaptureEnd,
 }
 
 impl Dma {
@@ -202,6 +233,19 @@ impl Dma {
             Event::VBlank => TimingMode::VBlank,
             Event::HBlank => TimingMode::HBlank,
             Event::AudioFifoA | Event::AudioFifoB => TimingMode::Special,
+            Event::VideoCapture => {
+                if self.in_video_capture_mode(3) {
+                    self.start_transfer(3);
+                }
+                return;
+            }
+            Event::VideoCaptureEnd => {
+                if self.in_video_capture_mode(3) {
+                    self.0[3].enabled = false;
+                    self.0[3].state = State::None;
+                }
+                return;
+            }
         };
 
         for chan_idx in 0..self.0.len() {
@@ -227,6 +271,70 @@ impl Dma {
     }
 }
 
+impl AddressControl {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Increment => "increment",
+            Self::Decrement => "decrement",
+            Self::Fixed => "fixed",
+            Self::IncrementAndReload => "increment_reload",
+        }
+    }
+}
+
+impl Dma {
+    pub(crate) fn io_registers(&self) -> Vec<IoRegister> {
+        let mut regs = Vec::new();
+        for (i, chan) in self.0.iter().enumerate() {
+            let names = [
+                ["DMA0SAD", "DMA0DAD", "DMA0CNT_L", "DMA0CNT_H"],
+                ["DMA1SAD", "DMA1DAD", "DMA1CNT_L", "DMA1CNT_H"],
+                ["DMA2SAD", "DMA2DAD", "DMA2CNT_L", "DMA2CNT_H"],
+                ["DMA3SAD", "DMA3DAD", "DMA3CNT_L", "DMA3CNT_H"],
+            ][i];
+            let addr = 0xb0 + 12 * u32::try_from(i).unwrap();
+            let timing = match chan.timing_mode {
+                TimingMode::Immediate => "immediate",
+                TimingMode::VBlank => "vblank",
+                TimingMode::HBlank => "hblank",
+                TimingMode::Special if i == 3 => "video_capture",
+                TimingMode::Special => "sound_fifo",
+            };
+
+            regs.push(
+                IoRegister::new(names[0], addr, 4, chan.initial_src_addr)
+                    .number("addr", chan.initial_src_addr),
+            );
+            regs.push(
+                IoRegister::new(names[1], addr + 4, 4, chan.initial_dst_addr)
+                    .number("addr", chan.initial_dst_addr),
+            );
+            regs.push(
+                IoRegister::new(names[2], addr + 8, 2, chan.initial_blocks)
+                    .number("count", chan.initial_blocks),
+            );
+            regs.push(
+                IoRegister::new(
+                    names[3],
+                    addr + 10,
+                    2,
+                    chan.cached_dmacnt_hi_bits.with_bit(15, chan.enabled).into(),
+                )
+                .named("dst_ctrl", chan.dst_addr_ctrl.name())
+                .named("src_ctrl", chan.src_addr_ctrl.name())
+                .flag("repeat", chan.repeat)
+                .flag("word", chan.transfer_word)
+                .flag("drq", chan.cart_drq)
+                .named("timing", timing)
+                .flag("irq", chan.irq_enabled)
+                .flag("enabled", chan.enabled),
+            );
+        }
+
+        regs
+    }
+}
+
 impl Bus for Dma {
     fn read_byte(&mut self, addr: u32) -> u8 {
         assert!((0xb0..0xe0).contains(&addr), "IO register address OOB");
@@ -300,3 +408,40 @@ impl Bus for Dma {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        arm7tdmi::CYCLES_PER_FRAME,
+        gba::tests::test_gba,
+        timeline::{Event as TimelineEvent, Timeline},
+        util,
+    };
+
+    use super::*;
+
+    #[test]
+    fn video_capture_works() {
+        let mut gba = test_gba(&[]);
+        gba.timeline = Some(Timeline::new());
+
+        // Repeating DMA3 video capture of 4 words per scanline.
+        gba.dma.write_word(0xd4, 0x0300_0000);
+        gba.dma.write_word(0xd8, 0x0300_0100);
+        gba.dma.write_hword(0xdc, 4);
+        gba.dma.write_hword(0xde, 0xb600);
+        while gba.timeline.as_ref().unwrap().cycle() < CYCLES_PER_FRAME.into() {
+            gba.step(
+                &mut util::video::NullCallback,
+                &mut util::audio::NullCallback,
+            );
+        }
+
+        let records = gba.timeline.as_ref().unwrap().records();
+        let count = |event| records.iter().filter(|r| r.event == event).count();
+        assert_eq!(count(TimelineEvent::DmaStart(3)), 160);
+        assert_eq!(count(TimelineEvent::DmaEnd(3)), 160);
+        assert!(!gba.dma.read_hword(0xde).bit(15));
+        assert_eq!(gba.dma.src_addr(3), 0x0300_0000 + 160 * 16);
+    }
+}
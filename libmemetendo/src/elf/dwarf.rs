@@ -0,0 +1,496 @@
+//! Just enough of a DWARF `.debug_line` parser to map code addresses to source lines.
+
+use super::{ParseElfError, SourceLine};
+
+struct Cursor<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ParseElfError> {
+        let bytes = self
+            .buf
+            .get(self.pos..)
+            .and_then(|b| b.get(..len))
+            .ok_or(ParseElfError::Truncated)?;
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ParseElfError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ParseElfError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, ParseElfError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn uleb(&mut self) -> Result<u64, ParseElfError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+
+        Ok(value)
+    }
+
+    fn sleb(&mut self) -> Result<i64, ParseElfError> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            value |= i64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                break;
+            }
+            if shift >= 64 {
+                break;
+            }
+        }
+
+        Ok(value)
+    }
+
+    fn str(&mut self) -> Result<&'a str, ParseElfError> {
+        let rest = self.buf.get(self.pos..).ok_or(ParseElfError::Truncated)?;
+        let len = rest
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or(ParseElfError::Truncated)?;
+        self.pos += len + 1;
+
+        Ok(std::str::from_utf8(&rest[..len]).unwrap_or(""))
+    }
+
+    fn uleb_usize(&mut self) -> Result<usize, ParseElfError> {
+        usize::try_from(self.uleb()?).map_err(|_| ParseElfError::UnsupportedDebugInfo)
+    }
+}
+
+/// String sections that DWARF 5 line program headers may refer to.
+pub(super) struct StrSections<'a> {
+    pub line_str: &'a [u8],
+    pub str: &'a [u8],
+}
+
+fn str_at(section: &[u8], offset: u32) -> Result<&str, ParseElfError> {
+    let mut cursor = Cursor::new(section);
+    cursor.pos = offset as usize;
+    cursor.str()
+}
+
+/// Reads an entry of a DWARF 5 directory or file name table, returning its path and directory
+/// index, if given.
+fn read_entry<'a>(
+    cursor: &mut Cursor<'a>,
+    formats: &[(u64, u64)],
+    strs: &StrSections<'a>,
+) -> Result<(&'a str, Option<usize>), ParseElfError> {
+    let (mut path, mut dir_idx) = ("", None);
+    for &(content, form) in formats {
+        let value = match form {
+            // DW_FORM_string
+            0x08 => Some(cursor.str()?),
+            // DW_FORM_line_strp
+            0x1f => Some(str_at(strs.line_str, cursor.u32()?)?),
+            // DW_FORM_strp
+            0x0e => Some(str_at(strs.str, cursor.u32()?)?),
+            // DW_FORM_udata
+            0x0f => {
+                let value = cursor.uleb_usize()?;
+                if content == 2 {
+                    dir_idx = Some(value);
+                }
+                None
+            }
+            // DW_FORM_data1, DW_FORM_data2
+            0x0b => {
+                let value = cursor.u8()?.into();
+                if content == 2 {
+                    dir_idx = Some(value);
+                }
+                None
+            }
+            0x05 => {
+                let value = cursor.u16()?.into();
+                if content == 2 {
+                    dir_idx = Some(value);
+                }
+                None
+            }
+            // DW_FORM_data4, DW_FORM_data8, DW_FORM_data16
+            0x06 => cursor.bytes(4).map(|_| None)?,
+            0x07 => cursor.bytes(8).map(|_| None)?,
+            0x1e => cursor.bytes(16).map(|_| None)?,
+            // DW_FORM_block
+            0x09 => {
+                let len = cursor.uleb_usize()?;
+                cursor.bytes(len).map(|_| None)?
+            }
+            _ => return Err(ParseElfError::UnsupportedDebugInfo),
+        };
+        // DW_LNCT_path
+        if let (1, Some(value)) = (content, value) {
+            path = value;
+        }
+    }
+
+    Ok((path, dir_idx))
+}
+
+fn join_path(dir: &str, file: &str) -> String {
+    if dir.is_empty() || file.starts_with('/') {
+        file.to_string()
+    } else {
+        format!("{}/{file}", dir.trim_end_matches('/'))
+    }
+}
+
+/// Parses the line number programs in a `.debug_line` section.
+pub(super) fn parse_lines<'a>(
+    debug_line: &'a [u8],
+    strs: &StrSections<'a>,
+) -> Result<Vec<SourceLine>, ParseElfError> {
+    let mut lines = Vec::new();
+    let mut cursor = Cursor::new(debug_line);
+    while !cursor.is_empty() {
+        let unit_len = cursor.u32()?;
+        // 64-bit DWARF.
+        if unit_len >= 0xffff_fff0 {
+            return Err(ParseElfError::UnsupportedDebugInfo);
+        }
+        let unit = Cursor::new(cursor.bytes(unit_len as usize)?);
+        parse_unit(unit, strs, &mut lines)?;
+    }
+
+    Ok(lines)
+}
+
+#[allow(clippy::too_many_lines)]
+fn parse_unit<'a>(
+    mut c: Cursor<'a>,
+    strs: &StrSections<'a>,
+    lines: &mut Vec<SourceLine>,
+) -> Result<(), ParseElfError> {
+    let version = c.u16()?;
+    if !(2..=5).contains(&version) {
+        return Err(ParseElfError::UnsupportedDebugInfo);
+    }
+    if version >= 5 {
+        let (addr_size, _seg_sel_size) = (c.u8()?, c.u8()?);
+        if addr_size != 4 {
+            return Err(ParseElfError::UnsupportedDebugInfo);
+        }
+    }
+    let header_len = c.u32()? as usize;
+    let program_start = c.pos + header_len;
+    let min_instr_len = u32::from(c.u8()?);
+    if version >= 4 {
+        c.u8()?; // maximum_operations_per_instruction (for VLIW)
+    }
+    let default_is_stmt = c.u8()? != 0;
+    #[allow(clippy::cast_possible_wrap)]
+    let line_base = i64::from(c.u8()? as i8);
+    let line_range = c.u8()?;
+    let opcode_base = c.u8()?;
+    if line_range == 0 || opcode_base == 0 {
+        return Err(ParseElfError::UnsupportedDebugInfo);
+    }
+    let standard_opcode_lens = c.bytes(usize::from(opcode_base - 1))?;
+
+    let mut files = Vec::new();
+    if version >= 5 {
+        let read_formats = |c: &mut Cursor| {
+            let count = c.u8()?;
+            (0..count)
+                .map(|_| Ok((c.uleb()?, c.uleb()?)))
+                .collect::<Result<Vec<_>, ParseElfError>>()
+        };
+        // Every entry takes at least a byte, so there can't be more than the header has left.
+        let read_count = |c: &mut Cursor, formats: &[(u64, u64)]| {
+            let count = c.uleb()?;
+            if formats.is_empty() && count != 0 {
+                return Err(ParseElfError::UnsupportedDebugInfo);
+            }
+            if count > program_start.saturating_sub(c.pos) as u64 {
+                return Err(ParseElfError::Truncated);
+            }
+            Ok(count)
+        };
+        let dir_formats = read_formats(&mut c)?;
+        let dir_count = read_count(&mut c, &dir_formats)?;
+        let dirs = (0..dir_count)
+            .map(|_| Ok(read_entry(&mut c, &dir_formats, strs)?.0))
+            .collect::<Result<Vec<_>, ParseElfError>>()?;
+        let file_formats = read_formats(&mut c)?;
+        let file_count = read_count(&mut c, &file_formats)?;
+        for _ in 0..file_count {
+            let (path, dir_idx) = read_entry(&mut c, &file_formats, strs)?;
+            let dir = dir_idx.and_then(|i| dirs.get(i)).copied().unwrap_or("");
+            files.push(join_path(dir, path));
+        }
+    } else {
+        // Directory 0 is the compilation directory, which is only known from .debug_info.
+        let mut dirs = vec![""];
+        loop {
+            let dir = c.str()?;
+            if dir.is_empty() {
+                break;
+            }
+            dirs.push(dir);
+        }
+        // File numbers start from 1.
+        files.push(String::new());
+        loop {
+            let path = c.str()?;
+            if path.is_empty() {
+                break;
+            }
+            let dir_idx = c.uleb_usize()?;
+            c.uleb()?; // modification time
+            c.uleb()?; // length
+            files.push(join_path(dirs.get(dir_idx).copied().unwrap_or(""), path));
+        }
+    }
+
+    c.pos = program_start;
+    let mut addr = 0u32;
+    let mut file = 1;
+    let mut line = 1u32;
+    let mut is_stmt = default_is_stmt;
+    // The last row emitted in this sequence, which covers the addresses up to the next row.
+    let mut prev_row: Option<(u32, usize, u32)> = None;
+
+    let mut emit_row = |addr: u32, file: usize, line: u32, end_sequence: bool| {
+        if let Some((prev_addr, prev_file, prev_line)) = prev_row.take() {
+            if addr > prev_addr {
+                if let Some(path) = files.get(prev_file).filter(|p| !p.is_empty()) {
+                    lines.push(SourceLine {
+                        file: path.clone(),
+                        line: prev_line,
+                        addrs: prev_addr..addr,
+                    });
+                }
+            }
+        }
+        if !end_sequence {
+            prev_row = Some((addr, file, line));
+        }
+    };
+    let advance_line = |line: &mut u32, delta: i64| {
+        *line = u32::try_from(i64::from(*line) + delta).unwrap_or(0);
+    };
+
+    while !c.is_empty() {
+        let opcode = c.u8()?;
+        if opcode >= opcode_base {
+            let adjusted = opcode - opcode_base;
+            addr = addr.wrapping_add(u32::from(adjusted / line_range) * min_instr_len);
+            advance_line(&mut line, line_base + i64::from(adjusted % line_range));
+            if is_stmt {
+                emit_row(addr, file, line, false);
+            }
+            continue;
+        }
+
+        match opcode {
+            // Extended opcodes.
+            0 => {
+                let len = c.uleb_usize()?;
+                let mut ext = Cursor::new(c.bytes(len)?);
+                match ext.u8()? {
+                    // DW_LNE_end_sequence
+                    1 => {
+                        emit_row(addr, file, line, true);
+                        (addr, file, line, is_stmt) = (0, 1, 1, default_is_stmt);
+                    }
+                    // DW_LNE_set_address
+                    2 => addr = ext.u32()?,
+                    _ => {}
+                }
+            }
+            // DW_LNS_copy
+            1 => {
+                if is_stmt {
+                    emit_row(addr, file, line, false);
+                }
+            }
+            // DW_LNS_advance_pc
+            2 => {
+                let delta = u32::try_from(c.uleb()?).unwrap_or(0);
+                addr = addr.wrapping_add(delta * min_instr_len);
+            }
+            // DW_LNS_advance_line
+            3 => advance_line(&mut line, c.sleb()?),
+            // DW_LNS_set_file
+            4 => file = c.uleb_usize()?,
+            // DW_LNS_negate_stmt
+            6 => is_stmt = !is_stmt,
+            // DW_LNS_const_add_pc
+            8 => {
+                let adjusted = 255 - opcode_base;
+                addr = addr.wrapping_add(u32::from(adjusted / line_range) * min_instr_len);
+            }
+            // DW_LNS_fixed_advance_pc
+            9 => addr = addr.wrapping_add(c.u16()?.into()),
+            // Skip the operands of others, like DW_LNS_set_column.
+            _ => {
+                for _ in 0..standard_opcode_lens[usize::from(opcode - 1)] {
+                    c.uleb()?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_lines_works() {
+        let no_strs = StrSections {
+            line_str: &[],
+            str: &[],
+        };
+
+        // DWARF 3: main.c line 3 at 0x08000000, line 5 at 0x08000004, ending at 0x0800000c.
+        let mut unit = vec![3, 0];
+        let mut header = vec![2, 1, 0xfb_u8, 14, 13, 0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+        header.extend(b"src\0\0main.c\0\x01\0\0\0");
+        unit.extend(u32::try_from(header.len()).unwrap().to_le_bytes());
+        unit.extend(header);
+        // set_address 0x08000000
+        unit.extend([0, 5, 2, 0x00, 0x00, 0x00, 0x08]);
+        // advance_line 2
+        unit.extend([3, 2]);
+        // copy
+        unit.push(1);
+        // Special opcode: address += 2 * 2 (min instr len), line += -5 + 7.
+        unit.push(13 + 7 + 14 * 2);
+        // advance_pc 8
+        unit.extend([2, 4]);
+        // end_sequence
+        unit.extend([0, 1, 1]);
+        let mut debug_line = u32::try_from(unit.len()).unwrap().to_le_bytes().to_vec();
+        debug_line.extend(unit);
+
+        let lines = parse_lines(&debug_line, &no_strs).unwrap();
+        assert_eq!(
+            lines,
+            [
+                SourceLine {
+                    file: "src/main.c".into(),
+                    line: 3,
+                    addrs: 0x0800_0000..0x0800_0004,
+                },
+                SourceLine {
+                    file: "src/main.c".into(),
+                    line: 5,
+                    addrs: 0x0800_0004..0x0800_000c,
+                },
+            ]
+        );
+
+        assert_eq!(
+            parse_lines(&debug_line[..debug_line.len() - 1], &no_strs),
+            Err(ParseElfError::Truncated)
+        );
+    }
+
+    /// Builds a DWARF 5 `.debug_line` section with `tables` as its directory and file tables,
+    /// and a short line number program.
+    fn v5_debug_line(tables: &[u8]) -> Vec<u8> {
+        let mut unit = vec![5, 0, 4, 0];
+        let mut header = vec![2, 1, 1, 0xfb_u8, 14, 13, 0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+        header.extend(tables);
+        unit.extend(u32::try_from(header.len()).unwrap().to_le_bytes());
+        unit.extend(header);
+        // set_address 0x03000010
+        unit.extend([0, 5, 2, 0x10, 0x00, 0x00, 0x03]);
+        // set_file 0
+        unit.extend([4, 0]);
+        // advance_line 9
+        unit.extend([3, 9]);
+        // copy
+        unit.push(1);
+        // fixed_advance_pc 6
+        unit.extend([9, 6, 0]);
+        // end_sequence
+        unit.extend([0, 1, 1]);
+        let mut debug_line = u32::try_from(unit.len()).unwrap().to_le_bytes().to_vec();
+        debug_line.extend(unit);
+
+        debug_line
+    }
+
+    #[test]
+    fn parse_lines_v5_works() {
+        let strs = StrSections {
+            line_str: b"/home/me\0game.c\0",
+            str: &[],
+        };
+
+        // Directories: path as line_strp.
+        let mut tables = vec![1, 1, 0x1f, 1, 0, 0, 0, 0];
+        // Files: path as line_strp, directory index as udata.
+        tables.extend([2, 1, 0x1f, 2, 0x0f, 1, 9, 0, 0, 0, 0]);
+        let debug_line = v5_debug_line(&tables);
+
+        assert_eq!(
+            parse_lines(&debug_line, &strs).unwrap(),
+            [SourceLine {
+                file: "/home/me/game.c".into(),
+                line: 10,
+                addrs: 0x0300_0010..0x0300_0016,
+            }]
+        );
+
+        // Huge entry counts, with no entry formats (so entries have no bytes to read) or more
+        // entries than the header has bytes.
+        let huge = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        let mut no_dir_formats = vec![0];
+        no_dir_formats.extend(huge);
+        assert_eq!(
+            parse_lines(&v5_debug_line(&no_dir_formats), &strs),
+            Err(ParseElfError::UnsupportedDebugInfo)
+        );
+        let mut no_file_formats = vec![0, 0, 0];
+        no_file_formats.extend(huge);
+        assert_eq!(
+            parse_lines(&v5_debug_line(&no_file_formats), &strs),
+            Err(ParseElfError::UnsupportedDebugInfo)
+        );
+        let too_many_files = [0, 0, 1, 1, 0x08, 10, b'a', 0];
+        assert_eq!(
+            parse_lines(&v5_debug_line(&too_many_files), &strs),
+            Err(ParseElfError::Truncated)
+        );
+    }
+}
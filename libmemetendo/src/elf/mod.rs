@@ -0,0 +1,475 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    ops::Range,
+    rc::Rc,
+};
+
+use crate::{cart, symbols::Symbols, InvalidRomSize};
+
+mod dwarf;
+
+const CART_ROM_START: u32 = 0x0800_0000;
+const CART_ROM_END: u32 = 0x0a00_0000;
+const EWRAM: Range<u32> = 0x0200_0000..0x0204_0000;
+const IWRAM: Range<u32> = 0x0300_0000..0x0300_8000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseElfError {
+    NotElf,
+    /// The file is an ELF, but not a 32-bit little-endian ARM executable.
+    Unsupported,
+    /// A header or table points outside of the file.
+    Truncated,
+    /// The DWARF debug info uses a version or encoding that isn't supported.
+    UnsupportedDebugInfo,
+    /// A loadable segment isn't in cartridge ROM or work RAM, or runs past the end of the address
+    /// space or of the work RAM it's loaded into.
+    SegmentOutOfBounds,
+}
+
+impl Display for ParseElfError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotElf => write!(f, "not an ELF file"),
+            Self::Unsupported => write!(f, "not a 32-bit little-endian ARM executable"),
+            Self::Truncated => write!(f, "ELF file is truncated"),
+            Self::UnsupportedDebugInfo => write!(f, "unsupported DWARF debug info"),
+            Self::SegmentOutOfBounds => write!(f, "ELF segment doesn't fit in memory"),
+        }
+    }
+}
+
+impl Error for ParseElfError {}
+
+/// A loadable segment, placed at its load (physical) address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub addr: u32,
+    pub data: Vec<u8>,
+    /// May be larger than `data`, in which case the rest is zero-filled (like `.bss`).
+    pub mem_size: u32,
+}
+
+impl Segment {
+    /// The work RAM region (EWRAM or IWRAM) the segment starts in, if any.
+    #[must_use]
+    pub fn work_ram(&self) -> Option<Range<u32>> {
+        [EWRAM, IWRAM].into_iter().find(|r| r.contains(&self.addr))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub addr: u32,
+    /// Empty for sections that don't occupy space in the file, like `.bss`.
+    pub data: Vec<u8>,
+}
+
+/// The code generated for a line of source code. A line may have several of these.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    pub file: String,
+    pub line: u32,
+    pub addrs: Range<u32>,
+}
+
+/// A homebrew executable, as produced by toolchains like `devkitARM`.
+#[derive(Debug, Clone)]
+pub struct Elf {
+    /// Bit 0 is set if the entry point is THUMB code.
+    pub entry: u32,
+    pub segments: Vec<Segment>,
+    pub sections: Vec<Section>,
+    pub symbols: Symbols,
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn bytes(&self, offset: u32, len: u32) -> Result<&[u8], ParseElfError> {
+        let start = usize::try_from(offset).map_err(|_| ParseElfError::Truncated)?;
+        let len = usize::try_from(len).map_err(|_| ParseElfError::Truncated)?;
+        self.0
+            .get(start..)
+            .and_then(|b| b.get(..len))
+            .ok_or(ParseElfError::Truncated)
+    }
+
+    /// The `idx`th entry of a table of `len`-byte entries starting at `offset`.
+    fn entry(&self, offset: u32, idx: u32, len: u16) -> Result<Reader, ParseElfError> {
+        let start = idx
+            .checked_mul(len.into())
+            .and_then(|o| o.checked_add(offset))
+            .ok_or(ParseElfError::Truncated)?;
+
+        Ok(Reader(self.bytes(start, len.into())?))
+    }
+
+    fn u8(&self, offset: u32) -> Result<u8, ParseElfError> {
+        Ok(self.bytes(offset, 1)?[0])
+    }
+
+    fn u16(&self, offset: u32) -> Result<u16, ParseElfError> {
+        let b = self.bytes(offset, 2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&self, offset: u32) -> Result<u32, ParseElfError> {
+        let b = self.bytes(offset, 4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Reads the NUL-terminated string at `idx` in the string table at `offset`.
+    fn str(&self, offset: u32, idx: u32) -> Result<&str, ParseElfError> {
+        let offset = offset.checked_add(idx).ok_or(ParseElfError::Truncated)?;
+        let start = usize::try_from(offset).map_err(|_| ParseElfError::Truncated)?;
+        let b = self.0.get(start..).ok_or(ParseElfError::Truncated)?;
+        let len = b.iter().position(|&b| b == 0).unwrap_or(b.len());
+
+        Ok(std::str::from_utf8(&b[..len]).unwrap_or(""))
+    }
+}
+
+impl Elf {
+    /// Parses the loadable segments, sections and symbol table of an ELF executable.
+    ///
+    /// # Errors
+    /// Returns an error if `buf` is not a 32-bit little-endian ARM executable, or is malformed.
+    pub fn parse(buf: &[u8]) -> Result<Self, ParseElfError> {
+        let r = Reader(buf);
+        if !buf.starts_with(b"\x7fELF") {
+            return Err(ParseElfError::NotElf);
+        }
+        // 32-bit, little-endian, executable, ARM.
+        if r.u8(4)? != 1 || r.u8(5)? != 1 || r.u16(16)? != 2 || r.u16(18)? != 40 {
+            return Err(ParseElfError::Unsupported);
+        }
+
+        let entry = r.u32(24)?;
+        let (prog_headers, prog_header_len, prog_header_count) =
+            (r.u32(28)?, r.u16(42)?, r.u16(44)?);
+        let (section_headers, section_header_len, section_count) =
+            (r.u32(32)?, r.u16(46)?, r.u16(48)?);
+        let section_names_idx = r.u16(50)?;
+
+        let mut segments = Vec::new();
+        for i in 0..prog_header_count {
+            let ph = r.entry(prog_headers, i.into(), prog_header_len)?;
+            // PT_LOAD
+            if ph.u32(0)? != 1 {
+                continue;
+            }
+            let (offset, addr, file_size, mem_size) =
+                (ph.u32(4)?, ph.u32(12)?, ph.u32(16)?, ph.u32(20)?);
+            let segment = Segment {
+                addr,
+                data: r.bytes(offset, file_size)?.to_vec(),
+                mem_size: mem_size.max(file_size),
+            };
+            let end = addr
+                .checked_add(segment.mem_size)
+                .ok_or(ParseElfError::SegmentOutOfBounds)?;
+            let in_bounds = match segment.work_ram() {
+                Some(ram) => end <= ram.end,
+                None => (CART_ROM_START..CART_ROM_END).contains(&addr),
+            };
+            if !in_bounds {
+                return Err(ParseElfError::SegmentOutOfBounds);
+            }
+            segments.push(segment);
+        }
+
+        let section_header = |i: u32| r.entry(section_headers, i, section_header_len);
+        let section_names = if section_names_idx != 0 && section_names_idx < section_count {
+            Some(section_header(section_names_idx.into())?.u32(16)?)
+        } else {
+            None
+        };
+
+        let mut sections = Vec::new();
+        let mut symbols = Symbols::new();
+        for i in 1..u32::from(section_count) {
+            let sh = section_header(i)?;
+            let (name_idx, kind, addr, offset, size, link) = (
+                sh.u32(0)?,
+                sh.u32(4)?,
+                sh.u32(12)?,
+                sh.u32(16)?,
+                sh.u32(20)?,
+                sh.u32(24)?,
+            );
+            let name = match section_names {
+                Some(names) => r.str(names, name_idx)?.to_string(),
+                None => String::new(),
+            };
+            // SHT_NOBITS
+            let data = if kind == 8 {
+                Vec::new()
+            } else {
+                r.bytes(offset, size)?.to_vec()
+            };
+            // SHT_SYMTAB
+            if kind == 2 {
+                let symbol_names = section_header(link)?.u32(16)?;
+                Self::parse_symbols(&r, &data, symbol_names, &mut symbols)?;
+            }
+            sections.push(Section { name, addr, data });
+        }
+
+        Ok(Self {
+            entry,
+            segments,
+            sections,
+            symbols,
+        })
+    }
+
+    fn parse_symbols(
+        r: &Reader,
+        table: &[u8],
+        names: u32,
+        symbols: &mut Symbols,
+    ) -> Result<(), ParseElfError> {
+        for sym in table.chunks_exact(16) {
+            let name_idx = u32::from_le_bytes([sym[0], sym[1], sym[2], sym[3]]);
+            let value = u32::from_le_bytes([sym[4], sym[5], sym[6], sym[7]]);
+            let kind = sym[12] & 0xf;
+            let section_idx = u16::from_le_bytes([sym[14], sym[15]]);
+            // Skip undefined symbols, and section (STT_SECTION) or file (STT_FILE) names.
+            if section_idx == 0 || kind == 3 || kind == 4 {
+                continue;
+            }
+
+            let name = r.str(names, name_idx)?;
+            // ARM mapping symbols ($a, $t, $d) mark the state of code rather than name anything.
+            if name.is_empty() || name.starts_with('$') {
+                continue;
+            }
+            // Functions (STT_FUNC) have bit 0 set if they're THUMB code.
+            let addr = if kind == 2 { value & !1 } else { value };
+            symbols.insert(addr, name);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the section named `name`, like `.debug_line`.
+    #[must_use]
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    /// Reads the source line of the code at each address from the DWARF line number info, if
+    /// the executable was built with debug info (like with `-g`).
+    ///
+    /// # Errors
+    /// Returns an error if the line number info is malformed or unsupported.
+    pub fn source_lines(&self) -> Result<Vec<SourceLine>, ParseElfError> {
+        let Some(debug_line) = self.section(".debug_line") else {
+            return Ok(Vec::new());
+        };
+        let section_data = |name| self.section(name).map_or(&[][..], |s| &s.data[..]);
+
+        dwarf::parse_lines(
+            &debug_line.data,
+            &dwarf::StrSections {
+                line_str: section_data(".debug_line_str"),
+                str: section_data(".debug_str"),
+            },
+        )
+    }
+
+    /// Builds a cartridge ROM image from the segments loaded into cartridge ROM.
+    ///
+    /// The image is empty if there are none, like for multiboot programs that run from work RAM.
+    ///
+    /// # Errors
+    /// Returns an error if the image would exceed the size of cartridge ROM.
+    pub fn rom(&self) -> Result<cart::Rom, InvalidRomSize> {
+        let rom_segments = || {
+            self.segments
+                .iter()
+                .filter(|s| (CART_ROM_START..CART_ROM_END).contains(&s.addr) && !s.data.is_empty())
+        };
+
+        let len = rom_segments()
+            .map(|s| (s.addr - CART_ROM_START) as usize + s.data.len())
+            .max()
+            .unwrap_or(0);
+        if len > (CART_ROM_END - CART_ROM_START) as usize {
+            return Err(InvalidRomSize);
+        }
+
+        let mut buf = vec![0; len];
+        for segment in rom_segments() {
+            let start = (segment.addr - CART_ROM_START) as usize;
+            buf[start..start + segment.data.len()].copy_from_slice(&segment.data);
+        }
+
+        cart::Rom::new(Rc::from(buf))
+    }
+
+    /// Whether the entry point is in cartridge ROM, and so can be booted through the BIOS.
+    #[must_use]
+    pub fn enters_from_rom(&self) -> bool {
+        (CART_ROM_START..CART_ROM_END).contains(&self.entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a minimal ELF with a ROM segment, an IWRAM segment with `.bss`, and symbols.
+    fn test_elf() -> Vec<u8> {
+        let mut buf = vec![0; 0x200];
+        let put16 = |buf: &mut [u8], off: usize, v: u16| {
+            buf[off..off + 2].copy_from_slice(&v.to_le_bytes());
+        };
+        let put32 = |buf: &mut [u8], off: usize, v: u32| {
+            buf[off..off + 4].copy_from_slice(&v.to_le_bytes());
+        };
+
+        buf[..6].copy_from_slice(b"\x7fELF\x01\x01");
+        put16(&mut buf, 16, 2);
+        put16(&mut buf, 18, 40);
+        put32(&mut buf, 24, 0x0800_0001);
+        put32(&mut buf, 28, 0x34);
+        put32(&mut buf, 32, 0x100);
+        put16(&mut buf, 42, 32);
+        put16(&mut buf, 44, 2);
+        put16(&mut buf, 46, 40);
+        put16(&mut buf, 48, 4);
+        put16(&mut buf, 50, 3);
+
+        // ROM segment: 4 bytes at 0x08000000.
+        put32(&mut buf, 0x34, 1);
+        put32(&mut buf, 0x34 + 4, 0x80);
+        put32(&mut buf, 0x34 + 12, 0x0800_0000);
+        put32(&mut buf, 0x34 + 16, 4);
+        put32(&mut buf, 0x34 + 20, 4);
+        buf[0x80..0x84].copy_from_slice(&[0x11, 0x22, 0x33, 0x44]);
+        // IWRAM segment: 2 bytes of data, then 2 of .bss.
+        put32(&mut buf, 0x54, 1);
+        put32(&mut buf, 0x54 + 4, 0x84);
+        put32(&mut buf, 0x54 + 12, 0x0300_0000);
+        put32(&mut buf, 0x54 + 16, 2);
+        put32(&mut buf, 0x54 + 20, 4);
+        buf[0x84..0x86].copy_from_slice(&[0xaa, 0xbb]);
+
+        // .symtab: null, main (THUMB func), $t, undefined, flag (object at an odd address).
+        let symtab = 0x90;
+        put32(&mut buf, symtab + 16, 1);
+        put32(&mut buf, symtab + 16 + 4, 0x0800_0001);
+        buf[symtab + 16 + 12] = 0x12;
+        put16(&mut buf, symtab + 16 + 14, 1);
+        put32(&mut buf, symtab + 32, 6);
+        put32(&mut buf, symtab + 32 + 4, 0x0800_0000);
+        put16(&mut buf, symtab + 32 + 14, 1);
+        put32(&mut buf, symtab + 48, 9);
+        put32(&mut buf, symtab + 48 + 4, 0x1234);
+        put32(&mut buf, symtab + 64, 16);
+        put32(&mut buf, symtab + 64 + 4, 0x0300_0001);
+        buf[symtab + 64 + 12] = 0x11;
+        put16(&mut buf, symtab + 64 + 14, 2);
+        // .strtab
+        let strtab = 0xe0;
+        buf[strtab..strtab + 21].copy_from_slice(b"\0main\0$t\0extern\0flag\0");
+        // .shstrtab
+        let shstrtab = 0x1a0;
+        buf[shstrtab..shstrtab + 27].copy_from_slice(b"\0.symtab\0.strtab\0.shstrtab\0");
+
+        let sections = [
+            (1, 2, symtab, 80, 2),
+            (9, 3, strtab, 21, 0),
+            (17, 3, shstrtab, 27, 0),
+        ];
+        for (i, (name, kind, offset, size, link)) in sections.into_iter().enumerate() {
+            let sh = 0x100 + (i + 1) * 40;
+            put32(&mut buf, sh, name);
+            put32(&mut buf, sh + 4, kind);
+            put32(&mut buf, sh + 16, offset.try_into().unwrap());
+            put32(&mut buf, sh + 20, size);
+            put32(&mut buf, sh + 24, link);
+        }
+
+        buf
+    }
+
+    #[test]
+    fn parse_works() {
+        let elf = Elf::parse(&test_elf()).unwrap();
+        assert_eq!(elf.entry, 0x0800_0001);
+        assert!(elf.enters_from_rom());
+        assert_eq!(elf.segments.len(), 2);
+        assert_eq!(elf.segments[1].addr, 0x0300_0000);
+        assert_eq!(elf.segments[1].data, [0xaa, 0xbb]);
+        assert_eq!(elf.segments[1].mem_size, 4);
+
+        assert_eq!(elf.symbols.len(), 2);
+        assert_eq!(elf.symbols.find("main"), Some(0x0800_0000));
+        assert_eq!(elf.symbols.find("flag"), Some(0x0300_0001));
+        assert!(elf.section(".strtab").is_some());
+
+        assert_eq!(elf.rom().unwrap().bytes(), [0x11, 0x22, 0x33, 0x44]);
+
+        assert_eq!(
+            Elf::parse(b"\x7fELF").unwrap_err(),
+            ParseElfError::Truncated
+        );
+        assert_eq!(Elf::parse(&[0; 64]).unwrap_err(), ParseElfError::NotElf);
+        let mut big_endian = test_elf();
+        big_endian[5] = 2;
+        assert_eq!(
+            Elf::parse(&big_endian).unwrap_err(),
+            ParseElfError::Unsupported
+        );
+    }
+
+    #[test]
+    fn parse_rejects_bad_offsets() {
+        let put32 = |buf: &mut [u8], off: usize, v: u32| {
+            buf[off..off + 4].copy_from_slice(&v.to_le_bytes());
+        };
+
+        // .symtab's sh_link.
+        let mut elf = test_elf();
+        put32(&mut elf, 0x100 + 40 + 24, u32::MAX);
+        assert_eq!(Elf::parse(&elf).unwrap_err(), ParseElfError::Truncated);
+        // e_shoff and e_phoff.
+        for offset in [32, 28] {
+            let mut elf = test_elf();
+            put32(&mut elf, offset, u32::MAX - 8);
+            assert_eq!(Elf::parse(&elf).unwrap_err(), ParseElfError::Truncated);
+        }
+        // A symbol's name.
+        let mut elf = test_elf();
+        put32(&mut elf, 0x90 + 16, u32::MAX);
+        assert_eq!(Elf::parse(&elf).unwrap_err(), ParseElfError::Truncated);
+
+        // The IWRAM segment's p_memsz, overflowing and running past the end of IWRAM.
+        for mem_size in [u32::MAX, 0x8001] {
+            let mut elf = test_elf();
+            put32(&mut elf, 0x54 + 20, mem_size);
+            assert_eq!(
+                Elf::parse(&elf).unwrap_err(),
+                ParseElfError::SegmentOutOfBounds
+            );
+        }
+        let mut elf = test_elf();
+        put32(&mut elf, 0x54 + 20, 0x8000);
+        assert!(Elf::parse(&elf).is_ok());
+
+        // Segments can only be loaded into cartridge ROM and work RAM.
+        for addr in [0x0600_0000, 0x0e00_0000, 0] {
+            let mut elf = test_elf();
+            put32(&mut elf, 0x54 + 12, addr);
+            assert_eq!(
+                Elf::parse(&elf).unwrap_err(),
+                ParseElfError::SegmentOutOfBounds
+            );
+        }
+    }
+}
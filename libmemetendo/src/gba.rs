@@ -1,18 +1,40 @@
+use std::{array, mem::take};
+
 use intbits::Bits;
 
+#[cfg(feature = "jit")]
+use crate::arm7tdmi::jit::Jit;
 use crate::{
-    arm7tdmi::Cpu,
+    arm7tdmi::{
+        history::{Fault, FaultKind, History},
+        reg::PC_INDEX,
+        Cpu,
+    },
     audio::{self, Audio},
     bios::{self, Bios},
+    breakpoint::{expr, Breakpoints},
     bus,
+    bus::Bus as _,
     cart::Cartridge,
+    debug_print::{self, DebugPrint, StringRequest},
     dma::Dma,
-    irq::Irq,
+    elf::Elf,
+    io_regs::IoRegister,
+    irq::{Interrupt, Irq},
     keypad::Keypad,
+    profiler::Profiler,
+    sanitizer::{IssueKind, Sanitizer},
+    symbols::{Location, Symbols},
+    timeline::{Event, Timeline},
     timer::Timers,
-    video::{self, Video},
+    video::{self, Video, HBLANK_DOT, VBLANK_DOT},
 };
 
+/// Every instruction is counted as taking this many cycles, as N, S and I cycles and memory
+/// waitstates aren't emulated. Compiled blocks are counted the same way so that the JIT doesn't
+/// change timing; counting real cycles needs doing for the interpreter and the JIT together.
+const CYCLES_PER_INSTR: u8 = 3;
+
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
 pub enum State {
     #[default]
@@ -49,6 +71,61 @@ impl bus::Bus for HaltControl {
     }
 }
 
+/// Tracks writes to work RAM, so that code compiled from it by the JIT can be invalidated.
+///
+/// Does nothing if the `jit` feature is disabled.
+#[derive(Debug, Default)]
+pub struct CodeWrites {
+    #[cfg(feature = "jit")]
+    dirty_pages: [u64; CodeWrites::PAGE_COUNT / 64],
+}
+
+impl CodeWrites {
+    #[cfg(feature = "jit")]
+    pub const PAGE_LEN: usize = 0x100;
+    #[cfg(feature = "jit")]
+    const PAGE_COUNT: usize = (0x40000 + 0x8000) / Self::PAGE_LEN;
+
+    /// Returns the index of the page of work RAM containing `addr`, accounting for mirrors.
+    // Panic is impossible as the offset always fits.
+    #[allow(clippy::missing_panics_doc)]
+    #[cfg(feature = "jit")]
+    #[must_use]
+    pub fn page(addr: u32) -> Option<usize> {
+        let offset = match addr {
+            0x0200_0000..=0x02ff_ffff => addr & 0x3_ffff,
+            0x0300_0000..=0x03ff_ffff => 0x4_0000 + (addr & 0x7fff),
+            _ => return None,
+        };
+
+        Some(usize::try_from(offset).unwrap() / Self::PAGE_LEN)
+    }
+
+    #[cfg_attr(not(feature = "jit"), allow(unused_variables, clippy::unused_self))]
+    #[inline]
+    pub fn notify(&mut self, addr: u32) {
+        #[cfg(feature = "jit")]
+        if let Some(page) = Self::page(addr) {
+            self.dirty_pages[page / 64] |= 1 << (page % 64);
+        }
+    }
+
+    /// Returns the indices of pages written to since the last call.
+    #[cfg(feature = "jit")]
+    pub fn take_dirty_pages(&mut self) -> impl Iterator<Item = usize> + '_ {
+        self.dirty_pages
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, bits)| **bits != 0)
+            .flat_map(|(i, bits)| {
+                let bits = std::mem::take(bits);
+                (0..64)
+                    .filter(move |&bit| bits.bit(bit))
+                    .map(move |bit| i * 64 + bit)
+            })
+    }
+}
+
 pub struct Gba {
     pub cpu: Cpu,
     pub irq: Irq,
@@ -62,7 +139,20 @@ pub struct Gba {
     pub keypad: Keypad,
     pub bios: Bios,
     pub cart: Cartridge,
+    pub debug_print: DebugPrint,
+    pub breakpoints: Breakpoints,
+    /// Reports suspicious hardware usage if set. Disables the JIT.
+    pub sanitizer: Option<Sanitizer>,
+    /// Attributes cycles to functions if set. Disables the JIT.
+    pub profiler: Option<Profiler>,
+    /// Records hardware events, like interrupts and DMA transfers, if set.
+    pub timeline: Option<Timeline>,
+    /// Names for addresses, used by debuggers and when reporting faults.
+    pub symbols: Symbols,
+    #[cfg(feature = "jit")]
+    pub jit: Option<Jit>,
     io_todo: Box<[u8]>,
+    code_writes: CodeWrites,
 }
 
 impl Gba {
@@ -81,7 +171,16 @@ impl Gba {
             keypad: Keypad::new(),
             bios: Bios::new(bios_rom),
             cart,
+            debug_print: DebugPrint::new(),
+            breakpoints: Breakpoints::new(),
+            sanitizer: None,
+            profiler: None,
+            timeline: None,
+            symbols: Symbols::new(),
+            #[cfg(feature = "jit")]
+            jit: None,
             io_todo: vec![0; 0x801].into_boxed_slice(),
+            code_writes: CodeWrites::default(),
         }
     }
 
@@ -95,6 +194,18 @@ impl Gba {
             self.iwram[0x7e00..].fill(0);
             self.bios.update_protection(0xdc + 8);
         }
+
+        #[cfg(feature = "jit")]
+        if let Some(jit) = &mut self.jit {
+            jit.clear();
+        }
+    }
+
+    /// Whether the next call to `step` will execute an instruction, rather than the CPU being
+    /// halted or stalled by a DMA transfer (breakpoints aside).
+    #[must_use]
+    pub fn is_cpu_running(&self) -> bool {
+        self.haltcnt.0 == State::Running && !self.dma.transfer_in_progress()
     }
 
     pub fn step(
@@ -102,22 +213,382 @@ impl Gba {
         video_cb: &mut impl video::Callback,
         audio_cb: &mut impl audio::Callback,
     ) {
+        let cpu_running = self.is_cpu_running();
+        if cpu_running && !self.breakpoints.is_empty() && self.check_exec_breakpoint() {
+            return;
+        }
+
         self.keypad.step(&mut self.irq);
 
-        if self.haltcnt.0 == State::Running && !self.dma.transfer_in_progress() {
-            self.cpu.step(&mut bus!(self));
+        let pc = self.cpu.next_instr_addr();
+        if let Some(profiler) = &mut self.profiler {
+            let halted = self.haltcnt.0 != State::Running;
+            profiler.sample(pc, halted, self.cpu.history.as_ref(), &self.symbols);
+        }
+
+        let mut instrs = 1;
+        if cpu_running {
+            instrs = self.step_cpu();
+            self.check_halt_change(true);
+        }
+        if let Some(exception) = self.cpu.take_entered_exception() {
+            if self.breakpoints.has_events() {
+                self.breakpoints.notify_exception(exception, &self.irq);
+            }
         }
         if self.haltcnt.0 != State::Stopped {
-            // TODO: actual cycle counting
-            self.video.step(video_cb, &mut self.irq, &mut self.dma, 3);
-            self.timers.step(&mut self.irq, &mut self.audio, 3);
-            if let Some(do_transfer) = self.dma.step(&mut self.irq, &mut self.cart, 3) {
-                do_transfer(&mut bus!(self));
+            // Compiled blocks execute several instructions at once, so catch up on all of them.
+            // They stop before anything would notice that the rest of the system is behind.
+            for _ in 0..instrs {
+                let video_pos = self
+                    .timeline
+                    .as_ref()
+                    .map(|_| (self.video.scanline(), self.video.dot()));
+
+                let cycles = CYCLES_PER_INSTR;
+                self.video
+                    .step(video_cb, &mut self.irq, &mut self.dma, cycles);
+                self.timers.step(&mut self.irq, &mut self.audio, cycles);
+                if self.breakpoints.has_events() || self.sanitizer.is_some() {
+                    self.check_dma_start();
+                }
+                let dma_chan_idx = video_pos.and_then(|_| self.dma.active_transfer());
+                if let Some(do_transfer) = self.dma.step(&mut self.irq, &mut self.cart, cycles) {
+                    do_transfer(&mut bus!(self));
+                }
+                self.audio.step(audio_cb, &mut self.dma, cycles);
+
+                if let Some(video_pos) = video_pos {
+                    self.record_timeline_events(video_pos, dma_chan_idx);
+                }
+            }
+            if let Some(profiler) = &mut self.profiler {
+                profiler.add_cycles(u32::from(CYCLES_PER_INSTR) * instrs, self.video.scanline());
             }
-            self.audio.step(audio_cb, &mut self.dma, 3);
         }
 
+        let running = self.haltcnt.0 == State::Running;
         self.irq.step(&mut self.cpu, &mut self.haltcnt);
+        self.check_halt_change(running);
+        if let Some(sanitizer) = &mut self.sanitizer {
+            sanitizer.flush(pc, self.cpu.history.as_ref());
+        }
+    }
+
+    fn check_dma_start(&mut self) {
+        let Some(chan_idx) = self.dma.starting_transfer() else {
+            return;
+        };
+        if self.breakpoints.has_events() {
+            self.breakpoints.notify_dma_start(chan_idx);
+        }
+        if let Some(sanitizer) = &mut self.sanitizer {
+            sanitizer.check_dma(chan_idx, self.dma.src_addr(chan_idx));
+        }
+    }
+
+    /// Records the events during the last instruction to the timeline, given the position of the
+    /// video hardware and the DMA channel that transferred during it.
+    fn record_timeline_events(&mut self, video_pos: (u8, u16), dma_chan_idx: Option<usize>) {
+        let Some(timeline) = &mut self.timeline else {
+            return;
+        };
+
+        let requested = self.irq.take_requested();
+        let acknowledged = self.irq.take_acknowledged();
+        for interrupt in (0..).map_while(Interrupt::from_repr) {
+            if acknowledged.bit(interrupt as usize) {
+                timeline.record(Event::IrqAcknowledge(interrupt));
+            }
+            if requested.bit(interrupt as usize) {
+                timeline.record(Event::IrqRequest(interrupt));
+            }
+        }
+
+        let (scanline, dot) = (self.video.scanline(), self.video.dot());
+        if video_pos.1 < HBLANK_DOT.into() && dot >= HBLANK_DOT.into() {
+            timeline.record(Event::HBlank);
+        }
+        if video_pos.0 != scanline {
+            if scanline == VBLANK_DOT {
+                timeline.record(Event::VBlank);
+            }
+            if scanline == self.video.vcount_target() {
+                timeline.record(Event::VCount);
+            }
+        }
+
+        for timer_idx in 0..4 {
+            if self.timers.take_overflowed(timer_idx) {
+                timeline.record(Event::TimerOverflow(timer_idx));
+            }
+        }
+        // The transfer also ends once its channel finishes or is preempted.
+        timeline.set_dma_transfer(dma_chan_idx);
+        if dma_chan_idx.is_some() && self.dma.active_transfer() != dma_chan_idx {
+            timeline.set_dma_transfer(None);
+        }
+        for (fifo_idx, requested) in self
+            .audio
+            .take_fifo_refill_requests()
+            .into_iter()
+            .enumerate()
+        {
+            if requested {
+                timeline.record(Event::FifoRefill(fifo_idx));
+            }
+        }
+
+        timeline.add_cycles(CYCLES_PER_INSTR.into());
+    }
+
+    /// Records a halt or wake to the timeline if the CPU is no longer in the state given by
+    /// `was_running`.
+    fn check_halt_change(&mut self, was_running: bool) {
+        let running = self.haltcnt.0 == State::Running;
+        if let Some(timeline) = self.timeline.as_mut().filter(|_| running != was_running) {
+            timeline.record(if running { Event::Wake } else { Event::Halt });
+        }
+    }
+
+    fn check_exec_breakpoint(&mut self) -> bool {
+        let addr = self.cpu.next_instr_addr();
+        let state = self.cpu.reg.cpsr().state();
+
+        // Conditions need to read memory through self.
+        let mut breakpoints = take(&mut self.breakpoints);
+        let hit = breakpoints.check_exec(addr, state, self);
+        self.breakpoints = breakpoints;
+
+        hit
+    }
+
+    /// Executes the next instruction, or the next block of instructions if the JIT is enabled.
+    /// Returns the number of instructions executed.
+    fn step_cpu(&mut self) -> u32 {
+        if self.cpu.history.is_some() {
+            let addr = self.cpu.next_instr_addr();
+            if !self.is_executable(addr) {
+                let history = self.cpu.history.as_mut().unwrap();
+                history.record_fault(FaultKind::InvalidRegion, addr);
+            }
+        }
+
+        // Compiled blocks don't check for breakpoints between instructions, nor attribute sanitizer
+        // issues or cycles to them.
+        #[cfg(feature = "jit")]
+        if let Some(jit) = self.jit.as_mut().filter(|_| {
+            self.breakpoints.is_empty() && self.sanitizer.is_none() && self.profiler.is_none()
+        }) {
+            jit.invalidate(&mut self.code_writes);
+            // The block may run up to and including the instruction during which the next event
+            // happens, as the interpreter would only notice it afterwards.
+            let cycles = self
+                .video
+                .cycles_until_event()
+                .min(self.timers.cycles_until_overflow().unwrap_or(u32::MAX));
+            let cycles_per_instr = u32::from(CYCLES_PER_INSTR);
+            let max_instrs = (cycles + cycles_per_instr - 1) / cycles_per_instr;
+            if let Some(instrs) = jit.step(&mut self.cpu, &mut bus!(self), max_instrs) {
+                return instrs;
+            }
+        }
+
+        self.cpu.step(&mut bus!(self));
+        1
+    }
+
+    /// Whether code at `addr` is backed by something sensible to execute from.
+    fn is_executable(&self, addr: u32) -> bool {
+        match addr >> 24 {
+            0x00 => addr < 0x4000,
+            0x02 | 0x03 | 0x05..=0x07 => true,
+            0x08..=0x0d => (addr & 0x01ff_ffff) < self.cart.rom().bytes().len().try_into().unwrap(),
+            _ => false,
+        }
+    }
+
+    /// Sets the address of the next instruction to execute.
+    pub fn set_pc(&mut self, addr: u32) {
+        self.cpu.reg.r[PC_INDEX] = addr;
+        self.cpu.reload_pipeline(&mut bus!(self));
+    }
+
+    /// Loads the segments of `elf` outside of cartridge ROM into work RAM, and adds its symbols.
+    /// The cartridge ROM should already be built from `Elf::rom`.
+    ///
+    /// If `jump_to_entry`, the CPU is set to start executing from the entry point, which should
+    /// be done after a reset that skipped the BIOS.
+    ///
+    /// `Elf::parse` rejects segments outside of cartridge ROM and work RAM, and those that don't
+    /// fit in the work RAM they start in; any such segments of an `Elf` built otherwise are
+    /// skipped or cut short.
+    #[allow(clippy::similar_names)]
+    pub fn load_elf(&mut self, elf: &Elf, jump_to_entry: bool) {
+        for segment in &elf.segments {
+            let Some(ram) = segment.work_ram() else {
+                continue;
+            };
+            let bss = (segment.data.len()..segment.mem_size as usize).map(|_| 0);
+            for (addr, byte) in (segment.addr..ram.end).zip(segment.data.iter().copied().chain(bss))
+            {
+                self.debug_write_byte(addr, byte);
+            }
+        }
+        self.symbols.extend(elf.symbols.clone());
+
+        if jump_to_entry {
+            let cpsr = self.cpu.reg.cpsr().bits();
+            self.cpu.reg.set_cpsr(cpsr.with_bit(5, elf.entry.bit(0)));
+            self.set_pc(elf.entry & !1);
+        }
+    }
+
+    /// Sets the callback to receive text printed by the program through the `mGBA` or no$gba debug
+    /// output ports. By default, it's sent to the `log` crate.
+    pub fn set_debug_print_callback(
+        &mut self,
+        callback: impl FnMut(debug_print::Level, &str) + 'static,
+    ) {
+        self.debug_print.set_callback(Some(Box::new(callback)));
+    }
+
+    /// Returns the symbol containing `addr`, if any.
+    #[must_use]
+    pub fn symbol_at(&self, addr: u32) -> Option<Location> {
+        self.symbols.lookup(addr)
+    }
+
+    /// Returns the IO registers with their fields decoded, in address order.
+    ///
+    /// `FIFO_A` and `FIFO_B` aren't included, as they queue samples rather than hold a value.
+    #[must_use]
+    pub fn io_registers(&self) -> Vec<IoRegister> {
+        // WAITCNT and POSTFLG aren't emulated yet, so they're as last written.
+        let waitcnt = u16::from_le_bytes([self.io_todo[0x204], self.io_todo[0x205]]);
+        let postflg = self.io_todo[0x300];
+        let mut regs = self.video.io_registers();
+        regs.extend(self.audio.io_registers());
+        regs.extend(self.dma.io_registers());
+        regs.extend(self.timers.io_registers());
+        regs.extend(self.keypad.io_registers());
+        regs.extend(self.irq.io_registers());
+        regs.push(
+            IoRegister::new("WAITCNT", 0x204, 2, waitcnt.into())
+                .number("sram", waitcnt.bits(..2))
+                .number("ws0_first", waitcnt.bits(2..4))
+                .number("ws0_second", waitcnt.bits(4..5))
+                .number("ws1_first", waitcnt.bits(5..7))
+                .number("ws1_second", waitcnt.bits(7..8))
+                .number("ws2_first", waitcnt.bits(8..10))
+                .number("ws2_second", waitcnt.bits(10..11))
+                .number("phi", waitcnt.bits(11..13))
+                .flag("prefetch", waitcnt.bit(14)),
+        );
+        regs.push(
+            IoRegister::new("POSTFLG", 0x300, 1, postflg.into()).flag("booted", postflg.bit(0)),
+        );
+        let (haltcnt, state) = match self.haltcnt.0 {
+            State::Running => (0, "running"),
+            State::Halted => (0, "halted"),
+            State::Stopped => (0x80, "stopped"),
+        };
+        regs.push(IoRegister::new("HALTCNT", 0x301, 1, haltcnt).named("state", state));
+        regs.sort_by_key(|reg| reg.addr);
+
+        regs
+    }
+
+    /// Returns the IO register named `name` (like "DISPCNT"), with its fields decoded.
+    #[must_use]
+    pub fn io_register(&self, name: &str) -> Option<IoRegister> {
+        self.io_registers().into_iter().find(|reg| reg.name == name)
+    }
+
+    /// Reads a byte for a debugger, without side effects, hitting watchpoints or being sanitized.
+    ///
+    /// Returns `None` for memory that cannot be read without side effects, such as the cartridge
+    /// backup.
+    #[must_use]
+    pub fn debug_read_byte(&mut self, addr: u32) -> Option<u8> {
+        let mut no_breakpoints = Breakpoints::new();
+        match addr >> 24 {
+            // Ignore BIOS read protection.
+            0x00 => self
+                .bios
+                .rom()
+                .bytes()
+                .get(usize::try_from(addr).ok()?)
+                .copied(),
+            0x02..=0x07 => Some(bus!(self, &mut no_breakpoints, &mut None).read_byte(addr)),
+            0x08..=0x0d => {
+                let offset = usize::try_from(addr & 0x01ff_ffff).ok()?;
+                self.cart.rom().bytes().get(offset).copied()
+            }
+            _ => None,
+        }
+    }
+
+    /// Writes a byte for a debugger, without side effects, hitting watchpoints or being sanitized.
+    ///
+    /// Returns false for memory that cannot be written without side effects, such as IO.
+    pub fn debug_write_byte(&mut self, addr: u32, value: u8) -> bool {
+        let mut no_breakpoints = Breakpoints::new();
+        let mut bus = bus!(self, &mut no_breakpoints, &mut None);
+        match addr >> 24 {
+            0x02 | 0x03 => bus.write_byte(addr, value),
+            // 8-bit writes to video memory act weird, so write a whole hword.
+            0x05..=0x07 => {
+                let [lo, hi] = bus.read_hword(addr & !1).to_le_bytes();
+                let hword = if addr.bit(0) {
+                    [lo, value]
+                } else {
+                    [value, hi]
+                };
+                bus.write_hword(addr & !1, u16::from_le_bytes(hword));
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// The CPU's call stack and branch history, if tracking was enabled by setting
+    /// `Cpu::history`.
+    #[must_use]
+    pub fn history(&self) -> Option<&History> {
+        self.cpu.history.as_ref()
+    }
+
+    /// Takes the first fault (like executing from an invalid region) recorded by the CPU's
+    /// history since the last call.
+    pub fn take_fault(&mut self) -> Option<Fault> {
+        self.cpu.history.as_mut().and_then(History::take_fault)
+    }
+}
+
+impl expr::Context for Gba {
+    fn reg(&self, index: usize) -> u32 {
+        if index == PC_INDEX {
+            self.cpu.next_instr_addr()
+        } else {
+            self.cpu.reg.r[index]
+        }
+    }
+
+    fn cpsr(&self) -> u32 {
+        self.cpu.reg.cpsr().bits()
+    }
+
+    fn spsr(&self) -> u32 {
+        self.cpu.reg.spsr()
+    }
+
+    fn read(&mut self, addr: u32, size: u32) -> Option<u32> {
+        (0..size).try_fold(0, |value, i| {
+            let byte = self.debug_read_byte(addr.wrapping_add(i))?;
+            Some(value | u32::from(byte) << (8 * i))
+        })
     }
 }
 
@@ -133,33 +604,48 @@ pub struct Bus<'a> {
     pub keypad: &'a mut Keypad,
     pub bios: &'a mut Bios,
     pub cart: &'a mut Cartridge,
+    pub debug_print: &'a mut DebugPrint,
     pub io_todo: &'a mut Box<[u8]>,
+    pub code_writes: &'a mut CodeWrites,
+    pub breakpoints: &'a mut Breakpoints,
+    pub sanitizer: &'a mut Option<Sanitizer>,
+    /// Set by `prefetch_instr`, so that the instruction fetch that follows doesn't hit
+    /// watchpoints.
+    pub fetching: bool,
 }
 
-// A member fn would be nicer, but using &mut self over $gba unnecessarily mutably borrows the
-// *whole* Gba struct.
-#[macro_export]
-macro_rules! bus {
-    ($gba:ident) => {{
-        $crate::gba::Bus {
-            irq: &mut $gba.irq,
-            haltcnt: &mut $gba.haltcnt,
-            timers: &mut $gba.timers,
-            dma: &mut $gba.dma,
-            iwram: &mut $gba.iwram,
-            ewram: &mut $gba.ewram,
-            video: &mut $gba.video,
-            audio: &mut $gba.audio,
-            keypad: &mut $gba.keypad,
-            cart: &mut $gba.cart,
-            bios: &mut $gba.bios,
-            io_todo: &mut $gba.io_todo,
+impl Bus<'_> {
+    fn sanitize(&mut self, kind: IssueKind, addr: u32) {
+        if let Some(sanitizer) = self.sanitizer.as_mut() {
+            sanitizer.record(kind, addr);
         }
-    }};
-}
+    }
 
-impl bus::Bus for Bus<'_> {
-    fn read_byte(&mut self, addr: u32) -> u8 {
+    fn print_nocash_string(&mut self, request: StringRequest) {
+        let mut s = Vec::new();
+        for addr in (request.addr..).take(0x1000) {
+            match self.read_byte(addr) {
+                0 => break,
+                c => s.push(c),
+            }
+        }
+        self.debug_print.print_nocash_string(&s, request.line_feed);
+    }
+
+    /// Reads the `N` bytes at `addr`, notifying watchpoints unless they're an instruction fetch.
+    fn read_bytes<const N: usize>(&mut self, addr: u32) -> [u8; N] {
+        let watch = self.breakpoints.is_watching() && !take(&mut self.fetching);
+
+        array::from_fn(|i| {
+            let addr = addr.wrapping_add(i.try_into().unwrap());
+            if watch {
+                self.breakpoints.notify_access(addr, false);
+            }
+            self.read_byte_unwatched(addr)
+        })
+    }
+
+    fn read_byte_unwatched(&mut self, addr: u32) -> u8 {
         match addr {
             // BIOS
             0x0000_0000..=0x0000_3fff => self.bios.read_byte(addr),
@@ -179,7 +665,13 @@ impl bus::Bus for Bus<'_> {
                     0x130..=0x133 => self.keypad.read_byte(addr),
                     0x200..=0x203 | 0x208..=0x20b => self.irq.read_byte(addr),
                     0x301 => self.haltcnt.read_byte(addr),
-                    0x000..=0x800 => self.io_todo[usize::try_from(addr).unwrap()], // TODO
+                    0x000..=0x800 => {
+                        self.sanitize(
+                            IssueKind::UnimplementedIo { write: false },
+                            addr | 0x0400_0000,
+                        );
+                        self.io_todo[usize::try_from(addr).unwrap()] // TODO
+                    }
                     _ => 0,
                 }
             }
@@ -191,20 +683,87 @@ impl bus::Bus for Bus<'_> {
             0x0700_0000..=0x07ff_ffff => self.video.oam.read_byte(addr & 0x3ff),
             // Cartridge
             0x0800_0000..=0x0fff_ffff => self.cart.read_byte(addr & 0x7ff_ffff),
+            // mGBA and no$gba debug output
+            0x04ff_f600..=0x04ff_f781 | 0x04ff_fa00..=0x04ff_fa1f => {
+                self.debug_print.read_byte(addr & 0xfff)
+            }
             // Unused
-            _ => 0xff,
+            _ => {
+                self.sanitize(IssueKind::UnmappedRead, addr);
+                0xff
+            }
         }
     }
+}
+
+// A member fn would be nicer, but using &mut self over $gba unnecessarily mutably borrows the
+// *whole* Gba struct.
+#[macro_export]
+macro_rules! bus {
+    ($gba:ident) => {
+        bus!($gba, &mut $gba.breakpoints, &mut $gba.sanitizer)
+    };
+
+    ($gba:ident, $breakpoints:expr, $sanitizer:expr) => {{
+        $crate::gba::Bus {
+            irq: &mut $gba.irq,
+            haltcnt: &mut $gba.haltcnt,
+            timers: &mut $gba.timers,
+            dma: &mut $gba.dma,
+            iwram: &mut $gba.iwram,
+            ewram: &mut $gba.ewram,
+            video: &mut $gba.video,
+            audio: &mut $gba.audio,
+            keypad: &mut $gba.keypad,
+            cart: &mut $gba.cart,
+            bios: &mut $gba.bios,
+            debug_print: &mut $gba.debug_print,
+            io_todo: &mut $gba.io_todo,
+            code_writes: &mut $gba.code_writes,
+            breakpoints: $breakpoints,
+            sanitizer: $sanitizer,
+            fetching: false,
+        }
+    }};
+}
+
+impl bus::Bus for Bus<'_> {
+    fn read_byte(&mut self, addr: u32) -> u8 {
+        let [value] = self.read_bytes(addr);
+        value
+    }
+
+    fn read_hword(&mut self, addr: u32) -> u16 {
+        u16::from_le_bytes(self.read_bytes(addr))
+    }
+
+    fn read_word(&mut self, addr: u32) -> u32 {
+        u32::from_le_bytes(self.read_bytes(addr))
+    }
 
     fn write_byte(&mut self, addr: u32, value: u8) {
+        if self.breakpoints.is_watching() {
+            self.breakpoints.notify_access(addr, true);
+        }
+
         match addr {
             // External WRAM
-            0x0200_0000..=0x02ff_ffff => self.ewram.write_byte(addr & 0x3_ffff, value),
+            0x0200_0000..=0x02ff_ffff => {
+                self.ewram.write_byte(addr & 0x3_ffff, value);
+                self.code_writes.notify(addr);
+            }
             // Internal WRAM
-            0x0300_0000..=0x03ff_ffff => self.iwram.write_byte(addr & 0x7fff, value),
+            0x0300_0000..=0x03ff_ffff => {
+                self.iwram.write_byte(addr & 0x7fff, value);
+                self.code_writes.notify(addr);
+            }
             // I/O Registers
             0x0400_0000..=0x0400_03fe => {
                 let addr = addr & 0x3ff;
+                // VCOUNT and KEYINPUT.
+                if matches!(addr, 0x006 | 0x007 | 0x130 | 0x131) {
+                    self.sanitize(IssueKind::ReadOnlyIoWrite, addr | 0x0400_0000);
+                }
                 #[allow(clippy::match_overlapping_arm)]
                 match addr {
                     0x000..=0x056 => self.video.write_byte(addr, value),
@@ -214,19 +773,48 @@ impl bus::Bus for Bus<'_> {
                     0x130..=0x133 => self.keypad.write_byte(addr, value),
                     0x200..=0x203 | 0x208..=0x20b => self.irq.write_byte(addr, value),
                     0x301 => self.haltcnt.write_byte(addr, value),
-                    0x000..=0x800 => self.io_todo[usize::try_from(addr).unwrap()] = value, // TODO
+                    0x000..=0x800 => {
+                        self.sanitize(
+                            IssueKind::UnimplementedIo { write: true },
+                            addr | 0x0400_0000,
+                        );
+                        self.io_todo[usize::try_from(addr).unwrap()] = value; // TODO
+                    }
                     _ => {}
                 }
             }
             // Palette RAM
-            0x0500_0000..=0x05ff_ffff => self.video.palette_ram.write_byte(addr & 0x3ff, value),
+            0x0500_0000..=0x05ff_ffff => {
+                self.video.sync_line();
+                self.video.palette_ram.write_byte(addr & 0x3ff, value);
+            }
             // VRAM
             0x0600_0000..=0x06ff_ffff => {
+                if self.video.vram().is_obj(addr & 0x1_ffff) {
+                    self.sanitize(IssueKind::ObjByteWrite, addr);
+                }
                 self.video.vram().write_byte(addr & 0x1_ffff, value);
             }
             // Cartridge
-            0x0800_0000..=0x0fff_ffff => self.cart.write_byte(addr & 0x7ff_ffff, value),
-            // Read-only, Unused, Ignored 8-bit writes to OAM/VRAM
+            0x0800_0000..=0x0fff_ffff => {
+                // Allow writes to the RTC's GPIO port and the EEPROM.
+                if addr < 0x0d00_0000 && !(0x0800_00c4..=0x0800_00c9).contains(&addr) {
+                    self.sanitize(IssueKind::RomWrite, addr);
+                }
+                self.cart.write_byte(addr & 0x7ff_ffff, value);
+            }
+            // mGBA and no$gba debug output
+            0x04ff_f600..=0x04ff_f781 | 0x04ff_fa00..=0x04ff_fa1f => {
+                self.debug_print.write_byte(addr & 0xfff, value);
+                if let Some(request) = self.debug_print.take_string_request() {
+                    self.print_nocash_string(request);
+                }
+            }
+            // BIOS (read-only)
+            0x0000_0000..=0x0000_3fff => self.sanitize(IssueKind::BiosWrite, addr),
+            // Ignored 8-bit writes to OAM
+            0x0700_0000..=0x07ff_ffff => self.sanitize(IssueKind::ObjByteWrite, addr),
+            // Unused
             _ => {}
         }
     }
@@ -234,18 +822,169 @@ impl bus::Bus for Bus<'_> {
     fn write_hword(&mut self, addr: u32, value: u16) {
         // Video memory has weird behaviour when writing 8-bit values, so we can't simply delegate
         // such writes to write_hword_as_bytes.
+        if (0x0500_0000..=0x07ff_ffff).contains(&addr) && self.breakpoints.is_watching() {
+            self.breakpoints.notify_access(addr, true);
+            self.breakpoints.notify_access(addr.wrapping_add(1), true);
+        }
+
         match addr {
             // Palette RAM
-            0x0500_0000..=0x05ff_ffff => self.video.palette_ram.write_hword(addr & 0x3ff, value),
+            0x0500_0000..=0x05ff_ffff => {
+                self.video.sync_line();
+                self.video.palette_ram.write_hword(addr & 0x3ff, value);
+            }
             // VRAM
             0x0600_0000..=0x06ff_ffff => self.video.vram().write_hword(addr & 0x1_ffff, value),
             // OAM
-            0x0700_0000..=0x07ff_ffff => self.video.oam.write_hword(addr & 0x3ff, value),
+            0x0700_0000..=0x07ff_ffff => {
+                self.video.sync_line();
+                self.video.oam.write_hword(addr & 0x3ff, value);
+            }
             _ => bus::write_hword_as_bytes(self, addr, value),
         }
     }
 
+    fn misaligned_access(&mut self, addr: u32, size: u32, write: bool) {
+        let kind = if write {
+            IssueKind::MisalignedStore { size }
+        } else {
+            IssueKind::MisalignedLoad { size }
+        };
+        self.sanitize(kind, addr);
+    }
+
     fn prefetch_instr(&mut self, addr: u32) {
+        self.fetching = true;
         self.bios.update_protection(addr);
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        bios,
+        breakpoint::{StopReason, WatchKind},
+        cart::{self, BackupType, Cartridge},
+        util,
+    };
+
+    use super::Gba;
+
+    /// A `Gba` with a blank BIOS and the ARM `program` as its cartridge ROM.
+    pub fn test_gba(program: &[u32]) -> Gba {
+        let bios_rom = bios::Rom::new(Rc::from(vec![0; 0x4000])).unwrap();
+        let rom = program.iter().flat_map(|instr| instr.to_le_bytes());
+        let cart_rom = cart::Rom::new(rom.collect()).unwrap();
+
+        Gba::new(bios_rom, Cartridge::new(cart_rom, BackupType::None))
+    }
+
+    #[cfg(feature = "jit")]
+    #[test]
+    fn jit_sees_same_hardware_state_as_interpreter() {
+        let run = |jit| {
+            let mut gba = test_gba(&[
+                0xe3a0_0301, // MOV R0,#0x4000000
+                0xe3a0_1403, // MOV R1,#0x3000000
+                0xe280_5c01, // ADD R5,R0,#0x100
+                0xe3a0_2080, // MOV R2,#0x80
+                0xe1c5_20b2, // STRH R2,[R5,#2] (start timer 0)
+                0xe284_4001, // ADD R4,R4,#1
+                0xe284_4001, // ADD R4,R4,#1
+                0xe1d5_20b0, // LDRH R2,[R5] (TM0CNT_L)
+                0xe0c1_20b2, // STRH R2,[R1],#2
+                0xe1d0_30b6, // LDRH R3,[R0,#6] (VCOUNT)
+                0xe0c1_30b2, // STRH R3,[R1],#2
+                0xeaff_fff8, // B 0x8000014
+            ]);
+            gba.reset(true);
+            if jit {
+                gba.jit = Some(crate::arm7tdmi::jit::Jit::new().unwrap());
+            }
+            while gba.cpu.reg.r[1] < 0x0300_4000 {
+                gba.step(
+                    &mut util::video::NullCallback,
+                    &mut util::audio::NullCallback,
+                );
+            }
+
+            gba.iwram[..0x4000].to_vec()
+        };
+
+        assert!(run(false) == run(true), "JIT read different values");
+    }
+
+    #[cfg(feature = "jit")]
+    #[test]
+    fn jit_records_same_history_as_interpreter() {
+        let run = |jit| {
+            let mut gba = test_gba(&[
+                0xe3a0_0000, // MOV R0,#0
+                0xeb00_0003, // BL 0x8000018
+                0xe280_0001, // ADD R0,R0,#1
+                0xe350_000a, // CMP R0,#10
+                0x1aff_fffb, // BNE 0x8000004
+                0xeaff_fffe, // B 0x8000014
+                0xe281_1001, // ADD R1,R1,#1
+                0xe1a0_f00e, // MOV PC,LR
+            ]);
+            gba.reset(true);
+            gba.cpu.history = Some(crate::arm7tdmi::history::History::new(64));
+            if jit {
+                gba.jit = Some(crate::arm7tdmi::jit::Jit::new().unwrap());
+            }
+            while gba.cpu.next_instr_addr() != 0x0800_0014 {
+                gba.step(
+                    &mut util::video::NullCallback,
+                    &mut util::audio::NullCallback,
+                );
+            }
+
+            let history = gba.cpu.history.unwrap();
+            (
+                history.branches().copied().collect::<Vec<_>>(),
+                history.call_stack().to_vec(),
+            )
+        };
+
+        let (branches, call_stack) = run(false);
+        assert_eq!(branches.len(), 29);
+        assert_eq!((branches, call_stack), run(true));
+    }
+
+    #[test]
+    fn fetches_dont_hit_watchpoints() {
+        let mut gba = test_gba(&[
+            0xe1a0_0000, // MOV R0,R0
+            0xe59f_0000, // LDR R0,[PC]
+            0xeaff_fffe, // B 0x8000008
+            0x1234_5678,
+        ]);
+        gba.reset(true);
+        gba.breakpoints
+            .add_watch(0x0800_0008..=0x0800_000f, WatchKind::Read);
+
+        // Executes the MOV and fetches the B.
+        gba.step(
+            &mut util::video::NullCallback,
+            &mut util::audio::NullCallback,
+        );
+        assert_eq!(gba.breakpoints.take_stop(), None);
+        // Executes the LDR, which fetches the literal just before loading it as data.
+        gba.step(
+            &mut util::video::NullCallback,
+            &mut util::audio::NullCallback,
+        );
+        assert_eq!(
+            gba.breakpoints.take_stop(),
+            Some(StopReason::Watchpoint {
+                addr: 0x0800_000c,
+                kind: WatchKind::Read,
+                write: false
+            })
+        );
+        assert_eq!(gba.cpu.reg.r[0], 0x1234_5678);
+    }
+}
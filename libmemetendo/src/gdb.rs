@@ -0,0 +1,804 @@
+//! A stub for the GDB remote serial protocol, so `arm-none-eabi-gdb` can debug a running [`Gba`].
+//!
+//! Registers are described to GDB with a target description that includes the banked registers
+//! of every mode (like `r8_fiq` or `spsr_irq`). Memory accesses made by GDB have no side effects,
+//! so IO registers can be read, but not written.
+
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    ops::RangeInclusive,
+    time::Duration,
+};
+
+use log::{info, warn};
+
+use crate::{
+    arm7tdmi::reg::{OperationMode, PC_INDEX},
+    audio,
+    breakpoint::{StopReason, WatchKind},
+    gba::Gba,
+    video,
+};
+
+/// How long `GdbStub::poll` waits for more packets while the emulator is stopped.
+const POLL_TIMEOUT: Duration = Duration::from_millis(10);
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Reg {
+    Gpr(usize),
+    Cpsr,
+    Spsr,
+    Banked(OperationMode, usize),
+    BankedSpsr(OperationMode),
+}
+
+fn reg_name(index: usize) -> String {
+    match index {
+        13 => "sp".into(),
+        14 => "lr".into(),
+        PC_INDEX => "pc".into(),
+        _ => format!("r{index}"),
+    }
+}
+
+/// Registers in the order of their GDB register numbers, starting from 0.
+///
+/// GDB numbers CPSR as 25, so the numbers of it and later registers are offset by 9.
+fn registers() -> Vec<(String, Reg)> {
+    let mut regs: Vec<_> = (0..16).map(|i| (reg_name(i), Reg::Gpr(i))).collect();
+    regs.push(("cpsr".into(), Reg::Cpsr));
+    regs.push(("spsr".into(), Reg::Spsr));
+
+    let banked_modes = [
+        (OperationMode::FastInterrupt, "fiq", 8),
+        (OperationMode::Interrupt, "irq", 13),
+        (OperationMode::Supervisor, "svc", 13),
+        (OperationMode::Abort, "abt", 13),
+        (OperationMode::UndefinedInstr, "und", 13),
+        (OperationMode::User, "usr", 8),
+    ];
+    for (mode, suffix, first) in banked_modes {
+        for i in first..=14 {
+            regs.push((format!("{}_{suffix}", reg_name(i)), Reg::Banked(mode, i)));
+        }
+        if mode.has_spsr() {
+            regs.push((format!("spsr_{suffix}"), Reg::BankedSpsr(mode)));
+        }
+    }
+
+    regs
+}
+
+fn reg_index(regnum: usize) -> Option<usize> {
+    match regnum {
+        0..=15 => Some(regnum),
+        25.. => Some(regnum - 9),
+        _ => None,
+    }
+}
+
+fn target_xml(regs: &[(String, Reg)]) -> String {
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd">"#,
+        "<target><architecture>arm</architecture>",
+        r#"<feature name="org.gnu.gdb.arm.core">"#
+    ));
+
+    for (i, (name, reg)) in regs.iter().enumerate() {
+        if *reg == Reg::Spsr {
+            xml.push_str(r#"</feature><feature name="org.memetendo.gba.banked">"#);
+        }
+
+        let ty = match reg {
+            Reg::Gpr(13) => "data_ptr",
+            Reg::Gpr(PC_INDEX) => "code_ptr",
+            _ => "uint32",
+        };
+        let regnum = if i < 16 { i } else { i + 9 };
+        write!(
+            xml,
+            r#"<reg name="{name}" bitsize="32" type="{ty}" regnum="{regnum}"/>"#
+        )
+        .unwrap();
+    }
+    xml.push_str("</feature></target>");
+
+    xml
+}
+
+fn read_reg(gba: &Gba, reg: Reg) -> u32 {
+    let r = &gba.cpu.reg;
+    match reg {
+        // GDB expects the address of the next instruction, not the pipelined PC.
+        Reg::Gpr(PC_INDEX) => gba.cpu.next_instr_addr(),
+        Reg::Gpr(i) => r.r[i],
+        Reg::Cpsr => r.cpsr().bits(),
+        Reg::Spsr => r.spsr(),
+        Reg::Banked(mode, i) => r.banked(mode, i),
+        Reg::BankedSpsr(mode) => r.banked_spsr(mode),
+    }
+}
+
+fn write_reg(gba: &mut Gba, reg: Reg, value: u32) {
+    let r = &mut gba.cpu.reg;
+    match reg {
+        Reg::Gpr(PC_INDEX) => gba.set_pc(value),
+        Reg::Gpr(i) => r.r[i] = value,
+        Reg::Cpsr => {
+            // Changing the state (ARM/THUMB) invalidates the pipeline.
+            let pc = gba.cpu.next_instr_addr();
+            gba.cpu.reg.set_cpsr(value);
+            gba.set_pc(pc);
+        }
+        Reg::Spsr => r.set_spsr(value),
+        Reg::Banked(mode, i) => r.set_banked(mode, i, value),
+        Reg::BankedSpsr(mode) => r.set_banked_spsr(mode, value),
+    }
+}
+
+fn parse_hex(s: &[u8]) -> Option<u32> {
+    u32::from_str_radix(std::str::from_utf8(s).ok()?, 16).ok()
+}
+
+fn decode_hex_bytes(s: &[u8]) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+
+    s.chunks(2)
+        .map(|pair| parse_hex(pair).and_then(|b| u8::try_from(b).ok()))
+        .collect()
+}
+
+fn encode_hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        write!(s, "{b:02x}").unwrap();
+        s
+    })
+}
+
+/// Parses "ADDR,LEN", as used by memory and breakpoint packets.
+fn parse_addr_len(s: &[u8]) -> Option<(u32, u32)> {
+    let mut parts = s.split(|&b| b == b',');
+    let addr = parse_hex(parts.next()?)?;
+    let len = parse_hex(parts.next()?)?;
+
+    Some((addr, len))
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Resume {
+    Continue,
+    Step,
+}
+
+/// A connection to GDB.
+///
+/// Call `poll` regularly to handle requests from GDB, and use `step` rather than `Gba::step`, so
+/// breakpoints and single-stepping are reported to GDB. The emulator starts stopped.
+#[allow(clippy::module_name_repetitions)]
+pub struct GdbStub {
+    stream: Option<TcpStream>,
+    recv_buf: Vec<u8>,
+    no_ack: bool,
+    resume: Option<Resume>,
+    last_stop: String,
+    regs: Vec<(String, Reg)>,
+    sw_breakpoints: HashSet<u32>,
+    hw_breakpoints: HashSet<u32>,
+    /// Breakpoints GDB added, rather than ones that were already set (e.g. by a debugger console).
+    owned_breakpoints: HashSet<u32>,
+    watchpoints: Vec<(RangeInclusive<u32>, WatchKind)>,
+}
+
+impl GdbStub {
+    /// Waits for GDB to connect on `addr`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if listening or accepting the connection failed.
+    pub fn listen(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        info!("waiting for GDB to connect on {}", listener.local_addr()?);
+        let (stream, peer) = listener.accept()?;
+        info!("GDB connected from {peer}");
+
+        Self::new(stream)
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if configuring the stream failed.
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+
+        Ok(Self {
+            stream: Some(stream),
+            recv_buf: Vec::new(),
+            no_ack: false,
+            resume: None,
+            last_stop: "S05".into(),
+            regs: registers(),
+            sw_breakpoints: HashSet::new(),
+            hw_breakpoints: HashSet::new(),
+            owned_breakpoints: HashSet::new(),
+            watchpoints: Vec::new(),
+        })
+    }
+
+    #[must_use]
+    pub fn is_attached(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Whether GDB has let the emulator run.
+    #[must_use]
+    pub fn is_running(&self) -> bool {
+        self.resume.is_some()
+    }
+
+    /// Handles requests from GDB.
+    ///
+    /// While the emulator is stopped, this keeps handling requests until none arrive for a short
+    /// while, as GDB usually sends many in a row.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if communicating with GDB failed.
+    pub fn poll(&mut self, gba: &mut Gba) -> io::Result<()> {
+        while self.is_attached() {
+            let timeout = if self.is_running() {
+                None
+            } else {
+                Some(POLL_TIMEOUT)
+            };
+            if !self.receive(gba, timeout)? {
+                break;
+            }
+            self.process_packets(gba)?;
+            if self.is_running() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Steps `gba` if GDB has let it run, notifying GDB if it stops.
+    /// Returns false if it was stopped instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if communicating with GDB failed.
+    pub fn step(
+        &mut self,
+        gba: &mut Gba,
+        video_cb: &mut impl video::Callback,
+        audio_cb: &mut impl audio::Callback,
+    ) -> io::Result<bool> {
+        let Some(resume) = self.resume else {
+            return Ok(false);
+        };
+        if !self.is_attached() {
+            gba.step(video_cb, audio_cb);
+            return Ok(true);
+        }
+
+        // The JIT runs whole blocks, so bypass it to single-step.
+        #[cfg(feature = "jit")]
+        let jit = if resume == Resume::Step {
+            gba.jit.take()
+        } else {
+            None
+        };
+        gba.step(video_cb, audio_cb);
+        #[cfg(feature = "jit")]
+        if jit.is_some() {
+            gba.jit = jit;
+        }
+
+        if let Some(reason) = gba.breakpoints.take_stop() {
+            let reply = match reason {
+                StopReason::Breakpoint { addr, .. } if self.sw_breakpoints.contains(&addr) => {
+                    "T05swbreak:;".into()
+                }
+                StopReason::Breakpoint { .. } => "T05hwbreak:;".into(),
+                StopReason::Watchpoint { addr, kind, .. } => {
+                    let kind = match kind {
+                        WatchKind::Read => "rwatch",
+                        WatchKind::Write => "watch",
+                        WatchKind::Access => "awatch",
+                    };
+                    format!("T05{kind}:{addr:08x};")
+                }
+                // GDB has no way to set these, but a frontend might.
+                StopReason::Interrupt(_) | StopReason::DmaStart(_) | StopReason::Exception(_) => {
+                    "T05".into()
+                }
+            };
+            self.stop(reply)?;
+        } else if resume == Resume::Step {
+            self.stop("T05".into())?;
+        }
+
+        Ok(true)
+    }
+
+    fn stop(&mut self, reply: String) -> io::Result<()> {
+        self.resume = None;
+        self.send_packet(&reply)?;
+        self.last_stop = reply;
+
+        Ok(())
+    }
+
+    /// Detaches from GDB, removing its breakpoints and letting the emulator run.
+    fn detach(&mut self, gba: &mut Gba) {
+        self.sw_breakpoints.clear();
+        self.hw_breakpoints.clear();
+        for addr in self.owned_breakpoints.drain() {
+            gba.breakpoints.remove_exec(addr);
+        }
+        for (range, kind) in self.watchpoints.drain(..) {
+            gba.breakpoints.remove_watch(&range, kind);
+        }
+
+        self.stream = None;
+        self.resume = Some(Resume::Continue);
+        info!("GDB detached");
+    }
+
+    /// Receives available data, waiting up to `timeout` for some (or not at all if `None`).
+    /// Returns false if nothing was received.
+    fn receive(&mut self, gba: &mut Gba, timeout: Option<Duration>) -> io::Result<bool> {
+        let Some(stream) = &mut self.stream else {
+            return Ok(false);
+        };
+        stream.set_nonblocking(timeout.is_none())?;
+        stream.set_read_timeout(timeout)?;
+
+        let mut buf = [0; 4096];
+        let result = stream.read(&mut buf);
+        stream.set_nonblocking(false)?;
+        match result {
+            Ok(0) => {
+                self.detach(gba);
+                Ok(false)
+            }
+            Ok(len) => {
+                self.recv_buf.extend_from_slice(&buf[..len]);
+                Ok(true)
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(false),
+            Err(e) if e.kind() == ErrorKind::Interrupted => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        match &mut self.stream {
+            Some(stream) => stream.write_all(data),
+            None => Ok(()),
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, u8::wrapping_add);
+        self.send(format!("${data}#{checksum:02x}").as_bytes())
+    }
+
+    fn process_packets(&mut self, gba: &mut Gba) -> io::Result<()> {
+        while self.is_attached() {
+            let Some(&first) = self.recv_buf.first() else {
+                break;
+            };
+
+            match first {
+                b'$' => {
+                    let Some(end) = self.recv_buf.iter().position(|&b| b == b'#') else {
+                        break;
+                    };
+                    if self.recv_buf.len() < end + 3 {
+                        break;
+                    }
+
+                    let packet: Vec<_> = self.recv_buf.drain(..end + 3).collect();
+                    let data = &packet[1..end];
+                    let checksum = parse_hex(&packet[end + 1..]);
+                    if checksum != Some(data.iter().fold(0u8, |a, &b| a.wrapping_add(b)).into()) {
+                        warn!("GDB packet has a bad checksum");
+                        if !self.no_ack {
+                            self.send(b"-")?;
+                        }
+                        continue;
+                    }
+
+                    if !self.no_ack {
+                        self.send(b"+")?;
+                    }
+                    self.handle_packet(gba, data)?;
+                }
+                // Ctrl-C.
+                0x03 => {
+                    self.recv_buf.remove(0);
+                    if self.is_running() {
+                        self.stop("T02".into())?;
+                    }
+                }
+                // Acks, or garbage.
+                _ => {
+                    self.recv_buf.remove(0);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_packet(&mut self, gba: &mut Gba, data: &[u8]) -> io::Result<()> {
+        let (&cmd, args) = data.split_first().unwrap_or((&0, &[]));
+        let reply = match cmd {
+            b'?' => self.last_stop.clone(),
+            b'g' => {
+                let mut reply = String::new();
+                for &(_, reg) in &self.regs {
+                    reply.push_str(&encode_hex_bytes(&read_reg(gba, reg).to_le_bytes()));
+                }
+                reply
+            }
+            b'G' => self.write_regs(gba, args),
+            b'p' => self.read_single_reg(gba, args),
+            b'P' => self.write_single_reg(gba, args),
+            b'm' => Self::read_mem(gba, args),
+            b'M' => Self::write_mem(gba, args),
+            b'c' | b's' => {
+                // Resuming from a different address is deprecated, but still easy to support.
+                if let Some(addr) = args.split(|&b| b == b';').next().and_then(parse_hex) {
+                    gba.set_pc(addr);
+                }
+                self.resume = Some(if cmd == b'c' {
+                    Resume::Continue
+                } else {
+                    Resume::Step
+                });
+                return Ok(());
+            }
+            b'Z' | b'z' => self.update_breakpoint(gba, cmd == b'Z', args),
+            b'D' => {
+                self.send_packet("OK")?;
+                self.detach(gba);
+                return Ok(());
+            }
+            b'k' => {
+                self.detach(gba);
+                return Ok(());
+            }
+            b'H' | b'T' => "OK".into(),
+            b'q' | b'Q' => self.handle_query(data),
+            _ => String::new(),
+        };
+
+        self.send_packet(&reply)?;
+        if data == b"QStartNoAckMode" {
+            self.no_ack = true;
+        }
+
+        Ok(())
+    }
+
+    fn handle_query(&self, data: &[u8]) -> String {
+        if data.starts_with(b"qSupported") {
+            return "PacketSize=4000;qXfer:features:read+;swbreak+;hwbreak+;QStartNoAckMode+"
+                .into();
+        }
+        if let Some(args) = data.strip_prefix(b"qXfer:features:read:target.xml:") {
+            let Some((offset, len)) = parse_addr_len(args) else {
+                return "E01".into();
+            };
+            let xml = target_xml(&self.regs);
+            let start = usize::try_from(offset).unwrap_or(usize::MAX).min(xml.len());
+            let end = start
+                .saturating_add(len.try_into().unwrap_or(usize::MAX))
+                .min(xml.len());
+            let prefix = if end == xml.len() { 'l' } else { 'm' };
+
+            return format!("{prefix}{}", &xml[start..end]);
+        }
+
+        match data {
+            b"QStartNoAckMode" => "OK".into(),
+            b"qAttached" => "1".into(),
+            b"qC" => "QC1".into(),
+            b"qfThreadInfo" => "m1".into(),
+            b"qsThreadInfo" => "l".into(),
+            _ => String::new(),
+        }
+    }
+
+    fn read_single_reg(&self, gba: &Gba, args: &[u8]) -> String {
+        let Some(&(_, reg)) = parse_hex(args)
+            .and_then(|n| reg_index(n.try_into().ok()?))
+            .and_then(|i| self.regs.get(i))
+        else {
+            return "E00".into();
+        };
+
+        encode_hex_bytes(&read_reg(gba, reg).to_le_bytes())
+    }
+
+    fn write_single_reg(&self, gba: &mut Gba, args: &[u8]) -> String {
+        let mut parts = args.split(|&b| b == b'=');
+        let reg = parts
+            .next()
+            .and_then(parse_hex)
+            .and_then(|n| reg_index(n.try_into().ok()?))
+            .and_then(|i| self.regs.get(i));
+        let value = parts
+            .next()
+            .and_then(decode_hex_bytes)
+            .and_then(|bytes| Some(u32::from_le_bytes(bytes.try_into().ok()?)));
+
+        let (Some(&(_, reg)), Some(value)) = (reg, value) else {
+            return "E00".into();
+        };
+        write_reg(gba, reg, value);
+
+        "OK".into()
+    }
+
+    fn write_regs(&self, gba: &mut Gba, args: &[u8]) -> String {
+        let Some(bytes) = decode_hex_bytes(args) else {
+            return "E00".into();
+        };
+
+        for (&(_, reg), value) in self.regs.iter().zip(bytes.chunks_exact(4)) {
+            write_reg(gba, reg, u32::from_le_bytes(value.try_into().unwrap()));
+        }
+
+        "OK".into()
+    }
+
+    fn read_mem(gba: &mut Gba, args: &[u8]) -> String {
+        let Some((addr, len)) = parse_addr_len(args) else {
+            return "E00".into();
+        };
+
+        // Reply with as much as could be read; GDB handles partial reads.
+        let bytes: Vec<_> = (0..len)
+            .map_while(|i| gba.debug_read_byte(addr.wrapping_add(i)))
+            .collect();
+        if bytes.is_empty() && len > 0 {
+            return "E14".into();
+        }
+
+        encode_hex_bytes(&bytes)
+    }
+
+    fn write_mem(gba: &mut Gba, args: &[u8]) -> String {
+        let mut parts = args.split(|&b| b == b':');
+        let (Some((addr, _)), Some(bytes)) = (
+            parts.next().and_then(parse_addr_len),
+            parts.next().and_then(decode_hex_bytes),
+        ) else {
+            return "E00".into();
+        };
+
+        let mut addr = addr;
+        for value in bytes {
+            if !gba.debug_write_byte(addr, value) {
+                return "E14".into();
+            }
+            addr = addr.wrapping_add(1);
+        }
+
+        "OK".into()
+    }
+
+    fn update_breakpoint(&mut self, gba: &mut Gba, insert: bool, args: &[u8]) -> String {
+        let addr_len = parse_addr_len(args.get(2..).unwrap_or_default());
+        let (Some(&ty), Some((addr, len))) = (args.first(), addr_len) else {
+            return "E00".into();
+        };
+
+        let bps = &mut gba.breakpoints;
+        let kind = match ty {
+            b'0' | b'1' => {
+                let (ours, other) = if ty == b'0' {
+                    (&mut self.sw_breakpoints, &self.hw_breakpoints)
+                } else {
+                    (&mut self.hw_breakpoints, &self.sw_breakpoints)
+                };
+                if insert {
+                    ours.insert(addr);
+                    if bps.add_exec(addr) {
+                        self.owned_breakpoints.insert(addr);
+                    }
+                } else if ours.remove(&addr)
+                    && !other.contains(&addr)
+                    && self.owned_breakpoints.remove(&addr)
+                {
+                    bps.remove_exec(addr);
+                }
+
+                return "OK".into();
+            }
+            b'2' => WatchKind::Write,
+            b'3' => WatchKind::Read,
+            b'4' => WatchKind::Access,
+            _ => return String::new(),
+        };
+
+        let range = addr..=addr.wrapping_add(len.max(1) - 1);
+        if insert {
+            bps.add_watch(range.clone(), kind);
+            self.watchpoints.push((range, kind));
+        } else if let Some(i) = self
+            .watchpoints
+            .iter()
+            .position(|(r, k)| *r == range && *k == kind)
+        {
+            self.watchpoints.remove(i);
+            bps.remove_watch(&range, kind);
+        }
+
+        "OK".into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Instant};
+
+    use super::*;
+
+    use crate::{
+        gba::tests::test_gba,
+        util::{audio, video},
+    };
+
+    struct Client(TcpStream);
+
+    impl Client {
+        fn send_raw(&mut self, data: &[u8]) {
+            self.0.write_all(data).unwrap();
+        }
+
+        fn recv_packet(&mut self) -> String {
+            let mut packet = Vec::new();
+            let mut byte = [0];
+            loop {
+                self.0.read_exact(&mut byte).unwrap();
+                match byte[0] {
+                    b'+' if packet.is_empty() => {}
+                    b'#' => break,
+                    b => packet.push(b),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.0.read_exact(&mut checksum).unwrap();
+            self.send_raw(b"+");
+
+            assert_eq!(packet.first(), Some(&b'$'));
+            String::from_utf8(packet[1..].to_vec()).unwrap()
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            let checksum = data.bytes().fold(0u8, u8::wrapping_add);
+            self.send_raw(format!("${data}#{checksum:02x}").as_bytes());
+            self.recv_packet()
+        }
+    }
+
+    fn program_gba() -> Gba {
+        let mut gba = test_gba(&[
+            0xe3a0_0001, // MOV R0,#1
+            0xe280_0001, // ADD R0,R0,#1
+            0xe3a0_1403, // MOV R1,#0x3000000
+            0xe581_0000, // STR R0,[R1]
+            0xeaff_fffe, // B 0x8000010
+        ]);
+        gba.reset(true);
+        gba
+    }
+
+    #[test]
+    fn registers_are_numbered_like_gdb() {
+        let regs = registers();
+        let xml = target_xml(&regs);
+        assert!(xml.contains(r#"name="cpsr" bitsize="32" type="uint32" regnum="25""#));
+        assert!(xml.contains(r#"name="r8_fiq""#));
+        assert!(xml.contains(r#"name="spsr_und""#));
+        assert!(!xml.contains(r#"name="spsr_usr""#));
+        assert_eq!(regs[reg_index(25).unwrap()].1, Reg::Cpsr);
+        assert_eq!(reg_index(16), None);
+    }
+
+    #[test]
+    fn stub_works() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let mut gdb = Client(TcpStream::connect(addr).unwrap());
+
+            assert!(gdb
+                .request("qSupported:swbreak+")
+                .contains("qXfer:features:read+"));
+            assert!(gdb
+                .request("qXfer:features:read:target.xml:0,fff")
+                .starts_with("l<?xml"));
+            assert_eq!(gdb.request("?"), "S05");
+            assert_eq!(gdb.request("QStartNoAckMode"), "OK");
+
+            // PC is reported as the address of the next instruction; register 15.
+            assert_eq!(gdb.request("pf"), "00000008");
+            assert_eq!(&gdb.request("g")[15 * 8..16 * 8], "00000008");
+            assert_eq!(gdb.request("P0=78563412"), "OK");
+            assert_eq!(gdb.request("p0"), "78563412");
+            // r8_fiq is regnum 27.
+            assert_eq!(gdb.request("P1b=efbeadde"), "OK");
+            assert_eq!(gdb.request("p1b"), "efbeadde");
+            assert_eq!(gdb.request("p8"), "00000000");
+            assert_eq!(gdb.request("p10"), "E00");
+
+            assert_eq!(gdb.request("m8000000,4"), "0100a0e3");
+            assert_eq!(gdb.request("M3000000,2:abcd"), "OK");
+            assert_eq!(gdb.request("m3000000,2"), "abcd");
+            assert_eq!(gdb.request("M4000000,1:00"), "E14");
+            assert_eq!(gdb.request("m10000000,4"), "E14");
+
+            assert_eq!(gdb.request("Z0,8000008,4"), "OK");
+            assert_eq!(gdb.request("c"), "T05swbreak:;");
+            assert_eq!(gdb.request("pf"), "08000008");
+            assert_eq!(gdb.request("p0"), "02000000");
+            assert_eq!(gdb.request("s"), "T05");
+            assert_eq!(gdb.request("pf"), "0c000008");
+            assert_eq!(gdb.request("z0,8000008,4"), "OK");
+            // Removing GDB's breakpoint leaves the one the debugger console already set.
+            assert_eq!(gdb.request("Z1,8000100,2"), "OK");
+            assert_eq!(gdb.request("z1,8000100,2"), "OK");
+
+            assert_eq!(gdb.request("Z2,3000000,4"), "OK");
+            assert_eq!(gdb.request("c"), "T05watch:03000000;");
+            assert_eq!(gdb.request("pf"), "10000008");
+            assert_eq!(gdb.request("m3000000,4"), "02000000");
+            assert_eq!(gdb.request("z2,3000000,4"), "OK");
+
+            // The program now loops forever, so interrupt it.
+            gdb.send_raw(b"$c#63");
+            thread::sleep(Duration::from_millis(50));
+            gdb.send_raw(&[0x03]);
+            assert_eq!(gdb.recv_packet(), "T02");
+
+            assert_eq!(gdb.request("D"), "OK");
+        });
+
+        let mut gba = program_gba();
+        gba.breakpoints.add_exec(0x0800_0100);
+        let mut stub = GdbStub::new(listener.accept().unwrap().0).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while stub.is_attached() && Instant::now() < deadline {
+            stub.poll(&mut gba).unwrap();
+            for _ in 0..1000 {
+                if !stub
+                    .step(&mut gba, &mut video::NullCallback, &mut audio::NullCallback)
+                    .unwrap()
+                {
+                    break;
+                }
+            }
+        }
+
+        client.join().unwrap();
+        assert!(!stub.is_attached());
+        assert!(gba.breakpoints.watchpoints().is_empty());
+        assert!(gba
+            .breakpoints
+            .exec()
+            .map(|(addr, _)| addr)
+            .eq([0x0800_0100]));
+    }
+}
@@ -0,0 +1,202 @@
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FieldValue {
+    Flag(bool),
+    Number(u32),
+    Signed(i32),
+    /// A value from a fixed set of options, like a DMA timing mode.
+    Named(&'static str),
+}
+
+impl Display for FieldValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Flag(value) => write!(f, "{}", u8::from(*value)),
+            Self::Number(value) => write!(f, "{value}"),
+            Self::Signed(value) => write!(f, "{value}"),
+            Self::Named(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub value: FieldValue,
+}
+
+/// A hardware register in the IO region, with its fields decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IoRegister {
+    pub name: &'static str,
+    pub addr: u32,
+    /// In bytes.
+    pub size: u32,
+    /// The value of the register, even if it's write-only.
+    pub value: u32,
+    pub fields: Vec<Field>,
+}
+
+impl IoRegister {
+    pub(crate) fn new(name: &'static str, addr: u32, size: u32, value: u32) -> Self {
+        Self {
+            name,
+            addr: 0x0400_0000 | addr,
+            size,
+            value,
+            fields: Vec::new(),
+        }
+    }
+
+    fn with_field(mut self, name: &'static str, value: FieldValue) -> Self {
+        self.fields.push(Field { name, value });
+        self
+    }
+
+    pub(crate) fn flag(self, name: &'static str, value: bool) -> Self {
+        self.with_field(name, FieldValue::Flag(value))
+    }
+
+    pub(crate) fn number(self, name: &'static str, value: impl Into<u32>) -> Self {
+        self.with_field(name, FieldValue::Number(value.into()))
+    }
+
+    pub(crate) fn signed(self, name: &'static str, value: impl Into<i32>) -> Self {
+        self.with_field(name, FieldValue::Signed(value.into()))
+    }
+
+    pub(crate) fn named(self, name: &'static str, value: &'static str) -> Self {
+        self.with_field(name, FieldValue::Named(value))
+    }
+
+    /// Adds a flag for each of `names`, from consecutive bits of `bits`.
+    pub(crate) fn flags(self, names: &[&'static str], bits: u32) -> Self {
+        names
+            .iter()
+            .enumerate()
+            .fold(self, |reg, (i, name)| reg.flag(name, bits & (1 << i) != 0))
+    }
+
+    /// Returns the value of the field named `name`.
+    #[must_use]
+    pub fn field(&self, name: &str) -> Option<FieldValue> {
+        self.fields.iter().find(|f| f.name == name).map(|f| f.value)
+    }
+}
+
+impl Display for IoRegister {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let digits = 2 * self.size as usize;
+        write!(
+            f,
+            "{:08x} {:<10} {:0digits$x}",
+            self.addr, self.name, self.value
+        )?;
+        for field in &self.fields {
+            write!(f, " {}={}", field.name, field.value)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{bus::Bus, gba::tests::test_gba, keypad::Key};
+
+    use super::*;
+
+    #[test]
+    fn io_register_works() {
+        let reg = IoRegister::new("TM0CNT_H", 0x102, 2, 0xc1)
+            .named("prescaler", "64")
+            .flags(&["irq", "start"], 0b11)
+            .signed("counter", -1);
+
+        assert_eq!(reg.addr, 0x0400_0102);
+        assert_eq!(reg.field("irq"), Some(FieldValue::Flag(true)));
+        assert_eq!(reg.field("prescaler"), Some(FieldValue::Named("64")));
+        assert_eq!(reg.field("cascade"), None);
+        assert_eq!(
+            reg.to_string(),
+            "04000102 TM0CNT_H   00c1 prescaler=64 irq=1 start=1 counter=-1"
+        );
+    }
+
+    #[test]
+    fn gba_io_registers_work() {
+        let mut gba = test_gba(&[]);
+
+        gba.video.write_hword(0x00, 0x1143);
+        gba.video.write_hword(0x0c, 0x0185);
+        gba.dma.write_word(0xd4, 0x0800_0000);
+        gba.dma.write_hword(0xde, 0x1400);
+        gba.timers.write_hword(0x106, 0x00c1);
+        gba.irq.write_hword(0x200, 0x2001);
+        gba.keypad.set_pressed(Key::Start, true);
+        gba.audio.write_byte(0x84, 0x80);
+        gba.audio.write_hword(0x62, 0xa6c5);
+        gba.audio.write_hword(0x72, 0x4000);
+        gba.audio.write_hword(0x88, 0x4200);
+
+        let regs = gba.io_registers();
+        assert!(regs.windows(2).all(|w| w[0].addr < w[1].addr));
+
+        let dispcnt = gba.io_register("DISPCNT").unwrap();
+        assert_eq!((dispcnt.addr, dispcnt.value), (0x0400_0000, 0x1143));
+        assert_eq!(dispcnt.field("mode"), Some(FieldValue::Number(3)));
+        assert_eq!(dispcnt.field("obj_1d"), Some(FieldValue::Flag(true)));
+        assert_eq!(dispcnt.field("bg0"), Some(FieldValue::Flag(true)));
+        assert_eq!(dispcnt.field("obj"), Some(FieldValue::Flag(true)));
+
+        let bg2cnt = gba.io_register("BG2CNT").unwrap();
+        assert_eq!(bg2cnt.field("priority"), Some(FieldValue::Number(1)));
+        assert_eq!(bg2cnt.field("tile_base"), Some(FieldValue::Number(1)));
+        assert_eq!(bg2cnt.field("color256"), Some(FieldValue::Flag(true)));
+        assert_eq!(bg2cnt.field("map_base"), Some(FieldValue::Number(1)));
+
+        let dma3sad = gba.io_register("DMA3SAD").unwrap();
+        assert_eq!(dma3sad.value, 0x0800_0000);
+        let dma3cnt = gba.io_register("DMA3CNT_H").unwrap();
+        assert_eq!(dma3cnt.field("timing"), Some(FieldValue::Named("vblank")));
+        assert_eq!(dma3cnt.field("word"), Some(FieldValue::Flag(true)));
+        assert_eq!(dma3cnt.field("enabled"), Some(FieldValue::Flag(false)));
+
+        let tm1cnt = gba.io_register("TM1CNT_H").unwrap();
+        assert_eq!(tm1cnt.field("prescaler"), Some(FieldValue::Named("64")));
+        assert_eq!(tm1cnt.field("start"), Some(FieldValue::Flag(true)));
+
+        let ie = gba.io_register("IE").unwrap();
+        assert_eq!(ie.field("vblank"), Some(FieldValue::Flag(true)));
+        assert_eq!(ie.field("gamepak"), Some(FieldValue::Flag(true)));
+        assert_eq!(ie.field("hblank"), Some(FieldValue::Flag(false)));
+
+        let sound1cnt_h = gba.io_register("SOUND1CNT_H").unwrap();
+        assert_eq!(sound1cnt_h.value, 0xa6c5);
+        assert_eq!(sound1cnt_h.field("length"), Some(FieldValue::Number(5)));
+        assert_eq!(sound1cnt_h.field("duty"), Some(FieldValue::Number(3)));
+        assert_eq!(
+            sound1cnt_h.field("envelope_step"),
+            Some(FieldValue::Number(6))
+        );
+        assert_eq!(
+            sound1cnt_h.field("initial_volume"),
+            Some(FieldValue::Number(10))
+        );
+        let sound3cnt_h = gba.io_register("SOUND3CNT_H").unwrap();
+        assert_eq!(sound3cnt_h.field("volume"), Some(FieldValue::Named("50%")));
+        let soundbias = gba.io_register("SOUNDBIAS").unwrap();
+        assert_eq!((soundbias.size, soundbias.value), (2, 0x4200));
+
+        gba.haltcnt.write_byte(0x301, 0x80);
+        let haltcnt = gba.io_register("HALTCNT").unwrap();
+        assert_eq!((haltcnt.addr, haltcnt.size), (0x0400_0301, 1));
+        assert_eq!(haltcnt.field("state"), Some(FieldValue::Named("stopped")));
+
+        let keyinput = gba.io_register("KEYINPUT").unwrap();
+        assert_eq!(keyinput.value, 0x3f7);
+        assert_eq!(keyinput.field("start"), Some(FieldValue::Flag(true)));
+        assert!(gba.io_register("NOPE").is_none());
+    }
+}
@@ -1,12 +1,22 @@
+use std::mem::take;
+
 use intbits::Bits;
+use strum_macros::FromRepr;
 
 use crate::{
     arm7tdmi::{Cpu, Exception},
     bus::Bus,
     gba::{HaltControl, State},
+    io_regs::IoRegister,
 };
 
-#[derive(Debug, Copy, Clone)]
+const INTERRUPT_NAMES: [&str; 14] = [
+    "vblank", "hblank", "vcount", "timer0", "timer1", "timer2", "timer3", "serial", "dma0", "dma1",
+    "dma2", "dma3", "keypad", "gamepak",
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, FromRepr)]
+#[repr(u8)]
 pub enum Interrupt {
     VBlank,
     HBlank,
@@ -29,6 +39,9 @@ pub struct Irq {
     intme: u32,
     inte: u16,
     intf: u16,
+    /// Bits of IF set and cleared since last taken.
+    requested: u16,
+    acknowledged: u16,
 }
 
 impl Irq {
@@ -50,6 +63,35 @@ impl Irq {
 
     pub fn request(&mut self, interrupt: Interrupt) {
         self.intf.set_bit(interrupt as usize, true);
+        self.requested.set_bit(interrupt as usize, true);
+    }
+
+    /// Whether `interrupt` is both requested and enabled in IE.
+    #[must_use]
+    pub fn is_pending(&self, interrupt: Interrupt) -> bool {
+        self.inte.bit(interrupt as usize) && self.intf.bit(interrupt as usize)
+    }
+}
+
+impl Irq {
+    /// Returns the bits of interrupts requested since the last call.
+    pub(crate) fn take_requested(&mut self) -> u16 {
+        take(&mut self.requested)
+    }
+
+    /// Returns the bits of IF cleared by writes since the last call.
+    pub(crate) fn take_acknowledged(&mut self) -> u16 {
+        take(&mut self.acknowledged)
+    }
+
+    pub(crate) fn io_registers(&self) -> Vec<IoRegister> {
+        vec![
+            IoRegister::new("IE", 0x200, 2, self.inte.into())
+                .flags(&INTERRUPT_NAMES, self.inte.into()),
+            IoRegister::new("IF", 0x202, 2, self.intf.into())
+                .flags(&INTERRUPT_NAMES, self.intf.into()),
+            IoRegister::new("IME", 0x208, 4, self.intme).flag("enabled", self.intme.bit(0)),
+        ]
     }
 }
 
@@ -77,12 +119,14 @@ impl Bus for Irq {
             0x200 => self.inte.set_bits(..8, value.into()),
             0x201 => self.inte.set_bits(8.., value.into()),
             // IF
-            0x202 => self
-                .intf
-                .set_bits(..8, self.intf.bits(..8) & u16::from(!value)),
-            0x203 => self
-                .intf
-                .set_bits(8.., self.intf.bits(8..) & u16::from(!value)),
+            0x202 => {
+                self.acknowledged |= self.intf & u16::from(value);
+                self.intf &= !u16::from(value);
+            }
+            0x203 => {
+                self.acknowledged |= self.intf & u16::from(value) << 8;
+                self.intf &= !(u16::from(value) << 8);
+            }
             // IME
             0x208 => self.intme.set_bits(..8, value.into()),
             0x209 => self.intme.set_bits(8..16, value.into()),
@@ -3,9 +3,14 @@ use strum_macros::EnumCount;
 
 use crate::{
     bus::Bus,
+    io_regs::IoRegister,
     irq::{Interrupt, Irq},
 };
 
+const KEY_NAMES: [&str; 10] = [
+    "a", "b", "select", "start", "right", "left", "up", "down", "r", "l",
+];
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, EnumCount)]
 pub enum Key {
     A,
@@ -59,6 +64,27 @@ impl Keypad {
     }
 }
 
+impl Keypad {
+    pub(crate) fn io_registers(self) -> Vec<IoRegister> {
+        let keyinput = !self.pressed & 0x3ff;
+        let keycnt = self
+            .keycnt
+            .irq_keys
+            .with_bit(14, self.keycnt.irq_enabled)
+            .with_bit(15, self.keycnt.irq_all_pressed);
+
+        vec![
+            // Keys are reported as pressed, unlike the register's bits.
+            IoRegister::new("KEYINPUT", 0x130, 2, keyinput.into())
+                .flags(&KEY_NAMES, self.pressed.into()),
+            IoRegister::new("KEYCNT", 0x132, 2, keycnt.into())
+                .flags(&KEY_NAMES, self.keycnt.irq_keys.into())
+                .flag("irq", self.keycnt.irq_enabled)
+                .flag("all_pressed", self.keycnt.irq_all_pressed),
+        ]
+    }
+}
+
 impl Bus for Keypad {
     fn read_byte(&mut self, addr: u32) -> u8 {
         match addr {
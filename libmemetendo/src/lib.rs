@@ -9,12 +9,21 @@ use std::{
 pub mod arm7tdmi;
 pub mod audio;
 pub mod bios;
+pub mod breakpoint;
 pub mod bus;
 pub mod cart;
+pub mod debug_print;
 pub mod dma;
+pub mod elf;
 pub mod gba;
+pub mod gdb;
+pub mod io_regs;
 pub mod irq;
 pub mod keypad;
+pub mod profiler;
+pub mod sanitizer;
+pub mod symbols;
+pub mod timeline;
 pub mod timer;
 pub mod util;
 pub mod video;
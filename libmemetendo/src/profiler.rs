@@ -0,0 +1,279 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use crate::{arm7tdmi::history::History, symbols::Symbols, video::VBLANK_DOT};
+
+/// Stand-in function for code outside of any known function, like before the first call when no
+/// symbols are loaded.
+const ROOT: u32 = u32::MAX;
+/// Stand-in function for time spent halted.
+const HALTED: u32 = u32::MAX - 1;
+
+/// CPU usage of a frame, as cycles not spent halted.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct FrameUsage {
+    pub busy_cycles: u32,
+    pub halted_cycles: u32,
+}
+
+impl FrameUsage {
+    /// Fraction of the frame's cycles that weren't spent halted.
+    #[must_use]
+    pub fn usage(&self) -> f64 {
+        let total = self.busy_cycles + self.halted_cycles;
+        if total == 0 {
+            0.0
+        } else {
+            f64::from(self.busy_cycles) / f64::from(total)
+        }
+    }
+}
+
+/// Attributes executed cycles to functions, and tracks the CPU usage of each frame.
+///
+/// Functions are named by the symbol containing the executing code, if any; otherwise they're
+/// identified by the targets of the calls on the CPU's shadow call stack, which must be enabled
+/// by setting `Cpu::history`.
+#[derive(Default, Debug)]
+pub struct Profiler {
+    /// Cycles by call stack of function addresses, outermost first.
+    stacks: HashMap<Vec<u32>, u64>,
+    stack: Vec<u32>,
+    stack_cycles: u64,
+    frames: Vec<FrameUsage>,
+    frame: Option<FrameUsage>,
+    in_vblank: bool,
+}
+
+impl Profiler {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the call stack that the next cycles are attributed to, for executing the instruction
+    /// at `pc`.
+    pub(crate) fn sample(
+        &mut self,
+        pc: u32,
+        halted: bool,
+        history: Option<&History>,
+        symbols: &Symbols,
+    ) {
+        let function = |addr| symbols.lookup(addr).map(|loc| loc.symbol.addr);
+
+        let mut stack = Vec::with_capacity(self.stack.len() + 1);
+        if halted {
+            stack.push(HALTED);
+        } else {
+            let frames = history.map_or(&[][..], History::call_stack);
+            stack.extend(
+                frames
+                    .iter()
+                    .map(|f| function(f.target).unwrap_or(f.target)),
+            );
+            match function(pc) {
+                Some(current) if stack.last() != Some(&current) => stack.push(current),
+                None if stack.is_empty() => stack.push(ROOT),
+                _ => {}
+            }
+        }
+
+        if stack != self.stack {
+            self.flush_stack();
+            self.stack = stack;
+        }
+    }
+
+    fn flush_stack(&mut self) {
+        if self.stack_cycles > 0 {
+            *self.stacks.entry(self.stack.clone()).or_default() += self.stack_cycles;
+            self.stack_cycles = 0;
+        }
+    }
+
+    /// Adds `cycles` to the current call stack and frame.
+    pub(crate) fn add_cycles(&mut self, cycles: u32, scanline: u8) {
+        self.stack_cycles += u64::from(cycles);
+
+        // Frames are counted from the start of VBlank, when games usually begin their work.
+        let in_vblank = scanline >= VBLANK_DOT;
+        if in_vblank && !self.in_vblank {
+            self.frames.extend(self.frame.take());
+            self.frame = Some(FrameUsage {
+                busy_cycles: 0,
+                halted_cycles: 0,
+            });
+        }
+        self.in_vblank = in_vblank;
+
+        if let Some(frame) = &mut self.frame {
+            if self.stack.first() == Some(&HALTED) {
+                frame.halted_cycles += cycles;
+            } else {
+                frame.busy_cycles += cycles;
+            }
+        }
+    }
+
+    /// CPU usage of each completed frame, oldest first.
+    #[must_use]
+    pub fn frames(&self) -> &[FrameUsage] {
+        &self.frames
+    }
+
+    fn stacks(&mut self) -> &HashMap<Vec<u32>, u64> {
+        self.flush_stack();
+        &self.stacks
+    }
+
+    /// Writes a flat profile of the cycles spent in each function, both in its own code (self)
+    /// and including the functions it called (total), followed by a summary of CPU usage per
+    /// frame.
+    ///
+    /// # Errors
+    /// Returns an error if writing fails.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn write_flat(&mut self, w: &mut impl Write, symbols: &Symbols) -> io::Result<()> {
+        let mut functions = HashMap::<u32, (u64, u64)>::new();
+        let mut all_cycles = 0;
+        for (stack, &cycles) in self.stacks() {
+            let Some(&current) = stack.last() else {
+                continue;
+            };
+            all_cycles += cycles;
+            functions.entry(current).or_default().0 += cycles;
+            for (i, addr) in stack.iter().enumerate() {
+                // Count recursive functions only once.
+                if !stack[..i].contains(addr) {
+                    functions.entry(*addr).or_default().1 += cycles;
+                }
+            }
+        }
+
+        let mut functions: Vec<_> = functions.into_iter().collect();
+        functions.sort_unstable_by_key(|&(addr, (self_cycles, _))| (!self_cycles, addr));
+        let percent = |cycles| 100.0 * cycles as f64 / all_cycles.max(1) as f64;
+
+        writeln!(
+            w,
+            " self %    self cycles  total %   total cycles  function"
+        )?;
+        for (addr, (self_cycles, total_cycles)) in functions {
+            writeln!(
+                w,
+                "{:6.2}%  {self_cycles:>13}  {:6.2}%  {total_cycles:>13}  {}",
+                percent(self_cycles),
+                percent(total_cycles),
+                function_name(addr, symbols)
+            )?;
+        }
+
+        if !self.frames.is_empty() {
+            let usages: Vec<_> = self.frames.iter().map(FrameUsage::usage).collect();
+            let (max_idx, max) =
+                usages
+                    .iter()
+                    .copied()
+                    .enumerate()
+                    .fold((0, 0.0), |a, b| if b.1 > a.1 { b } else { a });
+            writeln!(
+                w,
+                "\n{} frames; CPU usage: {:.1}% average, {:.1}% max (frame {max_idx}), {} frames \
+                 with no time halted",
+                usages.len(),
+                100.0 * usages.iter().sum::<f64>() / usages.len() as f64,
+                100.0 * max,
+                self.frames.iter().filter(|f| f.halted_cycles == 0).count(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the cycles spent in each call stack in the collapsed format used by flamegraph
+    /// tools, like `inferno-flamegraph` or Brendan Gregg's `flamegraph.pl`.
+    ///
+    /// # Errors
+    /// Returns an error if writing fails.
+    pub fn write_collapsed(&mut self, w: &mut impl Write, symbols: &Symbols) -> io::Result<()> {
+        let mut lines: Vec<_> = self
+            .stacks()
+            .iter()
+            .map(|(stack, cycles)| {
+                let names: Vec<_> = stack.iter().map(|&a| function_name(a, symbols)).collect();
+                (names.join(";"), cycles)
+            })
+            .collect();
+        lines.sort_unstable();
+
+        for (stack, cycles) in lines {
+            writeln!(w, "{stack} {cycles}")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn function_name(addr: u32, symbols: &Symbols) -> String {
+    match addr {
+        ROOT => "(root)".into(),
+        HALTED => "(halted)".into(),
+        _ => symbols
+            .lookup(addr)
+            .map_or_else(|| format!("{addr:08x}"), |loc| loc.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arm7tdmi::history::History;
+
+    use super::*;
+
+    #[test]
+    fn profiler_works() {
+        let mut symbols = Symbols::new();
+        symbols.insert(0x0800_0000, "main");
+        symbols.insert(0x0800_0100, "update");
+        let mut profiler = Profiler::new();
+
+        profiler.sample(0x0800_0004, false, None, &symbols);
+        profiler.add_cycles(10, VBLANK_DOT);
+        profiler.sample(0x0800_0104, false, None, &symbols);
+        profiler.add_cycles(20, 0);
+        profiler.sample(0x0800_0008, true, None, &symbols);
+        profiler.add_cycles(70, 0);
+        // Unknown code without history is attributed to the root.
+        profiler.sample(0x0300_0000, false, Some(&History::new(1)), &symbols);
+        profiler.add_cycles(1, VBLANK_DOT);
+
+        let mut collapsed = Vec::new();
+        profiler.write_collapsed(&mut collapsed, &symbols).unwrap();
+        assert_eq!(
+            String::from_utf8(collapsed).unwrap(),
+            "(halted) 70\n(root) 1\nmain 10\nupdate 20\n"
+        );
+
+        let mut flat = Vec::new();
+        profiler.write_flat(&mut flat, &symbols).unwrap();
+        let flat = String::from_utf8(flat).unwrap();
+        let lines: Vec<_> = flat.lines().collect();
+        assert!(lines[1].ends_with("(halted)"), "{flat}");
+        assert!(lines[2].ends_with("update"), "{flat}");
+        assert!(
+            flat.contains("1 frames; CPU usage: 30.0% average"),
+            "{flat}"
+        );
+
+        assert_eq!(
+            profiler.frames(),
+            [FrameUsage {
+                busy_cycles: 30,
+                halted_cycles: 70
+            }]
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Display, Formatter},
+    mem::take,
+};
+
+use crate::arm7tdmi::history::{Frame, History};
+
+/// Something a program did that real hardware tolerates, but is likely a bug.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum IssueKind {
+    /// A halfword or word load from an address not aligned to its size, which is forced into
+    /// alignment and rotated.
+    MisalignedLoad {
+        size: u32,
+    },
+    MisalignedStore {
+        size: u32,
+    },
+    RomWrite,
+    BiosWrite,
+    UnmappedRead,
+    /// An 8-bit write to OAM or OBJ VRAM, which is ignored.
+    ObjByteWrite,
+    InvalidDmaSource {
+        chan_idx: usize,
+    },
+    ReadOnlyIoWrite,
+    /// An access to an IO register that isn't emulated.
+    UnimplementedIo {
+        write: bool,
+    },
+}
+
+impl Display for IssueKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MisalignedLoad { size } => write!(f, "misaligned {size}-byte load from"),
+            Self::MisalignedStore { size } => write!(f, "misaligned {size}-byte store to"),
+            Self::RomWrite => write!(f, "write to cartridge ROM at"),
+            Self::BiosWrite => write!(f, "write to BIOS at"),
+            Self::UnmappedRead => write!(f, "read from unmapped address"),
+            Self::ObjByteWrite => write!(f, "ignored 8-bit write to OAM or OBJ VRAM at"),
+            Self::InvalidDmaSource { chan_idx } => write!(f, "DMA{chan_idx} from invalid source"),
+            Self::ReadOnlyIoWrite => write!(f, "write to read-only IO register"),
+            Self::UnimplementedIo { write: false } => write!(f, "read from unimplemented IO"),
+            Self::UnimplementedIo { write: true } => write!(f, "write to unimplemented IO"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub addr: u32,
+    /// Address of the instruction that caused the issue, or that was last executed if it was
+    /// caused by something else, like a DMA transfer.
+    pub pc: u32,
+    /// Empty if the CPU's history isn't being tracked.
+    pub call_stack: Vec<Frame>,
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:08x} (pc {:08x})", self.kind, self.addr, self.pc)
+    }
+}
+
+/// Checks for suspicious use of the hardware, like misaligned accesses or writes to ROM.
+///
+/// Each kind of issue is only reported once per instruction address.
+#[derive(Default, Debug)]
+pub struct Sanitizer {
+    pending: Vec<(IssueKind, u32)>,
+    reported: HashSet<(IssueKind, u32)>,
+    issues: Vec<Issue>,
+}
+
+impl Sanitizer {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the issues found since the last call.
+    pub fn take_issues(&mut self) -> Vec<Issue> {
+        take(&mut self.issues)
+    }
+
+    pub(crate) fn record(&mut self, kind: IssueKind, addr: u32) {
+        self.pending.push((kind, addr));
+    }
+
+    pub(crate) fn check_dma(&mut self, chan_idx: usize, src_addr: u32) {
+        // BIOS and unused memory can't be read; DMA0 can't read from the cartridge either.
+        let valid = match src_addr >> 24 {
+            0x02..=0x07 => true,
+            0x08..=0x0f => chan_idx != 0,
+            _ => false,
+        };
+        if !valid {
+            self.record(IssueKind::InvalidDmaSource { chan_idx }, src_addr);
+        }
+    }
+
+    /// Attributes the issues recorded since the last call to the instruction at `pc`.
+    pub(crate) fn flush(&mut self, pc: u32, history: Option<&History>) {
+        for (kind, addr) in self.pending.drain(..) {
+            if self.reported.insert((kind, pc)) {
+                self.issues.push(Issue {
+                    kind,
+                    addr,
+                    pc,
+                    call_stack: history.map_or_else(Vec::new, |h| h.call_stack().to_vec()),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitizer_works() {
+        let mut sanitizer = Sanitizer::new();
+        sanitizer.record(IssueKind::RomWrite, 0x0800_0000);
+        sanitizer.check_dma(0, 0x0800_0000);
+        sanitizer.check_dma(3, 0x0800_0000);
+        sanitizer.check_dma(3, 0x0000_0000);
+        sanitizer.flush(0x0300_0000, None);
+
+        let issues = sanitizer.take_issues();
+        assert_eq!(issues.len(), 3);
+        assert_eq!(issues[0].kind, IssueKind::RomWrite);
+        assert_eq!(issues[0].pc, 0x0300_0000);
+        assert_eq!(
+            issues[1].to_string(),
+            "DMA0 from invalid source 08000000 (pc 03000000)"
+        );
+        assert_eq!(issues[2].addr, 0);
+        assert!(sanitizer.take_issues().is_empty());
+
+        // Already reported at this address.
+        sanitizer.record(IssueKind::RomWrite, 0x0800_0004);
+        sanitizer.flush(0x0300_0000, None);
+        assert!(sanitizer.take_issues().is_empty());
+        sanitizer.record(IssueKind::RomWrite, 0x0800_0004);
+        sanitizer.flush(0x0300_0004, None);
+        assert_eq!(sanitizer.take_issues().len(), 1);
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::{self, Display, Formatter},
+};
+
+/// A name for an address, like a function.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Symbol {
+    pub addr: u32,
+    pub name: String,
+}
+
+/// The symbol containing an address, and the offset of the address from it.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Location<'a> {
+    pub symbol: &'a Symbol,
+    pub offset: u32,
+}
+
+impl Display for Location<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.offset == 0 {
+            write!(f, "{}", self.symbol.name)
+        } else {
+            write!(f, "{}+{:#x}", self.symbol.name, self.offset)
+        }
+    }
+}
+
+/// A table of symbols, for resolving addresses into names.
+#[derive(Clone, Default, Debug)]
+pub struct Symbols {
+    by_addr: BTreeMap<u32, Symbol>,
+}
+
+impl Symbols {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a no$gba-style `.sym` file, where each line is a hexadecimal address followed by a
+    /// name.
+    ///
+    /// Comments (starting with `;`) and lines that cannot be parsed are ignored. Directives (names
+    /// starting with `.`) aren't symbols, but `.thumb` marks THUMB code, so bit 0 is cleared from
+    /// the odd addresses of names there.
+    #[must_use]
+    pub fn from_sym(text: &str) -> Self {
+        let mut names = Vec::new();
+        let mut thumb_addrs = HashSet::new();
+        for line in text.lines() {
+            let line = line.split_once(';').map_or(line, |(line, _)| line);
+            let mut parts = line.split_whitespace();
+            let (Some(addr), Some(name)) = (parts.next(), parts.next()) else {
+                continue;
+            };
+            let Ok(addr) = u32::from_str_radix(addr, 16) else {
+                continue;
+            };
+            if name == ".thumb" {
+                thumb_addrs.insert(addr);
+            } else if !name.starts_with('.') {
+                names.push((addr, name));
+            }
+        }
+
+        let mut symbols = Self::new();
+        for (addr, name) in names {
+            let thumb = thumb_addrs.contains(&(addr & !1));
+            symbols.insert(if thumb { addr & !1 } else { addr }, name);
+        }
+
+        symbols
+    }
+
+    /// Parses a GNU ld `.map` file, taking symbols from lines consisting of just an address and a
+    /// name.
+    ///
+    /// Section lines and assignments like `__bss_start = .` are ignored.
+    #[must_use]
+    pub fn from_map(text: &str) -> Self {
+        let mut symbols = Self::new();
+        for line in text.lines() {
+            let mut parts = line.split_whitespace();
+            let (Some(addr), Some(name), None) = (parts.next(), parts.next(), parts.next()) else {
+                continue;
+            };
+            let Some(addr) = addr.strip_prefix("0x") else {
+                continue;
+            };
+            if !name.starts_with(|c: char| c == '_' || c.is_ascii_alphabetic()) {
+                continue;
+            }
+            if let Ok(addr) = u32::from_str_radix(addr, 16) {
+                symbols.insert(addr, name);
+            }
+        }
+
+        symbols
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.by_addr.is_empty()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.by_addr.len()
+    }
+
+    /// Adds a symbol, replacing any other at `addr`.
+    pub fn insert(&mut self, addr: u32, name: impl Into<String>) {
+        self.by_addr.insert(
+            addr,
+            Symbol {
+                addr,
+                name: name.into(),
+            },
+        );
+    }
+
+    /// Adds all of the symbols from `other`, replacing any at the same addresses.
+    pub fn extend(&mut self, other: Symbols) {
+        self.by_addr.extend(other.by_addr);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> + '_ {
+        self.by_addr.values()
+    }
+
+    /// Returns the symbol starting exactly at `addr`.
+    #[must_use]
+    pub fn get(&self, addr: u32) -> Option<&Symbol> {
+        self.by_addr.get(&addr)
+    }
+
+    /// Returns the closest symbol at or before `addr`, within the same memory region.
+    #[must_use]
+    pub fn lookup(&self, addr: u32) -> Option<Location> {
+        let (_, symbol) = self.by_addr.range(..=addr).next_back()?;
+        (symbol.addr >> 24 == addr >> 24).then_some(Location {
+            symbol,
+            offset: addr - symbol.addr,
+        })
+    }
+
+    /// Returns the address of the symbol named `name`.
+    #[must_use]
+    pub fn find(&self, name: &str) -> Option<u32> {
+        self.iter().find(|s| s.name == name).map(|s| s.addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sym_works() {
+        let symbols = Symbols::from_sym(
+            "; generated\n\
+             08000000 .arm\n\
+             08000000 _start\n\
+             080000c1 main\n\
+             080000c0 .thumb\n\
+             bogus\n\
+             03000000 irq_handler\n\
+             03000101 flags ; data\n",
+        );
+        assert_eq!(symbols.len(), 4);
+        assert_eq!(symbols.find("main"), Some(0x0800_00c0));
+        // Only THUMB code has bit 0 set in its addresses.
+        assert_eq!(symbols.find("flags"), Some(0x0300_0101));
+
+        let loc = symbols.lookup(0x0800_00c6).unwrap();
+        assert_eq!(loc.symbol.name, "main");
+        assert_eq!(loc.to_string(), "main+0x6");
+        assert_eq!(symbols.lookup(0x0800_0000).unwrap().to_string(), "_start");
+
+        // Symbols shouldn't extend into other regions.
+        assert!(symbols.lookup(0x0200_0000).is_none());
+        assert!(symbols.lookup(0x0400_0000).is_none());
+    }
+
+    #[test]
+    fn map_works() {
+        let symbols = Symbols::from_map(
+            " .text          0x08000000      0x1a4 crt0.o\n\
+             \x20               0x08000000                _start\n\
+             \x20               0x080001a4                main\n\
+             \x20               0x03000000                __iwram_start = .\n\
+             \x20*(.iwram .iwram.*)\n\
+             \x20               0x0000000003000010                irq_table\n",
+        );
+        assert_eq!(symbols.len(), 3);
+        assert_eq!(symbols.find("_start"), Some(0x0800_0000));
+        assert_eq!(symbols.find("main"), Some(0x0800_01a4));
+        assert_eq!(symbols.find("irq_table"), Some(0x0300_0010));
+        assert_eq!(symbols.find("__iwram_start"), None);
+    }
+}
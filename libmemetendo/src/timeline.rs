@@ -0,0 +1,299 @@
+use std::io::{self, Write};
+
+use crate::{arm7tdmi::CYCLES_PER_SECOND, irq::Interrupt};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// An interrupt's bit in IF was set.
+    IrqRequest(Interrupt),
+    /// An interrupt's bit in IF was cleared by a write.
+    IrqAcknowledge(Interrupt),
+    DmaStart(usize),
+    DmaEnd(usize),
+    TimerOverflow(usize),
+    HBlank,
+    VBlank,
+    /// VCOUNT reached the line set in DISPSTAT.
+    VCount,
+    /// An audio FIFO (0 for A, 1 for B) requested more samples by DMA.
+    FifoRefill(usize),
+    /// The CPU was halted or stopped by HALTCNT.
+    Halt,
+    Wake,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Cycles since recording started.
+    pub cycle: u64,
+    pub event: Event,
+}
+
+/// Track IDs in the exported trace.
+const CPU_TRACK: u32 = 0;
+const IRQ_TRACK: u32 = 1;
+const VIDEO_TRACK: u32 = 2;
+const DMA_TRACKS: u32 = 3;
+const TIMER_TRACKS: u32 = 7;
+const AUDIO_TRACK: u32 = 11;
+
+/// Records hardware events, timestamped in emulated cycles.
+#[derive(Default, Debug)]
+pub struct Timeline {
+    cycle: u64,
+    records: Vec<Record>,
+    /// The channel whose DMA transfer was last started and not yet ended.
+    dma_chan_idx: Option<usize>,
+}
+
+impl Timeline {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn record(&mut self, event: Event) {
+        self.records.push(Record {
+            cycle: self.cycle,
+            event,
+        });
+    }
+
+    /// Records the end of the last DMA transfer and the start of `chan_idx`'s, if it's a different
+    /// channel. So a transfer preempted by another channel ends, and starts again when resumed.
+    pub(crate) fn set_dma_transfer(&mut self, chan_idx: Option<usize>) {
+        if chan_idx == self.dma_chan_idx {
+            return;
+        }
+        if let Some(prev_chan_idx) = self.dma_chan_idx {
+            self.record(Event::DmaEnd(prev_chan_idx));
+        }
+        if let Some(chan_idx) = chan_idx {
+            self.record(Event::DmaStart(chan_idx));
+        }
+        self.dma_chan_idx = chan_idx;
+    }
+
+    pub(crate) fn add_cycles(&mut self, cycles: u32) {
+        self.cycle += u64::from(cycles);
+    }
+
+    /// Cycles since recording started.
+    #[must_use]
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    #[must_use]
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Writes the events as Chrome Trace Event JSON, as viewed by Perfetto or
+    /// `chrome://tracing`. DMA transfers and halts are shown as durations on their own tracks.
+    ///
+    /// # Errors
+    /// Returns an error if writing fails.
+    pub fn write_chrome_trace(&self, w: &mut impl Write) -> io::Result<()> {
+        write!(w, "{{\"displayTimeUnit\":\"ns\",\"traceEvents\":[")?;
+
+        let mut tracks = vec![
+            (CPU_TRACK, "CPU".to_string()),
+            (IRQ_TRACK, "IRQ".to_string()),
+            (VIDEO_TRACK, "Video".to_string()),
+            (AUDIO_TRACK, "Audio FIFOs".to_string()),
+        ];
+        tracks.extend((0..4).map(|i| (DMA_TRACKS + i, format!("DMA{i}"))));
+        tracks.extend((0..4).map(|i| (TIMER_TRACKS + i, format!("Timer {i}"))));
+        for (i, (track, name)) in tracks.iter().enumerate() {
+            if i > 0 {
+                write!(w, ",")?;
+            }
+            write!(
+                w,
+                "\n{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":0,\"tid\":{track},\
+                 \"args\":{{\"name\":\"{name}\"}}}}"
+            )?;
+            write!(
+                w,
+                ",\n{{\"name\":\"thread_sort_index\",\"ph\":\"M\",\"pid\":0,\"tid\":{track},\
+                 \"args\":{{\"sort_index\":{track}}}}}"
+            )?;
+        }
+
+        for record in &self.records {
+            #[allow(clippy::cast_possible_truncation)]
+            let (track, phase, name) = match record.event {
+                Event::IrqRequest(interrupt) => (IRQ_TRACK, "i", format!("request {interrupt:?}")),
+                Event::IrqAcknowledge(interrupt) => {
+                    (IRQ_TRACK, "i", format!("acknowledge {interrupt:?}"))
+                }
+                Event::DmaStart(chan_idx) => {
+                    (DMA_TRACKS + chan_idx as u32, "B", "transfer".to_string())
+                }
+                Event::DmaEnd(chan_idx) => {
+                    (DMA_TRACKS + chan_idx as u32, "E", "transfer".to_string())
+                }
+                Event::TimerOverflow(timer_idx) => {
+                    (TIMER_TRACKS + timer_idx as u32, "i", "overflow".to_string())
+                }
+                Event::HBlank => (VIDEO_TRACK, "i", "HBlank".to_string()),
+                Event::VBlank => (VIDEO_TRACK, "i", "VBlank".to_string()),
+                Event::VCount => (VIDEO_TRACK, "i", "VCount".to_string()),
+                Event::FifoRefill(fifo_idx) => (
+                    AUDIO_TRACK,
+                    "i",
+                    format!("FIFO {} refill", ["A", "B"][fifo_idx]),
+                ),
+                Event::Halt => (CPU_TRACK, "B", "halted".to_string()),
+                Event::Wake => (CPU_TRACK, "E", "halted".to_string()),
+            };
+            #[allow(clippy::cast_precision_loss)]
+            let micros = record.cycle as f64 * 1e6 / f64::from(CYCLES_PER_SECOND);
+            let scope = if phase == "i" { ",\"s\":\"t\"" } else { "" };
+
+            write!(
+                w,
+                ",\n{{\"name\":\"{name}\",\"ph\":\"{phase}\"{scope},\"ts\":{micros:.3},\"pid\":0,\
+                 \"tid\":{track},\"args\":{{\"cycle\":{}}}}}",
+                record.cycle
+            )?;
+        }
+
+        writeln!(w, "\n]}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{arm7tdmi::CYCLES_PER_FRAME, bus::Bus, gba::tests::test_gba, util};
+
+    use super::*;
+
+    #[test]
+    fn timeline_works() {
+        let mut timeline = Timeline::new();
+        timeline.record(Event::DmaStart(3));
+        timeline.add_cycles(CYCLES_PER_SECOND);
+        timeline.record(Event::IrqRequest(Interrupt::VBlank));
+        timeline.record(Event::DmaEnd(3));
+        assert_eq!(timeline.cycle(), CYCLES_PER_SECOND.into());
+        assert_eq!(
+            timeline.records()[1],
+            Record {
+                cycle: CYCLES_PER_SECOND.into(),
+                event: Event::IrqRequest(Interrupt::VBlank)
+            }
+        );
+
+        let mut trace = Vec::new();
+        timeline.write_chrome_trace(&mut trace).unwrap();
+        let trace = String::from_utf8(trace).unwrap();
+        assert!(trace.starts_with("{\"displayTimeUnit\":\"ns\",\"traceEvents\":["));
+        assert!(trace.ends_with("\n]}\n"));
+        assert!(trace.contains("\"args\":{\"name\":\"DMA3\"}"));
+        assert!(trace.contains(
+            "{\"name\":\"transfer\",\"ph\":\"B\",\"ts\":0.000,\"pid\":0,\"tid\":6,\
+             \"args\":{\"cycle\":0}}"
+        ));
+        assert!(trace.contains(
+            "{\"name\":\"request VBlank\",\"ph\":\"i\",\"s\":\"t\",\"ts\":1000000.000,\"pid\":0,\
+             \"tid\":1,\"args\":{\"cycle\":16779884}}"
+        ));
+        assert!(trace.contains("\"ph\":\"E\",\"ts\":1000000.000,\"pid\":0,\"tid\":6"));
+    }
+
+    #[test]
+    fn gba_timeline_works() {
+        let mut gba = test_gba(&[]);
+        gba.timeline = Some(Timeline::new());
+
+        // DISPSTAT: VBlank IRQ and VCOUNT at line 100.
+        gba.video.write_hword(0x04, 0x6408);
+        // Timer 0 overflowing every 256 * 64 cycles, and an immediate DMA3 of 4 words.
+        gba.timers.write_hword(0x100, 0xff00);
+        gba.timers.write_hword(0x102, 0x0081);
+        gba.dma.write_word(0xd4, 0x0300_0000);
+        gba.dma.write_word(0xd8, 0x0300_0100);
+        gba.dma.write_hword(0xdc, 4);
+        gba.dma.write_hword(0xde, 0x8400);
+        while gba.timeline.as_ref().unwrap().cycle() < CYCLES_PER_FRAME.into() {
+            gba.step(
+                &mut util::video::NullCallback,
+                &mut util::audio::NullCallback,
+            );
+        }
+
+        let records = gba.timeline.as_ref().unwrap().records();
+        let count = |event| records.iter().filter(|r| r.event == event).count();
+        assert_eq!(count(Event::HBlank), 228);
+        assert_eq!(count(Event::VBlank), 1);
+        assert_eq!(count(Event::VCount), 1);
+        assert_eq!(count(Event::IrqRequest(Interrupt::VBlank)), 1);
+        assert_eq!(count(Event::DmaStart(3)), 1);
+        assert_eq!(count(Event::DmaEnd(3)), 1);
+        assert_eq!(count(Event::TimerOverflow(0)), 17);
+
+        let pos = |event| records.iter().position(|r| r.event == event).unwrap();
+        assert!(pos(Event::DmaStart(3)) < pos(Event::DmaEnd(3)));
+        assert!(pos(Event::VCount) < pos(Event::VBlank));
+    }
+
+    #[test]
+    fn preempted_dma_works() {
+        let mut gba = test_gba(&[]);
+        gba.timeline = Some(Timeline::new());
+
+        // An immediate DMA3 of 0x10000 half-words, lasting many scanlines, preempted by a
+        // repeating HBlank DMA0 of 4 half-words.
+        gba.dma.write_word(0xd4, 0x0200_0000);
+        gba.dma.write_word(0xd8, 0x0202_0000);
+        gba.dma.write_hword(0xdc, 0);
+        gba.dma.write_word(0xb0, 0x0300_0000);
+        gba.dma.write_word(0xb4, 0x0300_0100);
+        gba.dma.write_hword(0xb8, 4);
+        gba.dma.write_hword(0xba, 0xa200);
+        gba.dma.write_hword(0xde, 0x8000);
+        while gba.timeline.as_ref().unwrap().cycle() < CYCLES_PER_FRAME.into() {
+            gba.step(
+                &mut util::video::NullCallback,
+                &mut util::audio::NullCallback,
+            );
+        }
+
+        // Transfers don't overlap, and each channel's start and end in turn.
+        let records = gba.timeline.as_ref().unwrap().records();
+        let dma_records: Vec<_> = records
+            .iter()
+            .filter(|r| matches!(r.event, Event::DmaStart(_) | Event::DmaEnd(_)))
+            .collect();
+        let mut open_chan_idx = None;
+        for record in &dma_records {
+            match record.event {
+                Event::DmaStart(chan_idx) => {
+                    assert_eq!(open_chan_idx, None, "{record:?} overlaps");
+                    open_chan_idx = Some(chan_idx);
+                }
+                Event::DmaEnd(chan_idx) => {
+                    assert_eq!(open_chan_idx, Some(chan_idx), "{record:?} didn't start");
+                    open_chan_idx = None;
+                }
+                _ => unreachable!(),
+            }
+        }
+        assert_eq!(open_chan_idx, None);
+
+        // DMA3 ends when preempted by DMA0, and starts again after.
+        let preemptions = dma_records
+            .windows(2)
+            .filter(|w| {
+                w[0].event == Event::DmaEnd(3)
+                    && w[1].event == Event::DmaStart(0)
+                    && w[0].cycle == w[1].cycle
+            })
+            .count();
+        let count = |event| records.iter().filter(|r| r.event == event).count();
+        assert!(preemptions > 10, "{preemptions}");
+        assert_eq!(count(Event::DmaStart(3)), preemptions + 1);
+    }
+}
@@ -6,6 +6,7 @@ use strum_macros::FromRepr;
 use crate::{
     audio::Audio,
     bus::Bus,
+    io_regs::IoRegister,
     irq::{Interrupt, Irq},
 };
 
@@ -19,6 +20,20 @@ enum PrescalarSelect {
     Div1024,
 }
 
+impl PrescalarSelect {
+    fn div(&self) -> u32 {
+        match self {
+            Self::Div1 => 1,
+            Self::Div64 => 64,
+            Self::Div256 => 256,
+            Self::Div1024 => MAX_DIV,
+        }
+    }
+}
+
+const MAX_DIV: u32 = 1024;
+
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Default)]
 struct Control {
     accum: u32,
@@ -29,6 +44,8 @@ struct Control {
     irq_enabled: bool,
     start: bool,
     cached_bits: u16,
+    /// Whether the timer overflowed since last taken.
+    overflowed: bool,
 }
 
 #[derive(Debug, Default)]
@@ -54,15 +71,7 @@ impl Timers {
                 if timer.cascade {
                     prev_overflow_count
                 } else {
-                    const MAX_DIV: u32 = 1024;
-
-                    let div = match timer.prescalar_select {
-                        PrescalarSelect::Div1 => 1,
-                        PrescalarSelect::Div64 => 64,
-                        PrescalarSelect::Div256 => 256,
-                        PrescalarSelect::Div1024 => MAX_DIV,
-                    };
-                    timer.accum += u32::from(cycles) * MAX_DIV / div;
+                    timer.accum += u32::from(cycles) * MAX_DIV / timer.prescalar_select.div();
                     if timer.accum < MAX_DIV {
                         continue;
                     }
@@ -93,6 +102,7 @@ impl Timers {
                     );
                 }
                 audio.notify_timer_overflow(i, overflow_count);
+                timer.overflowed = true;
                 prev_overflow_count = overflow_count.into();
 
                 new_counter
@@ -103,6 +113,63 @@ impl Timers {
     }
 }
 
+impl Timers {
+    /// The number of cycles until the next timer overflows, if any are running. Cascading timers
+    /// can only overflow when the timer before them does.
+    #[cfg(feature = "jit")]
+    pub(crate) fn cycles_until_overflow(&self) -> Option<u32> {
+        self.0
+            .iter()
+            .filter(|timer| timer.start && !timer.cascade)
+            .map(|timer| {
+                let accum_per_cycle = MAX_DIV / timer.prescalar_select.div();
+                let accum_left = (0x1_0000 - u32::from(timer.counter)) * MAX_DIV - timer.accum;
+
+                (accum_left + accum_per_cycle - 1) / accum_per_cycle
+            })
+            .min()
+    }
+
+    /// Returns whether timer `timer_idx` overflowed since the last call.
+    pub(crate) fn take_overflowed(&mut self, timer_idx: usize) -> bool {
+        take(&mut self.0[timer_idx].overflowed)
+    }
+
+    pub(crate) fn io_registers(&self) -> Vec<IoRegister> {
+        let mut regs = Vec::new();
+        for (i, timer) in self.0.iter().enumerate() {
+            let names = [
+                ("TM0CNT_L", "TM0CNT_H"),
+                ("TM1CNT_L", "TM1CNT_H"),
+                ("TM2CNT_L", "TM2CNT_H"),
+                ("TM3CNT_L", "TM3CNT_H"),
+            ][i];
+            let addr = 0x100 + 4 * u32::try_from(i).unwrap();
+            let prescaler = match timer.prescalar_select {
+                PrescalarSelect::Div1 => "1",
+                PrescalarSelect::Div64 => "64",
+                PrescalarSelect::Div256 => "256",
+                PrescalarSelect::Div1024 => "1024",
+            };
+
+            regs.push(
+                IoRegister::new(names.0, addr, 2, timer.counter.into())
+                    .number("counter", timer.counter)
+                    .number("reload", timer.initial),
+            );
+            regs.push(
+                IoRegister::new(names.1, addr + 2, 2, timer.cached_bits.into())
+                    .named("prescaler", prescaler)
+                    .flag("cascade", timer.cascade)
+                    .flag("irq", timer.irq_enabled)
+                    .flag("start", timer.start),
+            );
+        }
+
+        regs
+    }
+}
+
 impl Bus for Timers {
     fn read_byte(&mut self, addr: u32) -> u8 {
         assert!((0x100..0x110).contains(&addr), "IO register address OOB");
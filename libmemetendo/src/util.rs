@@ -12,37 +12,330 @@ macro_rules! arbitrary_sign_extend {
 }
 
 pub mod video {
-    use crate::video::{Callback, Dot, HBLANK_DOT, VBLANK_DOT};
+    use std::{
+        error::Error,
+        fmt::{self, Display, Formatter},
+        str::FromStr,
+    };
+
+    use crate::video::Callback;
+
+    pub mod filter;
+
+    /// Converts BGR555 dots to 8-bit RGB, 3 bytes per dot, with components scaled by bit
+    /// replication.
+    pub fn bgr555_to_rgb24(src: &[u16], dst: &mut [u8]) {
+        for (&dot, dst) in src.iter().zip(dst.chunks_exact_mut(3)) {
+            dst.copy_from_slice(&components_8bit(dot));
+        }
+    }
+
+    /// Converts BGR555 dots to 8-bit RGBA, 4 bytes per dot, with full alpha.
+    pub fn bgr555_to_rgba8888(src: &[u16], dst: &mut [u8]) {
+        for (&dot, dst) in src.iter().zip(dst.chunks_exact_mut(4)) {
+            let [r, g, b] = components_8bit(dot);
+            dst.copy_from_slice(&[r, g, b, 0xff]);
+        }
+    }
+
+    /// Converts BGR555 dots to RGB565, with green's lowest bit copied from its highest.
+    pub fn bgr555_to_rgb565(src: &[u16], dst: &mut [u16]) {
+        for (&dot, dst) in src.iter().zip(dst) {
+            let (r, g, b) = (dot & 0x1f, (dot >> 5) & 0x1f, (dot >> 10) & 0x1f);
+            *dst = r << 11 | g << 6 | (g >> 4) << 5 | b;
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn components_8bit(dot: u16) -> [u8; 3] {
+        [dot, dot >> 5, dot >> 10].map(|c| {
+            let c = (c & 0x1f) as u8;
+            c << 3 | c >> 2
+        })
+    }
+
+    /// Emulates how a screen shows colours: components are linearized with `gamma`, mixed by
+    /// `matrix` (each row giving an output component), then encoded for a display with a gamma
+    /// of 2.2.
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    pub struct ColorCorrection {
+        pub matrix: [[f32; 3]; 3],
+        pub gamma: f32,
+    }
+
+    impl ColorCorrection {
+        const DISPLAY_GAMMA: f32 = 2.2;
 
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        fn apply(&self, dot: u16) -> [u8; 3] {
+            let linear =
+                [dot, dot >> 5, dot >> 10].map(|c| (f32::from(c & 0x1f) / 31.0).powf(self.gamma));
+
+            self.matrix.map(|row| {
+                let c = row.iter().zip(linear).map(|(m, c)| m * c).sum::<f32>();
+                (c.clamp(0.0, 1.0).powf(1.0 / Self::DISPLAY_GAMMA) * 255.0).round() as u8
+            })
+        }
+    }
+
+    /// Screens whose colours can be emulated.
+    #[derive(Debug, Copy, Clone, PartialEq, Default)]
+    pub enum ColorProfile {
+        /// The dots as they are, like an emulator without colour correction.
+        #[default]
+        Raw,
+        /// The original GBA's LCD: dark without a backlight, and washed out.
+        GbaLcd,
+        /// The backlit GBA SP (AGS-101): brighter, with less colour bleed.
+        GbaSp,
+        /// The Game Boy Player on a TV: close to raw, with slightly lighter shades.
+        GameBoyPlayer,
+        Custom(ColorCorrection),
+    }
+
+    impl ColorProfile {
+        #[must_use]
+        pub fn correction(self) -> Option<ColorCorrection> {
+            match self {
+                Self::Raw => None,
+                Self::GbaLcd => Some(ColorCorrection {
+                    matrix: [
+                        [0.82, 0.24, -0.06],
+                        [0.125, 0.665, 0.21],
+                        [0.195, 0.075, 0.73],
+                    ]
+                    .map(|row| row.map(|m| m * 0.94)),
+                    gamma: 3.2,
+                }),
+                Self::GbaSp => Some(ColorCorrection {
+                    matrix: [
+                        [0.86, 0.19, -0.05],
+                        [0.11, 0.66, 0.23],
+                        [0.1325, 0.0575, 0.81],
+                    ],
+                    gamma: 2.2,
+                }),
+                Self::GameBoyPlayer => Some(ColorCorrection {
+                    matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+                    gamma: 2.0,
+                }),
+                Self::Custom(correction) => Some(correction),
+            }
+        }
+    }
+
+    #[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+    pub struct ParseColorProfileError;
+
+    impl Display for ParseColorProfileError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Invalid colour profile; expected raw, gba, gba-sp, gb-player, or 9 \
+                 comma-separated matrix elements (row by row) followed by a gamma"
+            )
+        }
+    }
+
+    impl Error for ParseColorProfileError {}
+
+    impl FromStr for ColorProfile {
+        type Err = ParseColorProfileError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "raw" => return Ok(Self::Raw),
+                "gba" => return Ok(Self::GbaLcd),
+                "gba-sp" => return Ok(Self::GbaSp),
+                "gb-player" => return Ok(Self::GameBoyPlayer),
+                _ => {}
+            }
+
+            let values = s
+                .split(',')
+                .map(|v| v.trim().parse::<f32>().map_err(|_| ParseColorProfileError))
+                .collect::<Result<Vec<_>, _>>()?;
+            let [m00, m01, m02, m10, m11, m12, m20, m21, m22, gamma] = values[..] else {
+                return Err(ParseColorProfileError);
+            };
+
+            Ok(Self::Custom(ColorCorrection {
+                matrix: [[m00, m01, m02], [m10, m11, m12], [m20, m21, m22]],
+                gamma,
+            }))
+        }
+    }
+
+    /// Maps every BGR555 colour to 8-bit RGB for a colour profile.
     #[derive(Clone, Debug)]
-    pub struct FrameBuffer<const STRIDE: usize = 3>(pub Box<[u8]>);
+    pub struct ColorTable(Box<[[u8; 3]]>);
 
-    impl<const STRIDE: usize> Default for FrameBuffer<STRIDE> {
+    impl Default for ColorTable {
         fn default() -> Self {
-            Self::new(0)
+            Self::new(ColorProfile::default())
+        }
+    }
+
+    impl ColorTable {
+        #[must_use]
+        pub fn new(profile: ColorProfile) -> Self {
+            let correction = profile.correction();
+            let table = (0..0x8000)
+                .map(|dot| correction.map_or_else(|| components_8bit(dot), |c| c.apply(dot)))
+                .collect();
+
+            Self(table)
+        }
+
+        #[must_use]
+        pub fn rgb(&self, dot: u16) -> [u8; 3] {
+            self.0[usize::from(dot & 0x7fff)]
+        }
+
+        /// Like [`bgr555_to_rgb24`], but with the table's colour profile.
+        pub fn to_rgb24(&self, src: &[u16], dst: &mut [u8]) {
+            for (&dot, dst) in src.iter().zip(dst.chunks_exact_mut(3)) {
+                dst.copy_from_slice(&self.rgb(dot));
+            }
         }
+
+        /// Like [`bgr555_to_rgba8888`], but with the table's colour profile.
+        pub fn to_rgba8888(&self, src: &[u16], dst: &mut [u8]) {
+            for (&dot, dst) in src.iter().zip(dst.chunks_exact_mut(4)) {
+                let [r, g, b] = self.rgb(dot);
+                dst.copy_from_slice(&[r, g, b, 0xff]);
+            }
+        }
+    }
+
+    /// How frames are mixed with the ones before them, emulating the ghosting of the GBA's slow
+    /// LCD. Some games rely on it to make sprites flickered on alternate frames look transparent.
+    #[derive(Debug, Copy, Clone, PartialEq, Default)]
+    pub enum FrameBlending {
+        #[default]
+        Off,
+        /// Mixes in this much (0 to 1) of the previous frame.
+        Mix(f32),
+        /// Dots move towards their new colour, keeping this much (0 to 1) of the colour they were
+        /// showing. Unlike [`Self::Mix`], changes fade out over several frames.
+        Lcd(f32),
+    }
+
+    impl FrameBlending {
+        const DEFAULT_WEIGHT: f32 = 0.5;
+    }
+
+    #[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+    pub struct ParseFrameBlendingError;
+
+    impl Display for ParseFrameBlendingError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Invalid frame blending; expected off, mix or lcd, optionally followed by a colon \
+                 and a weight from 0 to 1"
+            )
+        }
+    }
+
+    impl Error for ParseFrameBlendingError {}
+
+    impl FromStr for FrameBlending {
+        type Err = ParseFrameBlendingError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            if s == "off" {
+                return Ok(Self::Off);
+            }
+
+            let (mode, weight) = match s.split_once(':') {
+                Some((mode, weight)) => (
+                    mode,
+                    weight.trim().parse().map_err(|_| ParseFrameBlendingError)?,
+                ),
+                None => (s, Self::DEFAULT_WEIGHT),
+            };
+            if !(0.0..=1.0).contains(&weight) {
+                return Err(ParseFrameBlendingError);
+            }
+
+            match mode {
+                "mix" => Ok(Self::Mix(weight)),
+                "lcd" => Ok(Self::Lcd(weight)),
+                _ => Err(ParseFrameBlendingError),
+            }
+        }
+    }
+
+    /// Converts frames to 8-bit RGB like [`ColorTable`], blending them according to a
+    /// [`FrameBlending`].
+    #[derive(Clone, Debug, Default)]
+    pub struct FrameBlender {
+        blending: FrameBlending,
+        prev: Option<Box<[[f32; 3]]>>,
     }
 
-    impl<const STRIDE: usize> FrameBuffer<STRIDE> {
-        /// # Panics
-        ///
-        /// Panics if `STRIDE` < 3, as this is an RGB buffer.
+    impl FrameBlender {
         #[must_use]
-        pub fn new(fill: u8) -> Self {
-            assert!(STRIDE >= 3);
-            Self(vec![fill; STRIDE * HBLANK_DOT as usize * VBLANK_DOT as usize].into_boxed_slice())
+        pub fn new(blending: FrameBlending) -> Self {
+            Self {
+                blending,
+                prev: None,
+            }
+        }
+
+        /// Forgets the previous frame, so the next one is shown as-is.
+        pub fn reset(&mut self) {
+            self.prev = None;
         }
 
-        pub fn put_dot(&mut self, x: u8, y: u8, dot: Dot) {
-            let i = STRIDE * (usize::from(y) * usize::from(HBLANK_DOT) + usize::from(x));
-            self.0[i] = dot.red() * 8;
-            self.0[i + 1] = dot.green() * 8;
-            self.0[i + 2] = dot.blue() * 8;
+        pub fn to_rgb24(&mut self, colors: &ColorTable, src: &[u16], dst: &mut [u8]) {
+            let mut dst = dst.chunks_exact_mut(3);
+            self.blend(colors, src, |rgb| {
+                if let Some(dst) = dst.next() {
+                    dst.copy_from_slice(&rgb);
+                }
+            });
         }
 
-        pub fn green_swap(&mut self) {
-            for i in (0..self.0.len()).step_by(STRIDE * 2) {
-                self.0.swap(i + 1, i + STRIDE + 1);
+        pub fn to_rgba8888(&mut self, colors: &ColorTable, src: &[u16], dst: &mut [u8]) {
+            let mut dst = dst.chunks_exact_mut(4);
+            self.blend(colors, src, |[r, g, b]| {
+                if let Some(dst) = dst.next() {
+                    dst.copy_from_slice(&[r, g, b, 0xff]);
+                }
+            });
+        }
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        fn blend(&mut self, colors: &ColorTable, src: &[u16], mut emit: impl FnMut([u8; 3])) {
+            let (FrameBlending::Mix(weight) | FrameBlending::Lcd(weight)) = self.blending else {
+                src.iter().for_each(|&dot| emit(colors.rgb(dot)));
+                return;
+            };
+
+            if self
+                .prev
+                .as_ref()
+                .map_or(true, |prev| prev.len() != src.len())
+            {
+                self.prev = Some(
+                    src.iter()
+                        .map(|&dot| colors.rgb(dot).map(f32::from))
+                        .collect(),
+                );
+            }
+            let lcd = matches!(self.blending, FrameBlending::Lcd(_));
+
+            for (&dot, prev) in src.iter().zip(self.prev.as_mut().unwrap().iter_mut()) {
+                let cur = colors.rgb(dot).map(f32::from);
+                let mut out = cur;
+                for ((out, cur), prev) in out.iter_mut().zip(cur).zip(*prev) {
+                    *out = cur + (prev - cur) * weight;
+                }
+
+                *prev = if lcd { out } else { cur };
+                emit(out.map(|c| c.round() as u8));
             }
         }
     }
@@ -50,14 +343,102 @@ pub mod video {
     pub struct NullCallback;
 
     impl Callback for NullCallback {
-        fn put_dot(&mut self, _: u8, _: u8, _: Dot) {}
-
-        fn end_frame(&mut self, _: bool) {}
+        fn end_frame(&mut self, _: &[u16]) {}
 
         fn is_frame_skipping(&self) -> bool {
             false
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn conversions_work() {
+            let src = [0x7fff, 0x001f, 0x03e0, 0x7c00, 0x0421];
+
+            let mut rgb24 = [0; 15];
+            bgr555_to_rgb24(&src, &mut rgb24);
+            assert_eq!(
+                rgb24,
+                [255, 255, 255, 255, 0, 0, 0, 255, 0, 0, 0, 255, 8, 8, 8]
+            );
+
+            let mut rgba8888 = [0; 20];
+            bgr555_to_rgba8888(&src, &mut rgba8888);
+            assert_eq!(rgba8888[..8], [255, 255, 255, 255, 255, 0, 0, 255]);
+
+            let mut rgb565 = [0; 5];
+            bgr555_to_rgb565(&src, &mut rgb565);
+            assert_eq!(rgb565, [0xffff, 0xf800, 0x07e0, 0x001f, 0x0841]);
+        }
+
+        #[test]
+        fn color_profiles_work() {
+            let raw = ColorTable::new(ColorProfile::Raw);
+            assert_eq!(raw.rgb(0x7fff), [255; 3]);
+            assert_eq!(raw.rgb(0x4210), [132; 3]);
+
+            // The unlit LCD never reaches full white, and bleeds red into the other components.
+            let gba = ColorTable::new(ColorProfile::GbaLcd);
+            assert_eq!(gba.rgb(0), [0; 3]);
+            assert!(gba.rgb(0x7fff).iter().all(|&c| (240..255).contains(&c)));
+            let [_, g, b] = gba.rgb(0x001f);
+            assert!(g > 0 && b > 0);
+
+            assert_eq!("gba-sp".parse(), Ok(ColorProfile::GbaSp));
+            let identity: ColorProfile = "1,0,0, 0,1,0, 0,0,1, 2.2".parse().unwrap();
+            assert_eq!(
+                identity.correction().unwrap().matrix,
+                [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+            );
+            assert_eq!(ColorTable::new(identity).rgb(0x7c00), [0, 0, 255]);
+            assert_eq!("1,0,0".parse::<ColorProfile>(), Err(ParseColorProfileError));
+            assert_eq!("gbc".parse::<ColorProfile>(), Err(ParseColorProfileError));
+        }
+
+        #[test]
+        fn frame_blending_works() {
+            let colors = ColorTable::default();
+            let mut rgb = [0; 6];
+            let mut blend = |blender: &mut FrameBlender, src: [u16; 2]| {
+                blender.to_rgb24(&colors, &src, &mut rgb);
+                rgb
+            };
+
+            let mut off = FrameBlender::default();
+            blend(&mut off, [0x7fff, 0]);
+            assert_eq!(blend(&mut off, [0, 0x7fff]), [0, 0, 0, 255, 255, 255]);
+
+            // Sprites flickering on alternate frames look half transparent.
+            let mut mix = FrameBlender::new("mix".parse().unwrap());
+            assert_eq!(blend(&mut mix, [0x7fff, 0]), [255, 255, 255, 0, 0, 0]);
+            assert_eq!(blend(&mut mix, [0, 0]), [128, 128, 128, 0, 0, 0]);
+            assert_eq!(blend(&mut mix, [0x7fff, 0]), [128, 128, 128, 0, 0, 0]);
+            assert_eq!(blend(&mut mix, [0x7fff, 0]), [255, 255, 255, 0, 0, 0]);
+
+            // The LCD takes a few frames to settle.
+            let mut lcd = FrameBlender::new("lcd:0.5".parse().unwrap());
+            blend(&mut lcd, [0x7fff, 0]);
+            assert_eq!(blend(&mut lcd, [0, 0])[0], 128);
+            assert_eq!(blend(&mut lcd, [0, 0])[0], 64);
+            assert_eq!(blend(&mut lcd, [0, 0])[0], 32);
+            lcd.reset();
+            assert_eq!(blend(&mut lcd, [0x7fff, 0])[0], 255);
+
+            let mut rgba = [0; 8];
+            mix.to_rgba8888(&colors, &[0, 0x7fff], &mut rgba);
+            assert_eq!(rgba, [128, 128, 128, 255, 128, 128, 128, 255]);
+
+            assert_eq!("off".parse(), Ok(FrameBlending::Off));
+            assert_eq!("mix:0.25".parse(), Ok(FrameBlending::Mix(0.25)));
+            assert_eq!("lcd".parse(), Ok(FrameBlending::Lcd(0.5)));
+            assert!("lcd:1.5".parse::<FrameBlending>().is_err());
+            assert!("mix:".parse::<FrameBlending>().is_err());
+            assert!("blur".parse::<FrameBlending>().is_err());
+        }
+    }
 }
 
 pub mod audio {
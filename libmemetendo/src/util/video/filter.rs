@@ -0,0 +1,1367 @@
+//! Upscaling filters for pixel art, applied to frames of BGR555 dots before they're converted for
+//! display.
+
+#![allow(clippy::doc_markdown, clippy::many_single_char_names)]
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+    sync::OnceLock,
+};
+
+use super::components_8bit;
+
+pub trait Filter {
+    /// How many times wider and taller the output is than the input.
+    fn scale(&self) -> usize;
+
+    /// Upscales `src`, which is `width` dots wide, into `out`, which must be [`Self::scale`] times
+    /// as wide and as tall.
+    fn apply(&self, src: &[u16], width: usize, out: &mut [u16]);
+}
+
+/// Nearest-neighbour scaling, optionally darkening the gaps between rows like a CRT's scanlines,
+/// or between rows and columns like an LCD's grid.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Nearest {
+    scale: usize,
+    effect: Effect,
+}
+
+impl Nearest {
+    /// Returns [`None`] if `scale` isn't 2, 3 or 4.
+    #[must_use]
+    pub fn new(scale: usize, effect: Effect) -> Option<Self> {
+        (2..=4).contains(&scale).then_some(Self { scale, effect })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Effect {
+    #[default]
+    None,
+    Scanlines,
+    LcdGrid,
+}
+
+impl Filter for Nearest {
+    fn scale(&self) -> usize {
+        self.scale
+    }
+
+    fn apply(&self, src: &[u16], width: usize, out: &mut [u16]) {
+        let last = self.scale - 1;
+        upscale(src, width, out, self.scale, |kernel, block| {
+            let dot = kernel.at(0, 0);
+            match self.effect {
+                Effect::None => {}
+                Effect::Scanlines => {
+                    for col in 0..=last {
+                        block.set(last, col, darken(dot, 1, 2));
+                    }
+                }
+                Effect::LcdGrid => {
+                    for i in 0..=last {
+                        block.set(last, i, darken(dot, 3, 4));
+                        block.set(i, last, darken(dot, 3, 4));
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// The Scale2x, Scale3x and Scale4x (Scale2x applied twice) algorithms from `AdvanceMAME`, which
+/// extend edges without blending colours. Scale2x gives the same results as EPX, which it was
+/// derived from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ScaleNx(usize);
+
+impl ScaleNx {
+    /// Returns [`None`] if `scale` isn't 2, 3 or 4.
+    #[must_use]
+    pub fn new(scale: usize) -> Option<Self> {
+        (2..=4).contains(&scale).then_some(Self(scale))
+    }
+
+    fn scale2x(src: &[u16], width: usize, out: &mut [u16]) {
+        upscale(src, width, out, 2, |kernel, block| {
+            let (b, d, e, f, h) = (
+                kernel.at(0, -1),
+                kernel.at(-1, 0),
+                kernel.at(0, 0),
+                kernel.at(1, 0),
+                kernel.at(0, 1),
+            );
+            if b == h || d == f {
+                return;
+            }
+
+            block.set(0, 0, if d == b { d } else { e });
+            block.set(0, 1, if b == f { f } else { e });
+            block.set(1, 0, if d == h { d } else { e });
+            block.set(1, 1, if h == f { f } else { e });
+        });
+    }
+
+    fn scale3x(src: &[u16], width: usize, out: &mut [u16]) {
+        upscale(src, width, out, 3, |kernel, block| {
+            let [a, b, c, d, e, f, g, h, i] = kernel.neighbours();
+            if b == h || d == f {
+                return;
+            }
+
+            block.set(0, 0, if d == b { d } else { e });
+            let top = (d == b && e != c) || (b == f && e != a);
+            block.set(0, 1, if top { b } else { e });
+            block.set(0, 2, if b == f { f } else { e });
+            let left = (d == b && e != g) || (d == h && e != a);
+            block.set(1, 0, if left { d } else { e });
+            let right = (b == f && e != i) || (h == f && e != c);
+            block.set(1, 2, if right { f } else { e });
+            block.set(2, 0, if d == h { d } else { e });
+            let bottom = (d == h && e != i) || (h == f && e != g);
+            block.set(2, 1, if bottom { h } else { e });
+            block.set(2, 2, if h == f { f } else { e });
+        });
+    }
+}
+
+impl Filter for ScaleNx {
+    fn scale(&self) -> usize {
+        self.0
+    }
+
+    fn apply(&self, src: &[u16], width: usize, out: &mut [u16]) {
+        match self.0 {
+            2 => Self::scale2x(src, width, out),
+            3 => Self::scale3x(src, width, out),
+            4 => {
+                let mut doubled = vec![0; 4 * src.len()];
+                Self::scale2x(src, width, &mut doubled);
+                Self::scale2x(&doubled, 2 * width, out);
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Hyllian's 2xBR, which smooths edges found by comparing the gradients across them. Its output
+/// hasn't been compared with Hyllian's implementation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Xbr;
+
+impl Xbr {
+    fn diff(a: u16, b: u16) -> u32 {
+        let (a, b) = (yuv(a), yuv(b));
+        a.iter().zip(b).map(|(a, b)| a.abs_diff(b)).sum()
+    }
+
+    fn eq(a: u16, b: u16) -> bool {
+        Self::diff(a, b) < 155
+    }
+}
+
+impl Filter for Xbr {
+    fn scale(&self) -> usize {
+        2
+    }
+
+    #[allow(clippy::similar_names)]
+    fn apply(&self, src: &[u16], width: usize, out: &mut [u16]) {
+        let (df, eq) = (Self::diff, Self::eq);
+
+        // Each quarter turn smooths the dot's bottom-right corner, as seen from that rotation.
+        upscale(src, width, out, 2, |kernel, block| {
+            for turns in 0..4 {
+                kernel.turns = turns;
+                block.turns = turns;
+
+                let [_, pb, pc, pd, pe, pf, pg, ph, pi] = kernel.neighbours();
+                let (f4, i4, h5, i5) = (
+                    kernel.at(2, 0),
+                    kernel.at(2, 1),
+                    kernel.at(0, 2),
+                    kernel.at(1, 2),
+                );
+                if pe == ph || pe == pf {
+                    continue;
+                }
+
+                let e = df(pe, pc) + df(pe, pg) + df(pi, h5) + df(pi, f4) + 4 * df(ph, pf);
+                let i = df(ph, pd) + df(ph, i5) + df(pf, i4) + df(pf, pb) + 4 * df(pe, pi);
+                if e > i {
+                    continue;
+                }
+
+                let px = if df(pe, pf) <= df(pe, ph) { pf } else { ph };
+                let edge = e < i
+                    && ((!eq(pf, pb) && !eq(ph, pd))
+                        || (eq(pe, pi) && !eq(pf, i4) && !eq(ph, i5))
+                        || eq(pe, pg)
+                        || eq(pe, pc));
+                if !edge {
+                    block.blend(1, 1, px, (1, 2));
+                    continue;
+                }
+
+                let (ke, ki) = (df(pf, pg), df(ph, pc));
+                let shallow = 2 * ke <= ki && pe != pg && pd != pg;
+                let steep = ke >= 2 * ki && pe != pc && pb != pc;
+                match (shallow, steep) {
+                    (true, true) => {
+                        block.blend(1, 1, px, (7, 8));
+                        block.blend(1, 0, px, (1, 4));
+                        block.set(0, 1, block.get(1, 0));
+                    }
+                    (true, false) => {
+                        block.blend(1, 1, px, (3, 4));
+                        block.blend(1, 0, px, (1, 4));
+                    }
+                    (false, true) => {
+                        block.blend(1, 1, px, (3, 4));
+                        block.blend(0, 1, px, (1, 4));
+                    }
+                    (false, false) => block.blend(1, 1, px, (1, 2)),
+                }
+            }
+        });
+    }
+}
+
+/// Zenju's xBRZ, a refinement of xBR that preserves small details (like eyes) better. Its blend
+/// weights follow xBRZ's `Scaler2x` to `Scaler4x`, but its output hasn't been compared with the
+/// original's.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Xbrz(usize);
+
+/// Dots blended towards the edge's colour, as `(row, column, (numerator, denominator))`, with the
+/// edge through the bottom-right corner of the block.
+type XbrzBlends = &'static [(usize, usize, (u16, u16))];
+
+struct XbrzScaler {
+    /// The transpose of this is used for steep lines.
+    shallow: XbrzBlends,
+    steep_and_shallow: XbrzBlends,
+    diagonal: XbrzBlends,
+    corner: XbrzBlends,
+}
+
+impl Xbrz {
+    const BLEND_NONE: u8 = 0;
+    const BLEND_NORMAL: u8 = 1;
+    const BLEND_DOMINANT: u8 = 2;
+
+    const EQUAL_TOLERANCE: f64 = 30.0;
+    const DOMINANT_THRESHOLD: f64 = 3.6;
+    const STEEP_THRESHOLD: f64 = 2.2;
+
+    const SCALERS: [XbrzScaler; 3] = [
+        XbrzScaler {
+            shallow: &[(1, 0, (1, 4)), (1, 1, (3, 4))],
+            steep_and_shallow: &[(1, 0, (1, 4)), (0, 1, (1, 4)), (1, 1, (5, 6))],
+            diagonal: &[(1, 1, (1, 2))],
+            corner: &[(1, 1, (21, 100))],
+        },
+        XbrzScaler {
+            shallow: &[
+                (2, 0, (1, 4)),
+                (1, 2, (1, 4)),
+                (2, 1, (3, 4)),
+                (2, 2, (1, 1)),
+            ],
+            steep_and_shallow: &[
+                (2, 0, (1, 4)),
+                (0, 2, (1, 4)),
+                (2, 1, (3, 4)),
+                (1, 2, (3, 4)),
+                (2, 2, (1, 1)),
+            ],
+            diagonal: &[(1, 2, (1, 8)), (2, 1, (1, 8)), (2, 2, (7, 8))],
+            corner: &[(2, 2, (45, 100))],
+        },
+        XbrzScaler {
+            shallow: &[
+                (3, 0, (1, 4)),
+                (2, 2, (1, 4)),
+                (3, 1, (3, 4)),
+                (2, 3, (3, 4)),
+                (3, 2, (1, 1)),
+                (3, 3, (1, 1)),
+            ],
+            steep_and_shallow: &[
+                (3, 1, (3, 4)),
+                (1, 3, (3, 4)),
+                (3, 0, (1, 4)),
+                (0, 3, (1, 4)),
+                (2, 2, (1, 3)),
+                (3, 3, (1, 1)),
+                (3, 2, (1, 1)),
+                (2, 3, (1, 1)),
+            ],
+            diagonal: &[(3, 2, (1, 2)), (2, 3, (1, 2)), (3, 3, (1, 1))],
+            corner: &[(3, 3, (68, 100)), (3, 2, (9, 100)), (2, 3, (9, 100))],
+        },
+    ];
+
+    /// Returns [`None`] if `scale` isn't 2, 3 or 4.
+    #[must_use]
+    pub fn new(scale: usize) -> Option<Self> {
+        (2..=4).contains(&scale).then_some(Self(scale))
+    }
+
+    /// Distance between colours in YCbCr.
+    fn dist(a: u16, b: u16) -> f64 {
+        let (a, b) = (components_8bit(a), components_8bit(b));
+        let [r, g, b] = [0, 1, 2].map(|i| f64::from(a[i]) - f64::from(b[i]));
+
+        let (k_b, k_r) = (0.0593, 0.2627);
+        let y = k_r * r + (1.0 - k_b - k_r) * g + k_b * b;
+        let c_b = 0.5 / (1.0 - k_b) * (b - y);
+        let c_r = 0.5 / (1.0 - k_r) * (r - y);
+
+        (y * y + c_b * c_b + c_r * c_r).sqrt()
+    }
+
+    fn eq(a: u16, b: u16) -> bool {
+        Self::dist(a, b) < Self::EQUAL_TOLERANCE
+    }
+
+    /// Decides how to blend each corner of every dot, from the gradients across each 2x2 block of
+    /// dots, like xBRZ's `preProcessCorners`. Each dot's corners are packed from bit 0 as:
+    /// top-left, top-right, bottom-right, bottom-left.
+    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+    fn blend_info(src: &[u16], width: usize) -> Vec<u8> {
+        let height = src.len() / width;
+        let mut info = vec![0; src.len()];
+        let dist = Self::dist;
+
+        for y in -1..height as isize {
+            for x in -1..width as isize {
+                let at = |dx, dy| {
+                    let x = x.saturating_add(dx).clamp(0, width as isize - 1);
+                    let y = y.saturating_add(dy).clamp(0, height as isize - 1);
+                    src[y as usize * width + x as usize]
+                };
+                let (b, c) = (at(0, -1), at(1, -1));
+                let (e, f, g, h) = (at(-1, 0), at(0, 0), at(1, 0), at(2, 0));
+                let (i, j, k, l) = (at(-1, 1), at(0, 1), at(1, 1), at(2, 1));
+                let (n, o) = (at(0, 2), at(1, 2));
+                if (f == g && j == k) || (f == j && g == k) {
+                    continue;
+                }
+
+                let mut set = |dx, dy, shift, blend: u8| {
+                    let (x, y) = (x + dx, y + dy);
+                    if (0..width as isize).contains(&x) && (0..height as isize).contains(&y) {
+                        info[y as usize * width + x as usize] |= blend << shift;
+                    }
+                };
+                let blend = |a: f64, b: f64| {
+                    if Self::DOMINANT_THRESHOLD * a < b {
+                        Self::BLEND_DOMINANT
+                    } else {
+                        Self::BLEND_NORMAL
+                    }
+                };
+
+                let jg = dist(i, f) + dist(f, c) + dist(n, k) + dist(k, h) + 4.0 * dist(j, g);
+                let fk = dist(e, j) + dist(j, o) + dist(b, g) + dist(g, l) + 4.0 * dist(f, k);
+                if jg < fk {
+                    if f != g && f != j {
+                        set(0, 0, 4, blend(jg, fk));
+                    }
+                    if k != j && k != g {
+                        set(1, 1, 0, blend(jg, fk));
+                    }
+                } else if fk < jg {
+                    if j != f && j != k {
+                        set(0, 1, 2, blend(fk, jg));
+                    }
+                    if g != f && g != k {
+                        set(1, 0, 6, blend(fk, jg));
+                    }
+                }
+            }
+        }
+
+        info
+    }
+}
+
+impl Filter for Xbrz {
+    fn scale(&self) -> usize {
+        self.0
+    }
+
+    fn apply(&self, src: &[u16], width: usize, out: &mut [u16]) {
+        let (dist, eq) = (Self::dist, Self::eq);
+        let scaler = &Self::SCALERS[self.0 - 2];
+        let info = Self::blend_info(src, width);
+
+        upscale(src, width, out, self.0, |kernel, block| {
+            let info = info[kernel.y * width + kernel.x];
+            if info == 0 {
+                return;
+            }
+
+            // Each quarter turn blends the dot's bottom-right corner, as seen from that rotation.
+            for turns in 0..4 {
+                kernel.turns = turns;
+                block.turns = turns;
+                let corner = |dx, dy| {
+                    let shift = match rotate((dx, dy), turns) {
+                        (-1, -1) => 0,
+                        (1, -1) => 2,
+                        (1, 1) => 4,
+                        _ => 6,
+                    };
+                    (info >> shift) & 3
+                };
+                if corner(1, 1) == Self::BLEND_NONE {
+                    continue;
+                }
+
+                let [_, b, c, d, e, f, g, h, i] = kernel.neighbours();
+                let line = corner(1, 1) >= Self::BLEND_DOMINANT
+                    || !((corner(1, -1) != Self::BLEND_NONE && !eq(e, g))
+                        || (corner(-1, 1) != Self::BLEND_NONE && !eq(e, c))
+                        || (!eq(e, i) && eq(g, h) && eq(h, i) && eq(i, f) && eq(f, c)));
+                let px = if dist(e, f) <= dist(e, h) { f } else { h };
+
+                let (blends, transpose) = if line {
+                    let (fg, hc) = (dist(f, g), dist(h, c));
+                    let shallow = Self::STEEP_THRESHOLD * fg <= hc && e != g && d != g;
+                    let steep = Self::STEEP_THRESHOLD * hc <= fg && e != c && b != c;
+                    match (shallow, steep) {
+                        (true, true) => (scaler.steep_and_shallow, false),
+                        (true, false) => (scaler.shallow, false),
+                        (false, true) => (scaler.shallow, true),
+                        (false, false) => (scaler.diagonal, false),
+                    }
+                } else {
+                    (scaler.corner, false)
+                };
+                for &(row, col, weight) in blends {
+                    let (row, col) = if transpose { (col, row) } else { (row, col) };
+                    block.blend(row, col, px, weight);
+                }
+            }
+        });
+    }
+}
+
+/// Maxim Stepin's hq2x and hq3x. The neighbours that differ from each dot by hqx's thresholds in
+/// YUV form a pattern, which picks how each output dot interpolates them. The rules for each
+/// pattern are hqx's lookup tables in the condensed form used by FFmpeg's `hqx` filter, written
+/// for the top-left corner (and at 3x, the top edge) of the block.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Hqx(usize);
+
+/// Dots of a [`Kernel::neighbours`] mixed by integer weights summing to `1 << shift`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct HqxInterp {
+    weights: [(usize, u32); 3],
+    shift: u32,
+}
+
+impl HqxInterp {
+    fn new(weights: &[(usize, u32)], shift: u32) -> Self {
+        let mut padded = [(4, 0); 3];
+        padded[..weights.len()].copy_from_slice(weights);
+
+        Self {
+            weights: padded,
+            shift,
+        }
+    }
+
+    fn center() -> Self {
+        Self::new(&[(4, 1)], 0)
+    }
+
+    /// Mixes the dots like hqx does, in 8-bit components with the result truncated.
+    fn apply(self, neighbours: &[u16; 9]) -> u16 {
+        let mut sums = [0; 3];
+        for (i, weight) in self.weights {
+            for (sum, c) in sums.iter_mut().zip(components_8bit(neighbours[i])) {
+                *sum += u32::from(c) * weight;
+            }
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        sums.iter()
+            .enumerate()
+            .map(|(i, &sum)| ((sum >> self.shift >> 3) as u16) << (5 * i))
+            .sum()
+    }
+}
+
+impl Hqx {
+    /// Returns [`None`] if `scale` isn't 2 or 3.
+    #[must_use]
+    pub fn new(scale: usize) -> Option<Self> {
+        (2..=3).contains(&scale).then_some(Self(scale))
+    }
+
+    /// hqx's YUV, truncated to integers, without the offsets of U and V.
+    fn yuv(dot: u16) -> [i32; 3] {
+        let [r, g, b] = components_8bit(dot).map(i32::from);
+        let (rg, bg) = (r - g, b - g);
+
+        [
+            (299 * r + 587 * g + 114 * b) / 1000,
+            (500 * bg - 169 * rg) / 1000,
+            (500 * rg - 81 * bg) / 1000,
+        ]
+    }
+
+    fn diff(a: u16, b: u16) -> bool {
+        let (a, b) = (Self::yuv(a), Self::yuv(b));
+        a[0].abs_diff(b[0]) > 48 || a[1].abs_diff(b[1]) > 7 || a[2].abs_diff(b[2]) > 6
+    }
+
+    /// Which neighbours differ from the dot, from bit 0 in row-major order.
+    fn pattern(neighbours: &[u16; 9]) -> u8 {
+        [0, 1, 2, 3, 5, 6, 7, 8]
+            .into_iter()
+            .enumerate()
+            .fold(0, |k, (bit, i)| {
+                k | u8::from(Self::diff(neighbours[4], neighbours[i])) << bit
+            })
+    }
+
+    /// hq2x's rule for the top-left output dot, from the pattern `k` and whether neighbours
+    /// differ from each other.
+    fn hq2x_corner(k: u8, diff: &dyn Fn(usize, usize) -> bool) -> HqxInterp {
+        let p = |pats: &[(u8, u8)]| pats.iter().any(|&(mask, bits)| k & mask == bits);
+        let i = HqxInterp::new;
+
+        if p(&[(0xbf, 0x37), (0xdb, 0x13)]) && diff(1, 5) {
+            i(&[(4, 3), (3, 1)], 2)
+        } else if p(&[(0xdb, 0x49), (0xef, 0x6d)]) && diff(7, 3) {
+            i(&[(4, 3), (1, 1)], 2)
+        } else if p(&[(0x0b, 0x0b), (0xfe, 0x4a), (0xfe, 0x1a)]) && diff(3, 1) {
+            HqxInterp::center()
+        } else if p(&[
+            (0x6f, 0x2a),
+            (0x5b, 0x0a),
+            (0xbf, 0x3a),
+            (0xdf, 0x5a),
+            (0x9f, 0x8a),
+            (0xcf, 0x8a),
+            (0xef, 0x4e),
+            (0x3f, 0x0e),
+            (0xfb, 0x5a),
+            (0xbb, 0x8a),
+            (0x7f, 0x5a),
+            (0xaf, 0x8a),
+            (0xeb, 0x8a),
+        ]) && diff(3, 1)
+        {
+            i(&[(4, 3), (0, 1)], 2)
+        } else if p(&[(0x0b, 0x08)]) {
+            i(&[(4, 2), (0, 1), (1, 1)], 2)
+        } else if p(&[(0x0b, 0x02)]) {
+            i(&[(4, 2), (0, 1), (3, 1)], 2)
+        } else if p(&[(0x2f, 0x2f)]) {
+            i(&[(4, 14), (3, 1), (1, 1)], 4)
+        } else if p(&[(0xbf, 0x37), (0xdb, 0x13)]) {
+            i(&[(4, 5), (1, 2), (3, 1)], 3)
+        } else if p(&[(0xdb, 0x49), (0xef, 0x6d)]) {
+            i(&[(4, 5), (3, 2), (1, 1)], 3)
+        } else if p(&[(0x1b, 0x03), (0x4f, 0x43), (0x8b, 0x83), (0x6b, 0x43)]) {
+            i(&[(4, 3), (3, 1)], 2)
+        } else if p(&[(0x4b, 0x09), (0x8b, 0x89), (0x1f, 0x19), (0x3b, 0x19)]) {
+            i(&[(4, 3), (1, 1)], 2)
+        } else if p(&[(0x7e, 0x2a), (0xef, 0xab), (0xbf, 0x8f), (0x7e, 0x0e)]) {
+            i(&[(4, 2), (3, 3), (1, 3)], 3)
+        } else if p(&[
+            (0xfb, 0x6a),
+            (0x6f, 0x6e),
+            (0x3f, 0x3e),
+            (0xfb, 0xfa),
+            (0xdf, 0xde),
+            (0xdf, 0x1e),
+        ]) {
+            i(&[(4, 3), (0, 1)], 2)
+        } else if p(&[
+            (0x0a, 0x00),
+            (0x4f, 0x4b),
+            (0x9f, 0x1b),
+            (0x2f, 0x0b),
+            (0xbe, 0x0a),
+            (0xee, 0x0a),
+            (0x7e, 0x0a),
+            (0xeb, 0x4b),
+            (0x3b, 0x1b),
+        ]) {
+            i(&[(4, 2), (3, 1), (1, 1)], 2)
+        } else {
+            i(&[(4, 6), (3, 1), (1, 1)], 3)
+        }
+    }
+
+    /// hq3x's rule for the top-left output dot.
+    fn hq3x_corner(k: u8, diff: &dyn Fn(usize, usize) -> bool) -> HqxInterp {
+        let p = |pats: &[(u8, u8)]| pats.iter().any(|&(mask, bits)| k & mask == bits);
+        let i = HqxInterp::new;
+
+        if p(&[(0xdb, 0x49), (0xef, 0x6d)]) && diff(7, 3) {
+            i(&[(4, 3), (1, 1)], 2)
+        } else if p(&[(0xbf, 0x37), (0xdb, 0x13)]) && diff(1, 5) {
+            i(&[(4, 3), (3, 1)], 2)
+        } else if p(&[(0x0b, 0x0b), (0xfe, 0x4a), (0xfe, 0x1a)]) && diff(3, 1) {
+            HqxInterp::center()
+        } else if p(&[
+            (0x6f, 0x2a),
+            (0x5b, 0x0a),
+            (0xbf, 0x3a),
+            (0xdf, 0x5a),
+            (0x9f, 0x8a),
+            (0xcf, 0x8a),
+            (0xef, 0x4e),
+            (0x3f, 0x0e),
+            (0xfb, 0x5a),
+            (0xbb, 0x8a),
+            (0x7f, 0x5a),
+            (0xaf, 0x8a),
+            (0xeb, 0x8a),
+        ]) && diff(3, 1)
+        {
+            i(&[(4, 3), (0, 1)], 2)
+        } else if p(&[(0x4b, 0x09), (0x8b, 0x89), (0x1f, 0x19), (0x3b, 0x19)]) {
+            i(&[(4, 3), (1, 1)], 2)
+        } else if p(&[(0x1b, 0x03), (0x4f, 0x43), (0x8b, 0x83), (0x6b, 0x43)]) {
+            i(&[(4, 3), (3, 1)], 2)
+        } else if p(&[(0x7e, 0x2a), (0xef, 0xab), (0xbf, 0x8f), (0x7e, 0x0e)]) {
+            i(&[(3, 1), (1, 1)], 1)
+        } else if p(&[
+            (0x4f, 0x4b),
+            (0x9f, 0x1b),
+            (0x2f, 0x0b),
+            (0xbe, 0x0a),
+            (0xee, 0x0a),
+            (0x7e, 0x0a),
+            (0xeb, 0x4b),
+            (0x3b, 0x1b),
+        ]) {
+            i(&[(4, 2), (3, 7), (1, 7)], 4)
+        } else if p(&[
+            (0x0b, 0x08),
+            (0xf9, 0x68),
+            (0xf3, 0x62),
+            (0x6d, 0x6c),
+            (0x67, 0x66),
+            (0x3d, 0x3c),
+            (0x37, 0x36),
+            (0xf9, 0xf8),
+            (0xdd, 0xdc),
+            (0xf3, 0xf2),
+            (0xd7, 0xd6),
+            (0xdd, 0x1c),
+            (0xd7, 0x16),
+            (0x0b, 0x02),
+        ]) {
+            i(&[(4, 3), (0, 1)], 2)
+        } else {
+            i(&[(4, 2), (3, 1), (1, 1)], 2)
+        }
+    }
+
+    /// hq3x's rule for the top output dot.
+    fn hq3x_edge(k: u8, diff: &dyn Fn(usize, usize) -> bool) -> HqxInterp {
+        let p = |pats: &[(u8, u8)]| pats.iter().any(|&(mask, bits)| k & mask == bits);
+        let i = HqxInterp::new;
+
+        let top_right_edge = p(&[
+            (0xfe, 0xde),
+            (0x9e, 0x16),
+            (0xda, 0x12),
+            (0x17, 0x16),
+            (0x5b, 0x12),
+            (0xbb, 0x12),
+        ]) && diff(1, 5);
+        let top_left_edge = p(&[
+            (0x0f, 0x0b),
+            (0x5e, 0x0a),
+            (0xfb, 0x7b),
+            (0x3b, 0x0b),
+            (0xbe, 0x0a),
+            (0x7a, 0x0a),
+        ]) && diff(3, 1);
+
+        if top_right_edge || top_left_edge {
+            HqxInterp::center()
+        } else if p(&[(0xbf, 0x8f), (0x7e, 0x0e), (0xbf, 0x37), (0xdb, 0x13)]) {
+            i(&[(1, 3), (4, 1)], 2)
+        } else if p(&[
+            (0x02, 0x00),
+            (0x7c, 0x28),
+            (0xed, 0xa9),
+            (0xf5, 0xb4),
+            (0xd9, 0x90),
+        ]) {
+            i(&[(4, 3), (1, 1)], 2)
+        } else if p(&[
+            (0x4f, 0x4b),
+            (0xfb, 0x7b),
+            (0xfe, 0x7e),
+            (0x9f, 0x1b),
+            (0x2f, 0x0b),
+            (0xbe, 0x0a),
+            (0x7e, 0x0a),
+            (0xfb, 0x4b),
+            (0xfb, 0xdb),
+            (0xfe, 0xde),
+            (0xfe, 0x56),
+            (0x57, 0x56),
+            (0x97, 0x16),
+            (0x3f, 0x1e),
+            (0xdb, 0x12),
+            (0xbb, 0x12),
+        ]) {
+            i(&[(4, 7), (1, 1)], 3)
+        } else {
+            HqxInterp::center()
+        }
+    }
+}
+
+impl Filter for Hqx {
+    fn scale(&self) -> usize {
+        self.0
+    }
+
+    fn apply(&self, src: &[u16], width: usize, out: &mut [u16]) {
+        // Each quarter turn fills the block's top-left corner (and top edge), as seen from that
+        // rotation. At 3x, the middle is always the dot itself.
+        upscale(src, width, out, self.0, |kernel, block| {
+            for turns in 0..4 {
+                kernel.turns = turns;
+                block.turns = turns;
+
+                let neighbours = kernel.neighbours();
+                let k = Self::pattern(&neighbours);
+                let diff = |a: usize, b: usize| Self::diff(neighbours[a], neighbours[b]);
+                if self.0 == 2 {
+                    block.set(0, 0, Self::hq2x_corner(k, &diff).apply(&neighbours));
+                } else {
+                    block.set(0, 0, Self::hq3x_corner(k, &diff).apply(&neighbours));
+                    block.set(0, 1, Self::hq3x_edge(k, &diff).apply(&neighbours));
+                }
+            }
+        });
+    }
+}
+
+/// Filters that can be picked by name.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum FilterKind {
+    #[default]
+    None,
+    Nearest(Nearest),
+    ScaleNx(ScaleNx),
+    Xbr,
+    Xbrz(Xbrz),
+    Hqx(Hqx),
+}
+
+impl FilterKind {
+    #[must_use]
+    pub fn filter(self) -> Option<Box<dyn Filter>> {
+        match self {
+            Self::None => None,
+            Self::Nearest(filter) => Some(Box::new(filter)),
+            Self::ScaleNx(filter) => Some(Box::new(filter)),
+            Self::Xbr => Some(Box::new(Xbr)),
+            Self::Xbrz(filter) => Some(Box::new(filter)),
+            Self::Hqx(filter) => Some(Box::new(filter)),
+        }
+    }
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct ParseFilterError;
+
+impl Display for ParseFilterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid filter; expected none, epx, xbr, hq2x, hq3x, or nearest, scanlines, lcd, \
+             scale or xbrz followed by 2x, 3x or 4x"
+        )
+    }
+}
+
+impl Error for ParseFilterError {}
+
+impl FromStr for FilterKind {
+    type Err = ParseFilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => return Ok(Self::None),
+            "epx" => return Ok(Self::ScaleNx(ScaleNx(2))),
+            "xbr" | "xbr2x" => return Ok(Self::Xbr),
+            _ => {}
+        }
+
+        let (name, scale) = s
+            .strip_suffix('x')
+            .filter(|s| s.len() > 1 && s.is_char_boundary(s.len() - 1))
+            .map(|s| s.split_at(s.len() - 1))
+            .ok_or(ParseFilterError)?;
+        let scale = scale.parse().map_err(|_| ParseFilterError)?;
+        match name {
+            "nearest" => Nearest::new(scale, Effect::None).map(Self::Nearest),
+            "scanlines" => Nearest::new(scale, Effect::Scanlines).map(Self::Nearest),
+            "lcd" => Nearest::new(scale, Effect::LcdGrid).map(Self::Nearest),
+            "scale" => ScaleNx::new(scale).map(Self::ScaleNx),
+            "xbrz" => Xbrz::new(scale).map(Self::Xbrz),
+            "hq" => Hqx::new(scale).map(Self::Hqx),
+            _ => None,
+        }
+        .ok_or(ParseFilterError)
+    }
+}
+
+/// The 3x3 neighbourhood of a source dot, seen from a number of quarter turns anticlockwise.
+/// Dots outside of the frame are clamped to its edges.
+struct Kernel<'a> {
+    src: &'a [u16],
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+    turns: u8,
+}
+
+impl Kernel<'_> {
+    fn at(&self, dx: isize, dy: isize) -> u16 {
+        let (dx, dy) = rotate((dx, dy), self.turns);
+        let x = self.x.saturating_add_signed(dx).min(self.width - 1);
+        let y = self.y.saturating_add_signed(dy).min(self.height - 1);
+
+        self.src[y * self.width + x] & 0x7fff
+    }
+
+    /// The 3x3 neighbourhood in row-major order, with the dot itself in the middle.
+    fn neighbours(&self) -> [u16; 9] {
+        let mut dots = [0; 9];
+        for (i, dot) in dots.iter_mut().enumerate() {
+            #[allow(clippy::cast_possible_wrap)]
+            let i = i as isize;
+            *dot = self.at(i % 3 - 1, i / 3 - 1);
+        }
+
+        dots
+    }
+}
+
+/// The output dots for one source dot, seen from the same rotation as its [`Kernel`].
+struct Block {
+    dots: [u16; 16],
+    scale: usize,
+    turns: u8,
+}
+
+impl Block {
+    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+    fn idx(&self, row: usize, col: usize) -> usize {
+        // Rotate about the centre, in coordinates doubled so that it's at a whole number.
+        let last = self.scale as isize - 1;
+        let (x, y) = rotate(
+            (2 * col as isize - last, 2 * row as isize - last),
+            self.turns,
+        );
+
+        (y + last) as usize / 2 * self.scale + (x + last) as usize / 2
+    }
+
+    fn get(&self, row: usize, col: usize) -> u16 {
+        self.dots[self.idx(row, col)]
+    }
+
+    fn set(&mut self, row: usize, col: usize, dot: u16) {
+        self.dots[self.idx(row, col)] = dot;
+    }
+
+    /// Blends the dot by `numerator / denominator` of the way towards `dot`.
+    fn blend(&mut self, row: usize, col: usize, dot: u16, (numerator, denominator): (u16, u16)) {
+        let old = self.get(row, col);
+        self.set(
+            row,
+            col,
+            mix([(old, denominator - numerator), (dot, numerator)]),
+        );
+    }
+}
+
+/// Calls `fill` for each source dot to produce its `scale` by `scale` block of output dots, which
+/// starts as copies of the source dot.
+fn upscale(
+    src: &[u16],
+    width: usize,
+    out: &mut [u16],
+    scale: usize,
+    mut fill: impl FnMut(&mut Kernel, &mut Block),
+) {
+    let height = src.len() / width;
+    for y in 0..height {
+        for x in 0..width {
+            let mut kernel = Kernel {
+                src,
+                width,
+                height,
+                x,
+                y,
+                turns: 0,
+            };
+            let mut block = Block {
+                dots: [kernel.at(0, 0); 16],
+                scale,
+                turns: 0,
+            };
+            fill(&mut kernel, &mut block);
+
+            for row in 0..scale {
+                let i = (y * scale + row) * width * scale + x * scale;
+                out[i..i + scale].copy_from_slice(&block.dots[row * scale..][..scale]);
+            }
+        }
+    }
+}
+
+/// Rotates an offset by a number of quarter turns; from the bottom-right towards the top-right.
+fn rotate((x, y): (isize, isize), turns: u8) -> (isize, isize) {
+    (0..turns % 4).fold((x, y), |(x, y), _| (y, -x))
+}
+
+/// Mixes dots by integer weights.
+fn mix<const N: usize>(dots: [(u16, u16); N]) -> u16 {
+    let total = u32::from(dots.iter().map(|&(_, weight)| weight).sum::<u16>());
+    let mut sums = [0; 3];
+    for (dot, weight) in dots {
+        for (i, sum) in sums.iter_mut().enumerate() {
+            *sum += u32::from((dot >> (5 * i)) & 0x1f) * u32::from(weight);
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    sums.iter()
+        .enumerate()
+        .map(|(i, &sum)| (((sum + total / 2) / total) as u16) << (5 * i))
+        .sum()
+}
+
+fn darken(dot: u16, numerator: u16, denominator: u16) -> u16 {
+    mix([(dot, numerator), (0, denominator - numerator)])
+}
+
+/// The YUV used by xBR for comparing colours.
+#[allow(clippy::cast_possible_truncation)]
+fn yuv(dot: u16) -> [i32; 3] {
+    static TABLE: OnceLock<Box<[[i32; 3]]>> = OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        (0..0x8000)
+            .map(|dot| {
+                let [r, g, b] = components_8bit(dot).map(f64::from);
+                [
+                    0.299 * r + 0.587 * g + 0.114 * b,
+                    -0.169 * r - 0.331 * g + 0.5 * b,
+                    0.5 * r - 0.419 * g - 0.081 * b,
+                ]
+                .map(|c| c.round() as i32)
+            })
+            .collect()
+    });
+
+    table[usize::from(dot & 0x7fff)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale2x_works() {
+        // A diagonal of 1s on a background of 0s is joined up.
+        #[rustfmt::skip]
+        let src = [
+            0, 0, 0, 0,
+            0, 1, 0, 0,
+            0, 0, 1, 0,
+            0, 0, 0, 0,
+        ];
+        let mut out = [0; 64];
+        ScaleNx::new(2).unwrap().apply(&src, 4, &mut out);
+
+        #[rustfmt::skip]
+        assert_eq!(
+            out,
+            [
+                0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 1, 1, 0, 0, 0, 0,
+                0, 0, 1, 1, 1, 0, 0, 0,
+                0, 0, 0, 1, 1, 1, 0, 0,
+                0, 0, 0, 0, 1, 1, 0, 0,
+                0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0, 0, 0, 0, 0,
+            ]
+        );
+    }
+
+    /// Scale2x or Scale3x following the rules for each output dot as written on AdvanceMAME's
+    /// page about them, with the border dots repeated outwards.
+    fn advmame_scale(src: &[u16], width: usize, scale: usize) -> Vec<u16> {
+        let height = src.len() / width;
+        let mut out = vec![0; src.len() * scale * scale];
+
+        for y in 0..height {
+            for x in 0..width {
+                let at = |dx: usize, dy: usize| {
+                    let x = (x + dx).saturating_sub(1).min(width - 1);
+                    let y = (y + dy).saturating_sub(1).min(height - 1);
+                    src[y * width + x]
+                };
+                let (a, b, c) = (at(0, 0), at(1, 0), at(2, 0));
+                let (d, e, f) = (at(0, 1), at(1, 1), at(2, 1));
+                let (g, h, i) = (at(0, 2), at(1, 2), at(2, 2));
+
+                let block = if scale == 2 {
+                    vec![
+                        if d == b && b != f && d != h { d } else { e },
+                        if b == f && b != d && f != h { f } else { e },
+                        if d == h && d != b && h != f { d } else { e },
+                        if h == f && d != h && b != f { f } else { e },
+                    ]
+                } else {
+                    let e0 = d == b && b != f && d != h;
+                    let e2 = b == f && b != d && f != h;
+                    let e6 = d == h && d != b && h != f;
+                    let e8 = h == f && d != h && b != f;
+                    vec![
+                        if e0 { d } else { e },
+                        if (e0 && e != c) || (e2 && e != a) {
+                            b
+                        } else {
+                            e
+                        },
+                        if e2 { f } else { e },
+                        if (e0 && e != g) || (e6 && e != a) {
+                            d
+                        } else {
+                            e
+                        },
+                        e,
+                        if (e2 && e != i) || (e8 && e != c) {
+                            f
+                        } else {
+                            e
+                        },
+                        if e6 { d } else { e },
+                        if (e6 && e != i) || (e8 && e != g) {
+                            h
+                        } else {
+                            e
+                        },
+                        if e8 { f } else { e },
+                    ]
+                };
+                for (n, dot) in block.into_iter().enumerate() {
+                    let (row, col) = (y * scale + n / scale, x * scale + n % scale);
+                    out[row * width * scale + col] = dot;
+                }
+            }
+        }
+
+        out
+    }
+
+    #[test]
+    fn scale_nx_matches_advmame_rules() {
+        // Few colours, so that most neighbourhoods have some equal dots.
+        let mut seed = 1_u32;
+        let src: Vec<_> = (0..24 * 20)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                [0, 0x001f, 0x7fff][(seed >> 16) as usize % 3]
+            })
+            .collect();
+
+        for scale in 2..=4 {
+            let mut out = vec![0; src.len() * scale * scale];
+            ScaleNx::new(scale).unwrap().apply(&src, 24, &mut out);
+
+            // Scale4x is Scale2x applied twice.
+            let expected = match scale {
+                4 => advmame_scale(&advmame_scale(&src, 24, 2), 48, 2),
+                _ => advmame_scale(&src, 24, scale),
+            };
+            assert!(out == expected, "scale{scale}x");
+        }
+    }
+
+    /// Upscales a 5x5 image of white dots where `white` is true on black, returning the block of
+    /// output dots for the middle one.
+    fn middle_block(filter: &dyn Filter, white: fn(usize, usize) -> bool) -> Vec<u16> {
+        let src: Vec<_> = (0..25)
+            .map(|i| if white(i % 5, i / 5) { 0x7fff } else { 0 })
+            .collect();
+        let scale = filter.scale();
+        let mut out = vec![0; 25 * scale * scale];
+        filter.apply(&src, 5, &mut out);
+
+        (0..scale)
+            .flat_map(|row| {
+                let start = (2 * scale + row) * 5 * scale + 2 * scale;
+                out[start..start + scale].to_vec()
+            })
+            .collect()
+    }
+
+    /// Grey with each 5-bit component being `n`.
+    fn grey(n: u16) -> u16 {
+        n | n << 5 | n << 10
+    }
+
+    // The middle dot of these is black, with the white dots next to it across the edge.
+    fn diagonal(x: usize, y: usize) -> bool {
+        x + y > 4
+    }
+
+    fn shallow(x: usize, y: usize) -> bool {
+        y > 2 || (y == 2 && x > 2)
+    }
+
+    fn steep(x: usize, y: usize) -> bool {
+        shallow(y, x)
+    }
+
+    fn lone_dot(x: usize, y: usize) -> bool {
+        (x, y) == (2, 2)
+    }
+
+    #[test]
+    fn xbr_works() {
+        let (b, w) = (0, 0x7fff);
+
+        // With d the difference between black and white, a 45 degree edge has e = 0 and
+        // i = 6d, the edge rule holds as F != B and H != D, and ke = ki = d: the corner is
+        // blended halfway.
+        assert_eq!(middle_block(&Xbr, diagonal), [b, b, b, grey(16)]);
+        // A shallow edge has e = d and i = 6d, and 2ke = 0 <= ki = d with E != G and D != G:
+        // LEFT_2_2X blends the corner by 3/4 and the dot left of it by 1/4.
+        assert_eq!(middle_block(&Xbr, shallow), [b, b, grey(8), grey(23)]);
+        // A steep edge mirrors that as UP_2_2X.
+        assert_eq!(middle_block(&Xbr, steep), [b, grey(8), b, grey(23)]);
+        // A lone dot has e = 2d < i = 4d at every corner, but isn't an edge as F == B: each
+        // corner is blended halfway.
+        assert_eq!(middle_block(&Xbr, lone_dot), [grey(16); 4]);
+        // Nothing changes away from edges.
+        assert_eq!(middle_block(&Xbr, |_, _| true), [w; 4]);
+    }
+
+    #[test]
+    fn xbrz_works() {
+        let (b, w) = (0, 0x7fff);
+        let (xbrz2x, xbrz3x) = (Xbrz::new(2).unwrap(), Xbrz::new(3).unwrap());
+
+        // With d the difference between black and white, the 2x2 block right of and below the
+        // middle dot of a 45 degree edge has jg = 0 and fk = 6d, making its corner a dominant
+        // line, and dist(F, G) = dist(H, C) = d makes it diagonal.
+        assert_eq!(middle_block(&xbrz2x, diagonal), [b, b, b, grey(16)]);
+        #[rustfmt::skip]
+        assert_eq!(
+            middle_block(&xbrz3x, diagonal),
+            [
+                b, b,       b,
+                b, b,       grey(4),
+                b, grey(4), grey(27),
+            ]
+        );
+        // A shallow edge has jg = d and fk = 6d, with dist(F, G) = 0 so blendLineShallow is used.
+        assert_eq!(middle_block(&xbrz2x, shallow), [b, b, grey(8), grey(23)]);
+        #[rustfmt::skip]
+        assert_eq!(
+            middle_block(&xbrz3x, shallow),
+            [
+                b,       b,        b,
+                b,       b,        grey(8),
+                grey(8), grey(23), w,
+            ]
+        );
+        // A steep edge is its transpose.
+        assert_eq!(middle_block(&xbrz2x, steep), [b, grey(8), b, grey(23)]);
+        // A lone dot has jg = 2d and fk = 4d at every corner, which isn't dominant, and its
+        // other corners being blended too rule out a line: blendCorner rounds off each corner.
+        assert_eq!(middle_block(&xbrz2x, lone_dot), [grey(24); 4]);
+        #[rustfmt::skip]
+        assert_eq!(
+            middle_block(&xbrz3x, lone_dot),
+            [
+                grey(17), w, grey(17),
+                w,        w, w,
+                grey(17), w, grey(17),
+            ]
+        );
+    }
+
+    #[test]
+    fn nearest_works() {
+        let mut out = [0; 8];
+        let filter = Nearest::new(2, Effect::Scanlines).unwrap();
+        filter.apply(&[0x7fff, 0x001f], 2, &mut out);
+        assert_eq!(
+            out,
+            [0x7fff, 0x7fff, 0x001f, 0x001f, 0x4210, 0x4210, 0x0010, 0x0010]
+        );
+        assert_eq!(Nearest::new(0, Effect::None), None);
+        assert_eq!(Nearest::new(5, Effect::None), None);
+    }
+
+    type HqxRule = fn(u8, &dyn Fn(usize, usize) -> bool) -> HqxInterp;
+
+    /// The nonzero weights of `interp`, with the neighbours' indices mapped by `map`.
+    fn hqx_weights(interp: HqxInterp, map: fn(usize) -> usize) -> (Vec<(usize, u32)>, u32) {
+        let mut weights: Vec<_> = interp
+            .weights
+            .into_iter()
+            .filter(|&(_, weight)| weight != 0)
+            .map(|(i, weight)| (map(i), weight))
+            .collect();
+        weights.sort_unstable();
+
+        (weights, interp.shift)
+    }
+
+    /// Checks that `rule` gives the same interpolation when the neighbourhood is reflected by
+    /// `reflect`, for every pattern and every combination of the differences it checks.
+    fn assert_hqx_rule_symmetric(rule: HqxRule, reflect: fn(usize) -> usize) {
+        const NEIGHBOURS: [usize; 8] = [0, 1, 2, 3, 5, 6, 7, 8];
+        let pairs = [(1, 5), (3, 7), (1, 3), (5, 7)];
+
+        for k in 0..=u8::MAX {
+            let reflected_k = NEIGHBOURS.iter().enumerate().fold(0, |acc, (bit, &i)| {
+                let reflected_bit = NEIGHBOURS.iter().position(|&j| j == reflect(i)).unwrap();
+                acc | ((k >> bit) & 1) << reflected_bit
+            });
+
+            for diffs in 0..1 << pairs.len() {
+                let diff = |a: usize, b: usize| {
+                    pairs.iter().enumerate().any(|(n, &pair)| {
+                        diffs & (1 << n) != 0 && (pair == (a, b) || pair == (b, a))
+                    })
+                };
+                let reflected_diff = |a: usize, b: usize| diff(reflect(a), reflect(b));
+
+                assert_eq!(
+                    hqx_weights(rule(reflected_k, &reflected_diff), reflect),
+                    hqx_weights(rule(k, &diff), |i| i),
+                    "pattern {k:#04x}, differences {diffs:#b}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn hqx_rules_are_symmetric() {
+        let transpose = |i| i % 3 * 3 + i / 3;
+        let mirror = |i| i / 3 * 3 + 2 - i % 3;
+
+        assert_hqx_rule_symmetric(Hqx::hq2x_corner, transpose);
+        assert_hqx_rule_symmetric(Hqx::hq3x_corner, transpose);
+        assert_hqx_rule_symmetric(Hqx::hq3x_edge, mirror);
+    }
+
+    #[test]
+    fn hqx_rules_match_tables() {
+        // Cases from hq2x.c and hq3x.c, whose neighbours are numbered from 1 rather than 0.
+        let i = HqxInterp::new;
+        let (same, differ) = (&|_, _| false, &|_, _| true);
+
+        // case 0: PIXEL00_20
+        assert_eq!(Hqx::hq2x_corner(0, same), i(&[(4, 2), (3, 1), (1, 1)], 2));
+        // case 2: PIXEL00_22
+        assert_eq!(Hqx::hq2x_corner(2, same), i(&[(4, 2), (0, 1), (3, 1)], 2));
+        // case 3: PIXEL00_11
+        assert_eq!(Hqx::hq2x_corner(3, same), i(&[(4, 3), (3, 1)], 2));
+        // case 10: if (Diff(w[4], w[2])) PIXEL00_10 else PIXEL00_20
+        assert_eq!(Hqx::hq2x_corner(10, differ), i(&[(4, 3), (0, 1)], 2));
+        assert_eq!(Hqx::hq2x_corner(10, same), i(&[(4, 2), (3, 1), (1, 1)], 2));
+        // case 19: if (Diff(w[2], w[6])) PIXEL00_11 else PIXEL00_60
+        assert_eq!(Hqx::hq2x_corner(19, differ), i(&[(4, 3), (3, 1)], 2));
+        assert_eq!(Hqx::hq2x_corner(19, same), i(&[(4, 5), (1, 2), (3, 1)], 3));
+        // case 255: if (Diff(w[4], w[2])) PIXEL00_0 else PIXEL00_100
+        assert_eq!(Hqx::hq2x_corner(255, differ), HqxInterp::center());
+        assert_eq!(
+            Hqx::hq2x_corner(255, same),
+            i(&[(4, 14), (3, 1), (1, 1)], 4)
+        );
+
+        // case 0: PIXEL00_2 PIXEL01_1
+        assert_eq!(Hqx::hq3x_corner(0, same), i(&[(4, 2), (3, 1), (1, 1)], 2));
+        assert_eq!(Hqx::hq3x_edge(0, same), i(&[(4, 3), (1, 1)], 2));
+        // case 2: PIXEL00_1M PIXEL01_C
+        assert_eq!(Hqx::hq3x_corner(2, same), i(&[(4, 3), (0, 1)], 2));
+        assert_eq!(Hqx::hq3x_edge(2, same), HqxInterp::center());
+        // case 19: if (Diff(w[2], w[6])) PIXEL00_1L PIXEL01_C else PIXEL00_2 PIXEL01_6
+        assert_eq!(Hqx::hq3x_corner(19, differ), i(&[(4, 3), (3, 1)], 2));
+        assert_eq!(Hqx::hq3x_edge(19, differ), HqxInterp::center());
+        assert_eq!(Hqx::hq3x_corner(19, same), i(&[(4, 2), (3, 1), (1, 1)], 2));
+        assert_eq!(Hqx::hq3x_edge(19, same), i(&[(1, 3), (4, 1)], 2));
+        // case 255: if (Diff(w[4], w[2])) PIXEL00_C else PIXEL00_2; PIXEL01_C
+        assert_eq!(Hqx::hq3x_corner(255, differ), HqxInterp::center());
+        assert_eq!(Hqx::hq3x_corner(255, same), i(&[(4, 2), (3, 1), (1, 1)], 2));
+        assert_eq!(Hqx::hq3x_edge(255, same), HqxInterp::center());
+    }
+
+    #[test]
+    fn hqx_works() {
+        // A dot on a contrasting background is rounded off at its corners, but not at its edges.
+        let (bg, fg) = (0, 0x7fff);
+        #[rustfmt::skip]
+        let src = [
+            bg, bg, bg,
+            bg, fg, bg,
+            bg, bg, bg,
+        ];
+        let mut out = [0; 81];
+        Hqx::new(3).unwrap().apply(&src, 3, &mut out);
+
+        // case 255, with no neighbours differing from each other: PIXEL00_2 PIXEL01_C PIXEL11.
+        let corner = HqxInterp::new(&[(4, 2), (3, 1), (1, 1)], 2).apply(&src);
+        assert_eq!(out[3 * 9 + 3..][..3], [corner, fg, corner]);
+        assert_eq!(out[4 * 9 + 3..][..3], [fg, fg, fg]);
+        assert_eq!(out[5 * 9 + 3..][..3], [corner, fg, corner]);
+    }
+
+    #[test]
+    fn parsing_works() {
+        let scale = |s: &str| s.parse::<FilterKind>().unwrap().filter().map(|f| f.scale());
+        assert_eq!(scale("none"), None);
+        assert_eq!(scale("epx"), Some(2));
+        assert_eq!(scale("xbr"), Some(2));
+        assert_eq!(scale("scale3x"), Some(3));
+        assert_eq!(scale("xbrz4x"), Some(4));
+        assert_eq!(scale("lcd4x"), Some(4));
+        assert_eq!(scale("hq3x"), Some(3));
+
+        for s in [
+            "smooth2x",
+            "hq4x",
+            "xbrz5x",
+            "nearest1x",
+            "scale",
+            "x",
+            "2x",
+        ] {
+            assert_eq!(s.parse::<FilterKind>(), Err(ParseFilterError), "{s}");
+        }
+    }
+}
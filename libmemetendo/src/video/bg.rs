@@ -32,48 +32,59 @@ impl Video {
         });
     }
 
+    /// Whether BG `bg_idx` is displayed within `win`, ignoring the current BG mode.
+    pub(super) fn is_bg_displayed(&self, bg_idx: usize, win: Window) -> bool {
+        self.dispcnt.display_bg[bg_idx]
+            && self.layers.shows_bg(bg_idx)
+            && self
+                .window_control(win)
+                .map_or(true, |w| w.display_bg[bg_idx])
+    }
+
     pub(super) fn compute_bg_tile_mode_dot_iter(
         &self,
+        x: u16,
         win: Window,
     ) -> impl Iterator<Item = DotInfo> + '_ {
         self.tile_mode_bg_order
             .iter()
-            .filter(move |&&i| {
-                self.dispcnt.display_bg[i]
-                    && self.window_control(win).map_or(true, |w| w.display_bg[i])
-            })
-            .filter_map(|&i| self.compute_bg_tile_mode_dot(i))
+            .filter(move |&&i| self.is_bg_displayed(i, win))
+            .filter_map(move |&i| self.compute_bg_tile_mode_dot(i, x))
     }
 
-    fn compute_bg_tile_mode_dot(&self, bg_idx: usize) -> Option<DotInfo> {
+    pub(super) fn compute_bg_tile_mode_dot(&self, bg_idx: usize, x: u16) -> Option<DotInfo> {
         let text_mode = self.dispcnt.mode == 0 || bg_idx < 2;
-        let (x, y) = if text_mode {
-            let (x, y) = (i32::from(self.x), i32::from(self.y));
+        if text_mode {
+            let (x, y) = self.bg_mosaic_pos(bg_idx, x);
             let (scroll_x, scroll_y) = self.bgofs[bg_idx].get();
+            let (x, y) = (
+                i32::from(scroll_x) + i32::from(x),
+                i32::from(scroll_y) + i32::from(y),
+            );
+            let (dots_idx, flip, palette_idx) = self.text_mode_tile(
+                bg_idx,
+                (
+                    x.div_euclid(TILE_DOT_LEN.into()),
+                    y.div_euclid(TILE_DOT_LEN.into()),
+                ),
+            );
 
-            (i32::from(scroll_x) + x, i32::from(scroll_y) + y)
-        } else {
-            // Unlike text mode, this may result in a negative position.
-            self.bg_affine_transform_pos(bg_idx, self.x.into())
-        };
+            return self.compute_bg_tile_dot(
+                bg_idx,
+                dots_idx,
+                palette_idx,
+                Self::flip_tile_dot_pos(flip, (1, 1), Self::tile_dot_pos((x, y))),
+            );
+        }
 
+        // Unlike text mode, this may result in a negative position.
+        let (x, y) = self.bg_affine_transform_pos(bg_idx, self.bg_mosaic_pos(bg_idx, x).0.into());
         let (tile_x, tile_y) = (
             x.div_euclid(TILE_DOT_LEN.into()),
             y.div_euclid(TILE_DOT_LEN.into()),
         );
-        let screen_tile_len = self.bgcnt[bg_idx].screen_tile_len(text_mode);
-        let screen_idx = if text_mode {
-            self.bgcnt[bg_idx].text_mode_screen_index((
-                tile_x / i32::from(screen_tile_len),
-                tile_y / i32::from(screen_tile_len),
-            ))
-        } else {
-            0
-        };
-        let screen_base_offset = self.bgcnt[bg_idx].screen_vram_offset(screen_idx);
-        let screen_wraparound = text_mode || self.bgcnt[bg_idx].wraparound;
-
-        let (screen_tile_x, screen_tile_y) = if screen_wraparound {
+        let screen_tile_len = self.bgcnt[bg_idx].screen_tile_len(false);
+        let (screen_tile_x, screen_tile_y) = if self.bgcnt[bg_idx].wraparound {
             (
                 tile_x.rem_euclid(screen_tile_len.into()) as u32,
                 tile_y.rem_euclid(screen_tile_len.into()) as u32,
@@ -89,37 +100,110 @@ impl Video {
             (tile_x as u32, tile_y as u32)
         };
         let screen_tile_idx = screen_tile_y * u32::from(screen_tile_len) + screen_tile_x;
+        let dots_idx_offset =
+            self.bgcnt[bg_idx].screen_vram_offset(0) + usize::try_from(screen_tile_idx).unwrap();
+        if dots_idx_offset >= self.vram.len() {
+            return None;
+        }
 
-        let (mut dot_x, mut dot_y) = (
-            u8::try_from(x.rem_euclid(TILE_DOT_LEN.into()).bits(..8)).unwrap(),
-            u8::try_from(y.rem_euclid(TILE_DOT_LEN.into()).bits(..8)).unwrap(),
-        );
-        let (dots_idx, palette_idx) = if text_mode {
-            let tile_info_offset = u32::try_from(screen_base_offset).unwrap() + 2 * screen_tile_idx;
-            let tile_info = self.vram.as_ref().read_hword(tile_info_offset);
-            let dots_idx = usize::from(tile_info.bits(..10));
-
-            if self.dispcnt.mode == 0 || (self.dispcnt.mode == 1 && bg_idx < 2) {
-                (dot_x, dot_y) = Self::flip_tile_dot_pos(
-                    (tile_info.bit(10), tile_info.bit(11)),
-                    (1, 1),
-                    (dot_x, dot_y),
-                );
-            }
-            let color256 = self.bgcnt[bg_idx].color256
-                || (self.dispcnt.mode == 1 && bg_idx == 2)
-                || self.dispcnt.mode == 2;
+        self.compute_bg_tile_dot(
+            bg_idx,
+            self.vram[dots_idx_offset].into(),
+            None,
+            Self::tile_dot_pos((x, y)),
+        )
+    }
 
-            (dots_idx, (!color256).then_some(tile_info.bits(12..)))
-        } else {
-            let dots_idx_offset = screen_base_offset + usize::try_from(screen_tile_idx).unwrap();
-            if dots_idx_offset >= self.vram.len() {
-                return None;
+    /// Like calling [`Self::compute_bg_tile_mode_dot`] for every dot of text mode BG `bg_idx` on
+    /// the current scanline, but only reading the screen entry of each tile once.
+    pub(super) fn compute_bg_text_mode_line(
+        &self,
+        bg_idx: usize,
+        dots: &mut [Option<DotInfo>; HBLANK_DOT as usize],
+    ) {
+        let (scroll_x, scroll_y) = self.bgofs[bg_idx].get();
+        let y = i32::from(scroll_y) + i32::from(self.bg_mosaic_pos(bg_idx, 0).1);
+        let tile_y = y.div_euclid(TILE_DOT_LEN.into());
+        let mut tile_x = None;
+        let (mut dots_idx, mut flip, mut palette_idx) = (0, (false, false), None);
+
+        for (x, dot) in (0..).zip(dots.iter_mut()) {
+            let x = i32::from(scroll_x) + i32::from(self.bg_mosaic_pos(bg_idx, x).0);
+            let dot_tile_x = x.div_euclid(TILE_DOT_LEN.into());
+            if tile_x != Some(dot_tile_x) {
+                tile_x = Some(dot_tile_x);
+                (dots_idx, flip, palette_idx) = self.text_mode_tile(bg_idx, (dot_tile_x, tile_y));
             }
 
-            (usize::from(self.vram[dots_idx_offset]), None)
+            *dot = self.compute_bg_tile_dot(
+                bg_idx,
+                dots_idx,
+                palette_idx,
+                Self::flip_tile_dot_pos(flip, (1, 1), Self::tile_dot_pos((x, y))),
+            );
+        }
+    }
+
+    /// The screen position that BG `bg_idx` is drawn from at dot `x` of the current scanline,
+    /// which is the top-left dot of its mosaic block if mosaic is enabled for it.
+    ///
+    /// Affine BGs instead apply vertical mosaic through their reference point.
+    fn bg_mosaic_pos(&self, bg_idx: usize, x: u16) -> (u16, u8) {
+        if !self.bgcnt[bg_idx].mosaic {
+            return (x, self.y);
+        }
+
+        let (width, _) = self.mosaic_bg.size();
+        (x - x % u16::from(width), self.y - self.mosaic_bg_counter)
+    }
+
+    /// Reads the screen entry of a text mode BG tile, returning its tile index, whether it's
+    /// flipped horizontally and vertically, and its palette if it uses 16 colours.
+    fn text_mode_tile(
+        &self,
+        bg_idx: usize,
+        (tile_x, tile_y): (i32, i32),
+    ) -> (usize, (bool, bool), Option<u16>) {
+        let screen_tile_len = self.bgcnt[bg_idx].screen_tile_len(true);
+        let screen_idx = self.bgcnt[bg_idx].text_mode_screen_index((
+            tile_x / i32::from(screen_tile_len),
+            tile_y / i32::from(screen_tile_len),
+        ));
+        let screen_base_offset = self.bgcnt[bg_idx].screen_vram_offset(screen_idx);
+        let screen_tile_idx = tile_y.rem_euclid(screen_tile_len.into()) as u32
+            * u32::from(screen_tile_len)
+            + tile_x.rem_euclid(screen_tile_len.into()) as u32;
+
+        let tile_info_offset = u32::try_from(screen_base_offset).unwrap() + 2 * screen_tile_idx;
+        let tile_info = self.vram.as_ref().read_hword(tile_info_offset);
+        let dots_idx = usize::from(tile_info.bits(..10));
+
+        let flip = if self.dispcnt.mode == 0 || (self.dispcnt.mode == 1 && bg_idx < 2) {
+            (tile_info.bit(10), tile_info.bit(11))
+        } else {
+            (false, false)
         };
+        let color256 = self.bgcnt[bg_idx].color256
+            || (self.dispcnt.mode == 1 && bg_idx == 2)
+            || self.dispcnt.mode == 2;
+
+        (dots_idx, flip, (!color256).then_some(tile_info.bits(12..)))
+    }
+
+    fn tile_dot_pos((x, y): (i32, i32)) -> (u8, u8) {
+        (
+            u8::try_from(x.rem_euclid(TILE_DOT_LEN.into()).bits(..8)).unwrap(),
+            u8::try_from(y.rem_euclid(TILE_DOT_LEN.into()).bits(..8)).unwrap(),
+        )
+    }
 
+    fn compute_bg_tile_dot(
+        &self,
+        bg_idx: usize,
+        dots_idx: usize,
+        palette_idx: Option<u16>,
+        (dot_x, dot_y): (u8, u8),
+    ) -> Option<DotInfo> {
         let color256 = palette_idx.is_none();
         let dot_offset = self.bgcnt[bg_idx].dots_vram_offset()
             + if color256 { 64 } else { 32 } * dots_idx
@@ -135,13 +219,12 @@ impl Video {
             })
     }
 
-    pub(super) fn compute_bg_bitmap_mode_dot(&self, win: Window) -> Option<DotInfo> {
-        if !self.dispcnt.display_bg[2] || self.window_control(win).is_some_and(|w| !w.display_bg[2])
-        {
+    pub(super) fn compute_bg_bitmap_mode_dot(&self, x: u16, win: Window) -> Option<DotInfo> {
+        if !self.is_bg_displayed(2, win) {
             return None;
         }
 
-        let (x, y) = self.bg_affine_transform_pos(2, self.x.into());
+        let (x, y) = self.bg_affine_transform_pos(2, self.bg_mosaic_pos(2, x).0.into());
         if x < 0 || y < 0 {
             return None;
         }
@@ -167,7 +250,13 @@ impl Video {
     fn bg_affine_transform_pos(&self, bg_idx: usize, x: i32) -> (i32, i32) {
         let params = &self.bgp[bg_idx - 2];
         let d = (i32::from(params.a), i32::from(params.c));
+        let bg_ref = &self.bgref[bg_idx - 2];
+        let origin = if self.bgcnt[bg_idx].mosaic {
+            bg_ref.mosaic
+        } else {
+            bg_ref.internal
+        };
 
-        Self::affine_transform_pos(self.bgref[bg_idx - 2].internal, (0, 0), d, (x, 0))
+        Self::affine_transform_pos(origin, (0, 0), d, (x, 0))
     }
 }
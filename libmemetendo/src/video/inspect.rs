@@ -0,0 +1,392 @@
+//! Read-only views of video memory, for debug viewers, tile rippers and the like.
+
+use intbits::Bits;
+
+use crate::{bus::Bus, util};
+
+use super::{Dot, Video, TILE_DOT_LEN};
+
+pub use super::obj::Mode as ObjMode;
+
+/// An image decoded from video memory, in row-major order. Transparent dots are `None`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub dots: Vec<Option<Dot>>,
+}
+
+impl Image {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            dots: vec![None; width as usize * height as usize],
+        }
+    }
+
+    #[must_use]
+    pub fn dot(&self, x: u32, y: u32) -> Option<Dot> {
+        self.dots[y as usize * self.width as usize + x as usize]
+    }
+
+    fn put_dot(&mut self, x: u32, y: u32, dot: Dot) {
+        self.dots[y as usize * self.width as usize + x as usize] = Some(dot);
+    }
+
+    /// Converts the image to 8-bit RGB like `bgr555_to_rgb24`, with transparent dots drawn as
+    /// `key`.
+    #[must_use]
+    pub fn to_rgb(&self, key: Dot) -> Vec<u8> {
+        let dots: Vec<u16> = self
+            .dots
+            .iter()
+            .map(|dot| dot.unwrap_or(key).into())
+            .collect();
+        let mut rgb = vec![0; 3 * dots.len()];
+        util::video::bgr555_to_rgb24(&dots, &mut rgb);
+
+        rgb
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Palette {
+    Background,
+    Object,
+}
+
+impl Palette {
+    fn ram_offset(self) -> u32 {
+        match self {
+            Self::Background => 0,
+            Self::Object => 0x200,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ObjAffine {
+    pub params_idx: u8,
+    pub double_size: bool,
+}
+
+/// The attributes of an OBJ, as decoded from OAM.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ObjAttributes {
+    pub pos: (i16, i16),
+    /// Size in dots, before any affine doubling. (0, 0) if the shape is invalid.
+    pub size: (u8, u8),
+    /// `None` if the OBJ isn't affine.
+    pub affine: Option<ObjAffine>,
+    /// Only for OBJs that aren't affine.
+    pub hidden: bool,
+    /// Only for OBJs that aren't affine.
+    pub flip: (bool, bool),
+    /// `None` if the mode is invalid.
+    pub mode: Option<ObjMode>,
+    pub mosaic: bool,
+    pub tile_idx: u16,
+    pub priority: u8,
+    /// `None` for 256-colour OBJs.
+    pub palette_idx: Option<u8>,
+    /// Whether the OBJ is drawn at all, which is not the case if it's hidden, or its mode or
+    /// shape is invalid.
+    pub visible: bool,
+}
+
+/// A whole BG map, rendered from its tiles.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BgMap {
+    /// Whether the BG is affine (rotated or scaled), rather than text mode.
+    pub affine: bool,
+    /// Position of the screen's top-left corner within the map: the scroll offset for text
+    /// mode BGs, or the integer part of the reference point for affine BGs.
+    pub origin: (i32, i32),
+    pub image: Image,
+}
+
+impl Video {
+    /// The contents of VRAM, as if it were mapped at 0x6000000 without mirroring.
+    #[must_use]
+    pub fn vram_bytes(&self) -> &[u8] {
+        &self.vram
+    }
+
+    /// The 256 colours of a palette.
+    #[must_use]
+    pub fn palette_colors(&self, palette: Palette) -> [Dot; 256] {
+        let mut colors = [Dot::new(0, 0, 0); 256];
+        for (offset, color) in (palette.ram_offset()..).step_by(2).zip(&mut colors) {
+            *color = self.palette_dot(offset);
+        }
+
+        colors
+    }
+
+    /// Decodes `count` tiles starting from `vram_offset`, laid out in rows of `columns` tiles.
+    ///
+    /// Tiles are 16-colour (4bpp) using palette `palette_idx` (0-15), or 256-colour (8bpp) if
+    /// it's `None`. Tiles beyond the end of VRAM are left transparent.
+    #[must_use]
+    pub fn decode_tiles(
+        &self,
+        vram_offset: usize,
+        count: usize,
+        columns: usize,
+        palette: Palette,
+        palette_idx: Option<u8>,
+    ) -> Image {
+        let columns = columns.max(1);
+        let rows = (count + columns - 1) / columns;
+        let tile_len = u32::from(TILE_DOT_LEN);
+        #[allow(clippy::cast_possible_truncation)]
+        let mut image = Image::new(columns.min(count) as u32 * tile_len, rows as u32 * tile_len);
+        let tile_size = if palette_idx.is_some() { 32 } else { 64 };
+
+        for i in 0..count {
+            #[allow(clippy::cast_possible_truncation)]
+            let pos = (
+                (i % columns) as u32 * tile_len,
+                (i / columns) as u32 * tile_len,
+            );
+            self.draw_tile(
+                &mut image,
+                pos,
+                vram_offset + i * tile_size,
+                palette,
+                palette_idx.map(u16::from),
+                (false, false),
+            );
+        }
+
+        image
+    }
+
+    /// Renders the whole map of tile mode BG `bg_idx`, ignoring scrolling, windows and effects.
+    ///
+    /// Returns `None` if the BG doesn't exist in the current BG mode.
+    #[must_use]
+    pub fn render_bg_map(&self, bg_idx: usize) -> Option<BgMap> {
+        let affine = match (self.dispcnt.mode, bg_idx) {
+            (0, 0..=3) | (1, 0 | 1) => false,
+            (1, 2) | (2, 2 | 3) => true,
+            _ => return None,
+        };
+        let bgcnt = self.bgcnt[bg_idx];
+        let dots_offset = bgcnt.dots_vram_offset();
+        let tile_len = u32::from(TILE_DOT_LEN);
+
+        if affine {
+            let map_len = u16::from(bgcnt.screen_tile_len(false));
+            let map_offset = bgcnt.screen_vram_offset(0);
+            let mut image =
+                Image::new(u32::from(map_len) * tile_len, u32::from(map_len) * tile_len);
+            for tile_y in 0..map_len {
+                for tile_x in 0..map_len {
+                    let info_offset = map_offset + usize::from(tile_y * map_len + tile_x);
+                    let Some(&tile_idx) = self.vram.get(info_offset) else {
+                        continue;
+                    };
+                    self.draw_tile(
+                        &mut image,
+                        (u32::from(tile_x) * tile_len, u32::from(tile_y) * tile_len),
+                        dots_offset + 64 * usize::from(tile_idx),
+                        Palette::Background,
+                        None,
+                        (false, false),
+                    );
+                }
+            }
+
+            let (x, y) = self.bgref[bg_idx - 2].external;
+            return Some(BgMap {
+                affine,
+                origin: (x >> 8, y >> 8),
+                image,
+            });
+        }
+
+        let screen_len = u16::from(bgcnt.screen_tile_len(true));
+        let (screens_x, screens_y) = bgcnt.text_mode_screens();
+        let (map_width, map_height) = (
+            u16::from(screens_x) * screen_len,
+            u16::from(screens_y) * screen_len,
+        );
+        let mut image = Image::new(
+            u32::from(map_width) * tile_len,
+            u32::from(map_height) * tile_len,
+        );
+        for tile_y in 0..map_height {
+            for tile_x in 0..map_width {
+                let screen_idx = bgcnt.text_mode_screen_index((
+                    i32::from(tile_x / screen_len),
+                    i32::from(tile_y / screen_len),
+                ));
+                let info_offset = bgcnt.screen_vram_offset(screen_idx)
+                    + 2 * usize::from((tile_y % screen_len) * screen_len + tile_x % screen_len);
+                if info_offset + 1 >= self.vram.len() {
+                    continue;
+                }
+
+                #[allow(clippy::cast_possible_truncation)]
+                let tile_info = self.vram.as_ref().read_hword(info_offset as u32);
+                let palette_idx = (!bgcnt.color256).then_some(tile_info.bits(12..));
+                let tile_size = if bgcnt.color256 { 64 } else { 32 };
+                self.draw_tile(
+                    &mut image,
+                    (u32::from(tile_x) * tile_len, u32::from(tile_y) * tile_len),
+                    dots_offset + tile_size * usize::from(tile_info.bits(..10)),
+                    Palette::Background,
+                    palette_idx,
+                    (tile_info.bit(10), tile_info.bit(11)),
+                );
+            }
+        }
+
+        let (x, y) = self.bgofs[bg_idx].get();
+        Some(BgMap {
+            affine,
+            origin: (x.into(), y.into()),
+            image,
+        })
+    }
+
+    /// The decoded attributes of all 128 OBJs.
+    #[must_use]
+    pub fn objs(&self) -> Vec<ObjAttributes> {
+        (0..128).map(|idx| self.oam.attributes(idx)).collect()
+    }
+
+    /// Renders the tiles of OBJ `idx` (0-127), without flipping or affine transformations.
+    #[must_use]
+    pub fn render_obj(&self, idx: u8) -> Image {
+        let attrs = self.oam.attributes(idx);
+        let (width, height) = (attrs.size.0 / TILE_DOT_LEN, attrs.size.1 / TILE_DOT_LEN);
+        let mut image = Image::new(attrs.size.0.into(), attrs.size.1.into());
+
+        let tile_stride = if attrs.palette_idx.is_some() { 1 } else { 2 };
+        let row_stride = if self.dispcnt.obj_1d {
+            usize::from(width) * tile_stride
+        } else {
+            32 // 2D mapping always uses 32x32 tile maps
+        };
+        for tile_y in 0..height {
+            for tile_x in 0..width {
+                let tile_offset = 0x1_0000
+                    + 32 * (usize::from(attrs.tile_idx)
+                        + usize::from(tile_y) * row_stride
+                        + usize::from(tile_x) * tile_stride);
+                if tile_offset < self.dispcnt.obj_vram_offset() {
+                    continue; // Used by the bitmap in bitmap modes
+                }
+
+                self.draw_tile(
+                    &mut image,
+                    (
+                        u32::from(tile_x * TILE_DOT_LEN),
+                        u32::from(tile_y * TILE_DOT_LEN),
+                    ),
+                    tile_offset,
+                    Palette::Object,
+                    attrs.palette_idx.map(u16::from),
+                    (false, false),
+                );
+            }
+        }
+
+        image
+    }
+
+    fn palette_dot(&self, offset: u32) -> Dot {
+        Dot::from(self.palette_ram.0.as_ref().read_hword(offset))
+    }
+
+    fn draw_tile(
+        &self,
+        image: &mut Image,
+        (x, y): (u32, u32),
+        tile_offset: usize,
+        palette: Palette,
+        palette_idx: Option<u16>,
+        flip: (bool, bool),
+    ) {
+        let dots_per_byte = if palette_idx.is_some() { 2 } else { 1 };
+        for dot_y in 0..TILE_DOT_LEN {
+            for dot_x in 0..TILE_DOT_LEN {
+                let dot_offset = tile_offset
+                    + (usize::from(TILE_DOT_LEN * dot_y) + usize::from(dot_x)) / dots_per_byte;
+                if dot_offset >= self.vram.len() {
+                    return;
+                }
+
+                if let Some(info) = self.read_tile_dot_palette(palette_idx, dot_offset, dot_x) {
+                    let (flip_x, flip_y) = Self::flip_tile_dot_pos(flip, (1, 1), (dot_x, dot_y));
+                    let dot = self.palette_dot(palette.ram_offset() + info.ram_offset());
+                    image.put_dot(x + u32::from(flip_x), y + u32::from(flip_y), dot);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inspect_works() {
+        let mut video = Video::new();
+        let red = Dot::from(0x001f);
+        let green = Dot::from(0x03e0);
+        video.palette_ram.write_hword(2 * 17, 0x001f);
+        video.palette_ram.write_hword(0x200 + 2, 0x03e0);
+        assert_eq!(video.palette_colors(Palette::Background)[17], red);
+        assert_eq!(video.palette_colors(Palette::Object)[1], green);
+
+        // Tile 1: colour 1 in the top-left dot.
+        video.vram().write_hword(0x20, 0x0001);
+        let tiles = video.decode_tiles(0, 3, 2, Palette::Background, Some(1));
+        assert_eq!((tiles.width, tiles.height), (16, 16));
+        assert_eq!(tiles.dot(8, 0), Some(red));
+        assert_eq!(tiles.dot(9, 0), None);
+        assert_eq!(&tiles.to_rgb(green)[..3], [0, 0xff, 0]);
+
+        // BG0: mode 0, map at screen block 8, tile 1 with palette 1 flipped horizontally at
+        // (1, 0), and a 512x256 map scrolled by 3 dots.
+        video.write_byte(0x00, 0);
+        video.write_hword(0x08, 0x4800);
+        video.write_hword(0x10, 3);
+        video.vram().write_hword(0x4002, 0x1401);
+        let bg_map = video.render_bg_map(0).unwrap();
+        assert!(!bg_map.affine);
+        assert_eq!(bg_map.origin, (3, 0));
+        assert_eq!((bg_map.image.width, bg_map.image.height), (512, 256));
+        assert_eq!(bg_map.image.dot(15, 0), Some(red));
+        assert_eq!(bg_map.image.dot(8, 0), None);
+        assert!(!video.render_bg_map(2).unwrap().affine);
+
+        // Mode 2 only has affine BG2 and BG3.
+        video.write_byte(0x00, 2);
+        assert!(video.render_bg_map(0).is_none());
+        let bg_map = video.render_bg_map(2).unwrap();
+        assert!(bg_map.affine);
+        assert_eq!((bg_map.image.width, bg_map.image.height), (128, 128));
+
+        // OBJ 0: 16x8, 16 colours, tile 0 with colour 1 in the top-left dot.
+        video.write_byte(0x00, 0);
+        video.oam.write_hword(0, 0x4000);
+        video.oam.write_hword(2, 0);
+        video.vram().write_hword(0x1_0000, 0x0001);
+        let objs = video.objs();
+        assert_eq!(objs.len(), 128);
+        assert_eq!(objs[0].size, (16, 8));
+        assert_eq!(objs[0].mode, Some(ObjMode::Normal));
+        assert!(objs[0].visible);
+        let obj = video.render_obj(0);
+        assert_eq!((obj.width, obj.height), (16, 8));
+        assert_eq!(obj.dot(0, 0), Some(green));
+        assert_eq!(obj.dot(8, 0), None);
+    }
+}
@@ -0,0 +1,68 @@
+use super::{reg::BackgroundMode, Dot, DotInfo, Video, Window, HBLANK_DOT};
+
+const LINE_LEN: usize = HBLANK_DOT as usize;
+
+impl Video {
+    /// Draws the current scanline with one pass per layer, then composes its dots.
+    ///
+    /// The result is the same as drawing every dot with the per-dot renderer, as long as video
+    /// state doesn't change during the scanline.
+    pub(super) fn render_line(&mut self) {
+        if self.dispcnt.forced_blank {
+            self.line = [Dot::WHITE; LINE_LEN];
+            return;
+        }
+
+        let mut obj_dots = [None; LINE_LEN];
+        let mut inside_obj_window = [false; LINE_LEN];
+        self.compute_obj_line(&mut obj_dots, &mut inside_obj_window);
+
+        let mut bg_dots = [[None; LINE_LEN]; 4];
+        let mode = self.dispcnt.mode();
+        match mode {
+            BackgroundMode::Tile => {
+                for (bg_idx, dots) in bg_dots.iter_mut().enumerate() {
+                    if !self.is_bg_displayed(bg_idx, Window::None) {
+                        continue;
+                    }
+                    if self.dispcnt.mode == 0 || bg_idx < 2 {
+                        self.compute_bg_text_mode_line(bg_idx, dots);
+                    } else {
+                        for (x, dot) in (0..).zip(dots.iter_mut()) {
+                            *dot = self.compute_bg_tile_mode_dot(bg_idx, x);
+                        }
+                    }
+                }
+            }
+            BackgroundMode::Bitmap => {
+                for (x, dot) in (0..).zip(bg_dots[2].iter_mut()) {
+                    *dot = self.compute_bg_bitmap_mode_dot(x, Window::None);
+                }
+            }
+            BackgroundMode::Invalid => {}
+        }
+
+        for (x, i) in (0..).zip(0..LINE_LEN) {
+            let top_win = if self.layers.uses_windows() {
+                self.find_top_window(x, || inside_obj_window[i])
+            } else {
+                Window::None
+            };
+            let top_infos = if mode == BackgroundMode::Invalid {
+                [DotInfo::Backdrop; 2]
+            } else {
+                let win = self.window_control(top_win);
+                let obj_info = obj_dots[i].filter(|_| win.map_or(true, |w| w.display_obj));
+                let bg_infos = self
+                    .tile_mode_bg_order
+                    .iter()
+                    .filter(|&&bg_idx| win.map_or(true, |w| w.display_bg[bg_idx]))
+                    .filter_map(|&bg_idx| bg_dots[bg_idx][i]);
+
+                self.compute_top_dots(obj_info, bg_infos)
+            };
+
+            self.line[i] = self.blend_top_dots(top_win, top_infos);
+        }
+    }
+}
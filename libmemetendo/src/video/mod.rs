@@ -1,4 +1,6 @@
 mod bg;
+pub mod inspect;
+mod line;
 mod obj;
 mod reg;
 
@@ -55,6 +57,14 @@ impl Vram<'_> {
             addr & !0xf000
         }
     }
+
+    /// Whether `addr` is in OBJ VRAM, given the current BG mode.
+    // Panic is impossible as VRAM offsets always fit.
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn is_obj(&self, addr: u32) -> bool {
+        usize::try_from(Self::offset(addr)).unwrap() >= self.0.dispcnt.obj_vram_offset()
+    }
 }
 
 impl Bus for Vram<'_> {
@@ -64,15 +74,16 @@ impl Bus for Vram<'_> {
 
     fn write_byte(&mut self, addr: u32, value: u8) {
         // Like palette RAM, but only write a hword for BG data.
-        let addr = Self::offset(addr);
-        if usize::try_from(addr).unwrap() < self.0.dispcnt.obj_vram_offset() {
+        if !self.is_obj(addr) {
+            self.0.sync_line();
             self.0
                 .vram
-                .write_hword(addr, u16::from_le_bytes([value, value]));
+                .write_hword(Self::offset(addr), u16::from_le_bytes([value, value]));
         }
     }
 
     fn write_hword(&mut self, addr: u32, value: u16) {
+        self.0.sync_line();
         self.0.vram.write_hword(Self::offset(addr), value);
     }
 }
@@ -82,6 +93,17 @@ pub struct Video {
     x: u16,
     y: u8,
     cycle_accum: u16,
+    /// Dots of the current scanline, drawn when it enters H-Blank.
+    line: [Dot; HBLANK_DOT as usize],
+    /// Dots of the frame in BGR555, given to the callback.
+    frame: Box<[u16]>,
+    /// How many dots of `line` were already drawn by the per-dot renderer, due to changes to
+    /// video state mid-scanline.
+    line_drawn_dots: u16,
+    skip_line: bool,
+    /// OBJs from this OAM index onwards don't fit in the OBJ rendering budget of the current
+    /// scanline.
+    obj_line_end: u8,
     tile_mode_bg_order: ArrayVec<[usize; 4]>,
 
     vram: Box<[u8]>,
@@ -101,9 +123,17 @@ pub struct Video {
     winobj: WindowControl,
     mosaic_bg: Mosaic,
     mosaic_obj: Mosaic,
+    /// Scanlines since the vertical BG and OBJ mosaic blocks started.
+    mosaic_bg_counter: u8,
+    mosaic_obj_counter: u8,
     bldcnt: BlendControl,
     bldalpha: (BlendCoefficient, BlendCoefficient),
     bldy: BlendCoefficient,
+
+    pub layers: LayerSwitches,
+    /// Draws every OBJ on a scanline, rather than dropping those that don't fit in the
+    /// hardware's OBJ rendering budget. This is an enhancement, so it's off by default.
+    pub unlimited_objs: bool,
 }
 
 impl Default for Video {
@@ -125,6 +155,11 @@ impl Video {
             x: 0,
             y: 0,
             cycle_accum: 0,
+            line: [Dot::WHITE; HBLANK_DOT as usize],
+            frame: vec![0; usize::from(HBLANK_DOT) * usize::from(VBLANK_DOT)].into_boxed_slice(),
+            line_drawn_dots: 0,
+            skip_line: false,
+            obj_line_end: 128,
             tile_mode_bg_order: array_vec![0, 1, 2, 3],
             vram: vec![0; 0x1_8000].into_boxed_slice(),
             palette_ram: PaletteRam::default(),
@@ -142,46 +177,62 @@ impl Video {
             winobj: WindowControl::default(),
             mosaic_bg: Mosaic::default(),
             mosaic_obj: Mosaic::default(),
+            mosaic_bg_counter: 0,
+            mosaic_obj_counter: 0,
             bldcnt: BlendControl::default(),
             bldalpha: (BlendCoefficient::default(), BlendCoefficient::default()),
             bldy: BlendCoefficient::default(),
+            layers: LayerSwitches::default(),
+            unlimited_objs: false,
         }
     }
 
-    // Panic should be impossible as self.x should be < HBLANK_DOT when calling screen.put_dot(),
-    // which fits in a u8.
-    #[allow(clippy::missing_panics_doc)]
     pub fn step(&mut self, cb: &mut impl Callback, irq: &mut Irq, dma: &mut Dma, cycles: u8) {
         self.cycle_accum += u16::from(cycles);
         while self.cycle_accum >= 4 {
             self.cycle_accum -= 4;
 
-            if self.x < HBLANK_DOT.into() && self.y < VBLANK_DOT && !cb.is_frame_skipping() {
-                cb.put_dot(self.x.try_into().unwrap(), self.y, self.compute_dot());
+            if self.x == 0 && self.y < VBLANK_DOT {
+                self.skip_line = cb.is_frame_skipping();
+                self.update_obj_line_end();
             }
 
             self.x += 1;
             if self.x == HBLANK_DOT.into() {
+                if self.y < VBLANK_DOT && !self.skip_line {
+                    self.finish_line(cb);
+                }
                 if self.dispstat.hblank_irq_enabled {
                     irq.request(Interrupt::HBlank);
                 }
                 if self.y < VBLANK_DOT {
                     dma.notify(dma::Event::HBlank);
                 }
+                // DMA3 video capture runs from scanline 2 until scanline 162 starts.
+                if (2..VBLANK_DOT + 2).contains(&self.y) {
+                    dma.notify(dma::Event::VideoCapture);
+                }
 
                 if self.y < VBLANK_DOT - 1 {
+                    self.mosaic_bg.step_counter(&mut self.mosaic_bg_counter);
+                    self.mosaic_obj.step_counter(&mut self.mosaic_obj_counter);
+
                     for (i, bg_ref) in self.bgref.iter_mut().enumerate() {
                         bg_ref.internal.0 += i32::from(self.bgp[i].b);
                         bg_ref.internal.1 += i32::from(self.bgp[i].d);
+                        if self.mosaic_bg_counter == 0 {
+                            bg_ref.mosaic = bg_ref.internal;
+                        }
                     }
                 }
                 if self.y == VBLANK_DOT - 1 {
-                    cb.end_frame(self.greenswp.bit(0));
+                    cb.end_frame(&self.frame);
                 }
             }
 
             if self.x >= HORIZ_DOTS {
                 self.x = 0;
+                self.line_drawn_dots = 0;
                 self.y += 1;
                 if self.y >= VERT_DOTS {
                     self.y = 0;
@@ -191,9 +242,14 @@ impl Video {
                     }
                     dma.notify(dma::Event::VBlank);
 
+                    self.mosaic_bg_counter = 0;
+                    self.mosaic_obj_counter = 0;
                     for bg_ref in &mut self.bgref {
                         bg_ref.internal = bg_ref.external;
+                        bg_ref.mosaic = bg_ref.external;
                     }
+                } else if self.y == VBLANK_DOT + 2 {
+                    dma.notify(dma::Event::VideoCaptureEnd);
                 }
 
                 if self.dispstat.vcount_irq_enabled && self.y == self.dispstat.vcount_target {
@@ -203,13 +259,86 @@ impl Video {
         }
     }
 
+    /// Draws the dots of the current scanline before the current dot, so that changes to video
+    /// state made now (to registers, palette RAM, VRAM or OAM) only affect the dots after it.
+    ///
+    /// Scanlines that need this are drawn by the slower per-dot renderer.
+    pub fn sync_line(&mut self) {
+        if self.y >= VBLANK_DOT || self.skip_line {
+            return;
+        }
+
+        let end_x = self.x.min(HBLANK_DOT.into());
+        for x in self.line_drawn_dots..end_x {
+            self.line[usize::from(x)] = self.compute_dot(x);
+        }
+        self.line_drawn_dots = self.line_drawn_dots.max(end_x);
+    }
+
+    fn finish_line(&mut self, cb: &mut impl Callback) {
+        if self.line_drawn_dots == 0 {
+            self.render_line();
+        } else {
+            self.sync_line();
+        }
+        // Changes during H-Blank don't need the scanline to be drawn again.
+        self.line_drawn_dots = HBLANK_DOT.into();
+
+        let start = usize::from(self.y) * usize::from(HBLANK_DOT);
+        let frame_line = &mut self.frame[start..start + usize::from(HBLANK_DOT)];
+        for (frame_dot, &dot) in frame_line.iter_mut().zip(&self.line) {
+            *frame_dot = dot.into();
+        }
+        if self.greenswp.bit(0) {
+            // Each pair of dots swaps their green components.
+            for pair in frame_line.chunks_exact_mut(2) {
+                let (green_0, green_1) = (pair[0].bits(5..10), pair[1].bits(5..10));
+                pair[0].set_bits(5..10, green_1);
+                pair[1].set_bits(5..10, green_0);
+            }
+        }
+
+        cb.put_line(self.y, frame_line);
+    }
+
+    /// The scanline that VCOUNT is compared against for the V-Counter flag and interrupt.
+    #[must_use]
+    pub fn vcount_target(&self) -> u8 {
+        self.dispstat.vcount_target
+    }
+
+    /// The scanline being drawn (VCOUNT), including those within V-Blank.
+    #[must_use]
+    pub fn scanline(&self) -> u8 {
+        self.y
+    }
+
+    /// The number of cycles until the next H-Blank or scanline starts, which is when interrupts
+    /// and DMA transfers may be requested.
+    #[cfg(feature = "jit")]
+    pub(crate) fn cycles_until_event(&self) -> u32 {
+        let next_x = if self.x < HBLANK_DOT.into() {
+            HBLANK_DOT.into()
+        } else {
+            HORIZ_DOTS
+        };
+
+        u32::from(next_x - self.x) * 4 - u32::from(self.cycle_accum)
+    }
+
+    /// The dot being drawn on the current scanline, including those within H-Blank.
+    #[must_use]
+    pub fn dot(&self) -> u16 {
+        self.x
+    }
+
     #[must_use]
     pub fn vram(&mut self) -> Vram {
         Vram(self)
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Dot {
     r: u8,
     g: u8,
@@ -226,6 +355,12 @@ impl From<u16> for Dot {
     }
 }
 
+impl From<Dot> for u16 {
+    fn from(dot: Dot) -> Self {
+        u16::from(dot.r) | u16::from(dot.g) << 5 | u16::from(dot.b) << 10
+    }
+}
+
 impl Dot {
     pub const MAX_COMPONENT: u8 = 31;
     pub const WHITE: Dot = Dot::new(
@@ -257,9 +392,83 @@ impl Dot {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Layer {
+    Background(usize),
+    Object,
+}
+
+/// Debugging switches for hiding parts of the rendered output, regardless of `DISPCNT`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LayerSwitches {
+    pub bg: [bool; 4],
+    pub obj: bool,
+    pub windows: bool,
+    pub blending: bool,
+    /// If set, only this layer is rendered, ignoring windows and blending, and its transparent
+    /// dots are shown as `key`.
+    pub isolated: Option<Layer>,
+    pub key: Dot,
+}
+
+impl Default for LayerSwitches {
+    fn default() -> Self {
+        Self {
+            bg: [true; 4],
+            obj: true,
+            windows: true,
+            blending: true,
+            isolated: None,
+            key: Self::DEFAULT_KEY,
+        }
+    }
+}
+
+impl LayerSwitches {
+    /// Magenta.
+    pub const DEFAULT_KEY: Dot = Dot::new(Dot::MAX_COMPONENT, 0, Dot::MAX_COMPONENT);
+
+    /// Isolates BG0-3 and OBJ in turn, then goes back to rendering every layer.
+    pub fn isolate_next(&mut self) {
+        self.isolated = match self.isolated {
+            None => Some(Layer::Background(0)),
+            Some(Layer::Background(idx)) if idx < 3 => Some(Layer::Background(idx + 1)),
+            Some(Layer::Background(_)) => Some(Layer::Object),
+            Some(Layer::Object) => None,
+        };
+    }
+
+    fn shows_bg(&self, bg_idx: usize) -> bool {
+        self.isolated
+            .map_or(self.bg[bg_idx], |layer| layer == Layer::Background(bg_idx))
+    }
+
+    fn shows_obj(&self) -> bool {
+        self.isolated
+            .map_or(self.obj, |layer| layer == Layer::Object)
+    }
+
+    fn uses_windows(&self) -> bool {
+        self.windows && self.isolated.is_none()
+    }
+
+    fn uses_blending(&self) -> bool {
+        self.blending && self.isolated.is_none()
+    }
+}
+
+/// Receives the drawn frames. Dots are in the native BGR555 format (see the helpers in
+/// [`crate::util::video`] for converting them), with green swap already applied.
 pub trait Callback {
-    fn put_dot(&mut self, x: u8, y: u8, dot: Dot);
-    fn end_frame(&mut self, green_swap: bool);
+    /// Called when scanline `y` is drawn, unless frames are being skipped.
+    fn put_line(&mut self, y: u8, line: &[u16]) {
+        let _ = (y, line);
+    }
+
+    /// Called after the last visible scanline with the whole frame, row by row. Its contents are
+    /// stale if frames are being skipped.
+    fn end_frame(&mut self, frame: &[u16]);
+
     fn is_frame_skipping(&self) -> bool;
 }
 
@@ -271,15 +480,41 @@ enum DotInfo {
 }
 
 impl Video {
-    fn compute_dot(&mut self) -> Dot {
+    fn compute_dot(&mut self, x: u16) -> Dot {
         if self.dispcnt.forced_blank {
             return Dot::WHITE;
         }
 
-        // TODO: mosaics
-        let top_win = self.find_top_window();
-        let top_infos = self.compute_top_dots(top_win);
+        let top_win = if self.layers.uses_windows() {
+            self.find_top_window(x, || self.check_inside_obj_window(x))
+        } else {
+            Window::None
+        };
+        let obj_info = self.compute_top_obj_dot(x, top_win);
+        let top_infos = match self.dispcnt.mode() {
+            BackgroundMode::Tile => {
+                self.compute_top_dots(obj_info, self.compute_bg_tile_mode_dot_iter(x, top_win))
+            }
+            BackgroundMode::Bitmap => self.compute_top_dots(
+                obj_info,
+                self.compute_bg_bitmap_mode_dot(x, top_win).into_iter(),
+            ),
+            BackgroundMode::Invalid => [DotInfo::Backdrop; 2],
+        };
+
+        self.blend_top_dots(top_win, top_infos)
+    }
+
+    /// Computes the final colour of a dot from the top two layers visible at it.
+    fn blend_top_dots(&mut self, top_win: Window, top_infos: [DotInfo; 2]) -> Dot {
+        if self.layers.isolated.is_some() && matches!(top_infos[0], DotInfo::Backdrop) {
+            return self.layers.key;
+        }
+
         let top_dot = self.read_dot(top_infos[0]);
+        if !self.layers.uses_blending() {
+            return top_dot;
+        }
 
         let obj_alpha_mode = matches!(
             top_infos[0],
@@ -288,22 +523,29 @@ impl Video {
                 ..
             })
         );
+        let win_blendfx = self
+            .window_control(top_win)
+            .map_or(true, |w| w.blendfx_enabled);
         let is_target = |top_dot_idx: usize| {
             let targeted = match top_infos[top_dot_idx] {
-                DotInfo::Object(_) => self.bldcnt.obj_target[top_dot_idx] || obj_alpha_mode,
+                DotInfo::Object(_) => self.bldcnt.obj_target[top_dot_idx],
                 DotInfo::Background(bg) => self.bldcnt.bg_target[top_dot_idx][bg.index()],
                 DotInfo::Backdrop => self.bldcnt.backdrop_target[top_dot_idx],
             };
-            let win_blendfx = self
-                .window_control(top_win)
-                .map_or(true, |w| w.blendfx_enabled);
 
             targeted && win_blendfx
         };
 
+        // Semi-transparent OBJs are alpha blended with a 2nd target below them, whatever the
+        // mode and 1st targets are. Otherwise, brightness effects only apply to them if they're
+        // a 1st target.
         match self.bldcnt.mode {
+            _ if obj_alpha_mode && is_target(1) => {
+                let bot_dot = self.read_dot(top_infos[1]);
+                self.alpha_blend_dots(top_dot, bot_dot)
+            }
             _ if !is_target(0) => top_dot,
-            mode if is_target(1) && (mode == BlendMode::Alpha || obj_alpha_mode) => {
+            BlendMode::Alpha if is_target(1) => {
                 let bot_dot = self.read_dot(top_infos[1]);
                 self.alpha_blend_dots(top_dot, bot_dot)
             }
@@ -331,61 +573,44 @@ impl Video {
         }
     }
 
-    fn compute_top_dots(&mut self, top_win: Window) -> [DotInfo; 2] {
-        let mut obj_info = self.compute_top_obj_dot(top_win);
-
-        match self.dispcnt.mode() {
-            BackgroundMode::Tile => {
-                let mut infos = [DotInfo::Backdrop; 2];
-                let mut bg_iter = self.compute_bg_tile_mode_dot_iter(top_win).peekable();
-
-                while let DotInfo::Backdrop = infos[1] {
-                    let top = match (obj_info, bg_iter.peek()) {
-                        (Some(obj), Some(bg))
-                            if obj.priority <= self.bgcnt[bg.index()].priority =>
-                        {
-                            DotInfo::Object(obj_info.take().unwrap())
-                        }
-                        (_, Some(_)) => DotInfo::Background(bg_iter.next().unwrap()),
-                        (Some(_), None) => DotInfo::Object(obj_info.take().unwrap()),
-                        (None, None) => break,
-                    };
-
-                    if let DotInfo::Backdrop = infos[0] {
-                        infos[0] = top;
-                    } else {
-                        infos[1] = top;
-                    }
+    /// Picks the top two layers at a dot from the top OBJ dot and the BG dots (in priority
+    /// order) visible at it.
+    fn compute_top_dots(
+        &self,
+        mut obj_info: Option<obj::DotInfo>,
+        bg_infos: impl Iterator<Item = bg::DotInfo>,
+    ) -> [DotInfo; 2] {
+        let mut infos = [DotInfo::Backdrop; 2];
+        let mut bg_iter = bg_infos.peekable();
+
+        while let DotInfo::Backdrop = infos[1] {
+            let top = match (obj_info, bg_iter.peek()) {
+                (Some(obj), Some(bg)) if obj.priority <= self.bgcnt[bg.index()].priority => {
+                    DotInfo::Object(obj_info.take().unwrap())
                 }
+                (_, Some(_)) => DotInfo::Background(bg_iter.next().unwrap()),
+                (Some(_), None) => DotInfo::Object(obj_info.take().unwrap()),
+                (None, None) => break,
+            };
 
-                infos
-            }
-            BackgroundMode::Bitmap => {
-                let bg_info = self.compute_bg_bitmap_mode_dot(top_win);
-
-                match (obj_info, bg_info) {
-                    (Some(obj), Some(bg)) if obj.priority <= self.bgcnt[bg.index()].priority => {
-                        [DotInfo::Object(obj), DotInfo::Background(bg)]
-                    }
-                    (Some(obj), Some(bg)) => [DotInfo::Background(bg), DotInfo::Object(obj)],
-                    (Some(obj), None) => [DotInfo::Object(obj), DotInfo::Backdrop],
-                    (None, Some(bg)) => [DotInfo::Background(bg), DotInfo::Backdrop],
-                    (None, None) => [DotInfo::Backdrop; 2],
-                }
+            if let DotInfo::Backdrop = infos[0] {
+                infos[0] = top;
+            } else {
+                infos[1] = top;
             }
-            BackgroundMode::Invalid => [DotInfo::Backdrop; 2],
         }
+
+        infos
     }
 
     fn alpha_blend_dots(&self, top: Dot, bot: Dot) -> Dot {
-        let factor = (self.bldalpha.0.factor(), self.bldalpha.1.factor());
-        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let factor = (
+            u16::from(self.bldalpha.0.factor()),
+            u16::from(self.bldalpha.1.factor()),
+        );
         let blend = |top: u8, bot: u8| {
-            u8::try_from(
-                u32::from(Dot::MAX_COMPONENT)
-                    .min((f32::from(top) * factor.0 + f32::from(bot) * factor.1) as u32),
-            )
-            .unwrap()
+            let comp = (u16::from(top) * factor.0 + u16::from(bot) * factor.1) >> 4;
+            u8::try_from(comp.min(Dot::MAX_COMPONENT.into())).unwrap()
         };
 
         Dot::new(
@@ -396,13 +621,15 @@ impl Video {
     }
 
     fn adjust_dot_brightness(&self, darken: bool, dot: Dot) -> Dot {
-        let mul = if darken {
-            |comp| -f32::from(comp)
-        } else {
-            |comp| f32::from(Dot::MAX_COMPONENT - comp)
+        let factor = u16::from(self.bldy.factor());
+        let blend = |comp: u8| {
+            if darken {
+                comp - u8::try_from((u16::from(comp) * factor) >> 4).unwrap()
+            } else {
+                let delta = (u16::from(Dot::MAX_COMPONENT - comp) * factor) >> 4;
+                comp + u8::try_from(delta).unwrap()
+            }
         };
-        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-        let blend = |comp| (f32::from(comp) + mul(comp) * self.bldy.factor()) as u8;
 
         Dot::new(blend(dot.r), blend(dot.g), blend(dot.b))
     }
@@ -428,7 +655,7 @@ impl Video {
         }
     }
 
-    fn find_top_window(&self) -> Window {
+    fn find_top_window(&self, x: u16, inside_obj_window: impl FnOnce() -> bool) -> Window {
         if self.dispcnt.display_bg_window == [false; 2] && !self.dispcnt.display_obj_window {
             return Window::None;
         }
@@ -440,9 +667,9 @@ impl Video {
 
             let (win_x, win_y) = (self.win[win_idx].horiz, self.win[win_idx].vert);
             let inside_horiz = if win_x.0 <= win_x.1 {
-                self.x >= win_x.0.into() && self.x < win_x.1.into()
+                x >= win_x.0.into() && x < win_x.1.into()
             } else {
-                self.x < win_x.1.into() || self.x >= win_x.0.into()
+                x < win_x.1.into() || x >= win_x.0.into()
             };
             let inside_vert = if win_y.0 <= win_y.1 {
                 self.y >= win_y.0 && self.y < win_y.1
@@ -455,7 +682,7 @@ impl Video {
             }
         }
 
-        if self.dispcnt.display_obj_window && self.check_inside_obj_window() {
+        if self.dispcnt.display_obj_window && inside_obj_window() {
             Window::Object
         } else {
             Window::Outside
@@ -530,3 +757,349 @@ impl Video {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct LineCallback {
+        lines: Vec<Vec<u16>>,
+        frame: Vec<u16>,
+    }
+
+    impl Callback for LineCallback {
+        fn put_line(&mut self, y: u8, line: &[u16]) {
+            assert_eq!(self.lines.len(), usize::from(y));
+            self.lines.push(line.to_vec());
+        }
+
+        fn end_frame(&mut self, frame: &[u16]) {
+            self.frame = frame.to_vec();
+        }
+
+        fn is_frame_skipping(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn scanline_renderer_matches_per_dot() {
+        let mut seed = 0x1234_5678_u32;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as u16
+        };
+
+        for scene in 0..48 {
+            let mut video = Video::new();
+            for addr in (0..0x1_8000).step_by(2) {
+                video.vram().write_hword(addr, next());
+            }
+            for addr in (0..0x400).step_by(2) {
+                video.palette_ram.write_hword(addr, next());
+                video.oam.write_hword(addr, next());
+            }
+            for addr in (0x08..0x56).step_by(2) {
+                video.write_hword(addr, next());
+            }
+            // Any BG mode, including invalid ones, without forced blank.
+            let mut dispcnt = (next() & !0x87) | (scene % 8);
+            match scene % 4 {
+                1 => video.layers.isolated = Some(Layer::Object),
+                2 => {
+                    video.layers.bg[usize::from(scene / 4 % 4)] = false;
+                    video.layers.windows = false;
+                }
+                // Only the OBJ window.
+                3 => dispcnt = dispcnt & !0x6000 | 0x9000,
+                _ => {}
+            }
+            video.write_hword(0x00, dispcnt);
+
+            for y in 0..VBLANK_DOT {
+                video.y = y;
+                video.mosaic_bg_counter = (next() % 16).min(y.into()) as u8;
+                video.mosaic_obj_counter = (next() % 16).min(y.into()) as u8;
+                video.update_obj_line_end();
+                video.render_line();
+                for x in 0..HBLANK_DOT {
+                    assert_eq!(
+                        video.compute_dot(x.into()),
+                        video.line[usize::from(x)],
+                        "scene {scene}, dot ({x}, {y})"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mid_scanline_changes_work() {
+        let mut video = Video::new();
+        let (mut irq, mut dma) = (Irq::new(), Dma::new());
+        let mut cb = LineCallback::default();
+        let (red, blue) = (0x001f, 0x7c00);
+        for x in 0..3 * u32::from(HBLANK_DOT) {
+            video.vram().write_hword(2 * x, 0x001f);
+        }
+        video.write_hword(0x00, 0x0403); // Mode 3 with BG2.
+
+        let mut step_dots = |video: &mut Video, dots| {
+            for _ in 0..dots {
+                video.step(&mut cb, &mut irq, &mut dma, 4);
+            }
+        };
+        // Forced blank from dot 100 of line 0, then VRAM for dots 10 and 200 of line 1 changed
+        // at dot 50.
+        step_dots(&mut video, 100);
+        video.write_hword(0x00, 0x0483);
+        step_dots(&mut video, HORIZ_DOTS - 100);
+        video.write_hword(0x00, 0x0403);
+        step_dots(&mut video, 50);
+        video.vram().write_hword(2 * (240 + 10), 0x7c00);
+        video.vram().write_hword(2 * (240 + 200), 0x7c00);
+        step_dots(&mut video, HORIZ_DOTS - 50);
+        step_dots(&mut video, HORIZ_DOTS);
+
+        let lines = cb.lines;
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0][..100].iter().all(|&dot| dot == red));
+        assert!(lines[0][100..].iter().all(|&dot| dot == 0x7fff));
+        assert_eq!((lines[1][10], lines[1][200]), (red, blue));
+        assert_eq!((lines[2][0], lines[2][200]), (red, red));
+    }
+
+    #[test]
+    fn hblank_changes_dont_redraw_line() {
+        let mut video = Video::new();
+        let (mut irq, mut dma) = (Irq::new(), Dma::new());
+        let mut cb = LineCallback::default();
+        video.palette_ram.write_hword(0, 0x001f);
+        for _ in 0..=HBLANK_DOT {
+            video.step(&mut cb, &mut irq, &mut dma, 4);
+        }
+        let line = video.line;
+
+        // Raster effects commonly change the backdrop colour in H-Blank. Change it before syncing,
+        // so that drawing the scanline again would show the new colour.
+        video.palette_ram.write_hword(0, 0x7c00);
+        video.sync_line();
+        assert_eq!(video.line_drawn_dots, HBLANK_DOT.into());
+        assert!(video.line == line, "scanline was drawn again");
+        assert!(cb.lines[0].iter().all(|&dot| dot == 0x001f));
+    }
+
+    #[test]
+    fn mosaic_works() {
+        let mut video = Video::new();
+        let (mut irq, mut dma) = (Irq::new(), Dma::new());
+        let mut cb = LineCallback::default();
+        let bg_color = |x: u16, y: u16| (x + y * 240) & 0x7fff;
+        for y in 0..u16::from(VBLANK_DOT) {
+            for x in 0..HBLANK_DOT.into() {
+                let addr = 2 * u32::from(x + y * 240);
+                video.vram().write_hword(addr, bg_color(x, y));
+            }
+        }
+        // 8x8 256-colour OBJ at (16, 16) with mosaic, using the first tile of OBJ VRAM.
+        for i in 0..32 {
+            let idx = 1 + 2 * i;
+            video
+                .vram()
+                .write_hword(0x1_4000 + 2 * u32::from(i), idx | (idx + 1) << 8);
+        }
+        for (addr, i) in (0x200..).step_by(2).zip(0..256) {
+            video.palette_ram.write_hword(addr, 0x7c00 | i);
+        }
+        video.oam.write_hword(0, 0x3010);
+        video.oam.write_hword(2, 0x0010);
+        video.oam.write_hword(4, 0x0200);
+
+        video.write_hword(0x00, 0x1403); // Mode 3 with BG2 and OBJs.
+        video.write_hword(0x0c, 0x0040); // BG2 mosaic.
+        video.write_hword(0x4c, 0x1122); // 3x3 BG and 2x2 OBJ mosaic.
+        for _ in 0..u32::from(HORIZ_DOTS) * u32::from(VBLANK_DOT) {
+            video.step(&mut cb, &mut irq, &mut dma, 4);
+        }
+
+        for (y, line) in (0..).zip(&cb.lines) {
+            for (x, &dot) in (0..).zip(line) {
+                let expected = if (16..24).contains(&x) && (16..24).contains(&y) {
+                    let (dot_x, dot_y) = (x - x % 2 - 16, y - y % 2 - 16);
+                    0x7c00 | (1 + dot_x + 8 * dot_y)
+                } else {
+                    bg_color(x - x % 3, y - y % 3)
+                };
+                assert_eq!(dot, expected, "dot ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn frame_output_works() {
+        let mut video = Video::new();
+        let (mut irq, mut dma) = (Irq::new(), Dma::new());
+        let mut cb = LineCallback::default();
+        video.vram().write_hword(0, 0x7c21);
+        video.vram().write_hword(2, 0x0042);
+        video.write_hword(0x00, 0x0403); // Mode 3 with BG2.
+        video.write_hword(0x02, 1); // Green swap.
+        for _ in 0..u32::from(HORIZ_DOTS) * u32::from(VBLANK_DOT) {
+            video.step(&mut cb, &mut irq, &mut dma, 4);
+        }
+
+        assert_eq!(cb.lines.len(), VBLANK_DOT.into());
+        assert_eq!(cb.lines[0][..3], [0x7c41, 0x0022, 0]);
+        assert_eq!(cb.frame, cb.lines.concat());
+    }
+
+    #[test]
+    fn obj_budget_works() {
+        let mut video = Video::new();
+        let red = Dot::from(0x001f);
+        video.vram().write_hword(0x1_0000, 0x0011);
+        video.palette_ram.write_hword(0x202, 0x001f);
+        video.write_hword(0x00, 0x1040); // Mode 0 with OBJs.
+
+        // 64x64 OBJs, with only the last one on the screen, and the rest hidden.
+        let write_objs = |video: &mut Video, count, affine: bool| {
+            for i in 0..128 {
+                let x = if i == count - 1 { 0 } else { 300 };
+                let attr0 = if i >= count {
+                    0x0200
+                } else if affine {
+                    0x0100
+                } else {
+                    0
+                };
+                video.oam.write_hword(8 * i, attr0);
+                video.oam.write_hword(8 * i + 2, 0xc000 | x);
+                video.oam.write_hword(8 * i + 4, 0);
+            }
+        };
+        let check = |video: &mut Video, obj_line_end, visible| {
+            video.update_obj_line_end();
+            assert_eq!(video.obj_line_end, obj_line_end);
+            assert_eq!(video.compute_dot(0) == red, visible);
+        };
+
+        // 64 cycles each, so 18 fit in 1210 cycles.
+        write_objs(&mut video, 18, false);
+        check(&mut video, 128, true);
+        write_objs(&mut video, 19, false);
+        check(&mut video, 18, false);
+        video.unlimited_objs = true;
+        check(&mut video, 128, true);
+        video.unlimited_objs = false;
+
+        // 14 fit in 954 cycles with H-Blank interval free.
+        video.write_hword(0x00, 0x1060);
+        write_objs(&mut video, 15, false);
+        check(&mut video, 14, false);
+        video.write_hword(0x00, 0x1040);
+        check(&mut video, 128, true);
+
+        // Affine OBJs take 10 + 2 * 64 cycles each, so 8 fit.
+        write_objs(&mut video, 9, true);
+        check(&mut video, 8, false);
+    }
+
+    #[test]
+    fn blending_works() {
+        let mut video = Video::new();
+        video.palette_ram.write_hword(0, 0x03ff);
+        video.vram().write_hword(0, 0x7c0a);
+        video.write_hword(0x00, 0x0403); // Mode 3 with BG2.
+
+        // Alpha blending BG2 with the backdrop, with EVA saturating at 16.
+        video.write_hword(0x50, 0x2044);
+        video.write_hword(0x52, 0x0314);
+        assert_eq!(video.compute_dot(0), Dot::new(15, 5, 31));
+
+        // Brightness changes round towards the original dot.
+        video.vram().write_hword(0, 0x0001);
+        video.write_hword(0x50, 0x00c4);
+        video.write_hword(0x54, 8);
+        assert_eq!(video.compute_dot(0), Dot::new(1, 0, 0));
+        video.write_hword(0x50, 0x0084);
+        video.write_hword(0x54, 1);
+        assert_eq!(video.compute_dot(0), Dot::new(2, 1, 1));
+        video.write_hword(0x54, 20);
+        assert_eq!(video.compute_dot(0), Dot::WHITE);
+
+        // Semi-transparent red OBJ over BG2.
+        video.vram().write_hword(0, 0x7c0a);
+        video.vram().write_hword(0x1_4000, 0x0011);
+        video.palette_ram.write_hword(0x202, 0x001f);
+        video.oam.write_hword(0, 0x0400);
+        video.oam.write_hword(4, 0x0200);
+        video.write_hword(0x00, 0x1403);
+        video.write_hword(0x52, 0x0808);
+        video.write_hword(0x54, 16);
+
+        // Alpha blended with a 2nd target, even when brightening.
+        video.write_hword(0x50, 0x0480);
+        assert_eq!(video.compute_dot(0), Dot::new(20, 0, 15));
+        // Otherwise, only brightened if it's a 1st target.
+        video.write_hword(0x50, 0x0080);
+        assert_eq!(video.compute_dot(0), Dot::new(31, 0, 0));
+        video.write_hword(0x50, 0x0090);
+        assert_eq!(video.compute_dot(0), Dot::WHITE);
+    }
+
+    #[test]
+    fn layer_switches_work() {
+        let mut video = Video::new();
+        let red = Dot::from(0x001f);
+        let blue = Dot::from(0x7c00);
+        video.palette_ram.write_hword(0, 0x7c00);
+        video.vram().write_hword(0, 0x001f);
+        video.write_hword(0x00, 0x0403); // Mode 3 with BG2.
+        assert_eq!(video.compute_dot(0), red);
+
+        video.layers.bg[2] = false;
+        assert_eq!(video.compute_dot(0), blue);
+        video.layers.isolated = Some(Layer::Background(2));
+        assert_eq!(video.compute_dot(0), red);
+        video.layers.isolated = Some(Layer::Object);
+        assert_eq!(video.compute_dot(0), LayerSwitches::DEFAULT_KEY);
+        video.layers = LayerSwitches::default();
+
+        // Brighten BG2 fully.
+        video.write_hword(0x50, 0x0084);
+        video.write_hword(0x54, 16);
+        assert_eq!(video.compute_dot(0), Dot::WHITE);
+        video.layers.blending = false;
+        assert_eq!(video.compute_dot(0), red);
+        video.layers.blending = true;
+        video.layers.isolated = Some(Layer::Background(2));
+        assert_eq!(video.compute_dot(0), red);
+        video.layers = LayerSwitches::default();
+        video.write_hword(0x50, 0);
+
+        // WIN0 doesn't cover the dot, and WINOUT hides everything.
+        video.write_hword(0x00, 0x2403);
+        video.write_hword(0x40, 0x0a14);
+        video.write_hword(0x44, 0x00a0);
+        assert_eq!(video.compute_dot(0), blue);
+        video.layers.windows = false;
+        assert_eq!(video.compute_dot(0), red);
+
+        video.layers.isolated = None;
+        for expected in [
+            Some(Layer::Background(0)),
+            Some(Layer::Background(1)),
+            Some(Layer::Background(2)),
+            Some(Layer::Background(3)),
+            Some(Layer::Object),
+            None,
+        ] {
+            video.layers.isolate_next();
+            assert_eq!(video.layers.isolated, expected);
+        }
+    }
+}
@@ -8,7 +8,10 @@ use crate::{
     video::{HBLANK_DOT, VBLANK_DOT},
 };
 
-use super::{DotPaletteInfo, Video, Window, TILE_DOT_LEN};
+use super::{
+    inspect::{ObjAffine, ObjAttributes},
+    DotPaletteInfo, Video, Window, TILE_DOT_LEN,
+};
 
 #[derive(Debug, Copy, Clone)]
 enum AffineAttribute {
@@ -40,7 +43,7 @@ struct Attributes {
     pos: (i16, i16),
     affine: AffineAttribute,
     mode: Option<Mode>,
-    _mosaic: bool, // TODO
+    mosaic: bool,
     shape: Shape,
     size: u8,
     dots_base_idx: u16,
@@ -60,6 +63,38 @@ impl Attributes {
         tile_sizes[usize::from(self.size)]
     }
 
+    fn inspect(&self) -> ObjAttributes {
+        let (affine, hidden, flip) = match self.affine {
+            AffineAttribute::Enabled {
+                double_size,
+                params_idx,
+            } => (
+                Some(ObjAffine {
+                    params_idx,
+                    double_size,
+                }),
+                false,
+                (false, false),
+            ),
+            AffineAttribute::Disabled { hidden, flip } => (None, hidden, flip),
+        };
+        let (width, height) = self.tiles_size();
+
+        ObjAttributes {
+            pos: self.pos,
+            size: (width * TILE_DOT_LEN, height * TILE_DOT_LEN),
+            affine,
+            hidden,
+            flip,
+            mode: self.mode,
+            mosaic: self.mosaic,
+            tile_idx: self.dots_base_idx,
+            priority: self.priority,
+            palette_idx: self.palette_idx.map(|i| i.try_into().unwrap()),
+            visible: self.is_enabled(),
+        }
+    }
+
     fn is_enabled(&self) -> bool {
         !matches!(self.affine, AffineAttribute::Disabled { hidden: true, .. })
             && self.mode.is_some()
@@ -202,6 +237,14 @@ impl Oam {
 
 const OAM_ENTRY_STRIDE: u32 = 8;
 
+impl Oam {
+    /// Decoded attributes of OBJ `idx` (0-127).
+    #[must_use]
+    pub fn attributes(&self, idx: u8) -> ObjAttributes {
+        self.attrs[usize::from(idx)].inspect()
+    }
+}
+
 impl Bus for Oam {
     fn read_byte(&mut self, addr: u32) -> u8 {
         self.buf.read_byte(addr)
@@ -251,7 +294,7 @@ impl Oam {
             pos: (arbitrary_sign_extend!(i16, attrs[1].bits(..9), 9), y),
             affine,
             mode: Mode::from_repr(attrs[0].bits(10..12).into()),
-            _mosaic: attrs[0].bit(12),
+            mosaic: attrs[0].bit(12),
             shape: Shape::from_repr(attrs[0].bits(14..).try_into().unwrap()).unwrap(),
             size: attrs[1].bits(14..).try_into().unwrap(),
             dots_base_idx: attrs[2].bits(..10),
@@ -262,7 +305,7 @@ impl Oam {
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, FromRepr)]
-pub(super) enum Mode {
+pub enum Mode {
     Normal,
     AlphaBlend,
     WindowMask,
@@ -276,39 +319,131 @@ pub(super) struct DotInfo {
 }
 
 impl Video {
-    fn region_attrs_iter(&self) -> impl Iterator<Item = &Attributes> + '_ {
-        let region_idx = Oam::region_index(Oam::region_pos((self.x, self.y.into())));
+    /// Finds the OBJs that fit in the OBJ rendering budget of the current scanline.
+    ///
+    /// The hardware spends 1210 cycles per scanline on drawing OBJs, or 954 if OAM can be
+    /// accessed during H-Blank. OBJs on the scanline use up the budget in OAM order: normal OBJs
+    /// take a cycle per dot of their width, and affine OBJs 10 cycles plus 2 per dot of their
+    /// (possibly doubled) width. The first OBJ that doesn't fit and every OBJ after it are
+    /// dropped.
+    pub(super) fn update_obj_line_end(&mut self) {
+        self.obj_line_end = 128;
+        if self.unlimited_objs {
+            return;
+        }
+
+        let mut budget: u16 = if self.dispcnt.hblank_oam_access {
+            954
+        } else {
+            1210
+        };
+        let y = i16::from(self.y);
+        for (i, attrs) in (0..).zip(self.oam.attrs.iter()) {
+            let (obj_y, clip_height) = (attrs.pos.1, i16::from(attrs.clip_dots_size().1));
+            if !attrs.is_enabled() || !(obj_y..obj_y + clip_height).contains(&y) {
+                continue;
+            }
+
+            let width = u16::from(attrs.clip_dots_size().0);
+            let cost = match attrs.affine {
+                AffineAttribute::Enabled { .. } => 10 + 2 * width,
+                AffineAttribute::Disabled { .. } => width,
+            };
+            if cost > budget {
+                self.obj_line_end = i;
+                return;
+            }
+            budget -= cost;
+        }
+    }
+
+    fn region_attrs_iter(&self, x: u16) -> impl Iterator<Item = &Attributes> + '_ {
+        let region_idx = Oam::region_index(Oam::region_pos((x, self.y.into())));
 
         self.oam.regions[region_idx]
             .iter()
+            .filter(|&&i| i < self.obj_line_end)
             .map(|&i| &self.oam.attrs[usize::from(i)])
     }
 
-    pub(super) fn check_inside_obj_window(&self) -> bool {
+    pub(super) fn check_inside_obj_window(&self, x: u16) -> bool {
         self.dispcnt.display_obj
             && self
-                .region_attrs_iter()
+                .region_attrs_iter(x)
                 .filter(|&attrs| attrs.mode == Some(Mode::WindowMask))
-                .find_map(|attrs| self.compute_obj_dot(attrs))
+                .find_map(|attrs| self.compute_obj_dot(attrs, x))
                 .is_some()
     }
 
-    pub(super) fn compute_top_obj_dot(&self, win: Window) -> Option<DotInfo> {
-        if !self.dispcnt.display_obj || self.window_control(win).is_some_and(|w| !w.display_obj) {
+    pub(super) fn compute_top_obj_dot(&self, x: u16, win: Window) -> Option<DotInfo> {
+        if !self.dispcnt.display_obj
+            || !self.layers.shows_obj()
+            || self.window_control(win).is_some_and(|w| !w.display_obj)
+        {
             return None;
         }
 
-        self.region_attrs_iter()
+        self.region_attrs_iter(x)
             .filter(|&attrs| attrs.mode.is_some_and(|mode| mode != Mode::WindowMask))
-            .find_map(|attrs| self.compute_obj_dot(attrs))
+            .find_map(|attrs| self.compute_obj_dot(attrs, x))
+    }
+
+    /// Computes the top OBJ dot of each dot on the current scanline, ignoring windows, and
+    /// whether each dot is inside the OBJ window.
+    ///
+    /// Like the per-dot path, the first OBJ by priority then index with a visible dot wins.
+    pub(super) fn compute_obj_line(
+        &self,
+        dots: &mut [Option<DotInfo>; HBLANK_DOT as usize],
+        inside_window: &mut [bool; HBLANK_DOT as usize],
+    ) {
+        if !self.dispcnt.display_obj {
+            return;
+        }
+        let show_objs = self.layers.shows_obj();
+
+        let y = i16::from(self.y);
+        let mut line_idxs: ArrayVec<[u8; 128]> = (0..self.obj_line_end)
+            .filter(|&i| {
+                let attrs = &self.oam.attrs[usize::from(i)];
+                let (obj_y, clip_height) = (attrs.pos.1, i16::from(attrs.clip_dots_size().1));
+
+                attrs.is_enabled() && (obj_y..obj_y + clip_height).contains(&y)
+            })
+            .collect();
+        line_idxs.sort_by_key(|&i| self.oam.attrs[usize::from(i)].priority);
+
+        for attrs in line_idxs.iter().map(|&i| &self.oam.attrs[usize::from(i)]) {
+            let is_window = attrs.mode == Some(Mode::WindowMask);
+            if !is_window && !show_objs {
+                continue;
+            }
+
+            let (obj_x, clip_width) = (attrs.pos.0, i16::from(attrs.clip_dots_size().0));
+            #[allow(clippy::cast_sign_loss)]
+            let (start_x, end_x) = (
+                obj_x.max(0) as u16,
+                (obj_x + clip_width).clamp(0, HBLANK_DOT.into()) as u16,
+            );
+            for x in start_x..end_x {
+                let i = usize::from(x);
+                if is_window {
+                    if !inside_window[i] {
+                        inside_window[i] = self.compute_obj_dot(attrs, x).is_some();
+                    }
+                } else if dots[i].is_none() {
+                    dots[i] = self.compute_obj_dot(attrs, x);
+                }
+            }
+        }
     }
 
-    fn compute_obj_dot(&self, attrs: &Attributes) -> Option<DotInfo> {
+    fn compute_obj_dot(&self, attrs: &Attributes, x: u16) -> Option<DotInfo> {
         let (tile_width, tile_height) = attrs.tiles_size();
         let (obj_width, obj_height) = (tile_width * TILE_DOT_LEN, tile_height * TILE_DOT_LEN);
 
         #[allow(clippy::cast_possible_wrap)]
-        let (x, y) = (self.x as i16, i16::from(self.y));
+        let (x, y) = (x as i16, i16::from(self.y));
         let (obj_x, obj_y) = attrs.pos;
         let (clip_width, clip_height) = attrs.clip_dots_size();
         if !(obj_x..obj_x + i16::from(clip_width)).contains(&x)
@@ -317,6 +452,16 @@ impl Video {
             return None; // Clipped
         }
 
+        // Mosaic blocks are aligned to the screen, but don't extend past the OBJ's top-left.
+        let (x, y) = if attrs.mosaic {
+            let (width, _) = self.mosaic_obj.size();
+            (
+                (x - x % i16::from(width)).max(obj_x),
+                (y - i16::from(self.mosaic_obj_counter)).max(obj_y),
+            )
+        } else {
+            (x, y)
+        };
         let (mut obj_dot_x, mut obj_dot_y) = (
             u8::try_from((x - obj_x).bits(..8)).unwrap(),
             u8::try_from((y - obj_y).bits(..8)).unwrap(),
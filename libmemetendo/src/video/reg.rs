@@ -2,7 +2,7 @@ use intbits::Bits;
 use strum_macros::FromRepr;
 use tinyvec::array_vec;
 
-use crate::{arbitrary_sign_extend, bus::Bus};
+use crate::{arbitrary_sign_extend, bus::Bus, io_regs::IoRegister};
 
 use super::{Video, HBLANK_DOT, VBLANK_DOT};
 
@@ -18,7 +18,7 @@ pub enum BackgroundMode {
 pub(super) struct DisplayControl {
     pub mode: u8,
     frame_select: u8,
-    hblank_oam_access: bool,
+    pub hblank_oam_access: bool,
     pub obj_1d: bool,
     pub forced_blank: bool,
     pub display_bg: [bool; 4],
@@ -162,6 +162,16 @@ impl BackgroundControl {
         }
     }
 
+    /// The number of screens a text mode BG map is made of, horizontally and vertically.
+    pub fn text_mode_screens(self) -> (u8, u8) {
+        match self.screen_config {
+            ScreenAreas::One => (1, 1),
+            ScreenAreas::TwoHorizontal => (2, 1),
+            ScreenAreas::TwoVertical => (1, 2),
+            ScreenAreas::Four => (2, 2),
+        }
+    }
+
     pub fn text_mode_screen_index(self, (screen_x, screen_y): (i32, i32)) -> u8 {
         let layout = match self.screen_config {
             ScreenAreas::One => [[0, 0], [0, 0]],
@@ -188,6 +198,9 @@ impl BackgroundOffset {
 pub(super) struct ReferencePoint {
     pub external: (i32, i32),
     pub internal: (i32, i32),
+    /// The internal reference point as of the scanline the vertical BG mosaic counter was last
+    /// reset on.
+    pub mosaic: (i32, i32),
 }
 
 impl ReferencePoint {
@@ -206,11 +219,13 @@ impl ReferencePoint {
     fn set_x_byte(&mut self, idx: usize, bits: u8) {
         Self::set_byte(&mut self.external.0, idx, bits);
         self.internal.0 = self.external.0;
+        self.mosaic.0 = self.external.0;
     }
 
     fn set_y_byte(&mut self, idx: usize, bits: u8) {
         Self::set_byte(&mut self.external.1, idx, bits);
         self.internal.1 = self.external.1;
+        self.mosaic.1 = self.external.1;
     }
 }
 
@@ -267,6 +282,21 @@ impl Mosaic {
         self.0 = bits.bits(..4);
         self.1 = bits.bits(4..);
     }
+
+    /// Width and height of the mosaic blocks, in dots.
+    pub fn size(self) -> (u8, u8) {
+        (self.0 + 1, self.1 + 1)
+    }
+
+    /// Advances the vertical mosaic counter for the next scanline, which resets when it reaches
+    /// the block height. As the counter is only 4 bits, it also wraps if the height was lowered
+    /// below it.
+    pub fn step_counter(self, counter: &mut u8) {
+        *counter = (*counter + 1) % 16;
+        if *counter == self.size().1 {
+            *counter = 0;
+        }
+    }
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, FromRepr, Default, Debug)]
@@ -315,8 +345,9 @@ impl BlendControl {
 pub(super) struct BlendCoefficient(u8);
 
 impl BlendCoefficient {
-    pub fn factor(self) -> f32 {
-        1.0f32.min(f32::from(self.0.bits(..5)) / 16.0)
+    /// The coefficient in sixteenths, which saturates at 16.
+    pub fn factor(self) -> u8 {
+        self.0.bits(..5).min(16)
     }
 }
 
@@ -368,6 +399,11 @@ impl Bus for Video {
     }
 
     fn write_byte(&mut self, addr: u32, value: u8) {
+        // DISPSTAT and VCOUNT don't affect rendering.
+        if !(0x04..0x08).contains(&addr) {
+            self.sync_line();
+        }
+
         match addr {
             // DISPCNT
             0x00 => {
@@ -493,3 +529,259 @@ impl Bus for Video {
         }
     }
 }
+
+const BG_NAMES: [&str; 4] = ["bg0", "bg1", "bg2", "bg3"];
+
+impl Video {
+    #[allow(clippy::too_many_lines)]
+    pub(crate) fn io_registers(&self) -> Vec<IoRegister> {
+        let dispcnt = &self.dispcnt;
+        let mut regs = vec![
+            IoRegister::new("DISPCNT", 0x00, 2, dispcnt.cached_bits.into())
+                .number("mode", dispcnt.mode)
+                .number("frame", dispcnt.frame_select)
+                .flag("hblank_oam_access", dispcnt.hblank_oam_access)
+                .flag("obj_1d", dispcnt.obj_1d)
+                .flag("forced_blank", dispcnt.forced_blank)
+                .flags(&BG_NAMES, dispcnt.cached_bits.bits(8..12).into())
+                .flag("obj", dispcnt.display_obj)
+                .flag("win0", dispcnt.display_bg_window[0])
+                .flag("win1", dispcnt.display_bg_window[1])
+                .flag("obj_win", dispcnt.display_obj_window),
+            IoRegister::new("GREENSWP", 0x02, 2, self.greenswp.into())
+                .flag("green_swap", self.greenswp.bit(0)),
+        ];
+
+        let dispstat_lo = self.dispstat.lo_bits(
+            self.y >= VBLANK_DOT && self.y != 227,
+            self.x >= HBLANK_DOT.into(),
+            self.y,
+        );
+        regs.push(
+            IoRegister::new(
+                "DISPSTAT",
+                0x04,
+                2,
+                u32::from(dispstat_lo) | u32::from(self.dispstat.vcount_target) << 8,
+            )
+            .flags(
+                &[
+                    "vblank",
+                    "hblank",
+                    "vcount_match",
+                    "vblank_irq",
+                    "hblank_irq",
+                    "vcount_irq",
+                ],
+                dispstat_lo.into(),
+            )
+            .number("vcount_target", self.dispstat.vcount_target),
+        );
+        regs.push(IoRegister::new("VCOUNT", 0x06, 2, self.y.into()).number("scanline", self.y));
+
+        for (i, bgcnt) in (0..).zip(&self.bgcnt) {
+            let name = ["BG0CNT", "BG1CNT", "BG2CNT", "BG3CNT"][i as usize];
+            regs.push(
+                IoRegister::new(name, 0x08 + 2 * i, 2, bgcnt.cached_bits.into())
+                    .number("priority", bgcnt.priority)
+                    .number("tile_base", bgcnt.dots_base_block)
+                    .flag("mosaic", bgcnt.mosaic)
+                    .flag("color256", bgcnt.color256)
+                    .number("map_base", bgcnt.screen_base_block)
+                    .flag("wraparound", bgcnt.wraparound)
+                    .number("size", bgcnt.screen_config as u8),
+            );
+        }
+        for (i, bgofs) in (0..).zip(&self.bgofs) {
+            let names = [
+                ("BG0HOFS", "BG0VOFS"),
+                ("BG1HOFS", "BG1VOFS"),
+                ("BG2HOFS", "BG2VOFS"),
+                ("BG3HOFS", "BG3VOFS"),
+            ][i as usize];
+            let addr = 0x10 + 4 * i;
+            regs.push(IoRegister::new(names.0, addr, 2, bgofs.0.into()).number("offset", bgofs.0));
+            regs.push(
+                IoRegister::new(names.1, addr + 2, 2, bgofs.1.into()).number("offset", bgofs.1),
+            );
+        }
+
+        for (i, (bgp, bgref)) in (0..).zip(self.bgp.iter().zip(&self.bgref)) {
+            let names = [
+                ["BG2PA", "BG2PB", "BG2PC", "BG2PD", "BG2X", "BG2Y"],
+                ["BG3PA", "BG3PB", "BG3PC", "BG3PD", "BG3X", "BG3Y"],
+            ][i as usize];
+            let addr = 0x20 + 0x10 * i;
+            for (j, param) in (0..).zip([bgp.a, bgp.b, bgp.c, bgp.d]) {
+                #[allow(clippy::cast_sign_loss)]
+                regs.push(
+                    IoRegister::new(names[j as usize], addr + 2 * j, 2, (param as u16).into())
+                        .signed("value", param),
+                );
+            }
+            #[allow(clippy::cast_sign_loss)]
+            let ref_reg = |name, offset, coord: i32| {
+                IoRegister::new(name, addr + offset, 4, coord as u32 & 0x0fff_ffff)
+                    .signed("value", coord)
+            };
+            regs.push(ref_reg(names[4], 8, bgref.external.0));
+            regs.push(ref_reg(names[5], 12, bgref.external.1));
+        }
+
+        for (i, win) in (0..).zip(&self.win) {
+            let names = [("WIN0H", "WIN0V"), ("WIN1H", "WIN1V")][i as usize];
+            let addr = 0x40 + 2 * i;
+            let (horiz, vert) = (win.horiz, win.vert);
+            regs.push(
+                IoRegister::new(
+                    names.0,
+                    addr,
+                    2,
+                    u32::from(horiz.1) | u32::from(horiz.0) << 8,
+                )
+                .number("left", horiz.0)
+                .number("right", horiz.1),
+            );
+            regs.push(
+                IoRegister::new(
+                    names.1,
+                    addr + 4,
+                    2,
+                    u32::from(vert.1) | u32::from(vert.0) << 8,
+                )
+                .number("top", vert.0)
+                .number("bottom", vert.1),
+            );
+        }
+        regs.push(
+            IoRegister::new(
+                "WININ",
+                0x48,
+                2,
+                u32::from(self.winin[0].cached_bits) | u32::from(self.winin[1].cached_bits) << 8,
+            )
+            .flags(
+                &[
+                    "win0_bg0",
+                    "win0_bg1",
+                    "win0_bg2",
+                    "win0_bg3",
+                    "win0_obj",
+                    "win0_blend",
+                ],
+                self.winin[0].cached_bits.into(),
+            )
+            .flags(
+                &[
+                    "win1_bg0",
+                    "win1_bg1",
+                    "win1_bg2",
+                    "win1_bg3",
+                    "win1_obj",
+                    "win1_blend",
+                ],
+                self.winin[1].cached_bits.into(),
+            ),
+        );
+        regs.push(
+            IoRegister::new(
+                "WINOUT",
+                0x4a,
+                2,
+                u32::from(self.winout.cached_bits) | u32::from(self.winobj.cached_bits) << 8,
+            )
+            .flags(
+                &[
+                    "out_bg0",
+                    "out_bg1",
+                    "out_bg2",
+                    "out_bg3",
+                    "out_obj",
+                    "out_blend",
+                ],
+                self.winout.cached_bits.into(),
+            )
+            .flags(
+                &[
+                    "obj_win_bg0",
+                    "obj_win_bg1",
+                    "obj_win_bg2",
+                    "obj_win_bg3",
+                    "obj_win_obj",
+                    "obj_win_blend",
+                ],
+                self.winobj.cached_bits.into(),
+            ),
+        );
+
+        let (bg_mosaic, obj_mosaic) = (self.mosaic_bg, self.mosaic_obj);
+        regs.push(
+            IoRegister::new(
+                "MOSAIC",
+                0x4c,
+                2,
+                u32::from(bg_mosaic.0)
+                    | u32::from(bg_mosaic.1) << 4
+                    | u32::from(obj_mosaic.0) << 8
+                    | u32::from(obj_mosaic.1) << 12,
+            )
+            .number("bg_width", bg_mosaic.0 + 1)
+            .number("bg_height", bg_mosaic.1 + 1)
+            .number("obj_width", obj_mosaic.0 + 1)
+            .number("obj_height", obj_mosaic.1 + 1),
+        );
+
+        let bldcnt = &self.bldcnt;
+        regs.push(
+            IoRegister::new("BLDCNT", 0x50, 2, bldcnt.cached_bits.into())
+                .flags(
+                    &[
+                        "first_bg0",
+                        "first_bg1",
+                        "first_bg2",
+                        "first_bg3",
+                        "first_obj",
+                        "first_backdrop",
+                    ],
+                    bldcnt.cached_bits.bits(..6).into(),
+                )
+                .named(
+                    "mode",
+                    match bldcnt.mode {
+                        BlendMode::None => "none",
+                        BlendMode::Alpha => "alpha",
+                        BlendMode::Brighten => "brighten",
+                        BlendMode::Dim => "dim",
+                    },
+                )
+                .flags(
+                    &[
+                        "second_bg0",
+                        "second_bg1",
+                        "second_bg2",
+                        "second_bg3",
+                        "second_obj",
+                        "second_backdrop",
+                    ],
+                    bldcnt.cached_bits.bits(8..14).into(),
+                ),
+        );
+        let (first, second) = (self.bldalpha.0 .0, self.bldalpha.1 .0);
+        regs.push(
+            IoRegister::new(
+                "BLDALPHA",
+                0x52,
+                2,
+                u32::from(first) | u32::from(second) << 8,
+            )
+            .number("eva", first.bits(..5))
+            .number("evb", second.bits(..5)),
+        );
+        regs.push(
+            IoRegister::new("BLDY", 0x54, 2, self.bldy.0.into())
+                .number("evy", self.bldy.0.bits(..5)),
+        );
+
+        regs
+    }
+}
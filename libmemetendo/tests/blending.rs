@@ -0,0 +1,278 @@
+//! Pixel-exact tests for colour special effects: alpha blending, brightness increase/decrease and
+//! semi-transparent OBJs.
+//!
+//! The expected frames are computed independently of the renderer with the formulas from
+//! GBATEK's "LCD I/O Color Special Effects", not captured from hardware: jsmolka's PPU tests only
+//! cover shades and stripes, and no captures of a blending test ROM (like those in mGBA's test
+//! suite) are checked in. Those should replace these tests once they are, compared with
+//! `runner.rs` like `jsmolka.rs` does. The scene is BG2 in mode 3 showing every BGR555 colour,
+//! optionally under a 64x64 semi-transparent OBJ.
+
+use std::rc::Rc;
+
+use libmemetendo::{
+    bios,
+    cart::{self, BackupType, Cartridge},
+    gba::Gba,
+    util,
+    video::{self, HBLANK_DOT, VBLANK_DOT},
+};
+
+const OBJ_POS: (usize, usize) = (100, 40);
+const OBJ_SIZE: usize = 64;
+const OBJ_COLOR: u16 = 0x4e73;
+const BACKDROP_COLOR: u16 = 0x1d8a;
+
+const BLDCNT_BG2: u16 = 1 << 2;
+const BLDCNT_OBJ: u16 = 1 << 4;
+const BLDCNT_BACKDROP: u16 = 1 << 5;
+const BLDCNT_ALPHA: u16 = 1 << 6;
+const BLDCNT_BRIGHTEN: u16 = 2 << 6;
+const BLDCNT_DIM: u16 = 3 << 6;
+
+#[derive(Copy, Clone, Debug)]
+struct Scene {
+    obj: bool,
+    bldcnt: u16,
+    eva: u16,
+    evb: u16,
+    evy: u16,
+}
+
+impl Scene {
+    fn new(bldcnt: u16) -> Self {
+        Self {
+            obj: false,
+            bldcnt,
+            eva: 0,
+            evb: 0,
+            evy: 0,
+        }
+    }
+
+    fn with_obj(self) -> Self {
+        Self { obj: true, ..self }
+    }
+
+    fn with_alpha(self, eva: u16, evb: u16) -> Self {
+        Self { eva, evb, ..self }
+    }
+
+    fn with_brightness(self, evy: u16) -> Self {
+        Self { evy, ..self }
+    }
+}
+
+#[derive(Default)]
+struct FrameCallback {
+    frames: Vec<Vec<u16>>,
+}
+
+impl video::Callback for FrameCallback {
+    fn end_frame(&mut self, frame: &[u16]) {
+        self.frames.push(frame.to_vec());
+    }
+
+    fn is_frame_skipping(&self) -> bool {
+        false
+    }
+}
+
+fn bg2_color(x: usize, y: usize) -> u16 {
+    u16::try_from((y * usize::from(HBLANK_DOT) + x) & 0x7fff).unwrap()
+}
+
+fn write_hword(gba: &mut Gba, addr: u32, value: u16) {
+    let [lo, hi] = value.to_le_bytes();
+    assert!(gba.debug_write_byte(addr, lo) && gba.debug_write_byte(addr + 1, hi));
+}
+
+/// Draws `scene` and returns its second frame.
+fn draw(scene: Scene) -> Vec<u16> {
+    let dispcnt = if scene.obj {
+        3 | 1 << 6 | 1 << 10 | 1 << 12
+    } else {
+        3 | 1 << 10
+    };
+    let io_writes = [
+        (0x0400_0000, dispcnt),
+        (0x0400_0050, scene.bldcnt),
+        (0x0400_0052, scene.evb << 8 | scene.eva),
+        (0x0400_0054, scene.evy),
+        (0, 0),
+    ];
+
+    // IO registers can only be written by the CPU, so the cartridge does that from a table.
+    let program = [
+        0xe28f_0010, // ADD R0,PC,#0x10
+        0xe8b0_0006, // LDMIA R0!,{R1,R2}
+        0xe351_0000, // CMP R1,#0
+        0x11c1_20b0, // STRHNE R2,[R1]
+        0x1aff_fffb, // BNE 0x8000004
+        0xeaff_fffe, // B 0x8000014
+    ];
+    let rom = program
+        .into_iter()
+        .chain(
+            io_writes
+                .into_iter()
+                .flat_map(|(addr, value)| [addr, value.into()]),
+        )
+        .flat_map(u32::to_le_bytes);
+    let bios_rom = bios::Rom::new(Rc::from(vec![0; 0x4000])).unwrap();
+    let cart_rom = cart::Rom::new(rom.collect()).unwrap();
+    let mut gba = Gba::new(bios_rom, Cartridge::new(cart_rom, BackupType::None));
+    gba.reset(true);
+
+    write_hword(&mut gba, 0x0500_0000, BACKDROP_COLOR);
+    for y in 0..usize::from(VBLANK_DOT) {
+        for x in 0..usize::from(HBLANK_DOT) {
+            let offset = 2 * (y * usize::from(HBLANK_DOT) + x);
+            write_hword(
+                &mut gba,
+                0x0600_0000 + u32::try_from(offset).unwrap(),
+                bg2_color(x, y),
+            );
+        }
+    }
+
+    // OBJ 0 is semi-transparent, 64x64 and uses colour 1 everywhere. The others are disabled.
+    write_hword(&mut gba, 0x0500_0202, OBJ_COLOR);
+    for offset in (0..32 * 64).step_by(2) {
+        write_hword(&mut gba, 0x0601_4000 + offset, 0x1111);
+    }
+    let (obj_x, obj_y) = OBJ_POS;
+    write_hword(
+        &mut gba,
+        0x0700_0000,
+        1 << 10 | u16::try_from(obj_y).unwrap(),
+    );
+    write_hword(
+        &mut gba,
+        0x0700_0002,
+        3 << 14 | u16::try_from(obj_x).unwrap(),
+    );
+    write_hword(&mut gba, 0x0700_0004, 512);
+    for addr in (0x0700_0008..0x0700_0400).step_by(8) {
+        write_hword(&mut gba, addr, 1 << 9);
+    }
+
+    let mut cb = FrameCallback::default();
+    while cb.frames.len() < 2 {
+        gba.step(&mut cb, &mut util::audio::NullCallback);
+    }
+
+    cb.frames.swap_remove(1)
+}
+
+/// Applies `f` to each 5-bit component of the BGR555 colours.
+fn map_components(colors: [u16; 2], f: impl Fn([u16; 2]) -> u16) -> u16 {
+    [0, 5, 10].into_iter().fold(0, |acc, shift| {
+        acc | f(colors.map(|c| (c >> shift) & 0x1f)).min(31) << shift
+    })
+}
+
+fn expected_dot(scene: Scene, x: usize, y: usize) -> u16 {
+    let in_obj = scene.obj
+        && (OBJ_POS.0..OBJ_POS.0 + OBJ_SIZE).contains(&x)
+        && (OBJ_POS.1..OBJ_POS.1 + OBJ_SIZE).contains(&y);
+    let (top, top_bit, bot, bot_bit) = if in_obj {
+        (OBJ_COLOR, BLDCNT_OBJ, bg2_color(x, y), BLDCNT_BG2)
+    } else {
+        (bg2_color(x, y), BLDCNT_BG2, BACKDROP_COLOR, BLDCNT_BACKDROP)
+    };
+    let first_target = scene.bldcnt & top_bit != 0;
+    let second_target = scene.bldcnt & bot_bit << 8 != 0;
+
+    let (eva, evb, evy) = (scene.eva.min(16), scene.evb.min(16), scene.evy.min(16));
+    let alpha = || map_components([top, bot], |[a, b]| (a * eva + b * evb) >> 4);
+    let brighten = || map_components([top, 0], |[c, _]| c + (((31 - c) * evy) >> 4));
+    let dim = || map_components([top, 0], |[c, _]| c - ((c * evy) >> 4));
+
+    // A semi-transparent OBJ over a 2nd target is always alpha blended, and brightness effects
+    // don't apply to it then.
+    if in_obj && second_target {
+        return alpha();
+    }
+    if !first_target {
+        return top;
+    }
+    match scene.bldcnt & (3 << 6) {
+        BLDCNT_ALPHA if second_target => alpha(),
+        BLDCNT_BRIGHTEN => brighten(),
+        BLDCNT_DIM => dim(),
+        _ => top,
+    }
+}
+
+fn assert_draws_expected(scene: Scene) {
+    let frame = draw(scene);
+    for y in 0..usize::from(VBLANK_DOT) {
+        for x in 0..usize::from(HBLANK_DOT) {
+            let actual = frame[y * usize::from(HBLANK_DOT) + x] & 0x7fff;
+            let expected = expected_dot(scene, x, y);
+            assert_eq!(
+                actual, expected,
+                "dot ({x}, {y}) was {actual:#06x}, expected {expected:#06x} for {scene:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn alpha_blending() {
+    let scene = Scene::new(BLDCNT_ALPHA | BLDCNT_BG2 | BLDCNT_BACKDROP << 8);
+    for (eva, evb) in [
+        (16, 0),
+        (0, 16),
+        (8, 8),
+        (12, 7),
+        (3, 13),
+        (16, 16),
+        (31, 20),
+    ] {
+        assert_draws_expected(scene.with_alpha(eva, evb));
+    }
+
+    // Without a 2nd target below, nothing is blended.
+    assert_draws_expected(Scene::new(BLDCNT_ALPHA | BLDCNT_BG2).with_alpha(8, 8));
+}
+
+#[test]
+fn brightness_increase() {
+    for evy in [0, 1, 7, 15, 16, 31] {
+        assert_draws_expected(Scene::new(BLDCNT_BRIGHTEN | BLDCNT_BG2).with_brightness(evy));
+    }
+}
+
+#[test]
+fn brightness_decrease() {
+    for evy in [0, 1, 7, 15, 16, 31] {
+        assert_draws_expected(Scene::new(BLDCNT_DIM | BLDCNT_BG2).with_brightness(evy));
+    }
+}
+
+#[test]
+fn semi_transparent_objs() {
+    // Alpha blended with a 2nd target, even when not a 1st target or in alpha blending mode.
+    assert_draws_expected(Scene::new(BLDCNT_BG2 << 8).with_obj().with_alpha(10, 6));
+    assert_draws_expected(
+        Scene::new(BLDCNT_DIM | BLDCNT_BG2 | BLDCNT_OBJ | BLDCNT_BG2 << 8)
+            .with_obj()
+            .with_alpha(5, 11)
+            .with_brightness(8),
+    );
+
+    // Without a 2nd target below, brightness effects apply if the OBJ is a 1st target.
+    assert_draws_expected(
+        Scene::new(BLDCNT_BRIGHTEN | BLDCNT_OBJ)
+            .with_obj()
+            .with_alpha(10, 6)
+            .with_brightness(8),
+    );
+    assert_draws_expected(
+        Scene::new(BLDCNT_BRIGHTEN | BLDCNT_BG2)
+            .with_obj()
+            .with_brightness(8),
+    );
+}
@@ -0,0 +1,97 @@
+//! Compares the upscaling filters' output against earlier output
+//!
+//! The expected images in `tests/filters` were produced by these filters and checked by eye, not
+//! by the original implementations (Hyllian's xBR, Zenju's xBRZ, etc.), so they only catch
+//! unintended changes. The filters' rules are checked in `util::video::filter`'s unit tests,
+//! against AdvanceMAME's rules for ScaleNx, hqx's tables and hand-worked xBR and xBRZ cases.
+
+mod util;
+
+use image::RgbImage;
+use libmemetendo::util::video::{bgr555_to_rgb24, filter::FilterKind};
+use util::read_image;
+
+fn run_test(name: &str) {
+    let input = read_image("tests/filters/input.png");
+    let (width, height) = (input.width() as usize, input.height() as usize);
+    let src = input
+        .pixels()
+        .map(|p| {
+            let [r, g, b] = p.0.map(|c| u16::from(c >> 3));
+            r | g << 5 | b << 10
+        })
+        .collect::<Vec<_>>();
+
+    let filter = name.parse::<FilterKind>().unwrap().filter().unwrap();
+    let scale = filter.scale();
+    let mut out = vec![0; src.len() * scale * scale];
+    filter.apply(&src, width, &mut out);
+
+    let mut image = RgbImage::new((width * scale) as u32, (height * scale) as u32);
+    bgr555_to_rgb24(&out, &mut image);
+    // Don't use assert_eq! here; it'll pretty-print all of the bytes, which isn't useful.
+    assert!(
+        image == read_image(format!("tests/filters/{name}.png")),
+        "{name} output did not match!"
+    );
+}
+
+#[test]
+fn nearest() {
+    run_test("nearest3x");
+}
+
+#[test]
+fn scanlines() {
+    run_test("scanlines3x");
+}
+
+#[test]
+fn lcd_grid() {
+    run_test("lcd3x");
+}
+
+#[test]
+fn scale2x() {
+    run_test("scale2x");
+}
+
+#[test]
+fn scale3x() {
+    run_test("scale3x");
+}
+
+#[test]
+fn scale4x() {
+    run_test("scale4x");
+}
+
+#[test]
+fn xbr() {
+    run_test("xbr");
+}
+
+#[test]
+fn xbrz2x() {
+    run_test("xbrz2x");
+}
+
+#[test]
+fn xbrz3x() {
+    run_test("xbrz3x");
+}
+
+#[test]
+fn xbrz4x() {
+    run_test("xbrz4x");
+}
+
+#[test]
+fn hq2x() {
+    run_test("hq2x");
+}
+
+#[test]
+fn hq3x() {
+    run_test("hq3x");
+}
@@ -5,7 +5,7 @@ use libmemetendo::{
     bios,
     cart::{self, Cartridge},
     gba::Gba,
-    util::{self, video::FrameBuffer},
+    util,
     video::{self, HBLANK_DOT, VBLANK_DOT},
 };
 
@@ -64,7 +64,6 @@ impl Runner {
 pub struct VideoCallback {
     pub image: RgbImage,
     new_frame: bool,
-    buf: FrameBuffer,
 }
 
 impl Default for VideoCallback {
@@ -78,26 +77,21 @@ impl VideoCallback {
         Self {
             image: RgbImage::new(HBLANK_DOT.into(), VBLANK_DOT.into()),
             new_frame: false,
-            buf: FrameBuffer::default(),
         }
     }
 }
 
 impl video::Callback for VideoCallback {
-    fn put_dot(&mut self, x: u8, y: u8, dot: video::Dot) {
-        self.buf.put_dot(x, y, dot);
-    }
-
-    fn end_frame(&mut self, green_swap: bool) {
+    fn end_frame(&mut self, frame: &[u16]) {
         self.new_frame = true;
-        if green_swap {
-            self.buf.green_swap();
-        }
 
-        self.image
-            .as_flat_samples_mut()
-            .as_mut_slice()
-            .copy_from_slice(&self.buf.0);
+        // The pass screens were captured with components scaled by 8, rather than with bit
+        // replication.
+        let buf: &mut [u8] = &mut self.image;
+        for (&dot, rgb) in frame.iter().zip(buf.chunks_exact_mut(3)) {
+            let component = |shift: u16| u8::try_from((dot >> shift) & 0x1f).unwrap() * 8;
+            rgb.copy_from_slice(&[component(0), component(5), component(10)]);
+        }
     }
 
     fn is_frame_skipping(&self) -> bool {
@@ -11,6 +11,7 @@ pub fn read_image(path: impl AsRef<Path>) -> RgbImage {
         .into_rgb8()
 }
 
+#[allow(unused)]
 pub fn read_cart_rom(path: impl AsRef<Path>) -> cart::Rom {
     cart::Rom::new(Rc::from(
         fs::read(path).expect("failed to read test ROM; did you fetch the submodules?"),
@@ -0,0 +1,647 @@
+use std::{
+    io::{self, BufRead, Write},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use libmemetendo::{
+    arm7tdmi::{
+        disasm::disassemble,
+        reg::{OperationMode, OperationState, PC_INDEX},
+        Exception,
+    },
+    audio,
+    breakpoint::{expr::ParseExprError, Event, ExecBreakpoint, StopReason, WatchKind},
+    gba::Gba,
+    irq::Interrupt,
+    symbols::Symbols,
+    video,
+};
+use log::info;
+
+const HELP: &str = "\
+commands (an empty line repeats the last one):
+  c, continue            resume execution
+  p, pause               pause execution
+  s, step [N]            execute N instructions (default 1)
+  n, next                step over calls
+  b, break <ADDR> [arm|thumb] [if <EXPR>]
+                         add an execute breakpoint, optionally only in one state or if
+                         EXPR is non-zero (like `r0 == 1 && u16[sp + 4] != 0`)
+  d, delete <ADDR>       remove an execute breakpoint
+  ignore <ADDR> <N>      ignore the next N hits of a breakpoint
+  w, watch <ADDR> [LEN] [r|w|rw]
+                         add a watchpoint (default: 4 bytes, writes)
+  unwatch <ADDR> [LEN] [r|w|rw]
+                         remove a watchpoint
+  catch irq <INTERRUPT>  break on IRQ entry for an interrupt (like vblank or timer0)
+  catch dma <CHANNEL>    break on a DMA transfer starting
+  catch exception <EXCEPTION>
+                         break on entering an exception (like swi or undef)
+  uncatch ...            remove a catch
+  bl, breakpoints        list breakpoints, watchpoints and catches
+  r, regs                show registers and the current scanline and dot
+  x <ADDR> [LEN]         dump memory (default: 64 bytes)
+  io [NAME|ADDR [LEN]]   decode IO registers, or dump IO memory
+  dis [COUNT]            disassemble around the next instruction (default: 5)
+  bt                     show the call stack
+  hist [COUNT]           show the last taken branches
+ADDR is hexadecimal, or a symbol name with an optional +offset.";
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Run {
+    Paused,
+    Continue,
+    Steps(u32),
+}
+
+/// An interactive debugger console, reading commands from stdin.
+///
+/// The console starts inactive, with the emulator running normally; `pause` or `toggle` activate
+/// it.
+pub struct Debugger {
+    commands: Option<Receiver<String>>,
+    run: Run,
+    /// Breakpoint added by `next`, removed when next paused.
+    temp_breakpoint: Option<u32>,
+    last_command: String,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            commands: None,
+            run: Run::Continue,
+            temp_breakpoint: None,
+            last_command: String::new(),
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.run == Run::Paused
+    }
+
+    /// Activates the console if needed, and pauses execution.
+    pub fn pause(&mut self, gba: &mut Gba) {
+        self.stop(gba, None);
+        prompt();
+    }
+
+    fn stop(&mut self, gba: &mut Gba, reason: Option<StopReason>) {
+        if self.commands.is_none() {
+            let (tx, rx) = mpsc::channel();
+            thread::spawn(move || {
+                for line in io::stdin().lock().lines() {
+                    let Ok(line) = line else { break };
+                    if tx.send(line).is_err() {
+                        break;
+                    }
+                }
+            });
+            self.commands = Some(rx);
+            println!("debugger console active; type \"help\" for a list of commands");
+        }
+
+        if let Some(addr) = self.temp_breakpoint.take() {
+            gba.breakpoints.remove_exec(addr);
+        }
+        self.run = Run::Paused;
+
+        match reason {
+            Some(reason) => println!("stopped: {reason}"),
+            None => println!("paused"),
+        }
+        print_location(gba);
+    }
+
+    /// Pauses if running, or continues if paused.
+    pub fn toggle(&mut self, gba: &mut Gba) {
+        if self.is_paused() {
+            self.run = Run::Continue;
+            println!("continuing");
+        } else {
+            self.pause(gba);
+        }
+    }
+
+    /// Executes any commands entered since the last call.
+    pub fn poll(&mut self, gba: &mut Gba) {
+        loop {
+            let Some(commands) = &self.commands else {
+                return;
+            };
+            match commands.try_recv() {
+                Ok(line) => {
+                    self.execute(gba, &line);
+                    if self.is_paused() {
+                        prompt();
+                    }
+                }
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    info!("debugger console closed");
+                    self.commands = None;
+                    self.run = Run::Continue;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Steps `gba` unless paused, pausing if a breakpoint is hit or a `step` command finishes.
+    /// Returns whether `gba` was stepped.
+    pub fn step(
+        &mut self,
+        gba: &mut Gba,
+        video_cb: &mut impl video::Callback,
+        audio_cb: &mut impl audio::Callback,
+    ) -> bool {
+        if self.is_paused() {
+            return false;
+        }
+        let executes = gba.is_cpu_running();
+        gba.step(video_cb, audio_cb);
+
+        if let Some(reason) = gba.breakpoints.take_stop() {
+            match reason {
+                StopReason::Breakpoint { addr, .. } if self.temp_breakpoint == Some(addr) => {
+                    self.pause(gba);
+                }
+                _ => {
+                    self.stop(gba, Some(reason));
+                    prompt();
+                }
+            }
+        } else if let Run::Steps(n) = &mut self.run {
+            if executes {
+                *n -= 1;
+                if *n == 0 {
+                    self.pause(gba);
+                }
+            }
+        }
+
+        true
+    }
+
+    fn execute(&mut self, gba: &mut Gba, line: &str) {
+        let line = line.trim();
+        let line = if line.is_empty() {
+            self.last_command.clone()
+        } else {
+            self.last_command = line.to_string();
+            line.to_string()
+        };
+        let mut args = line.split_whitespace();
+        let Some(cmd) = args.next() else {
+            return;
+        };
+        let args: Vec<_> = args.collect();
+
+        if let Err(e) = self.execute_command(gba, cmd, &args) {
+            println!("error: {e}");
+        }
+    }
+
+    fn execute_command(&mut self, gba: &mut Gba, cmd: &str, args: &[&str]) -> Result<(), String> {
+        match cmd {
+            "h" | "help" => println!("{HELP}"),
+            "c" | "continue" => self.run = Run::Continue,
+            "p" | "pause" => {
+                if !self.is_paused() {
+                    self.stop(gba, None);
+                }
+            }
+            "s" | "step" => {
+                let n = args.first().map_or(Ok(1), |s| parse_num(s))?;
+                if n > 0 {
+                    self.run = Run::Steps(n);
+                }
+            }
+            "n" | "next" => self.step_over(gba),
+            "b" | "break" => add_breakpoint(gba, args)?,
+            "d" | "delete" => {
+                let addr = parse_addr(&gba.symbols, arg(args, 0)?)?;
+                if !gba.breakpoints.remove_exec(addr) {
+                    return Err("no such breakpoint".into());
+                }
+            }
+            "ignore" => {
+                let addr = parse_addr(&gba.symbols, arg(args, 0)?)?;
+                let count = parse_num(arg(args, 1)?)?;
+                let breakpoint = gba
+                    .breakpoints
+                    .exec_at(addr)
+                    .ok_or_else(|| "no such breakpoint".to_string())?;
+                breakpoint.ignore_count = breakpoint.hits.saturating_add(count);
+            }
+            "catch" => {
+                if !gba.breakpoints.add_event(parse_event(args)?) {
+                    println!("already catching that");
+                }
+            }
+            "uncatch" => {
+                if !gba.breakpoints.remove_event(parse_event(args)?) {
+                    return Err("no such catch".into());
+                }
+            }
+            "w" | "watch" | "unwatch" => {
+                let addr = parse_addr(&gba.symbols, arg(args, 0)?)?;
+                let len = args.get(1).map_or(Ok(4), |s| parse_num(s))?.max(1);
+                let kind = match args.get(2).copied().unwrap_or("w") {
+                    "r" => WatchKind::Read,
+                    "w" => WatchKind::Write,
+                    "rw" | "a" => WatchKind::Access,
+                    s => return Err(format!("invalid watchpoint kind {s:?}")),
+                };
+                let range = addr..=addr.wrapping_add(len - 1);
+                if cmd == "unwatch" {
+                    if !gba.breakpoints.remove_watch(&range, kind) {
+                        return Err("no such watchpoint".into());
+                    }
+                } else {
+                    gba.breakpoints.add_watch(range, kind);
+                }
+            }
+            "bl" | "breakpoints" => print_breakpoints(gba),
+            "r" | "regs" => print_regs(gba),
+            "x" => {
+                let addr = parse_addr(&gba.symbols, arg(args, 0)?)?;
+                let len = args.get(1).map_or(Ok(64), |s| parse_num(s))?;
+                print_memory(gba, addr, len);
+            }
+            "io" => match args.first() {
+                Some(arg) => {
+                    if let Some(reg) = gba.io_register(&arg.to_ascii_uppercase()) {
+                        println!("{reg}");
+                    } else {
+                        let addr = parse_hex(arg)?;
+                        let len = args.get(1).map_or(Ok(2), |s| parse_num(s))?;
+                        print_memory(gba, addr | 0x0400_0000, len);
+                    }
+                }
+                None => print_io(gba),
+            },
+            "dis" => {
+                let count = args.first().map_or(Ok(5), |s| parse_num(s))?;
+                print_disassembly(gba, count);
+            }
+            "bt" => print_call_stack(gba),
+            "hist" => {
+                let count = args.first().map_or(Ok(16), |s| parse_num(s))?;
+                print_branches(gba, count);
+            }
+            _ => return Err(format!("unknown command {cmd:?}; try \"help\"")),
+        }
+
+        Ok(())
+    }
+
+    /// Steps over BL instructions by running until they return; otherwise steps once.
+    fn step_over(&mut self, gba: &mut Gba) {
+        let addr = gba.cpu.next_instr_addr();
+        let state = gba.cpu.reg.cpsr().state();
+        let is_call = read_instr(gba, state, addr).map_or(false, |instr| match state {
+            OperationState::Arm => instr >> 25 & 0b111 == 0b101 && instr >> 24 & 1 == 1,
+            // BL is split in two; the first half sets up LR.
+            OperationState::Thumb => instr >> 11 == 0b11110,
+        });
+
+        if is_call {
+            let return_addr = addr.wrapping_add(4);
+            if gba.breakpoints.add_exec(return_addr) {
+                self.temp_breakpoint = Some(return_addr);
+            }
+            self.run = Run::Continue;
+        } else {
+            self.run = Run::Steps(1);
+        }
+    }
+}
+
+fn add_breakpoint(gba: &mut Gba, args: &[&str]) -> Result<(), String> {
+    let addr = parse_addr(&gba.symbols, arg(args, 0)?)?;
+    let mut args = &args[1..];
+    let mut breakpoint = ExecBreakpoint::default();
+
+    if let Some(state) = args.first().and_then(|&s| match s {
+        "arm" => Some(OperationState::Arm),
+        "thumb" => Some(OperationState::Thumb),
+        _ => None,
+    }) {
+        breakpoint.state = Some(state);
+        args = &args[1..];
+    }
+    match args.split_first() {
+        Some((&"if", expr)) => {
+            let expr = expr
+                .join(" ")
+                .parse()
+                .map_err(|e: ParseExprError| e.to_string())?;
+            breakpoint.condition = Some(expr);
+        }
+        Some((s, _)) => return Err(format!("unexpected {s:?}")),
+        None => {}
+    }
+
+    if gba.breakpoints.insert_exec(addr, breakpoint).is_some() {
+        println!("replaced breakpoint at {}", describe(&gba.symbols, addr));
+    } else {
+        println!("breakpoint at {}", describe(&gba.symbols, addr));
+    }
+
+    Ok(())
+}
+
+fn print_breakpoints(gba: &Gba) {
+    for (addr, breakpoint) in gba.breakpoints.exec() {
+        print!("break {}", describe(&gba.symbols, addr));
+        match breakpoint.state {
+            Some(OperationState::Arm) => print!(" arm"),
+            Some(OperationState::Thumb) => print!(" thumb"),
+            None => {}
+        }
+        if let Some(condition) = &breakpoint.condition {
+            print!(" if {condition}");
+        }
+        print!(" (hits: {}", breakpoint.hits);
+        if breakpoint.ignore_count > breakpoint.hits {
+            print!(", ignoring {}", breakpoint.ignore_count - breakpoint.hits);
+        }
+        println!(")");
+    }
+    for w in gba.breakpoints.watchpoints() {
+        println!(
+            "watch {:08x}-{:08x} ({:?}, hits: {})",
+            w.range.start(),
+            w.range.end(),
+            w.kind,
+            w.hits
+        );
+    }
+    for event in gba.breakpoints.events() {
+        match event {
+            Event::Interrupt(interrupt) => println!("catch irq {interrupt:?}"),
+            Event::DmaStart(chan_idx) => println!("catch dma {chan_idx}"),
+            Event::Exception(exception) => println!("catch exception {exception:?}"),
+        }
+    }
+}
+
+fn print_location(gba: &mut Gba) {
+    let addr = gba.cpu.next_instr_addr();
+    let state = gba.cpu.reg.cpsr().state();
+    println!("=> {}", format_instr(gba, state, addr));
+}
+
+fn format_instr(gba: &mut Gba, state: OperationState, addr: u32) -> String {
+    let desc = describe(&gba.symbols, addr);
+    match (read_instr(gba, state, addr), state) {
+        (Some(instr), OperationState::Arm) => {
+            format!("{desc}: {instr:08x} {}", disassemble(state, instr, addr))
+        }
+        (Some(instr), OperationState::Thumb) => {
+            format!(
+                "{desc}:     {instr:04x} {}",
+                disassemble(state, instr, addr)
+            )
+        }
+        (None, _) => format!("{desc}: <unreadable>"),
+    }
+}
+
+fn print_disassembly(gba: &mut Gba, count: u32) {
+    let pc = gba.cpu.next_instr_addr();
+    let state = gba.cpu.reg.cpsr().state();
+    let size = state.instr_size();
+    let breakpoints: Vec<_> = gba.breakpoints.exec().map(|(addr, _)| addr).collect();
+
+    for i in 0..=2 * count {
+        let addr = pc.wrapping_sub(count * size).wrapping_add(i * size);
+        if let Some(symbol) = gba.symbols.get(addr) {
+            println!("<{}>:", symbol.name);
+        }
+        let marker = if addr == pc {
+            "=>"
+        } else if breakpoints.contains(&addr) {
+            " *"
+        } else {
+            "  "
+        };
+        println!("{marker} {}", format_instr(gba, state, addr));
+    }
+}
+
+fn print_call_stack(gba: &Gba) {
+    let Some(history) = gba.history() else {
+        println!("history tracking is disabled");
+        return;
+    };
+
+    println!("#0 {}", describe(&gba.symbols, gba.cpu.next_instr_addr()));
+    for (i, frame) in history.call_stack().iter().rev().enumerate() {
+        let exception = frame
+            .exception
+            .map_or(String::new(), |e| format!(" ({e:?})"));
+        println!(
+            "#{} {}, called{exception} from {}",
+            i + 1,
+            describe(&gba.symbols, frame.return_addr),
+            describe(&gba.symbols, frame.call_site)
+        );
+    }
+}
+
+fn print_branches(gba: &Gba, count: u32) {
+    let Some(history) = gba.history() else {
+        println!("history tracking is disabled");
+        return;
+    };
+
+    let count = usize::try_from(count).unwrap_or(usize::MAX);
+    for branch in history.branches().rev().take(count) {
+        println!(
+            "{} -> {}: {branch}",
+            describe(&gba.symbols, branch.from),
+            describe(&gba.symbols, branch.to)
+        );
+    }
+}
+
+fn prompt() {
+    print!("(memetendo) ");
+    io::stdout().flush().ok();
+}
+
+fn arg<'a>(args: &[&'a str], i: usize) -> Result<&'a str, String> {
+    args.get(i)
+        .copied()
+        .ok_or_else(|| "missing argument".to_string())
+}
+
+/// Formats `addr` with the name of its symbol, if any.
+fn describe(symbols: &Symbols, addr: u32) -> String {
+    symbols.lookup(addr).map_or_else(
+        || format!("{addr:08x}"),
+        |loc| format!("{addr:08x} <{loc}>"),
+    )
+}
+
+fn parse_addr(symbols: &Symbols, s: &str) -> Result<u32, String> {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        return parse_hex(s);
+    }
+
+    let (name, offset) = s.split_once('+').unwrap_or((s, "0"));
+    match symbols.find(name) {
+        Some(addr) => Ok(addr.wrapping_add(parse_num(offset)?)),
+        None => parse_hex(s),
+    }
+}
+
+fn parse_hex(s: &str) -> Result<u32, String> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+
+    u32::from_str_radix(digits, 16).map_err(|e| format!("invalid address {s:?}: {e}"))
+}
+
+/// Parses a decimal number, or a hexadecimal one prefixed with `0x`.
+fn parse_num(s: &str) -> Result<u32, String> {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        return parse_hex(s);
+    }
+
+    s.parse().map_err(|e| format!("invalid number {s:?}: {e}"))
+}
+
+fn parse_event(args: &[&str]) -> Result<Event, String> {
+    let name = arg(args, 1)?.to_ascii_lowercase();
+    let event = match arg(args, 0)? {
+        "irq" => Event::Interrupt(match name.as_str() {
+            "vblank" => Interrupt::VBlank,
+            "hblank" => Interrupt::HBlank,
+            "vcount" => Interrupt::VCount,
+            "timer0" => Interrupt::Timer0,
+            "timer1" => Interrupt::Timer1,
+            "timer2" => Interrupt::Timer2,
+            "timer3" => Interrupt::Timer3,
+            "serial" => Interrupt::Serial,
+            "dma0" => Interrupt::Dma0,
+            "dma1" => Interrupt::Dma1,
+            "dma2" => Interrupt::Dma2,
+            "dma3" => Interrupt::Dma3,
+            "keypad" => Interrupt::Keypad,
+            "gamepak" => Interrupt::GamePak,
+            _ => return Err(format!("unknown interrupt {name:?}")),
+        }),
+        "dma" => match parse_num(&name)? {
+            chan_idx @ 0..=3 => Event::DmaStart(chan_idx.try_into().unwrap()),
+            _ => return Err("DMA channel must be 0 to 3".into()),
+        },
+        "exception" => Event::Exception(match name.as_str() {
+            "reset" => Exception::Reset,
+            "undef" => Exception::UndefinedInstr,
+            "swi" => Exception::SoftwareInterrupt,
+            "pabt" => Exception::PrefetchAbort,
+            "dabt" => Exception::DataAbort,
+            "irq" => Exception::Interrupt,
+            "fiq" => Exception::FastInterrupt,
+            _ => return Err(format!("unknown exception {name:?}")),
+        }),
+        s => {
+            return Err(format!(
+                "cannot catch {s:?}; expected irq, dma or exception"
+            ))
+        }
+    };
+
+    Ok(event)
+}
+
+fn read_instr(gba: &mut Gba, state: OperationState, addr: u32) -> Option<u32> {
+    let mut instr = 0;
+    for i in 0..state.instr_size() {
+        let byte = gba.debug_read_byte(addr.wrapping_add(i))?;
+        instr |= u32::from(byte) << (8 * i);
+    }
+
+    Some(instr)
+}
+
+fn print_memory(gba: &mut Gba, addr: u32, len: u32) {
+    for row_addr in (0..len).step_by(16).map(|i| addr.wrapping_add(i)) {
+        let bytes: Vec<_> = (0..16.min(len - row_addr.wrapping_sub(addr)))
+            .map(|i| gba.debug_read_byte(row_addr.wrapping_add(i)))
+            .collect();
+        let hex: Vec<_> = bytes
+            .iter()
+            .map(|b| b.map_or("??".to_string(), |b| format!("{b:02x}")))
+            .collect();
+        let ascii: String = bytes
+            .iter()
+            .map(|b| match b {
+                Some(b) if b.is_ascii_graphic() || *b == b' ' => char::from(*b),
+                _ => '.',
+            })
+            .collect();
+        println!("{row_addr:08x}: {:<47}  {ascii}", hex.join(" "));
+    }
+}
+
+fn print_io(gba: &Gba) {
+    for reg in gba.io_registers() {
+        println!("{reg}");
+    }
+}
+
+fn print_regs(gba: &Gba) {
+    let reg = &gba.cpu.reg;
+    for row in 0..4 {
+        let line: Vec<_> = (row * 4..row * 4 + 4)
+            .map(|i| {
+                let value = if i == PC_INDEX {
+                    gba.cpu.next_instr_addr()
+                } else {
+                    reg.r[i]
+                };
+                format!("{:>3} {value:08x}", format!("r{i}"))
+            })
+            .collect();
+        println!("{}", line.join("  "));
+    }
+
+    let cpsr = reg.cpsr();
+    let flags: String = [
+        (cpsr.signed, 'n'),
+        (cpsr.zero, 'z'),
+        (cpsr.carry, 'c'),
+        (cpsr.overflow, 'v'),
+        (cpsr.irq_disabled, 'i'),
+        (cpsr.fiq_disabled, 'f'),
+    ]
+    .iter()
+    .map(|&(set, c)| if set { c.to_ascii_uppercase() } else { c })
+    .collect();
+    let mode = match cpsr.mode() {
+        OperationMode::User => "usr",
+        OperationMode::FastInterrupt => "fiq",
+        OperationMode::Interrupt => "irq",
+        OperationMode::Supervisor => "svc",
+        OperationMode::Abort => "abt",
+        OperationMode::UndefinedInstr => "und",
+        OperationMode::System => "sys",
+    };
+    let state = match cpsr.state() {
+        OperationState::Arm => "arm",
+        OperationState::Thumb => "thumb",
+    };
+    print!("cpsr {:08x} [{flags} {mode} {state}]", cpsr.bits());
+    if cpsr.mode().has_spsr() {
+        print!("  spsr {:08x}", reg.spsr());
+    }
+    println!();
+    println!("scanline {}, dot {}", gba.video.scanline(), gba.video.dot());
+}
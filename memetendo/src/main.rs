@@ -1,23 +1,37 @@
 #![warn(clippy::pedantic)]
 
 use std::{
-    fs, io,
+    fs,
+    io::{self, Write as _},
     mem::take,
-    path::Path,
+    path::{Path, PathBuf},
+    process::ExitCode,
     rc::Rc,
     thread::sleep,
     time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Context, Result};
-use clap::{arg, command, value_parser};
+use clap::{arg, command, value_parser, Arg, ArgMatches, Command};
+#[cfg(feature = "jit")]
+use libmemetendo::arm7tdmi::jit::Jit;
 use libmemetendo::{
+    arm7tdmi::{coverage::Coverage, history::History},
     bios,
     cart::{self, BackupType, Cartridge},
+    elf::Elf,
     gba::Gba,
+    gdb::GdbStub,
     keypad::{Key, Keypad},
-    util::video::FrameBuffer,
-    video::{self, HBLANK_DOT, VBLANK_DOT},
+    profiler::Profiler,
+    sanitizer::Sanitizer,
+    symbols::Symbols,
+    timeline::Timeline,
+    util::video::{
+        filter::{Filter, FilterKind},
+        ColorProfile, ColorTable, FrameBlender, FrameBlending,
+    },
+    video::{self, LayerSwitches, HBLANK_DOT, VBLANK_DOT},
 };
 use log::{error, info, warn};
 use sdl2::{
@@ -30,9 +44,11 @@ use sdl2::{
     AudioSubsystem, EventPump,
 };
 
-use crate::audio::Audio;
+use crate::{audio::Audio, debugger::Debugger};
 
 mod audio;
+mod debugger;
+mod trace;
 
 struct SdlContext {
     sdl_audio: Option<AudioSubsystem>,
@@ -92,41 +108,53 @@ struct VideoCallback<'r> {
     texture: Texture<'r>,
     new_frame: bool,
     frame_skipping: bool,
-    buf: FrameBuffer,
+    colors: ColorTable,
+    blender: FrameBlender,
+    filter: Option<Box<dyn Filter>>,
+    filtered: Box<[u16]>,
 }
 
 impl<'r> VideoCallback<'r> {
-    fn new<T>(texture_creator: &'r TextureCreator<T>) -> Result<Self> {
+    fn new<T>(
+        texture_creator: &'r TextureCreator<T>,
+        colors: ColorTable,
+        blender: FrameBlender,
+        filter: Option<Box<dyn Filter>>,
+    ) -> Result<Self> {
+        #[allow(clippy::cast_possible_truncation)]
+        let scale = filter.as_ref().map_or(1, |filter| filter.scale() as u32);
+        let (width, height) = (scale * u32::from(HBLANK_DOT), scale * u32::from(VBLANK_DOT));
         let texture = texture_creator
-            .create_texture_streaming(PixelFormatEnum::RGB24, HBLANK_DOT.into(), VBLANK_DOT.into())
+            .create_texture_streaming(PixelFormatEnum::RGB24, width, height)
             .context("failed to create screen texture")?;
 
         Ok(Self {
             texture,
             new_frame: false,
             frame_skipping: false,
-            buf: FrameBuffer::default(),
+            colors,
+            blender,
+            filtered: vec![0; (width * height) as usize].into_boxed_slice(),
+            filter,
         })
     }
 }
 
 impl video::Callback for VideoCallback<'_> {
-    fn put_dot(&mut self, x: u8, y: u8, dot: video::Dot) {
-        self.buf.put_dot(x, y, dot);
-    }
-
-    fn end_frame(&mut self, green_swap: bool) {
+    fn end_frame(&mut self, frame: &[u16]) {
         self.new_frame = true;
         if self.frame_skipping {
             return;
         }
 
-        if green_swap {
-            self.buf.green_swap();
-        }
-
+        let frame = if let Some(filter) = &self.filter {
+            filter.apply(frame, HBLANK_DOT.into(), &mut self.filtered);
+            &self.filtered
+        } else {
+            frame
+        };
         if let Err(e) = self.texture.with_lock(None, |texture_buf, _| {
-            texture_buf.copy_from_slice(&self.buf.0);
+            self.blender.to_rgb24(&self.colors, frame, texture_buf);
         }) {
             warn!("failed to lock screen texture: {e}");
         }
@@ -137,6 +165,19 @@ impl video::Callback for VideoCallback<'_> {
     }
 }
 
+fn parse_backup_type(s: &str) -> BackupType {
+    match s {
+        "none" => BackupType::None,
+        "eeprom-unknown" => BackupType::EepromUnknownSize,
+        "eeprom-512" => BackupType::Eeprom512B,
+        "eeprom-8k" => BackupType::Eeprom8KiB,
+        "sram-32k" => BackupType::Sram32KiB,
+        "flash-64k" => BackupType::Flash64KiB,
+        "flash-128k" => BackupType::Flash128KiB,
+        _ => unreachable!(),
+    }
+}
+
 fn load_cart(
     rom: cart::Rom,
     backup_path: &impl AsRef<Path>,
@@ -174,13 +215,66 @@ fn load_cart(
     })
 }
 
-fn main() -> Result<()> {
-    env_logger::builder()
-        .format_timestamp(None)
-        .parse_env(env_logger::Env::default().default_filter_or("info"))
-        .init();
+/// Reads a cartridge ROM image, or an ELF executable to build one from.
+fn load_rom(path: &Path) -> Result<(cart::Rom, Option<Elf>)> {
+    let buf = fs::read(path).context("failed to read cartridge ROM file")?;
+    if !buf.starts_with(b"\x7fELF") {
+        let rom = cart::Rom::new(Rc::from(buf)).context("invalid cartridge ROM size")?;
+        return Ok((rom, None));
+    }
+
+    let elf = Elf::parse(&buf).context("failed to parse ELF file")?;
+    let rom = elf.rom().context("invalid cartridge ROM size")?;
+    info!("loaded ELF with entry point {:#010x}", elf.entry);
+
+    Ok((rom, Some(elf)))
+}
+
+fn load_elf(gba: &mut Gba, elf: &Elf, skip_bios: bool) {
+    if !skip_bios && !elf.enters_from_rom() {
+        warn!("ELF entry point is outside of cartridge ROM; use --skip-bios to jump to it");
+    }
+    gba.load_elf(elf, skip_bios);
+}
 
-    let matches = command!()
+/// Reads a no$gba-style `.sym` or GNU ld `.map` symbol file from `path`, or if not given, one
+/// next to the cartridge ROM file with the same name.
+fn load_symbols(path: Option<&PathBuf>, cart_path: &Path) -> Result<Symbols> {
+    let parse = |path: &Path, text: &str| {
+        if path.extension().map_or(false, |ext| ext == "map") {
+            Symbols::from_map(text)
+        } else {
+            Symbols::from_sym(text)
+        }
+    };
+
+    if let Some(path) = path {
+        let text = fs::read_to_string(path).context("failed to read symbol file")?;
+        return Ok(parse(path, &text));
+    }
+
+    for ext in ["sym", "map"] {
+        let path = cart_path.with_extension(ext);
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                let symbols = parse(&path, &text);
+                info!(
+                    "loaded {} symbols from {}",
+                    symbols.len(),
+                    path.to_string_lossy()
+                );
+                return Ok(symbols);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!("failed to read symbol file {}: {e}", path.to_string_lossy()),
+        }
+    }
+
+    Ok(Symbols::new())
+}
+
+fn cli() -> Command<'static> {
+    command!()
         .arg(arg!(--"skip-bios" "Skip executing BIOS ROM after boot").required(false))
         .arg(arg!(-b --bios <FILE> "BIOS ROM file to use").allow_invalid_utf8(true))
         .arg(
@@ -196,50 +290,208 @@ fn main() -> Result<()> {
                 ])
                 .required(false),
         )
-        .arg(arg!(<ROM_FILE> "Cartridge ROM file to execute").allow_invalid_utf8(true))
+        .arg(arg!(<ROM_FILE> "Cartridge ROM or ELF file to execute").allow_invalid_utf8(true))
         .arg(
             arg!(--"frame-skip" <FRAMES> "Maximum frames to skip when behind")
                 .value_parser(value_parser!(u32))
                 .default_value("3")
                 .required(false),
         )
-        .get_matches();
+        .arg(
+            arg!(--colors <PROFILE> "Colour profile: raw, gba, gba-sp, gb-player or a matrix")
+                .value_parser(value_parser!(ColorProfile))
+                .default_value("raw")
+                .required(false),
+        )
+        .arg(
+            arg!(--"frame-blend" <MODE> "Frame blending for LCD ghosting: off, mix or lcd[:WEIGHT]")
+                .value_parser(value_parser!(FrameBlending))
+                .default_value("off")
+                .required(false),
+        )
+        .arg(
+            arg!(--filter <FILTER> "Upscaling filter, like scale2x, xbrz4x, hq3x or scanlines3x")
+                .value_parser(value_parser!(FilterKind))
+                .default_value("none")
+                .required(false),
+        )
+        .arg(
+            arg!(--"no-sprite-limit" "Draw every sprite, ignoring the hardware's per-line limit")
+                .required(false),
+        )
+        .arg(
+            arg!(--trace <FILE> "Write a trace of executed instructions (gzipped if *.gz)")
+                .value_parser(value_parser!(PathBuf))
+                .required(false),
+        )
+        .arg(
+            arg!(--"trace-range" <RANGE> "Only trace instructions within START-END (hex)")
+                .value_parser(trace::parse_range)
+                .requires("trace")
+                .required(false),
+        )
+        .arg(
+            arg!(--"branch-history" <COUNT> "Taken branches to report on crashes (0 disables)")
+                .value_parser(value_parser!(usize))
+                .default_value("32")
+                .required(false),
+        )
+        .arg(
+            arg!(--sanitize "Warn about suspicious hardware usage, like misaligned accesses")
+                .required(false),
+        )
+        .arg(
+            arg!(--profile <FILE> "Write a profile of cycles spent per function on exit")
+                .value_parser(value_parser!(PathBuf))
+                .required(false),
+        )
+        .arg(
+            arg!(--coverage <FILE> "Write the addresses of executed instructions on exit")
+                .value_parser(value_parser!(PathBuf))
+                .required(false),
+        )
+        .arg(
+            arg!(--timeline <FILE> "Write hardware events as a Chrome trace on exit")
+                .value_parser(value_parser!(PathBuf))
+                .required(false),
+        )
+        .arg(
+            arg!(--gdb <PORT> "Wait for GDB to connect on localhost:PORT before running")
+                .value_parser(value_parser!(u16))
+                .required(false),
+        )
+        .arg(
+            arg!(--debug "Start paused in the debugger console (toggled with F12)")
+                .conflicts_with("gdb")
+                .required(false),
+        )
+        .arg(
+            arg!(--symbols <FILE> "Symbol file (no$gba .sym or GNU ld .map) for the debugger")
+                .value_parser(value_parser!(PathBuf))
+                .required(false),
+        )
+        .args(jit_args())
+        .subcommand_negates_reqs(true)
+        .subcommand(trace::diff_command())
+}
+
+#[cfg(feature = "jit")]
+fn jit_args() -> [Arg<'static>; 2] {
+    [
+        arg!(--jit "Run code with the dynamic recompiler where possible").required(false),
+        arg!(--"jit-differential" "Check the recompiler against the interpreter as it runs")
+            .requires("jit")
+            .required(false),
+    ]
+}
+
+#[cfg(not(feature = "jit"))]
+fn jit_args() -> [Arg<'static>; 0] {
+    []
+}
+
+/// Sets up tracing, CPU history, coverage, the sanitizer, the profiler and the hardware timeline
+/// as requested.
+fn enable_diagnostics(gba: &mut Gba, matches: &ArgMatches) -> Result<()> {
+    gba.cpu.tracer = matches
+        .get_one::<PathBuf>("trace")
+        .map(|path| {
+            let range = matches.get_one("trace-range").cloned();
+            trace::create_tracer(path, range)
+        })
+        .transpose()?;
+
+    let branch_history = *matches.get_one::<usize>("branch-history").unwrap();
+    if branch_history > 0 {
+        gba.cpu.history = Some(History::new(branch_history));
+    }
+    if matches.is_present("coverage") {
+        gba.cpu.coverage = Some(Coverage::new());
+    }
+    if matches.is_present("sanitize") {
+        gba.sanitizer = Some(Sanitizer::new());
+    }
+    if matches.is_present("profile") {
+        gba.profiler = Some(Profiler::new());
+    }
+    if matches.is_present("timeline") {
+        gba.timeline = Some(Timeline::new());
+    }
+
+    Ok(())
+}
+
+/// Enables the JIT if requested.
+#[cfg(feature = "jit")]
+fn enable_jit(gba: &mut Gba, matches: &ArgMatches) -> Result<()> {
+    if matches.is_present("jit") {
+        let mut jit = Jit::new().context("failed to allocate memory for the JIT")?;
+        jit.differential = matches.is_present("jit-differential");
+        gba.jit = Some(jit);
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<ExitCode> {
+    env_logger::builder()
+        .format_timestamp(None)
+        .parse_env(env_logger::Env::default().default_filter_or("info"))
+        .init();
+
+    let matches = cli().get_matches();
+
+    if let Some(("trace-diff", diff_matches)) = matches.subcommand() {
+        return trace::run_diff(diff_matches);
+    }
 
     let skip_bios = matches.is_present("skip-bios");
     let bios_path = Path::new(matches.value_of_os("bios").unwrap());
-    let cart_fallback_backup_type =
-        matches
-            .get_one::<String>("backup-fallback")
-            .map(|s| match s.as_str() {
-                "none" => BackupType::None,
-                "eeprom-unknown" => BackupType::EepromUnknownSize,
-                "eeprom-512" => BackupType::Eeprom512B,
-                "eeprom-8k" => BackupType::Eeprom8KiB,
-                "sram-32k" => BackupType::Sram32KiB,
-                "flash-64k" => BackupType::Flash64KiB,
-                "flash-128k" => BackupType::Flash128KiB,
-                _ => unreachable!(),
-            });
+    let cart_fallback_backup_type = matches
+        .get_one::<String>("backup-fallback")
+        .map(|s| parse_backup_type(s));
     let cart_path = Path::new(matches.value_of_os("ROM_FILE").unwrap());
     let max_frame_skip = *matches.get_one::<u32>("frame-skip").unwrap();
 
+    let symbols = load_symbols(matches.get_one("symbols"), cart_path)?;
+
     let bios_rom_buf = fs::read(bios_path).context("failed to read BIOS ROM file")?;
     let bios_rom = bios::Rom::new(Rc::from(bios_rom_buf)).context("invalid BIOS ROM size")?;
 
-    let cart_rom_buf = fs::read(cart_path).context("failed to read cartridge ROM file")?;
-    let cart_rom = cart::Rom::new(Rc::from(cart_rom_buf)).context("invalid cartridge ROM size")?;
+    let (cart_rom, elf) = load_rom(cart_path)?;
     let mut cart_backup_path = cart_path.to_owned();
     cart_backup_path.set_extension("sav");
     let cart = load_cart(cart_rom, &cart_backup_path, cart_fallback_backup_type);
 
     let mut sdl = SdlContext::init()?;
-    let mut video_cb = VideoCallback::new(&sdl.win_texture_creator)?;
+    let colors = ColorTable::new(*matches.get_one::<ColorProfile>("colors").unwrap());
+    let blender = FrameBlender::new(*matches.get_one::<FrameBlending>("frame-blend").unwrap());
+    let filter = matches.get_one::<FilterKind>("filter").unwrap().filter();
+    let mut video_cb = VideoCallback::new(&sdl.win_texture_creator, colors, blender, filter)?;
     sdl.win_canvas.set_draw_color(Color::BLACK);
     sdl.win_canvas.clear();
     sdl.win_canvas.present();
 
     let mut gba = Gba::new(bios_rom, cart);
+    gba.video.unlimited_objs = matches.is_present("no-sprite-limit");
+    enable_diagnostics(&mut gba, &matches)?;
+    #[cfg(feature = "jit")]
+    enable_jit(&mut gba, &matches)?;
     gba.reset(skip_bios);
+    if let Some(elf) = &elf {
+        load_elf(&mut gba, elf, skip_bios);
+    }
+    gba.symbols.extend(symbols);
+
+    let mut gdb = matches
+        .get_one::<u16>("gdb")
+        .map(|&port| GdbStub::listen(("127.0.0.1", port)))
+        .transpose()
+        .context("failed to connect to GDB")?;
+    let mut debugger = Debugger::new();
+    if matches.is_present("debug") {
+        debugger.pause(&mut gba);
+    }
 
     let mut audio = Audio::new(sdl.sdl_audio.as_ref().map(|sdl_audio| {
         (
@@ -262,6 +514,8 @@ fn main() -> Result<()> {
         &mut video_cb,
         &mut audio,
         &mut gba,
+        &mut gdb,
+        &mut debugger,
         max_frame_skip,
     );
 
@@ -274,8 +528,12 @@ fn main() -> Result<()> {
             error!("failed to write backup file: {e}");
         }
     }
+    write_reports(&mut gba, &matches, elf.as_ref())?;
+    if let Some(tracer) = &mut gba.cpu.tracer {
+        tracer.flush().context("failed to write trace file")?;
+    }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
 
 fn update_keypad(kp: &mut Keypad, kb: &KeyboardState) {
@@ -299,12 +557,159 @@ fn update_keypad(kp: &mut Keypad, kb: &KeyboardState) {
     kp.set_pressed(Key::R, pressed(Scancode::S));
 }
 
+/// Handles the hotkeys for toggling BG0-3, OBJ, windows and blending (1 to 7), isolating a layer
+/// (8) and resetting the layers (0).
+fn update_layers(layers: &mut LayerSwitches, scancode: Scancode) {
+    match scancode {
+        Scancode::Num1 => layers.bg[0] = !layers.bg[0],
+        Scancode::Num2 => layers.bg[1] = !layers.bg[1],
+        Scancode::Num3 => layers.bg[2] = !layers.bg[2],
+        Scancode::Num4 => layers.bg[3] = !layers.bg[3],
+        Scancode::Num5 => layers.obj = !layers.obj,
+        Scancode::Num6 => layers.windows = !layers.windows,
+        Scancode::Num7 => layers.blending = !layers.blending,
+        Scancode::Num8 => layers.isolate_next(),
+        Scancode::Num0 => *layers = LayerSwitches::default(),
+        _ => return,
+    }
+    info!("layers: {layers:?}");
+}
+
+/// Writes the profile, coverage and hardware timeline, if requested.
+fn write_reports(gba: &mut Gba, matches: &ArgMatches, elf: Option<&Elf>) -> Result<()> {
+    if let Some(path) = matches.get_one::<PathBuf>("profile") {
+        write_profile(gba, path).context("failed to write profile")?;
+    }
+    if let Some(path) = matches.get_one::<PathBuf>("coverage") {
+        write_coverage(gba, path, elf).context("failed to write coverage")?;
+    }
+    if let Some(path) = matches.get_one::<PathBuf>("timeline") {
+        write_timeline(gba, path).context("failed to write timeline")?;
+    }
+
+    Ok(())
+}
+
+/// Writes the executed addresses to `path`, and if the ELF has line number info, lcov source
+/// line coverage next to it.
+fn write_coverage(gba: &Gba, path: &Path, elf: Option<&Elf>) -> Result<()> {
+    let Some(coverage) = &gba.cpu.coverage else {
+        return Ok(());
+    };
+
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    coverage.write_addrs(&mut file)?;
+    file.flush()?;
+    info!("wrote coverage to {}", path.to_string_lossy());
+
+    let lines = elf
+        .map(Elf::source_lines)
+        .transpose()
+        .context("failed to read ELF line number info")?
+        .unwrap_or_default();
+    if !lines.is_empty() {
+        let lcov_path = path.with_extension("info");
+        let mut file = io::BufWriter::new(fs::File::create(&lcov_path)?);
+        coverage.write_lcov(&mut file, &lines)?;
+        file.flush()?;
+        info!(
+            "wrote lcov line coverage to {}",
+            lcov_path.to_string_lossy()
+        );
+    }
+
+    Ok(())
+}
+
+/// Writes a flat profile to `path`, and collapsed stacks for flamegraph tools next to it.
+fn write_profile(gba: &mut Gba, path: &Path) -> Result<()> {
+    let Some(profiler) = &mut gba.profiler else {
+        return Ok(());
+    };
+
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    profiler.write_flat(&mut file, &gba.symbols)?;
+    file.flush()?;
+
+    let collapsed_path = path.with_extension("folded");
+    let mut file = io::BufWriter::new(fs::File::create(&collapsed_path)?);
+    profiler.write_collapsed(&mut file, &gba.symbols)?;
+    file.flush()?;
+    info!(
+        "wrote profile to {} and {}",
+        path.to_string_lossy(),
+        collapsed_path.to_string_lossy()
+    );
+
+    Ok(())
+}
+
+fn write_timeline(gba: &Gba, path: &Path) -> Result<()> {
+    let Some(timeline) = &gba.timeline else {
+        return Ok(());
+    };
+
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    timeline.write_chrome_trace(&mut file)?;
+    file.flush()?;
+    info!("wrote timeline to {}", path.to_string_lossy());
+
+    Ok(())
+}
+
+fn report_sanitizer_issues(gba: &mut Gba) {
+    let Some(sanitizer) = &mut gba.sanitizer else {
+        return;
+    };
+
+    for issue in sanitizer.take_issues() {
+        let mut msg = format!("sanitizer: {issue}");
+        if let Some(loc) = gba.symbols.lookup(issue.pc) {
+            msg += &format!(" in {loc}");
+        }
+        for frame in issue.call_stack.iter().rev() {
+            msg += &format!("\n  {frame}");
+        }
+        warn!("{msg}");
+    }
+}
+
+fn drop_gdb(gba: &mut Gba, gdb: &mut Option<GdbStub>, e: &io::Error) {
+    error!("GDB connection failed, resuming: {e}");
+    gba.breakpoints.clear();
+    *gdb = None;
+}
+
+/// Steps the GBA, unless GDB or the debugger has paused it. Returns whether it was stepped.
+fn step(
+    gba: &mut Gba,
+    gdb: &mut Option<GdbStub>,
+    debugger: &mut Debugger,
+    video_cb: &mut VideoCallback,
+    audio: &mut Audio,
+) -> bool {
+    let Some(stub) = gdb else {
+        return debugger.step(gba, video_cb, audio);
+    };
+
+    match stub.step(gba, video_cb, audio) {
+        Ok(stepped) => stepped,
+        Err(e) => {
+            drop_gdb(gba, gdb, &e);
+            true
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn main_loop(
     event_pump: &mut EventPump,
     win_canvas: &mut WindowCanvas,
     video_cb: &mut VideoCallback,
     audio: &mut Audio,
     gba: &mut Gba,
+    gdb: &mut Option<GdbStub>,
+    debugger: &mut Debugger,
     max_frame_skip: u32,
 ) {
     const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
@@ -314,9 +719,23 @@ fn main_loop(
         let mut skipped_frames = 0;
         loop {
             video_cb.frame_skipping = skipped_frames > 0;
+            if let Some(stub) = gdb {
+                match stub.poll(gba) {
+                    Ok(()) if !stub.is_attached() => *gdb = None,
+                    Ok(()) => {}
+                    Err(e) => drop_gdb(gba, gdb, &e),
+                }
+            }
+            debugger.poll(gba);
             while !take(&mut video_cb.new_frame) {
-                gba.step(video_cb, audio);
+                if !step(gba, gdb, debugger, video_cb, audio) {
+                    break;
+                }
+            }
+            if let Some(fault) = gba.take_fault() {
+                error!("{fault}");
             }
+            report_sanitizer_issues(gba);
             if let Err(e) = audio.queue_samples() {
                 warn!("failed to queue audio samples: {e}");
             }
@@ -335,8 +754,19 @@ fn main_loop(
         }
 
         for event in event_pump.poll_iter() {
-            if let Event::Quit { .. } = event {
-                break 'main_loop;
+            match event {
+                Event::Quit { .. } => break 'main_loop,
+                Event::KeyDown {
+                    scancode: Some(Scancode::F12),
+                    repeat: false,
+                    ..
+                } if gdb.is_none() => debugger.toggle(gba),
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    repeat: false,
+                    ..
+                } => update_layers(&mut gba.video.layers, scancode),
+                _ => {}
             }
         }
         update_keypad(&mut gba.keypad, &event_pump.keyboard_state());
@@ -0,0 +1,130 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use anyhow::{Context, Result};
+use clap::{arg, value_parser, ArgMatches, Command};
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use libmemetendo::arm7tdmi::trace::{self, Entry, Tracer};
+
+fn is_gzip(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == "gz")
+}
+
+/// Creates a tracer writing to the file at `path`, compressed with gzip if it ends in `.gz`.
+pub fn create_tracer(path: &Path, range: Option<RangeInclusive<u32>>) -> Result<Tracer> {
+    let file = File::create(path).context("failed to create trace file")?;
+    let mut tracer = if is_gzip(path) {
+        Tracer::new(GzEncoder::new(file, Compression::fast()))
+    } else {
+        Tracer::new(file)
+    };
+    tracer.range = range;
+
+    Ok(tracer)
+}
+
+/// Parses an address range like `08000000-0800ffff`.
+pub fn parse_range(s: &str) -> Result<RangeInclusive<u32>, String> {
+    let parse_addr = |s: &str| {
+        let s = s.trim();
+        let s = s
+            .strip_prefix("0x")
+            .or_else(|| s.strip_prefix("0X"))
+            .unwrap_or(s);
+
+        u32::from_str_radix(s, 16).map_err(|e| format!("invalid address {s:?}: {e}"))
+    };
+
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| "expected a range like START-END".to_string())?;
+
+    Ok(parse_addr(start)?..=parse_addr(end)?)
+}
+
+fn read_trace(path: &Path) -> Result<impl Iterator<Item = Result<Entry>>> {
+    let file = File::open(path)
+        .with_context(|| format!("failed to open trace file {}", path.to_string_lossy()))?;
+    let reader: Box<dyn Read> = if is_gzip(path) {
+        Box::new(MultiGzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+
+    let path = path.to_string_lossy().into_owned();
+    Ok(BufReader::new(reader)
+        .lines()
+        .enumerate()
+        .map(move |(i, line)| {
+            let line = line.with_context(|| format!("failed to read trace file {path}"))?;
+            line.parse()
+                .with_context(|| format!("{path}:{}: {line:?}", i + 1))
+        }))
+}
+
+pub fn diff_command() -> Command<'static> {
+    Command::new("trace-diff")
+        .about("Report the first divergence between two instruction traces")
+        .arg(arg!(<A> "First trace file").value_parser(value_parser!(PathBuf)))
+        .arg(arg!(<B> "Second trace file").value_parser(value_parser!(PathBuf)))
+        .arg(
+            arg!(--context <LINES> "Matching lines to show before the divergence")
+                .value_parser(value_parser!(usize))
+                .default_value("10")
+                .required(false),
+        )
+}
+
+/// Runs the `trace-diff` subcommand, failing if the traces diverge.
+pub fn run_diff(matches: &ArgMatches) -> Result<ExitCode> {
+    let traces_match = diff(
+        matches.get_one::<PathBuf>("A").unwrap(),
+        matches.get_one::<PathBuf>("B").unwrap(),
+        *matches.get_one::<usize>("context").unwrap(),
+    )?;
+
+    Ok(if traces_match {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// Compares two traces, printing the first divergence between them with `context_len` lines of
+/// context.
+///
+/// Returns whether the traces matched.
+fn diff(a_path: &Path, b_path: &Path, context_len: usize) -> Result<bool> {
+    let Some(divergence) = trace::diff(read_trace(a_path)?, read_trace(b_path)?, context_len)?
+    else {
+        println!("traces match");
+        return Ok(true);
+    };
+
+    let a_name = a_path.to_string_lossy();
+    let b_name = b_path.to_string_lossy();
+    println!(
+        "traces diverge at {a_name}:{} and {b_name}:{}",
+        divergence.line_a, divergence.line_b
+    );
+    for entry in &divergence.context {
+        println!("  {entry}");
+    }
+
+    let print_entry = |name: &str, entry: &Option<Entry>| match entry {
+        Some(entry) => println!("- {entry}    ({name})"),
+        None => println!("- <end of trace>    ({name})"),
+    };
+    print_entry(&a_name, &divergence.a);
+    print_entry(&b_name, &divergence.b);
+    if let (Some(a), Some(b)) = (&divergence.a, &divergence.b) {
+        println!("differences: {}", a.differences(b).join(", "));
+    }
+
+    Ok(false)
+}
@@ -1,19 +1,23 @@
 #![warn(clippy::pedantic)]
 
-use std::{cell::RefCell, mem::take, panic, rc::Rc};
+use std::{cell::RefCell, fmt::Display, mem::take, panic, rc::Rc, str::FromStr};
 
 use anyhow::{Context, Result};
 use audio::Audio;
 use js_sys::{Reflect, Uint8Array};
 use libmemetendo::{
+    arm7tdmi::history::History,
     bios,
     cart::{self, Cartridge},
     gba::Gba,
     keypad::Key,
-    util::video::FrameBuffer,
-    video::{self, HBLANK_DOT, VBLANK_DOT},
+    util::video::{
+        filter::{Filter, FilterKind},
+        ColorProfile, ColorTable, FrameBlender, FrameBlending,
+    },
+    video::{self, LayerSwitches, HBLANK_DOT, VBLANK_DOT},
 };
-use log::{info, Level};
+use log::{error, info, Level};
 use wasm_bindgen::{prelude::*, Clamped, JsCast};
 use web_sys::{
     CanvasRenderingContext2d, Document, Event, FileReader, HtmlCanvasElement, HtmlInputElement,
@@ -26,29 +30,30 @@ struct VideoCallback {
     canvas_ctx: CanvasRenderingContext2d,
     new_frame: bool,
     frame_skipping: bool,
-    buf: FrameBuffer<4>,
+    colors: ColorTable,
+    blender: FrameBlender,
+    filter: Option<Box<dyn Filter>>,
+    filtered: Box<[u16]>,
+    buf: Box<[u8]>,
 }
 
 impl video::Callback for VideoCallback {
-    fn put_dot(&mut self, x: u8, y: u8, dot: video::Dot) {
-        self.buf.put_dot(x, y, dot);
-    }
-
-    fn end_frame(&mut self, green_swap: bool) {
+    fn end_frame(&mut self, frame: &[u16]) {
         self.new_frame = true;
         if self.frame_skipping {
             return;
         }
-        if green_swap {
-            self.buf.green_swap();
-        }
+        let frame = if let Some(filter) = &self.filter {
+            filter.apply(frame, HBLANK_DOT.into(), &mut self.filtered);
+            &self.filtered
+        } else {
+            frame
+        };
+        self.blender.to_rgba8888(&self.colors, frame, &mut self.buf);
 
-        let image_data = ImageData::new_with_u8_clamped_array_and_sh(
-            Clamped(&self.buf.0),
-            HBLANK_DOT.into(),
-            VBLANK_DOT.into(),
-        )
-        .unwrap();
+        let (width, height) = self.size();
+        let image_data =
+            ImageData::new_with_u8_clamped_array_and_sh(Clamped(&self.buf), width, height).unwrap();
         self.canvas_ctx
             .put_image_data(&image_data, 0.0, 0.0)
             .unwrap();
@@ -84,13 +89,40 @@ impl VideoCallback {
             canvas_ctx,
             new_frame: false,
             frame_skipping: false,
-            buf: FrameBuffer::new(0xff),
+            colors: ColorTable::default(),
+            blender: FrameBlender::default(),
+            filter: None,
+            filtered: Box::new([]),
+            buf: vec![0; 4 * usize::from(HBLANK_DOT) * usize::from(VBLANK_DOT)].into_boxed_slice(),
         })
     }
 
+    fn size(&self) -> (u32, u32) {
+        #[allow(clippy::cast_possible_truncation)]
+        let scale = self
+            .filter
+            .as_ref()
+            .map_or(1, |filter| filter.scale() as u32);
+
+        (scale * u32::from(HBLANK_DOT), scale * u32::from(VBLANK_DOT))
+    }
+
+    fn set_filter(&mut self, filter: Option<Box<dyn Filter>>) {
+        self.filter = filter;
+        let (width, height) = self.size();
+        let canvas = self.canvas_ctx.canvas().unwrap();
+        canvas.set_width(width);
+        canvas.set_height(height);
+
+        let len = width as usize * height as usize;
+        self.filtered = vec![0; len].into_boxed_slice();
+        self.buf = vec![0; 4 * len].into_boxed_slice();
+    }
+
     fn clear(&self) {
+        let (width, height) = self.size();
         self.canvas_ctx
-            .clear_rect(0.0, 0.0, HBLANK_DOT.into(), VBLANK_DOT.into());
+            .clear_rect(0.0, 0.0, width.into(), height.into());
     }
 }
 
@@ -144,10 +176,12 @@ fn maybe_start_emulation(state: &Rc<RefCell<State>>) -> bool {
 
     let backup_type = cart_rom.parse_backup_type();
     info!("starting emulation - using cart backup type: {backup_type:?}");
-    borrowed_state.gba = Some(Gba::new(
+    let mut gba = Gba::new(
         bios_rom.clone(),
         Cartridge::new(cart_rom.clone(), backup_type),
-    ));
+    );
+    gba.cpu.history = Some(History::new(32));
+    borrowed_state.gba = Some(gba);
     borrowed_state.video_cb.borrow().clear();
     borrowed_state.audio.borrow().resume();
     borrowed_state.next_frame_ms = None;
@@ -180,6 +214,9 @@ fn maybe_start_emulation(state: &Rc<RefCell<State>>) -> bool {
                         while !take(&mut video_cb.new_frame) {
                             gba.step(&mut *video_cb, &mut *audio);
                         }
+                        if let Some(fault) = gba.take_fault() {
+                            error!("{fault}");
+                        }
                         audio.queue_samples();
 
                         next_frame_ms += FRAME_DURATION_MS;
@@ -303,6 +340,29 @@ async fn memetendo_main() {
             .unchecked_ref()
         })
         .unwrap();
+
+    init_parsed_input(&state.borrow(), "memetendo-colors", "raw", {
+        let state = Rc::clone(&state);
+        move |profile: ColorProfile| {
+            state.borrow().video_cb.borrow_mut().colors = ColorTable::new(profile);
+        }
+    });
+    init_parsed_input(&state.borrow(), "memetendo-filter", "none", {
+        let state = Rc::clone(&state);
+        move |kind: FilterKind| {
+            state
+                .borrow()
+                .video_cb
+                .borrow_mut()
+                .set_filter(kind.filter());
+        }
+    });
+    init_parsed_input(&state.borrow(), "memetendo-frame-blend", "off", {
+        let state = Rc::clone(&state);
+        move |blending: FrameBlending| {
+            state.borrow().video_cb.borrow_mut().blender = FrameBlender::new(blending);
+        }
+    });
 }
 
 // TODO: uses event.code(), so we need to have some sort of prompt that shows the actual key if the
@@ -316,6 +376,10 @@ fn create_keypress_handler(
         let Some(ref mut gba) = state.borrow_mut().gba else {
             return;
         };
+        if pressed && update_layers(&mut gba.video.layers, &event.code()) {
+            event.prevent_default();
+            return;
+        }
         let key = match event.code().as_str() {
             "KeyX" => Key::A,
             "KeyZ" => Key::B,
@@ -334,6 +398,60 @@ fn create_keypress_handler(
     })
 }
 
+/// Handles the hotkeys for toggling BG0-3, OBJ, windows and blending (1 to 7), isolating a layer
+/// (8) and resetting the layers (0). Returns whether `code` was a hotkey.
+fn update_layers(layers: &mut LayerSwitches, code: &str) -> bool {
+    match code {
+        "Digit1" => layers.bg[0] = !layers.bg[0],
+        "Digit2" => layers.bg[1] = !layers.bg[1],
+        "Digit3" => layers.bg[2] = !layers.bg[2],
+        "Digit4" => layers.bg[3] = !layers.bg[3],
+        "Digit5" => layers.obj = !layers.obj,
+        "Digit6" => layers.windows = !layers.windows,
+        "Digit7" => layers.blending = !layers.blending,
+        "Digit8" => layers.isolate_next(),
+        "Digit0" => *layers = LayerSwitches::default(),
+        _ => return false,
+    }
+
+    true
+}
+
+fn init_parsed_input<T: FromStr>(
+    state: &State,
+    id: &str,
+    initial_value: &str,
+    callback: impl Fn(T) + 'static,
+) where
+    T::Err: Display,
+{
+    let input = state
+        .document
+        .get_element_by_id(id)
+        .unwrap()
+        .dyn_into::<HtmlInputElement>()
+        .unwrap();
+    input.set_value(initial_value);
+    input
+        .add_event_listener_with_callback("change", {
+            let window = state.window.clone();
+            Closure::<dyn Fn(_)>::new(move |event: Event| {
+                let input = event
+                    .target()
+                    .unwrap()
+                    .dyn_into::<HtmlInputElement>()
+                    .unwrap();
+                match input.value().parse() {
+                    Ok(value) => callback(value),
+                    Err(e) => alert(&window, format!("{e}.")),
+                }
+            })
+            .into_js_value()
+            .unchecked_ref()
+        })
+        .unwrap();
+}
+
 fn init_file_input(state: &State, id: &str, mut callback: impl FnMut(Vec<u8>) + 'static) {
     let reader = FileReader::new().unwrap();
     reader